@@ -0,0 +1,199 @@
+//! Gas used by the membership and checkpoint flows of the subnet actor as
+//! the validator set grows, measured on the FVM against a real IPC gateway.
+//!
+//! The gas used by each flow is deterministic, so it is measured once per
+//! size of the validator set and written as a JSON report, to compare
+//! implementations of the validator set and of signature aggregation. The
+//! wall time of the same messages is then benchmarked with criterion.
+//!
+//! ```text
+//! IPC_GATEWAY_WASM=<path> BUILTIN_ACTORS_BUNDLE=<path> \
+//!     cargo bench --features bench --bench gas
+//! ```
+//!
+//! The report is written to the path in `GAS_REPORT`, or to
+//! `target/gas-report.json`.
+#[path = "../tests/harness/mod.rs"]
+mod harness;
+
+use std::time::Duration;
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use fvm::executor::ApplyRet;
+use fvm_ipld_encoding::RawBytes;
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::error::ExitCode;
+use harness::{construct_params, Harness};
+use ipc_gateway::{DEFAULT_CHECKPOINT_PERIOD, MIN_COLLATERAL_AMOUNT};
+use ipc_subnet_actor::{
+    AggregatedCheckpointParams, CheckpointSubmission, Method, ValidatorSignature,
+};
+use num_traits::Zero;
+use serde::Serialize;
+
+/// Sizes of the validator set the flows are measured with.
+const VALIDATORS: &[usize] = &[1, 4, 16, 64];
+
+/// Gas used by a flow with `validators` validators in the subnet.
+#[derive(Serialize)]
+struct GasEntry {
+    flow: &'static str,
+    validators: usize,
+    gas_used: i64,
+}
+
+/// Flows measured, each applying the measured message to a subnet set up
+/// with the given number of validators.
+const FLOWS: &[(&str, fn(&mut Harness, usize) -> ApplyRet)] = &[
+    ("join", join),
+    ("leave", leave),
+    ("submit_vote", submit_vote),
+    ("commit_vote", commit_vote),
+    ("aggregated_checkpoint", aggregated_checkpoint),
+];
+
+/// Constructs the subnet with `validators` joined validators, and one more
+/// account to join.
+fn setup(validators: usize) -> Harness {
+    let mut h = Harness::new(validators + 1);
+    h.check_invariants = false;
+    expect_ok(&h.construct(construct_params()));
+    for i in 0..validators {
+        let params = h.join_params(i);
+        expect_ok(&h.apply(i, Method::Join, params, collateral()));
+    }
+    h
+}
+
+fn collateral() -> TokenAmount {
+    TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT)
+}
+
+fn expect_ok(ret: &ApplyRet) {
+    assert_eq!(
+        ret.msg_receipt.exit_code,
+        ExitCode::OK,
+        "{:?}",
+        ret.failure_info
+    );
+}
+
+/// Submission of the checkpoint of the first window, without signature.
+fn submission(h: &Harness) -> CheckpointSubmission {
+    h.submission(DEFAULT_CHECKPOINT_PERIOD)
+}
+
+fn vote(h: &mut Harness, voter: usize) -> ApplyRet {
+    let mut submission = submission(h);
+    let signature = h.sign(voter, &submission);
+    submission.checkpoint.set_signature(signature);
+    let params = RawBytes::serialize(&submission).unwrap();
+    h.apply(voter, Method::SubmitCheckpoint, params, TokenAmount::zero())
+}
+
+/// Number of votes of validators with the same stake reaching the two
+/// thirds majority.
+fn quorum(validators: usize) -> usize {
+    (2 * validators + 2) / 3
+}
+
+fn join(h: &mut Harness, validators: usize) -> ApplyRet {
+    let params = h.join_params(validators);
+    h.apply(validators, Method::Join, params, collateral())
+}
+
+fn leave(h: &mut Harness, validators: usize) -> ApplyRet {
+    h.apply(
+        validators - 1,
+        Method::Leave,
+        RawBytes::default(),
+        TokenAmount::zero(),
+    )
+}
+
+/// First vote of a checkpoint, which doesn't commit it unless there is a
+/// single validator.
+fn submit_vote(h: &mut Harness, _validators: usize) -> ApplyRet {
+    vote(h, 0)
+}
+
+/// Vote reaching the majority, which commits the checkpoint.
+fn commit_vote(h: &mut Harness, validators: usize) -> ApplyRet {
+    let last = quorum(validators) - 1;
+    for i in 0..last {
+        expect_ok(&vote(h, i));
+    }
+    vote(h, last)
+}
+
+/// Checkpoint committed in a single message with the signatures of all
+/// the validators.
+fn aggregated_checkpoint(h: &mut Harness, validators: usize) -> ApplyRet {
+    let submission = submission(h);
+    let signatures = (0..validators)
+        .map(|i| ValidatorSignature {
+            validator: h.validators[i].addr(),
+            signature: h.signature(i, &submission),
+        })
+        .collect();
+    let params = RawBytes::serialize(AggregatedCheckpointParams {
+        submission,
+        signatures,
+    })
+    .unwrap();
+    h.apply(
+        0,
+        Method::SubmitAggregatedCheckpoint,
+        params,
+        TokenAmount::zero(),
+    )
+}
+
+/// Measures the gas used by every flow and writes the report.
+fn write_report() {
+    let mut report = Vec::new();
+    for &(flow, apply) in FLOWS {
+        for &validators in VALIDATORS {
+            let mut h = setup(validators);
+            let ret = apply(&mut h, validators);
+            expect_ok(&ret);
+            report.push(GasEntry {
+                flow,
+                validators,
+                gas_used: ret.msg_receipt.gas_used,
+            });
+        }
+    }
+
+    let path = std::env::var("GAS_REPORT").unwrap_or_else(|_| "target/gas-report.json".to_string());
+    std::fs::write(&path, serde_json::to_vec_pretty(&report).unwrap()).unwrap();
+    eprintln!("gas report written to {}", path);
+}
+
+fn bench_flows(c: &mut Criterion) {
+    write_report();
+
+    for &(flow, apply) in FLOWS {
+        let mut group = c.benchmark_group(flow);
+        // setting up the subnet is much slower than the measured message
+        group.sample_size(10);
+        group.measurement_time(Duration::from_secs(30));
+        for &validators in VALIDATORS {
+            group.bench_with_input(
+                BenchmarkId::from_parameter(validators),
+                &validators,
+                |b, &validators| {
+                    b.iter_batched(
+                        || setup(validators),
+                        |mut h| apply(&mut h, validators),
+                        BatchSize::PerIteration,
+                    )
+                },
+            );
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_flows);
+criterion_main!(benches);
@@ -1,5 +1,8 @@
 fn main() {
     use wasm_builder::WasmBuilder;
+    if std::env::var_os("CARGO_FEATURE_FIL_ACTOR").is_none() {
+        return;
+    }
     WasmBuilder::new()
         .with_current_project()
         .import_memory()
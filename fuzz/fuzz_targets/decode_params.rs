@@ -0,0 +1,140 @@
+//! Feeds arbitrary bytes as the params of every method of the subnet
+//! actor, decoding them the way its dispatcher does. Malformed params need
+//! to fail with an error, a panic would trap the actor with a
+//! non-deterministic abort.
+//!
+//! ```text
+//! cargo +nightly fuzz run decode_params
+//! ```
+//!
+//! Only decoding is fuzzed: `MockRuntime` panics on any send or caller
+//! validation it wasn't told to expect, which arbitrary params can't
+//! predict, so running the methods on it would report its own panics.
+#![no_main]
+
+use cid::Cid;
+use fil_actors_runtime::{cbor, ActorError};
+use fvm_ipld_encoding::RawBytes;
+use fvm_shared::address::Address;
+use fvm_shared::clock::ChainEpoch;
+use ipc_subnet_actor::evidence::DoubleSignEvidence;
+use ipc_subnet_actor::ext::frc46;
+use ipc_subnet_actor::{
+    AggregatedCheckpointParams, BootstrapValidatorsParams, CanJoinParams, CanLeaveParams,
+    CanSubmitCheckpointParams, ChallengeCheckpointParams, CheckpointRangeParams,
+    CheckpointSubmission, ConstructParams, DelegateParams, EconParams, IssueInvitationsParams,
+    JoinParams, ListParams, Method, ParentFinality, PutCrossMsgBatchParams, RedelegateParams,
+    RegisterCrossMsgParams, ReleaseStakeForParams, RotateValidatorKeyParams, SetMetadataParams,
+    SpendParams, SubmitEvidenceParams, TransferOwnershipParams, UndelegateParams,
+    WithdrawStakeParams,
+};
+use libfuzzer_sys::fuzz_target;
+use num_traits::FromPrimitive;
+
+fuzz_target!(|input: (u64, Vec<u8>)| {
+    let (method, params) = input;
+    // errors are expected, only panics are findings
+    let _ = decode(method, &RawBytes::new(params));
+});
+
+fn decode_as<T: serde::de::DeserializeOwned>(params: &RawBytes) -> Result<T, ActorError> {
+    cbor::deserialize_params(params)
+}
+
+/// Decodes `params` as the params of `method`, along with the payloads
+/// nested in them. The match has no catch-all arm, so new methods don't
+/// build until they are covered.
+fn decode(method: u64, params: &RawBytes) -> Result<(), ActorError> {
+    let method = match Method::from_u64(method) {
+        Some(m) => m,
+        None => return Ok(()),
+    };
+    match method {
+        Method::Constructor => decode_as::<ConstructParams>(params).map(drop),
+        Method::Join => decode_as::<JoinParams>(params).map(drop),
+        Method::SubmitCheckpoint | Method::SubmitCatchUpCheckpoint => {
+            decode_as::<CheckpointSubmission>(params).map(drop)
+        }
+        Method::IssueInvitations => decode_as::<IssueInvitationsParams>(params).map(drop),
+        Method::RegisterCrossMsg => decode_as::<RegisterCrossMsgParams>(params).map(drop),
+        Method::SubmitAggregatedCheckpoint => {
+            decode_as::<AggregatedCheckpointParams>(params).map(drop)
+        }
+        Method::Receive => {
+            let params = decode_as::<frc46::UniversalReceiverParams>(params)?;
+            let received: frc46::FRC46TokenReceived = params.payload.deserialize()?;
+            received.operator_data.deserialize::<JoinParams>()?;
+            Ok(())
+        }
+        Method::SetMetadata => decode_as::<SetMetadataParams>(params).map(drop),
+        Method::GetStakeByRobustAddress
+        | Method::GetMembershipTicket
+        | Method::GetExitQueuePosition
+        | Method::UpdateGatewayAddr => decode_as::<Address>(params).map(drop),
+        Method::BootstrapValidators => decode_as::<BootstrapValidatorsParams>(params).map(drop),
+        Method::ListStakers
+        | Method::ListCheckpoints
+        | Method::CompactStake
+        | Method::GetMembershipHistory => decode_as::<ListParams>(params).map(drop),
+        Method::ChallengeCheckpoint => decode_as::<ChallengeCheckpointParams>(params).map(drop),
+        Method::RotateValidatorKey => decode_as::<RotateValidatorKeyParams>(params).map(drop),
+        Method::SubmitParentFinality => decode_as::<ParentFinality>(params).map(drop),
+        Method::GetCheckpointVotingStatus | Method::SetCheckPeriod | Method::GetLiveValidators => {
+            decode_as::<ChainEpoch>(params).map(drop)
+        }
+        Method::Delegate => decode_as::<DelegateParams>(params).map(drop),
+        Method::Undelegate => decode_as::<UndelegateParams>(params).map(drop),
+        Method::SetCommissionRate
+        | Method::RenounceOwnership
+        | Method::GetCheckpointProof
+        | Method::GetValidatorSetAt => decode_as::<u64>(params).map(drop),
+        Method::ReleaseStakeFor => decode_as::<ReleaseStakeForParams>(params).map(drop),
+        Method::TransferOwnership => decode_as::<TransferOwnershipParams>(params).map(drop),
+        Method::WithdrawStake => decode_as::<WithdrawStakeParams>(params).map(drop),
+        Method::SetValidatorSetNotifications => decode_as::<bool>(params).map(drop),
+        Method::ProposeSpend => decode_as::<SpendParams>(params).map(drop),
+        Method::ApproveSpend | Method::Upgrade => decode_as::<Cid>(params).map(drop),
+        Method::Redelegate => decode_as::<RedelegateParams>(params).map(drop),
+        Method::CanJoin => decode_as::<CanJoinParams>(params).map(drop),
+        Method::CanLeave => decode_as::<CanLeaveParams>(params).map(drop),
+        Method::CanSubmitCheckpoint => decode_as::<CanSubmitCheckpointParams>(params).map(drop),
+        Method::PutCrossMsgBatch => decode_as::<PutCrossMsgBatchParams>(params).map(drop),
+        Method::SetGatewayCodes | Method::SetUpgradeCodes => {
+            decode_as::<Vec<Cid>>(params).map(drop)
+        }
+        Method::ProposeEconParams => decode_as::<EconParams>(params).map(drop),
+        Method::ListCheckpointsInRange => decode_as::<CheckpointRangeParams>(params).map(drop),
+        Method::SubmitEvidence => {
+            let params = decode_as::<SubmitEvidenceParams>(params)?;
+            fvm_ipld_encoding::from_slice::<DoubleSignEvidence>(params.evidence.bytes())
+                .map_err(|e| ActorError::illegal_argument(e.to_string()))?;
+            Ok(())
+        }
+        // methods without params don't decode them
+        Method::Leave
+        | Method::Kill
+        | Method::MigrateState
+        | Method::AddStake
+        | Method::NextCheckpointEpoch
+        | Method::Unjail
+        | Method::DistributeFees
+        | Method::GetLastStateCommitment
+        | Method::RelayCheckpoints
+        | Method::GetPowerTable
+        | Method::GetParentFinality
+        | Method::Pause
+        | Method::Unpause
+        | Method::FundRewardPool
+        | Method::GetGenesisEpoch
+        | Method::GetStats
+        | Method::Heartbeat
+        | Method::GetFinalityConfig
+        | Method::GetCheckpointTemplate
+        | Method::RetryGatewayCalls
+        | Method::ListSpendProposals
+        | Method::GetCheckpointWindows
+        | Method::GetEconParams
+        | Method::GetDelegatedAddress
+        | Method::GetSubnetSummary => Ok(()),
+    }
+}
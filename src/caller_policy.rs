@@ -0,0 +1,89 @@
+//! Validation of the actor type of the callers of validator methods.
+//!
+//! Validators interact with the subnet from their own actor, which by
+//! default can be any actor. Subnets can restrict, per method, the type of
+//! actors allowed to call it, e.g. to keep contracts out of the validator
+//! set while still allowing multisig validators.
+use fil_actors_runtime::runtime::builtins::Type;
+use fil_actors_runtime::runtime::Runtime;
+use fil_actors_runtime::{actor_error, ActorError};
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::repr::*;
+use fvm_ipld_encoding::tuple::{Deserialize_tuple, Serialize_tuple};
+use fvm_shared::address::Address;
+
+/// Actor types allowed to call a method.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default, Deserialize_repr, Serialize_repr)]
+#[repr(u64)]
+pub enum CallerPolicy {
+    /// Any actor.
+    #[default]
+    Any,
+    /// Account and Ethereum account actors.
+    Accounts,
+    /// Account, Ethereum account and multisig actors.
+    AccountsAndMultisigs,
+}
+
+impl CallerPolicy {
+    /// Actor types accepted by the policy, `None` if any actor is.
+    pub fn types(&self) -> Option<&'static [Type]> {
+        match self {
+            CallerPolicy::Any => None,
+            CallerPolicy::Accounts => Some(&[Type::Account, Type::EthAccount]),
+            CallerPolicy::AccountsAndMultisigs => {
+                Some(&[Type::Account, Type::EthAccount, Type::Multisig])
+            }
+        }
+    }
+
+    /// Validates the immediate caller against the policy.
+    pub fn validate<BS, RT>(&self, rt: &mut RT) -> Result<(), ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        match self.types() {
+            None => rt.validate_immediate_caller_accept_any(),
+            Some(types) => rt.validate_immediate_caller_type(types),
+        }
+    }
+
+    /// Checks that `addr` is accepted by the policy, as `validate` would
+    /// if it was the immediate caller.
+    pub fn check<BS, RT>(&self, rt: &RT, addr: &Address) -> Result<(), ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        let types = match self.types() {
+            None => return Ok(()),
+            Some(types) => types,
+        };
+        let actor_type = rt
+            .resolve_address(addr)
+            .and_then(|id| rt.get_actor_code_cid(&id))
+            .and_then(|code| rt.resolve_builtin_actor_type(&code));
+        match actor_type {
+            Some(t) if types.contains(&t) => Ok(()),
+            _ => Err(actor_error!(
+                forbidden,
+                "{} is not of a type allowed by the caller policy",
+                addr
+            )),
+        }
+    }
+}
+
+/// Caller policies of the methods called by validators, set at
+/// construction.
+#[derive(Clone, Debug, Default, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct CallerPolicies {
+    /// Applies to `Join` and `AddStake`, and to the senders of token
+    /// collateral.
+    pub join: CallerPolicy,
+    /// Applies to `Leave`.
+    pub leave: CallerPolicy,
+    /// Applies to `SubmitCheckpoint` and `SubmitCatchUpCheckpoint`.
+    pub submit_checkpoint: CallerPolicy,
+}
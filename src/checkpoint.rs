@@ -0,0 +1,84 @@
+//! Checkpoint window math shared by the actor and the nodes of the subnet.
+//!
+//! Nodes and relayers get the [`CheckpointWindows`] of a subnet from
+//! `GetCheckpointWindows` and use the same functions the actor validates
+//! submissions with, instead of reimplementing them.
+//!
+//! ```ignore
+//! let windows: CheckpointWindows = get_checkpoint_windows(subnet_actor)?;
+//! let (_, end) = windows.window_for_epoch(current_epoch);
+//! if windows.is_submission_open(end, current_epoch) {
+//!     // sign and submit the checkpoint of `end`
+//! }
+//! ```
+use fvm_ipld_encoding::tuple::{Deserialize_tuple, Serialize_tuple};
+use fvm_ipld_encoding::Cbor;
+use fvm_shared::clock::ChainEpoch;
+
+use crate::types::CheckPeriodChange;
+
+/// Checkpoint windows of a subnet. A window covers the epochs after its
+/// start up to its end, the epoch its checkpoint is signed at.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct CheckpointWindows {
+    /// Epoch windows of the current period are counted from.
+    pub anchor: ChainEpoch,
+    /// Length of the windows.
+    pub period: ChainEpoch,
+    /// Change of the period taking effect with the next committed
+    /// checkpoint, whose windows are counted from its `effective_epoch`.
+    pub pending: Option<CheckPeriodChange>,
+    /// End of the window of the next checkpoint expected.
+    pub next_epoch: ChainEpoch,
+}
+impl Cbor for CheckpointWindows {}
+
+impl CheckpointWindows {
+    /// Whether `epoch` ends a checkpoint window.
+    ///
+    /// While a change of the period is pending, the windows of both
+    /// periods are valid: the current ones until the change takes effect,
+    /// and the new ones after it.
+    pub fn is_window_end(&self, epoch: ChainEpoch) -> bool {
+        // epochs are given by callers, and may be far from the anchor
+        if epoch
+            .checked_sub(self.anchor)
+            .map_or(false, |d| d % self.period == 0)
+        {
+            return true;
+        }
+        match &self.pending {
+            Some(c) => epoch > c.effective_epoch && (epoch - c.effective_epoch) % c.new_period == 0,
+            None => false,
+        }
+    }
+
+    /// Start and end of the window containing `epoch`, with the period in
+    /// effect at `epoch`.
+    pub fn window_for_epoch(&self, epoch: ChainEpoch) -> (ChainEpoch, ChainEpoch) {
+        let (anchor, period) = match &self.pending {
+            Some(c) if epoch > c.effective_epoch => (c.effective_epoch, c.new_period),
+            _ => (self.anchor, self.period),
+        };
+        // rounds up to the next window end, or `epoch` itself if it is one
+        let behind = anchor.saturating_sub(epoch);
+        let windows = -behind.div_euclid(period);
+        let end = anchor.saturating_add(windows.saturating_mul(period));
+        (end.saturating_sub(period), end)
+    }
+
+    /// Whether the checkpoint of the window ending at `epoch` can be
+    /// submitted at `current_epoch`, as the next checkpoint with
+    /// `SubmitCheckpoint` or catching up with skipped windows with
+    /// `SubmitCatchUpCheckpoint`.
+    pub fn is_submission_open(&self, epoch: ChainEpoch, current_epoch: ChainEpoch) -> bool {
+        self.is_window_end(epoch)
+            && (epoch == self.next_epoch || self.is_catch_up_open(epoch, current_epoch))
+    }
+
+    /// Whether the window ending at `epoch` was skipped and closed by
+    /// `current_epoch`, so a catch-up checkpoint can be submitted for it.
+    pub fn is_catch_up_open(&self, epoch: ChainEpoch, current_epoch: ChainEpoch) -> bool {
+        epoch > self.next_epoch && epoch <= current_epoch
+    }
+}
@@ -0,0 +1,89 @@
+//! Everything the actor takes from `ipc_gateway` goes through this module,
+//! so a new version of the gateway only needs changes here.
+//!
+//! The gateway pins its own version of `fvm_shared`. While it matches ours,
+//! values are handed to the gateway types as they are. With the
+//! `gateway-skew` feature, they are re-encoded into the types of the
+//! gateway instead, which only relies on their CBOR encoding being the
+//! same across versions.
+use fvm_ipld_encoding::RawBytes;
+use fvm_shared::address::Address;
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::MethodNum;
+
+pub use ipc_gateway::{
+    Checkpoint, CrossMsgMeta, SubnetID, DEFAULT_CHECKPOINT_PERIOD, MIN_COLLATERAL_AMOUNT,
+};
+
+/// Methods of the gateway called by the actor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GatewayMethod {
+    Register,
+    AddStake,
+    ReleaseStake,
+    Kill,
+    CommitChildCheckpoint,
+}
+
+impl GatewayMethod {
+    pub fn method_num(self) -> MethodNum {
+        let method = match self {
+            GatewayMethod::Register => ipc_gateway::Method::Register,
+            GatewayMethod::AddStake => ipc_gateway::Method::AddStake,
+            GatewayMethod::ReleaseStake => ipc_gateway::Method::ReleaseStake,
+            GatewayMethod::Kill => ipc_gateway::Method::Kill,
+            GatewayMethod::CommitChildCheckpoint => ipc_gateway::Method::CommitChildCheckpoint,
+        };
+        method as MethodNum
+    }
+
+    /// Method called with `method_num`, if it's one the actor calls.
+    pub fn from_method_num(method_num: MethodNum) -> Option<Self> {
+        [
+            GatewayMethod::Register,
+            GatewayMethod::AddStake,
+            GatewayMethod::ReleaseStake,
+            GatewayMethod::Kill,
+            GatewayMethod::CommitChildCheckpoint,
+        ]
+        .into_iter()
+        .find(|m| m.method_num() == method_num)
+    }
+}
+
+/// Converts a value into the equivalent type of another version of its
+/// crate by re-encoding it.
+pub fn convert<T, U>(value: &T) -> anyhow::Result<U>
+where
+    T: serde::Serialize,
+    U: serde::de::DeserializeOwned,
+{
+    let bytes = fvm_ipld_encoding::to_vec(value)?;
+    Ok(fvm_ipld_encoding::from_slice(&bytes)?)
+}
+
+/// ID of the subnet governed by `actor`, child of `parent`.
+#[cfg(not(feature = "gateway-skew"))]
+pub fn subnet_id(parent: &SubnetID, actor: Address) -> anyhow::Result<SubnetID> {
+    Ok(SubnetID::new(parent, actor))
+}
+
+#[cfg(feature = "gateway-skew")]
+pub fn subnet_id(parent: &SubnetID, actor: Address) -> anyhow::Result<SubnetID> {
+    Ok(SubnetID::new(parent, convert(&actor)?))
+}
+
+/// Parameters of the gateway methods moving `value` of stake or funds.
+#[cfg(not(feature = "gateway-skew"))]
+pub fn fund_params(value: &TokenAmount) -> anyhow::Result<RawBytes> {
+    Ok(RawBytes::serialize(ipc_gateway::FundParams {
+        value: value.clone(),
+    })?)
+}
+
+#[cfg(feature = "gateway-skew")]
+pub fn fund_params(value: &TokenAmount) -> anyhow::Result<RawBytes> {
+    Ok(RawBytes::serialize(ipc_gateway::FundParams {
+        value: convert(value)?,
+    })?)
+}
@@ -0,0 +1,249 @@
+use cid::Cid;
+use fil_actors_runtime::{ActorDowncast, ActorError};
+use fvm_shared::address::Address;
+use fvm_shared::clock::ChainEpoch;
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::error::ExitCode;
+use fvm_shared::MethodNum;
+use thiserror::Error;
+
+use crate::compat::{GatewayMethod, SubnetID};
+use crate::types::{ConsensusType, ERR_SUBNET_KILLED, ERR_SUBNET_TERMINATING};
+
+/// Failures of the subnet actor, each mapped to a stable exit code so
+/// clients can match on them instead of parsing error messages.
+///
+/// Exit codes are part of the interface of the actor: new variants need
+/// to take new codes, and existing codes must not be reused.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum SubnetActorError {
+    #[error("the subnet is terminating")]
+    SubnetTerminating,
+    #[error("the subnet has been killed")]
+    SubnetKilled,
+    #[error("address doesn't have enough stake: {0}")]
+    NotEnoughStake(Address),
+    #[error("stake of {0} would exceed the maximum validator stake")]
+    StakeCapExceeded(Address),
+    #[error("stake of {0} is already at the maximum validator stake")]
+    StakeAtCap(Address),
+    #[error("validator is not jailed: {0}")]
+    NotJailed(Address),
+    #[error("invitation already issued")]
+    InvitationAlreadyIssued,
+    #[error("invalid invitation")]
+    InvalidInvitation,
+    #[error("invitation already redeemed")]
+    InvitationAlreadyRedeemed,
+    #[error("epoch doesn't correspond with a signing window")]
+    NotSigningWindow,
+    #[error("checkpoint for epoch already committed")]
+    CheckpointAlreadyCommitted,
+    #[error("submitting checkpoints is not allowed while subnet is not active")]
+    SubnetNotActive,
+    #[error("checkpoint not for the next expected window: {0}")]
+    NotNextWindow(ChainEpoch),
+    #[error("no skipped windows to catch up with")]
+    NoSkippedWindows,
+    #[error("submitting checkpoint with the wrong source")]
+    WrongCheckpointSource,
+    #[error("previous checkpoint not consistent with previously committed")]
+    InconsistentPrevCheckpoint,
+    #[error("cross-msgs batch in checkpoint not registered for the window")]
+    CrossMsgsNotRegistered,
+    #[error("not validator: {0}")]
+    NotValidator(Address),
+    #[error("validator has already voted the checkpoint: {0}")]
+    AlreadyVoted(Address),
+    #[error("the validator set can only be bootstrapped once before activation")]
+    AlreadyBootstrapped,
+    #[error("checkpoint doesn't carry a valid state commitment")]
+    InvalidStateCommitment,
+    #[error("no checkpoint in its dispute period for epoch {0}")]
+    NotDisputable(ChainEpoch),
+    #[error("invalid key rotation nonce, expected {0}")]
+    InvalidKeyNonce(u64),
+    #[error("stake of {0} is already vesting")]
+    AlreadyVesting(Address),
+    #[error("no vested stake to withdraw: {0}")]
+    NoVestedStake(Address),
+    #[error("join below the minimum join amount")]
+    JoinAmountTooLow,
+    #[error("parent height {0} not final yet or older than the committed finality")]
+    InvalidParentFinality(ChainEpoch),
+    #[error("the subnet actor is paused")]
+    Paused,
+    #[error("invalid ownership nonce, expected {0}")]
+    InvalidOwnerNonce(u64),
+    #[error("stake arithmetic underflow")]
+    StakeUnderflow,
+    #[error("negative stake amount")]
+    NegativeStakeAmount,
+    #[error("checkpoint period below the minimum: {0}")]
+    InvalidCheckPeriod(ChainEpoch),
+    #[error("validator already voted another checkpoint in the window: {0}")]
+    ConflictingVote(Address),
+    #[error("withdrawal would leave validator {0} below the minimum stake")]
+    WithdrawalBelowMinStake(Address),
+    #[error("{0} count underflow")]
+    CountUnderflow(&'static str),
+    #[error("validator {0} exceeded the checkpoint submissions of the window")]
+    TooManySubmissions(Address),
+    #[error("validator {0} is already in the exit queue")]
+    ExitPending(Address),
+    #[error("the treasury can't cover a spending of {0}")]
+    InsufficientTreasury(TokenAmount),
+    #[error("collateral doesn't cover the join fee of {0}")]
+    JoinFeeNotCovered(TokenAmount),
+    #[error("stake can't be redelegated again until epoch {0}")]
+    RedelegationCooldown(ChainEpoch),
+    #[error("checkpoint of {0} bytes exceeds the maximum size")]
+    CheckpointTooLarge(usize),
+    #[error("cross-msgs batch of {0} bytes exceeds the maximum size")]
+    CrossMsgBatchTooLarge(usize),
+    #[error("{0} can only join with a worker key to sign checkpoints")]
+    WorkerKeyRequired(Address),
+    #[error("worker key already in use: {0}")]
+    WorkerKeyInUse(Address),
+    #[error("checkpoint wasn't produced under the validator set of configuration {0}")]
+    ValidatorSetMismatch(u64),
+    #[error("code of the gateway {0} is not allowed")]
+    GatewayCodeNotAllowed(Address),
+    #[error("the gateway rejected the registration of the subnet with exit code {0}")]
+    GatewayRejectedRegistration(ExitCode),
+    #[error("the gateway doesn't hold enough collateral of the subnet to release")]
+    InsufficientGatewayCollateral,
+    #[error("the gateway rejected the checkpoint with exit code {0}")]
+    GatewayRejectedCheckpoint(ExitCode),
+    #[error("call to method {0} of the gateway failed with exit code {1}")]
+    GatewayCallFailed(MethodNum, ExitCode),
+    #[error("the gateway is deployed on {0}, not on the parent of the subnet")]
+    ParentMismatch(SubnetID),
+    #[error("{0:?} consensus doesn't support misbehavior evidence")]
+    UnsupportedEvidence(ConsensusType),
+    #[error("the window at {0} is not open for bottom-up messages")]
+    WindowNotOpen(ChainEpoch),
+    #[error("too many batches of bottom-up messages registered by {0} in the window")]
+    TooManyCrossMsgBatches(Address),
+    #[error("the unjail penalty would leave {0} below the minimum stake, top it up first")]
+    UnjailBelowMinStake(Address),
+    #[error("stake of {0} is still vesting, withdraw the vested stake instead of leaving")]
+    StakeStillVesting(Address),
+    #[error("call to method {0} of the gateway can't be queued, the retry queue is full")]
+    GatewayQueueFull(MethodNum),
+    #[error("evidence of misbehavior at height {0} is older than the unbonding period")]
+    EvidenceTooOld(ChainEpoch),
+    #[error("the offence proven by the evidence was already slashed")]
+    EvidenceAlreadyUsed,
+    #[error("the subnet actor can't be upgraded to code {0}")]
+    UpgradeCodeNotAllowed(Cid),
+}
+
+impl SubnetActorError {
+    pub fn exit_code(&self) -> ExitCode {
+        match self {
+            SubnetActorError::SubnetTerminating => ERR_SUBNET_TERMINATING,
+            SubnetActorError::SubnetKilled => ERR_SUBNET_KILLED,
+            SubnetActorError::NotEnoughStake(_) => ExitCode::new(34),
+            SubnetActorError::StakeCapExceeded(_) => ExitCode::new(35),
+            SubnetActorError::StakeAtCap(_) => ExitCode::new(36),
+            SubnetActorError::NotJailed(_) => ExitCode::new(37),
+            SubnetActorError::InvitationAlreadyIssued => ExitCode::new(38),
+            SubnetActorError::InvalidInvitation => ExitCode::new(39),
+            SubnetActorError::InvitationAlreadyRedeemed => ExitCode::new(40),
+            SubnetActorError::NotSigningWindow => ExitCode::new(41),
+            SubnetActorError::CheckpointAlreadyCommitted => ExitCode::new(42),
+            SubnetActorError::SubnetNotActive => ExitCode::new(43),
+            SubnetActorError::NotNextWindow(_) => ExitCode::new(44),
+            SubnetActorError::NoSkippedWindows => ExitCode::new(45),
+            SubnetActorError::WrongCheckpointSource => ExitCode::new(46),
+            SubnetActorError::InconsistentPrevCheckpoint => ExitCode::new(47),
+            SubnetActorError::CrossMsgsNotRegistered => ExitCode::new(48),
+            SubnetActorError::NotValidator(_) => ExitCode::new(49),
+            SubnetActorError::AlreadyVoted(_) => ExitCode::new(50),
+            SubnetActorError::AlreadyBootstrapped => ExitCode::new(51),
+            SubnetActorError::InvalidStateCommitment => ExitCode::new(52),
+            SubnetActorError::NotDisputable(_) => ExitCode::new(53),
+            SubnetActorError::InvalidKeyNonce(_) => ExitCode::new(54),
+            SubnetActorError::AlreadyVesting(_) => ExitCode::new(55),
+            SubnetActorError::NoVestedStake(_) => ExitCode::new(56),
+            SubnetActorError::JoinAmountTooLow => ExitCode::new(57),
+            SubnetActorError::InvalidParentFinality(_) => ExitCode::new(58),
+            SubnetActorError::Paused => ExitCode::new(59),
+            SubnetActorError::InvalidOwnerNonce(_) => ExitCode::new(60),
+            SubnetActorError::StakeUnderflow => ExitCode::new(61),
+            SubnetActorError::NegativeStakeAmount => ExitCode::new(62),
+            SubnetActorError::InvalidCheckPeriod(_) => ExitCode::new(63),
+            SubnetActorError::ConflictingVote(_) => ExitCode::new(64),
+            SubnetActorError::WithdrawalBelowMinStake(_) => ExitCode::new(65),
+            SubnetActorError::CountUnderflow(_) => ExitCode::new(66),
+            SubnetActorError::TooManySubmissions(_) => ExitCode::new(67),
+            SubnetActorError::ExitPending(_) => ExitCode::new(68),
+            SubnetActorError::InsufficientTreasury(_) => ExitCode::new(69),
+            SubnetActorError::JoinFeeNotCovered(_) => ExitCode::new(70),
+            SubnetActorError::RedelegationCooldown(_) => ExitCode::new(71),
+            SubnetActorError::CheckpointTooLarge(_) => ExitCode::new(72),
+            SubnetActorError::CrossMsgBatchTooLarge(_) => ExitCode::new(73),
+            SubnetActorError::WorkerKeyRequired(_) => ExitCode::new(74),
+            SubnetActorError::WorkerKeyInUse(_) => ExitCode::new(75),
+            SubnetActorError::GatewayCodeNotAllowed(_) => ExitCode::new(76),
+            SubnetActorError::ValidatorSetMismatch(_) => ExitCode::new(77),
+            SubnetActorError::GatewayRejectedRegistration(_) => ExitCode::new(78),
+            SubnetActorError::InsufficientGatewayCollateral => ExitCode::new(79),
+            SubnetActorError::GatewayRejectedCheckpoint(_) => ExitCode::new(80),
+            SubnetActorError::GatewayCallFailed(..) => ExitCode::new(81),
+            SubnetActorError::ParentMismatch(_) => ExitCode::new(82),
+            SubnetActorError::UnsupportedEvidence(_) => ExitCode::new(83),
+            SubnetActorError::WindowNotOpen(_) => ExitCode::new(84),
+            SubnetActorError::TooManyCrossMsgBatches(_) => ExitCode::new(85),
+            SubnetActorError::UnjailBelowMinStake(_) => ExitCode::new(86),
+            SubnetActorError::StakeStillVesting(_) => ExitCode::new(87),
+            SubnetActorError::GatewayQueueFull(_) => ExitCode::new(88),
+            SubnetActorError::EvidenceTooOld(_) => ExitCode::new(89),
+            SubnetActorError::EvidenceAlreadyUsed => ExitCode::new(90),
+            SubnetActorError::UpgradeCodeNotAllowed(_) => ExitCode::new(91),
+        }
+    }
+
+    /// Maps the exit code a call to `method` of the IPC gateway failed
+    /// with into the error it stands for.
+    pub fn from_gateway(method: MethodNum, code: ExitCode) -> Self {
+        match GatewayMethod::from_method_num(method) {
+            Some(GatewayMethod::Register) => SubnetActorError::GatewayRejectedRegistration(code),
+            // the gateway refuses to release more than the subnet staked
+            Some(GatewayMethod::ReleaseStake)
+                if code == ExitCode::USR_ILLEGAL_STATE
+                    || code == ExitCode::USR_INSUFFICIENT_FUNDS =>
+            {
+                SubnetActorError::InsufficientGatewayCollateral
+            }
+            Some(GatewayMethod::CommitChildCheckpoint) => {
+                SubnetActorError::GatewayRejectedCheckpoint(code)
+            }
+            _ => SubnetActorError::GatewayCallFailed(method, code),
+        }
+    }
+}
+
+impl From<SubnetActorError> for ActorError {
+    fn from(e: SubnetActorError) -> Self {
+        ActorError::unchecked(e.exit_code(), e.to_string())
+    }
+}
+
+/// Converts errors returned by the state into an `ActorError`, keeping the
+/// exit code of a [`SubnetActorError`].
+pub trait SubnetActorDowncast {
+    /// Like `ActorDowncast::downcast_default`, but mapping a
+    /// [`SubnetActorError`] to its own exit code.
+    fn downcast_subnet(self, default_exit_code: ExitCode, msg: &str) -> ActorError;
+}
+
+impl SubnetActorDowncast for anyhow::Error {
+    fn downcast_subnet(self, default_exit_code: ExitCode, msg: &str) -> ActorError {
+        match self.downcast::<SubnetActorError>() {
+            Ok(e) => ActorError::unchecked(e.exit_code(), format!("{}: {}", msg, e)),
+            Err(e) => e.downcast_default(default_exit_code, msg),
+        }
+    }
+}
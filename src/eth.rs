@@ -0,0 +1,88 @@
+//! Ethereum addresses of validators signing with EVM-compatible wallets.
+//!
+//! These validators are identified by an f410 address, the delegated
+//! address of the Ethereum Address Manager, whose subaddress is the
+//! Ethereum address of the secp256k1 key of the validator. Their accounts
+//! don't expose the public key, so it is recovered from the signature.
+use anyhow::anyhow;
+use fil_actors_runtime::runtime::Runtime;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_shared::address::{Address, Payload};
+use fvm_shared::crypto::signature::{Signature, SignatureType, SECP_PUB_LEN, SECP_SIG_LEN};
+use fvm_shared::ActorID;
+use multihash::{Code, MultihashDigest};
+
+/// ID of the Ethereum Address Manager, the namespace of f410 addresses.
+pub const EAM_ACTOR_ID: ActorID = 10;
+/// Length of an Ethereum address.
+pub const ETH_ADDRESS_LEN: usize = 20;
+
+/// Returns the Ethereum address of an f410 address.
+pub fn eth_address(addr: &Address) -> Option<[u8; ETH_ADDRESS_LEN]> {
+    match addr.payload() {
+        Payload::Delegated(d) if d.namespace() == EAM_ACTOR_ID => d.subaddress().try_into().ok(),
+        _ => None,
+    }
+}
+
+/// Whether `addr` is an f410 address.
+pub fn is_eth_address(addr: &Address) -> bool {
+    eth_address(addr).is_some()
+}
+
+/// Derives the Ethereum address of an uncompressed secp256k1 public key:
+/// the last 20 bytes of the Keccak-256 hash of the key without its prefix.
+pub fn eth_address_from_pubkey(pubkey: &[u8; SECP_PUB_LEN]) -> [u8; ETH_ADDRESS_LEN] {
+    let hash = Code::Keccak256.digest(&pubkey[1..]);
+    let mut addr = [0u8; ETH_ADDRESS_LEN];
+    addr.copy_from_slice(&hash.digest()[32 - ETH_ADDRESS_LEN..]);
+    addr
+}
+
+/// Hashes `msg` the way Ethereum wallets do before signing it, as an
+/// EIP-191 personal message: the Keccak-256 hash of the message prefixed
+/// with `"\x19Ethereum Signed Message:\n"` and its length in decimal.
+pub fn eip191_hash(msg: &[u8]) -> [u8; 32] {
+    let mut prefixed = format!("\x19Ethereum Signed Message:\n{}", msg.len()).into_bytes();
+    prefixed.extend_from_slice(msg);
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(Code::Keccak256.digest(&prefixed).digest());
+    hash
+}
+
+/// Verifies a secp256k1 signature of `msg` by the key of the f410 address
+/// `signer`, as signed by Ethereum wallets over its [`eip191_hash`],
+/// recovering the public key from the signature. The recovery ID can be
+/// given either as is or in the legacy format of 27 or 28.
+pub fn verify_signature<BS, RT>(
+    rt: &RT,
+    sig: &Signature,
+    signer: &Address,
+    msg: &[u8],
+) -> anyhow::Result<()>
+where
+    BS: Blockstore,
+    RT: Runtime<BS>,
+{
+    let expected = eth_address(signer).ok_or_else(|| anyhow!("not an f410 address: {}", signer))?;
+    if sig.sig_type != SignatureType::Secp256k1 {
+        return Err(anyhow!("f410 signers need to sign with secp256k1"));
+    }
+    let mut bytes: [u8; SECP_SIG_LEN] = sig
+        .bytes
+        .as_slice()
+        .try_into()
+        .map_err(|_| anyhow!("invalid secp256k1 signature length: {}", sig.bytes.len()))?;
+    if bytes[SECP_SIG_LEN - 1] >= 27 {
+        bytes[SECP_SIG_LEN - 1] -= 27;
+    }
+
+    let pubkey = rt.recover_secp_public_key(&eip191_hash(msg), &bytes)?;
+    if eth_address_from_pubkey(&pubkey) != expected {
+        return Err(anyhow!(
+            "signature doesn't match the f410 address {}",
+            signer
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,138 @@
+//! Verification of evidence of misbehavior in the consensus of a subnet.
+//!
+//! Validators can misbehave in the subnet without it showing in its
+//! checkpoints, e.g. by voting two blocks at the same height. Evidence of
+//! it is submitted as a blob in the format of the consensus, which
+//! implements [`EvidenceVerifier`] for it, so the faults of a new consensus
+//! only need a verifier and an arm in the one of `ConsensusType`.
+use anyhow::anyhow;
+use cid::multihash::{Code, MultihashDigest};
+use cid::Cid;
+use fil_actors_runtime::runtime::Runtime;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::tuple::{Deserialize_tuple, Serialize_tuple};
+use fvm_ipld_encoding::{Cbor, DAG_CBOR};
+use fvm_shared::address::Address;
+use fvm_shared::clock::ChainEpoch;
+use fvm_shared::crypto::signature::Signature;
+
+use crate::compat::{self, SubnetID};
+use crate::error::SubnetActorError;
+use crate::state::State;
+use crate::types::{ConsensusType, EVIDENCE_MAX_AGE_WINDOWS};
+
+/// Verifies evidence of the misbehavior of a validator.
+pub trait EvidenceVerifier {
+    /// Verifies that `evidence` proves the misbehavior of `validator`, a
+    /// member of the validator set of `st`. Returns the digest of the
+    /// offence, so that it is only slashed once whatever evidence of it is
+    /// submitted.
+    fn verify<BS, RT>(
+        &self,
+        rt: &mut RT,
+        st: &State,
+        validator: &Address,
+        evidence: &[u8],
+    ) -> anyhow::Result<Cid>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>;
+}
+
+/// Vote of a validator for a block of the subnet. Votes name the subnet
+/// and its chain ID, so those of a validator in several subnets can't be
+/// used as evidence against it in another one.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct Vote {
+    pub subnet: SubnetID,
+    pub chain_id: u64,
+    pub height: ChainEpoch,
+    pub round: u64,
+    pub block_hash: Vec<u8>,
+}
+impl Cbor for Vote {}
+
+/// Vote signed with the key the validator signs checkpoints with, over the
+/// CBOR encoding of the vote.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct SignedVote {
+    pub vote: Vote,
+    pub signature: Signature,
+}
+
+/// Two votes of a validator for different blocks in the same height and
+/// round.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct DoubleSignEvidence {
+    pub first: SignedVote,
+    pub second: SignedVote,
+}
+impl Cbor for DoubleSignEvidence {}
+
+/// Verifies [`DoubleSignEvidence`], for BFT consensuses. Evidence expires
+/// `EVIDENCE_MAX_AGE_WINDOWS` checkpoint windows behind the last committed
+/// checkpoint, the stake at the height of the votes may be gone by then.
+pub struct DoubleSignVerifier;
+
+impl EvidenceVerifier for DoubleSignVerifier {
+    fn verify<BS, RT>(
+        &self,
+        rt: &mut RT,
+        st: &State,
+        validator: &Address,
+        evidence: &[u8],
+    ) -> anyhow::Result<Cid>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        let evidence: DoubleSignEvidence = fvm_ipld_encoding::from_slice(evidence)?;
+        let (first, second) = (&evidence.first.vote, &evidence.second.vote);
+        let subnet = compat::subnet_id(&st.parent_id, rt.message().receiver())?;
+        if [first, second]
+            .iter()
+            .any(|v| v.subnet != subnet || v.chain_id != st.chain_id)
+        {
+            return Err(anyhow!("votes are not for this subnet"));
+        }
+        if first.height != second.height || first.round != second.round {
+            return Err(anyhow!("votes are not for the same height and round"));
+        }
+        if first.block_hash == second.block_hash {
+            return Err(anyhow!("votes are for the same block"));
+        }
+        let oldest = st.last_committed_epoch - EVIDENCE_MAX_AGE_WINDOWS * st.check_period;
+        if first.height < oldest {
+            return Err(SubnetActorError::EvidenceTooOld(first.height).into());
+        }
+        // the key the validator signed with at the height of the votes, it
+        // may have been rotated since
+        for v in [&evidence.first, &evidence.second] {
+            let plaintext = v.vote.marshal_cbor()?;
+            st.verify_validator_signature(rt, validator, &v.signature, &plaintext, v.vote.height)?;
+        }
+        let bytes = fvm_ipld_encoding::to_vec(&(validator, first.height, first.round))?;
+        Ok(Cid::new_v1(DAG_CBOR, Code::Blake2b256.digest(&bytes)))
+    }
+}
+
+impl EvidenceVerifier for ConsensusType {
+    fn verify<BS, RT>(
+        &self,
+        rt: &mut RT,
+        st: &State,
+        validator: &Address,
+        evidence: &[u8],
+    ) -> anyhow::Result<Cid>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        match self {
+            ConsensusType::Tendermint | ConsensusType::Mir => {
+                DoubleSignVerifier.verify(rt, st, validator, evidence)
+            }
+            _ => Err(SubnetActorError::UnsupportedEvidence(*self).into()),
+        }
+    }
+}
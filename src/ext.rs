@@ -2,3 +2,86 @@ pub mod account {
     /// Public key account actor method.
     pub const PUBKEY_ADDRESS_METHOD: u64 = 2;
 }
+
+pub mod init {
+    use cid::Cid;
+    use fvm_ipld_encoding::tuple::{Deserialize_tuple, Serialize_tuple};
+    use fvm_ipld_encoding::{Cbor, RawBytes};
+
+    /// Init actor method deploying an actor with a delegated address in
+    /// the namespace of the caller.
+    pub const EXEC4_METHOD: u64 = 3;
+
+    #[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+    pub struct Exec4Params {
+        pub code_cid: Cid,
+        pub constructor_params: RawBytes,
+        pub subaddress: RawBytes,
+    }
+    impl Cbor for Exec4Params {}
+}
+
+pub mod gateway {
+    use cid::Cid;
+    use fvm_ipld_encoding::tuple::{Deserialize_tuple, Serialize_tuple};
+    use fvm_ipld_encoding::Cbor;
+
+    /// FRC-42 method number of `ValidatorSetUpdated`, implemented by IPC
+    /// gateways enforcing top-down checkpointing policies tied to the
+    /// membership of their subnets.
+    pub const VALIDATOR_SET_UPDATED_METHOD: u64 = 787631777;
+    /// FRC-42 method number of `NetworkName`, returning the ID of the
+    /// network the gateway is deployed on.
+    pub const NETWORK_NAME_METHOD: u64 = 3569346886;
+
+    #[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+    pub struct ValidatorSetUpdatedParams {
+        /// Configuration of the validator set after the change.
+        pub config_number: u64,
+        /// CID of the validators of the power table after the change.
+        pub power_table: Cid,
+    }
+    impl Cbor for ValidatorSetUpdatedParams {}
+}
+
+pub mod frc46 {
+    use fvm_ipld_encoding::tuple::{Deserialize_tuple, Serialize_tuple};
+    use fvm_ipld_encoding::{Cbor, RawBytes};
+    use fvm_shared::address::Address;
+    use fvm_shared::econ::TokenAmount;
+    use fvm_shared::ActorID;
+
+    /// FRC-42 method number of the `Transfer` method of FRC-46 tokens.
+    pub const TRANSFER_METHOD: u64 = 80475954;
+    /// FRC-42 method number of the universal receiver hook.
+    pub const RECEIVE_METHOD: u64 = 3726118371;
+    /// Receiver hook type of FRC-46 token transfers.
+    pub const FRC46_TOKEN_TYPE: u32 = 0x85223bdf;
+
+    #[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+    pub struct UniversalReceiverParams {
+        pub type_: u32,
+        pub payload: RawBytes,
+    }
+    impl Cbor for UniversalReceiverParams {}
+
+    /// Payload of the receiver hook for FRC-46 transfers.
+    #[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+    pub struct FRC46TokenReceived {
+        pub from: ActorID,
+        pub to: ActorID,
+        pub operator: ActorID,
+        pub amount: TokenAmount,
+        pub operator_data: RawBytes,
+        pub token_data: RawBytes,
+    }
+    impl Cbor for FRC46TokenReceived {}
+
+    #[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+    pub struct TransferParams {
+        pub to: Address,
+        pub amount: TokenAmount,
+        pub operator_data: RawBytes,
+    }
+    impl Cbor for TransferParams {}
+}
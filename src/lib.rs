@@ -1,26 +1,65 @@
 #![feature(is_some_and)]
 
+pub mod caller_policy;
+pub mod checkpoint;
+pub mod compat;
+pub mod error;
+pub mod eth;
+pub mod evidence;
 pub mod ext;
+pub mod migrations;
+pub mod params;
+pub mod proofs;
+pub mod quorum;
+pub mod sig;
+pub mod stake_math;
 pub mod state;
+#[cfg(feature = "testing")]
+pub mod testing;
 pub mod types;
+pub mod validator_set;
+pub mod voting;
 
-use fil_actors_runtime::runtime::{ActorCode, Runtime};
-use fil_actors_runtime::{actor_error, cbor, ActorDowncast, ActorError, INIT_ACTOR_ADDR};
+use cid::Cid;
+use fil_actors_runtime::runtime::builtins::Type;
+#[cfg(feature = "fil-actor")]
+use fil_actors_runtime::runtime::ActorCode;
+use fil_actors_runtime::runtime::Runtime;
+use fil_actors_runtime::{actor_error, cbor, ActorError, BURNT_FUNDS_ACTOR_ADDR, INIT_ACTOR_ADDR};
 use fvm_ipld_blockstore::Blockstore;
 use fvm_ipld_encoding::RawBytes;
 
+use fvm_shared::address::{Address, Protocol};
+use fvm_shared::clock::ChainEpoch;
 use fvm_shared::econ::TokenAmount;
 use fvm_shared::error::ExitCode;
-use fvm_shared::{MethodNum, METHOD_CONSTRUCTOR};
-use ipc_gateway::{Checkpoint, FundParams, MIN_COLLATERAL_AMOUNT};
+use fvm_shared::{MethodNum, METHOD_CONSTRUCTOR, METHOD_SEND};
 use num_derive::FromPrimitive;
 use num_traits::{FromPrimitive, Zero};
+use primitives::TCid;
 
+use crate::checkpoint::CheckpointWindows;
+use crate::compat::{Checkpoint, CrossMsgMeta, GatewayMethod, SubnetID, MIN_COLLATERAL_AMOUNT};
+use crate::error::{SubnetActorDowncast, SubnetActorError};
+use crate::eth;
+use crate::evidence::EvidenceVerifier;
+use crate::ext::{frc46, gateway};
+use crate::proofs;
+use crate::sig::CheckpointSigVerifier;
 pub use crate::state::State;
 pub use crate::types::*;
 
+// The entry point of the actor is only needed when deploying it, crates
+// using the state and types of the actor can disable `fil-actor`.
+#[cfg(feature = "fil-actor")]
 fil_actors_runtime::wasm_trampoline!(Actor);
 
+/// Compiled WASM of the actor, used to deploy it in integration tests.
+#[cfg(all(feature = "fil-actor", not(target_arch = "wasm32")))]
+pub mod wasm {
+    include!(concat!(env!("OUT_DIR"), "/wasm_binary.rs"));
+}
+
 /// Atomic execution coordinator actor methods available
 #[derive(FromPrimitive)]
 #[repr(u64)]
@@ -30,6 +69,144 @@ pub enum Method {
     Leave = 3,
     Kill = 4,
     SubmitCheckpoint = 5,
+    IssueInvitations = 6,
+    RegisterCrossMsg = 7,
+    SubmitAggregatedCheckpoint = 8,
+    MigrateState = 9,
+    AddStake = 10,
+    Receive = frc46::RECEIVE_METHOD,
+    SubmitCatchUpCheckpoint = 11,
+    NextCheckpointEpoch = 12,
+    Unjail = 13,
+    SetMetadata = 14,
+    GetStakeByRobustAddress = 15,
+    BootstrapValidators = 16,
+    ListStakers = 17,
+    ListCheckpoints = 18,
+    DistributeFees = 19,
+    GetLastStateCommitment = 20,
+    RelayCheckpoints = 21,
+    ChallengeCheckpoint = 22,
+    RotateValidatorKey = 23,
+    GetPowerTable = 24,
+    // 25 was `SetMinJoinAmount`, replaced by `ProposeEconParams`
+    CompactStake = 26,
+    SubmitParentFinality = 27,
+    GetParentFinality = 28,
+    GetCheckpointVotingStatus = 29,
+    Pause = 30,
+    Unpause = 31,
+    Delegate = 32,
+    Undelegate = 33,
+    SetCommissionRate = 34,
+    ReleaseStakeFor = 35,
+    TransferOwnership = 36,
+    RenounceOwnership = 37,
+    FundRewardPool = 38,
+    GetGenesisEpoch = 39,
+    GetStats = 40,
+    SetCheckPeriod = 41,
+    GetMembershipTicket = 42,
+    GetCheckpointProof = 43,
+    Heartbeat = 44,
+    GetLiveValidators = 45,
+    GetFinalityConfig = 46,
+    WithdrawStake = 47,
+    GetValidatorSetAt = 48,
+    SetValidatorSetNotifications = 49,
+    GetCheckpointTemplate = 50,
+    RetryGatewayCalls = 51,
+    GetExitQueuePosition = 52,
+    ProposeSpend = 53,
+    ApproveSpend = 54,
+    ListSpendProposals = 55,
+    GetMembershipHistory = 56,
+    GetCheckpointWindows = 57,
+    Redelegate = 58,
+    CanJoin = 59,
+    CanLeave = 60,
+    CanSubmitCheckpoint = 61,
+    PutCrossMsgBatch = 62,
+    // 63 was `SetLeavingCoeff`, replaced by `ProposeEconParams`
+    SetGatewayCodes = 64,
+    UpdateGatewayAddr = 65,
+    GetEconParams = 66,
+    ProposeEconParams = 67,
+    ListCheckpointsInRange = 68,
+    GetDelegatedAddress = 69,
+    SubmitEvidence = 70,
+    GetSubnetSummary = 71,
+    SetUpgradeCodes = 72,
+    Upgrade = 73,
+}
+
+impl Method {
+    /// Whether the method is frozen while the actor is paused. Reads, the
+    /// methods the owner needs to handle the pause, and the releases of
+    /// stake by the gateway remain available.
+    pub fn is_pausable(&self) -> bool {
+        matches!(
+            self,
+            Method::Join
+                | Method::Leave
+                | Method::Kill
+                | Method::SubmitCheckpoint
+                | Method::IssueInvitations
+                | Method::RegisterCrossMsg
+                | Method::PutCrossMsgBatch
+                | Method::SubmitAggregatedCheckpoint
+                | Method::AddStake
+                | Method::Receive
+                | Method::SubmitCatchUpCheckpoint
+                | Method::Unjail
+                | Method::BootstrapValidators
+                | Method::DistributeFees
+                | Method::RelayCheckpoints
+                | Method::ChallengeCheckpoint
+                | Method::RotateValidatorKey
+                | Method::CompactStake
+                | Method::SubmitParentFinality
+                | Method::Delegate
+                | Method::Undelegate
+                | Method::SetCommissionRate
+                | Method::FundRewardPool
+                | Method::Heartbeat
+                | Method::WithdrawStake
+                | Method::RetryGatewayCalls
+                | Method::ProposeSpend
+                | Method::ApproveSpend
+                | Method::Redelegate
+                | Method::ProposeEconParams
+                | Method::SubmitEvidence
+                | Method::MigrateState
+        )
+    }
+
+    /// Whether the method can change the power table, by changing the
+    /// members of the validator set, their stake or whether they are
+    /// jailed.
+    pub fn changes_validator_set(&self) -> bool {
+        matches!(
+            self,
+            Method::Join
+                | Method::Leave
+                | Method::SubmitCheckpoint
+                | Method::SubmitAggregatedCheckpoint
+                | Method::AddStake
+                | Method::Receive
+                | Method::SubmitCatchUpCheckpoint
+                | Method::Unjail
+                | Method::BootstrapValidators
+                | Method::ChallengeCheckpoint
+                | Method::Delegate
+                | Method::Undelegate
+                | Method::ReleaseStakeFor
+                | Method::WithdrawStake
+                | Method::Redelegate
+                | Method::ProposeEconParams
+                | Method::SubmitEvidence
+        )
+    }
 }
 
 /// SubnetActor trait. Custom subnet actors need to implement this trait
@@ -43,12 +220,12 @@ pub trait SubnetActor {
         BS: Blockstore,
         RT: Runtime<BS>;
     /// Logic for new peers to join a subnet.
-    fn join<BS, RT>(rt: &mut RT, params: JoinParams) -> Result<Option<RawBytes>, ActorError>
+    fn join<BS, RT>(rt: &mut RT, params: JoinParams) -> Result<JoinReturn, ActorError>
     where
         BS: Blockstore,
         RT: Runtime<BS>;
     /// Called by peers to leave a subnet.
-    fn leave<BS, RT>(rt: &mut RT) -> Result<Option<RawBytes>, ActorError>
+    fn leave<BS, RT>(rt: &mut RT) -> Result<LeaveReturn, ActorError>
     where
         BS: Blockstore,
         RT: Runtime<BS>;
@@ -57,14 +234,32 @@ pub trait SubnetActor {
     where
         BS: Blockstore,
         RT: Runtime<BS>;
-    /// Submits a new checkpoint for the subnet.
+    /// Submits a new checkpoint for the subnet, along with the state of the
+    /// subnet it claims.
     fn submit_checkpoint<BS, RT>(
         rt: &mut RT,
-        ch: Checkpoint,
+        submission: CheckpointSubmission,
     ) -> Result<Option<RawBytes>, ActorError>
     where
         BS: Blockstore,
         RT: Runtime<BS>;
+    /// Validates the batch of bottom-up messages of a checkpoint before it
+    /// is voted and propagated to the IPC gateway.
+    ///
+    /// Custom subnet actors can override it to apply their own policies on
+    /// the messages leaving the subnet, e.g. limiting the value they carry.
+    /// By default every batch is allowed.
+    fn validate_cross_msg<BS, RT>(
+        _rt: &mut RT,
+        _ch: &Checkpoint,
+        _meta: &CrossMsgMeta,
+    ) -> Result<(), ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        Ok(())
+    }
 }
 
 /// SubnetActor trait. Custom subnet actors need to implement this trait
@@ -85,9 +280,40 @@ impl SubnetActor for Actor {
     {
         rt.validate_immediate_caller_is(std::iter::once(&*INIT_ACTOR_ADDR))?;
 
-        let st = State::new(rt.store(), params).map_err(|e| {
-            e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "Failed to create actor state")
+        params
+            .validate()
+            .map_err(|e| actor_error!(illegal_argument, "{}", e))?;
+        // the gateway is an actor, and it needs to exist to be resolved
+        let gateway_id = resolve_id_address(rt, &params.ipc_gateway_addr)?;
+        let parent_checked = check_gateway_network(rt, gateway_id, &params.parent)?;
+
+        let params_owner = params.owner;
+        let mut st = State::new(rt.store(), params).map_err(|e| {
+            e.downcast_subnet(ExitCode::USR_ILLEGAL_STATE, "Failed to create actor state")
         })?;
+        st.ipc_gateway_addr = gateway_id;
+        st.parent_checked_gateway = parent_checked.then_some(gateway_id);
+        st.owner = match params_owner {
+            Some(owner) => Some(resolve_id_address(rt, &owner)?),
+            None => None,
+        };
+
+        let bond = rt.message().value_received();
+        if st.collateral_token.is_some() {
+            if bond < TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT) {
+                return Err(actor_error!(
+                    illegal_argument,
+                    "subnets with a collateral token need a registration bond of {}",
+                    TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT)
+                ));
+            }
+            st.registration_bond = bond;
+        } else if !bond.is_zero() {
+            return Err(actor_error!(
+                illegal_argument,
+                "subnets with native collateral don't take a registration bond"
+            ));
+        }
 
         rt.create(&st)?;
 
@@ -97,16 +323,15 @@ impl SubnetActor for Actor {
     /// Called by peers looking to join a subnet.
     ///
     /// It implements the basic logic to onboard new peers to the subnet.
-    fn join<BS, RT>(rt: &mut RT, params: JoinParams) -> Result<Option<RawBytes>, ActorError>
+    fn join<BS, RT>(rt: &mut RT, params: JoinParams) -> Result<JoinReturn, ActorError>
     where
         BS: Blockstore,
         RT: Runtime<BS>,
     {
-        rt.validate_immediate_caller_accept_any()?;
+        let st: State = rt.state()?;
+        st.caller_policies.join.validate(rt)?;
 
         let caller = rt.message().caller();
-        // TODO: shall we check caller interface instead here?
-
         let amount = rt.message().value_received();
         if amount == TokenAmount::zero() {
             return Err(actor_error!(
@@ -115,99 +340,88 @@ impl SubnetActor for Actor {
             ));
         }
 
-        let mut msg = None;
-        rt.transaction(|st: &mut State, rt| {
-            // increase collateral
-            st.add_stake(rt.store(), &caller, &params.validator_net_addr, &amount)
-                .map_err(|e| {
-                    e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load subnet")
-                })?;
-
-            let total_stake = st.total_stake.clone();
-
-            if st.status == Status::Instantiated {
-                if total_stake >= TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT) {
-                    msg = Some(CrossActorPayload::new(
-                        st.ipc_gateway_addr,
-                        ipc_gateway::Method::Register as u64,
-                        RawBytes::default(),
-                        total_stake,
-                    ));
-                }
-            } else {
-                msg = Some(CrossActorPayload::new(
-                    st.ipc_gateway_addr,
-                    ipc_gateway::Method::AddStake as u64,
-                    RawBytes::default(),
-                    amount,
-                ));
-            }
-
-            st.mutate_state();
-
-            Ok(true)
-        })?;
-
-        if let Some(p) = msg {
-            rt.send(p.to, p.method, p.params, p.value)?;
-        }
-
-        Ok(None)
+        Self::stake_collateral(rt, caller, amount, params, false)
     }
 
     /// Called by peers looking to leave a subnet.
-    fn leave<BS, RT>(rt: &mut RT) -> Result<Option<RawBytes>, ActorError>
+    fn leave<BS, RT>(rt: &mut RT) -> Result<LeaveReturn, ActorError>
     where
         BS: Blockstore,
         RT: Runtime<BS>,
     {
-        rt.validate_immediate_caller_accept_any()?;
+        let st: State = rt.state()?;
+        st.caller_policies.leave.validate(rt)?;
 
         let caller = rt.message().caller();
 
-        // TODO: shall we check caller interface instead here?
-        // let code_cid = get_actor_code_cid(&caller).unwrap_or(Cid::default());
-        // if sdk::actor::get_builtin_actor_type(&code_cid) != Some(Type::Account) {
-        //     abort!(USR_FORBIDDEN, "caller not account actor type");
-        // }
+        let mut msgs = Vec::new();
+        let ret = rt.transaction(|st: &mut State, rt| {
+            // validators with vesting stake can't leave, and the stake
+            // delegated to the caller is withdrawn by its delegators
+            let (stake, withdrawn) = leaving_stake(st, rt.store(), &caller, rt.curr_epoch())?;
 
-        let mut msg = None;
-        rt.transaction(|st: &mut State, rt| {
-            let stake = st.get_stake(rt.store(), &caller).map_err(|e| {
-                e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load stake")
-            })?;
-
-            if stake.is_none() || stake.as_ref().is_some_and(|a| *a == TokenAmount::zero()) {
-                return Err(actor_error!(illegal_state, "caller has no stake in subnet"));
-            }
-
-            let stake = stake.unwrap();
-            if st.status != Status::Terminating {
-                msg = Some(CrossActorPayload::new(
-                    st.ipc_gateway_addr,
-                    ipc_gateway::Method::ReleaseStake as u64,
-                    RawBytes::serialize(FundParams {
-                        value: stake.clone(),
-                    })?,
-                    TokenAmount::zero(),
-                ));
+            // validators leaving beyond the exit or churn limits stay in the
+            // validator set until the next windows, and withdraw once out
+            if let Some(v) = st
+                .validators
+                .get(rt.store(), &caller)
+                .map_err(|_| actor_error!(illegal_state, "cannot load validator set"))?
+            {
+                if st.exit_queue_position(&caller).is_some() {
+                    return Err(SubnetActorError::ExitPending(caller).into());
+                }
+                // exits are processed in order, so nobody skips the queue
+                if !st.exit_queue.is_empty() || !st.consume_exit() {
+                    st.exit_queue.push(ExitRequest {
+                        validator: caller,
+                        requested_at: rt.curr_epoch(),
+                    });
+                    return Ok(LeaveReturn {
+                        refunded: TokenAmount::zero(),
+                        remaining_stake: stake,
+                        refunds: Vec::new(),
+                    });
+                }
+                if !st.consume_churn() {
+                    st.queue_validator_change(v, false);
+                    return Ok(LeaveReturn {
+                        refunded: TokenAmount::zero(),
+                        remaining_stake: stake,
+                        refunds: Vec::new(),
+                    });
+                }
             }
 
-            // remove stake from balance table
-            st.rm_stake(&rt.store(), &caller, &stake).map_err(|e| {
-                e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "cannot remove stake")
-            })?;
+            // remove stake from balance table, refunding its funders
+            let refunds = st
+                .rm_stake(&rt.store(), &caller, &withdrawn, rt.curr_epoch())
+                .map_err(|e| {
+                    e.downcast_subnet(ExitCode::USR_ILLEGAL_STATE, "cannot remove stake")
+                })?;
+            let (stake, release) = release_stake_msgs(st, &refunds)?;
+            msgs = release;
+            let remaining_stake = st
+                .get_stake(rt.store(), &caller)
+                .map_err(|e| {
+                    e.downcast_subnet(ExitCode::USR_ILLEGAL_STATE, "failed to load stake")
+                })?
+                .unwrap_or_else(TokenAmount::zero);
 
-            st.mutate_state();
+            st.stats.leaves += 1;
+            st.update_status(rt.curr_epoch(), rt.message().caller());
 
-            Ok(true)
+            Ok(LeaveReturn {
+                refunded: stake,
+                remaining_stake,
+                refunds,
+            })
         })?;
 
-        if let Some(p) = msg {
-            rt.send(p.to, p.method, p.params, p.value)?;
+        for p in msgs {
+            send_stake_msg(rt, p)?;
         }
 
-        Ok(None)
+        Ok(ret)
     }
 
     fn kill<BS, RT>(rt: &mut RT) -> Result<Option<RawBytes>, ActorError>
@@ -217,8 +431,16 @@ impl SubnetActor for Actor {
     {
         rt.validate_immediate_caller_accept_any()?;
 
-        // prevent a subnet from being killed until all its locked balance has been withdrawn
-        if rt.current_balance() != TokenAmount::zero() {
+        // prevent a subnet from being killed until all its locked balance has
+        // been withdrawn, except for a registration bond not yet sent to the
+        // gateway, and the reward pool and join fees, burnt on kill
+        let st: State = rt.state()?;
+        let unlocked_bond = if st.registered_with_gateway {
+            TokenAmount::zero()
+        } else {
+            st.registration_bond.clone()
+        };
+        if rt.current_balance() != unlocked_bond + &st.reward_pool + &st.treasury_held {
             return Err(actor_error!(
                 illegal_state,
                 format!("the subnet has non-zero balance: {:}", rt.current_balance())
@@ -226,7 +448,9 @@ impl SubnetActor for Actor {
         }
 
         let mut msg = None;
-        rt.transaction(|st: &mut State, _| {
+        let mut bond = None;
+        let mut burn = TokenAmount::zero();
+        rt.transaction(|st: &mut State, rt| {
             if st.status == Status::Terminating || st.status == Status::Killed {
                 return Err(actor_error!(
                     illegal_state,
@@ -234,31 +458,64 @@ impl SubnetActor for Actor {
                 ));
             }
 
-            if !st.validator_set.is_empty() || st.total_stake != TokenAmount::zero() {
+            if !st.validators.is_empty() || st.total_stake != TokenAmount::zero() {
                 return Err(actor_error!(
                     illegal_state,
                     "this subnet can only be killed when all validators have left"
                 ));
             }
+            if !st.pending_gateway_calls.is_empty() {
+                return Err(actor_error!(
+                    illegal_state,
+                    "the calls to the gateway queued for retry need to be sent first"
+                ));
+            }
 
             // move to terminating state
             st.status = Status::Terminating;
+            st.status_changed_at = rt.curr_epoch();
 
-            st.mutate_state();
+            st.update_status(rt.curr_epoch(), rt.message().caller());
 
             msg = Some(CrossActorPayload::new(
                 st.ipc_gateway_addr,
-                ipc_gateway::Method::Kill as u64,
+                GatewayMethod::Kill.method_num(),
                 RawBytes::default(),
                 TokenAmount::zero(),
             ));
+            if !st.registration_bond.is_zero() {
+                let owner = st
+                    .owner
+                    .ok_or_else(|| actor_error!(illegal_state, "no owner to refund the bond"))?;
+                bond = Some((owner, std::mem::take(&mut st.registration_bond)));
+            }
+            // no relayer is paid from the pool, nor spend from the treasury
+            // approved, once the validators left
+            let held = std::mem::take(&mut st.treasury_held);
+            st.treasury = stake_math::checked_sub(&st.treasury, &held)?;
+            burn = std::mem::take(&mut st.reward_pool) + held;
 
             Ok(true)
         })?;
 
         // unregister subnet
         if let Some(p) = msg {
-            rt.send(p.to, p.method, p.params, p.value)?;
+            send_gateway_msg(rt, p, OnGatewayFailure::Abort)?;
+        }
+        if !burn.is_zero() {
+            rt.send(
+                *BURNT_FUNDS_ACTOR_ADDR,
+                METHOD_SEND,
+                RawBytes::default(),
+                burn,
+            )?;
+        }
+        // the gateway returns the bond of registered subnets when killed
+        if let Some((owner, amount)) = bond {
+            let amount = std::cmp::min(amount, rt.current_balance());
+            if !amount.is_zero() {
+                rt.send(owner, METHOD_SEND, RawBytes::default(), amount)?;
+            }
         }
 
         Ok(None)
@@ -271,7 +528,52 @@ impl SubnetActor for Actor {
     /// votes from 2/3 of miners with collateral.
     fn submit_checkpoint<BS, RT>(
         rt: &mut RT,
-        ch: Checkpoint,
+        submission: CheckpointSubmission,
+    ) -> Result<Option<RawBytes>, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        Self::vote_checkpoint::<Self, BS, RT>(rt, submission, false)
+    }
+}
+
+impl Actor {
+    /// Issues single-use invitations for invitation-only subnets.
+    ///
+    /// Only the owner of the subnet is entitled to issue invitations.
+    /// Invitations are commitments of a secret and the invitee, see
+    /// [`invitation_commitment`], and the secret has to be presented by
+    /// the invitee when joining the subnet.
+    pub fn issue_invitations<BS, RT>(
+        rt: &mut RT,
+        params: IssueInvitationsParams,
+    ) -> Result<Option<RawBytes>, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        validate_owner(rt)?;
+
+        rt.transaction(|st: &mut State, rt| {
+            st.add_invitations(rt.store(), &params.commitments)
+                .map_err(|e| {
+                    e.downcast_subnet(ExitCode::USR_ILLEGAL_ARGUMENT, "cannot issue invitations")
+                })?;
+            Ok(true)
+        })?;
+
+        Ok(None)
+    }
+
+    /// Registers the CID of the batch of bottom-up messages to be included
+    /// in the checkpoint of a window.
+    ///
+    /// Validators call this method during the window so that `submit_checkpoint`
+    /// only accepts checkpoints referencing a registered batch.
+    pub fn register_cross_msg<BS, RT>(
+        rt: &mut RT,
+        params: RegisterCrossMsgParams,
     ) -> Result<Option<RawBytes>, ActorError>
     where
         BS: Blockstore,
@@ -279,110 +581,3501 @@ impl SubnetActor for Actor {
     {
         rt.validate_immediate_caller_accept_any()?;
 
-        let state: State = rt.state()?;
         let caller = rt.message().caller();
 
-        if !state.is_validator(&caller) {
-            return Err(actor_error!(illegal_state, "not validator"));
-        }
+        rt.transaction(|st: &mut State, rt| {
+            if !st
+                .is_active_validator(rt.store(), &caller)
+                .map_err(|_| actor_error!(illegal_state, "cannot load participation"))?
+            {
+                return Err(SubnetActorError::NotValidator(caller).into());
+            }
+            st.register_cross_msgs(
+                rt.store(),
+                &params.epoch,
+                &params.msgs_cid,
+                &caller,
+                rt.curr_epoch(),
+            )
+            .map_err(|e| {
+                e.downcast_subnet(ExitCode::USR_ILLEGAL_ARGUMENT, "cannot register cross-msgs")
+            })?;
+            Ok(true)
+        })?;
+
+        Ok(None)
+    }
+
+    /// Puts a batch of bottom-up messages in the store of the actor and
+    /// registers it for the window, for batches too large to fit in a
+    /// checkpoint under `MAX_CHECKPOINT_SIZE`. The checkpoint then only
+    /// carries the returned CID in its `CrossMsgMeta`.
+    pub fn put_cross_msg_batch<BS, RT>(
+        rt: &mut RT,
+        params: PutCrossMsgBatchParams,
+    ) -> Result<PutCrossMsgBatchReturn, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let caller = rt.message().caller();
+
+        let msgs_cid = rt.transaction(|st: &mut State, rt| {
+            if !st
+                .is_active_validator(rt.store(), &caller)
+                .map_err(|_| actor_error!(illegal_state, "cannot load participation"))?
+            {
+                return Err(SubnetActorError::NotValidator(caller).into());
+            }
+            st.put_cross_msg_batch(
+                rt.store(),
+                &params.epoch,
+                params.batch.bytes(),
+                &caller,
+                rt.curr_epoch(),
+            )
+            .map_err(|e| {
+                e.downcast_subnet(
+                    ExitCode::USR_ILLEGAL_ARGUMENT,
+                    "cannot put cross-msgs batch",
+                )
+            })
+        })?;
+
+        Ok(PutCrossMsgBatchReturn {
+            msgs_cid,
+            size: params.batch.bytes().len() as u64,
+        })
+    }
+
+    /// Submits a checkpoint together with the signatures of the validators
+    /// collected off-chain.
+    ///
+    /// All signatures are verified in a single call, and the checkpoint is
+    /// committed straight away if the signers (along with the votes already
+    /// submitted for the checkpoint) hold a majority of the stake. This saves
+    /// each validator from sending its own `SubmitCheckpoint` message.
+    pub fn submit_aggregated_checkpoint<BS, RT>(
+        rt: &mut RT,
+        params: AggregatedCheckpointParams,
+    ) -> Result<Option<RawBytes>, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let state: State = rt.state()?;
+        let submission = params.submission;
+        let ch = &submission.checkpoint;
 
         state
-            .verify_checkpoint(rt, &ch)
-            .map_err(|_| actor_error!(illegal_state, "checkpoint failed"))?;
+            .check_checkpoint(rt, &submission, false)
+            .map_err(|e| e.downcast_subnet(ExitCode::USR_ILLEGAL_STATE, "checkpoint failed"))?;
+        if let Some(meta) = &ch.data.cross_msgs {
+            Self::validate_cross_msg(rt, ch, meta)?;
+        }
 
-        let mut msg = None;
+        let mut signers = Vec::new();
+        for s in &params.signatures {
+            // votes are keyed by ID address
+            let validator = resolve_id_address(rt, &s.validator)?;
+            if !state
+                .is_active_validator(rt.store(), &validator)
+                .map_err(|_| actor_error!(illegal_state, "cannot load participation"))?
+            {
+                return Err(SubnetActorError::NotValidator(s.validator).into());
+            }
+            if signers.contains(&validator) {
+                return Err(actor_error!(
+                    illegal_argument,
+                    format!("duplicated signature from {}", s.validator)
+                ));
+            }
+            state
+                .verify_checkpoint_signature(rt, &validator, &s.signature, &submission)
+                .map_err(|_| actor_error!(illegal_argument, "invalid checkpoint signature"))?;
+            signers.push(validator);
+        }
+        let ch_cid = submission
+            .digest()
+            .map_err(|_| actor_error!(illegal_argument, "cannot digest checkpoint"))?;
 
-        rt.transaction(|st: &mut State, rt| {
-            let ch_cid = ch.cid();
+        let (msg, fee) = rt.transaction(|st: &mut State, rt| {
+            st.expire_votes(rt.store(), rt.curr_epoch())
+                .map_err(|_| actor_error!(illegal_state, "cannot expire votes"))?;
 
-            let mut found = false;
-            let mut votes = match st.get_votes(rt.store(), &ch_cid)? {
-                Some(v) => {
-                    found = true;
-                    v
+            let stored = st.get_votes(rt.store(), &ch_cid)?;
+            let found = stored.is_some();
+            let mut votes = stored.unwrap_or(Votes {
+                validators: Vec::new(),
+            });
+            for v in signers {
+                if !votes.validators.contains(&v) {
+                    if st
+                        .conflicting_vote(rt.store(), ch.epoch(), &ch_cid, &v)
+                        .map_err(|_| actor_error!(illegal_state, "cannot load candidates"))?
+                        .is_some()
+                    {
+                        return Err(SubnetActorError::ConflictingVote(v).into());
+                    }
+                    votes.validators.push(v);
                 }
-                None => Votes {
-                    validators: Vec::new(),
-                },
-            };
+            }
 
-            if votes.validators.iter().any(|x| x == &caller) {
+            st.snapshot_weights(rt.store(), ch.epoch())
+                .map_err(|_| actor_error!(illegal_state, "cannot snapshot weights"))?;
+            if !st.has_majority_vote(rt.store(), ch.epoch(), &votes)? {
                 return Err(actor_error!(
-                    illegal_state,
-                    "miner has already voted the checkpoint"
+                    illegal_argument,
+                    "not enough voting power to commit the checkpoint"
                 ));
             }
 
-            // add miner vote
-            votes.validators.push(caller);
+            let msg = commit_checkpoint(
+                st,
+                rt.store(),
+                &submission,
+                &votes.validators,
+                rt.curr_epoch(),
+            )?;
+            if found {
+                st.remove_votes(rt.store(), &ch_cid)?;
+            }
 
-            // if has majority
-            if st.has_majority_vote(rt.store(), &votes)? {
-                // commit checkpoint
-                st.flush_checkpoint(rt.store(), &ch)
-                    .map_err(|_| actor_error!(illegal_state, "cannot flush checkpoint"))?;
-
-                // prepare the message
-                msg = Some(CrossActorPayload::new(
-                    st.ipc_gateway_addr,
-                    ipc_gateway::Method::CommitChildCheckpoint as u64,
-                    RawBytes::serialize(ch)?,
-                    TokenAmount::zero(),
-                ));
+            Ok((msg, st.take_relayer_fee()))
+        })?;
 
-                // remove votes used for commitment
-                if found {
-                    st.remove_votes(rt.store(), &ch_cid)?;
-                }
-            } else {
-                // if no majority store vote and return
-                st.set_votes(rt.store(), &ch_cid, votes)?;
+        // propagate to sca
+        if let Some(p) = msg {
+            send_gateway_msg(rt, p, OnGatewayFailure::Abort)?;
+        }
+        let relayer = rt.message().caller();
+        pay_relayer(rt, relayer, fee)?;
+
+        Ok(None)
+    }
+
+    /// Migrates the state of an already deployed actor to the schema
+    /// of the current code.
+    ///
+    /// Migrations are deterministic and idempotent, so anyone can trigger
+    /// them after the code of the actor is upgraded.
+    pub fn migrate_state<BS, RT>(rt: &mut RT) -> Result<Option<RawBytes>, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+
+        rt.transaction(|st: &mut State, rt| {
+            let from_version = st.version;
+            migrations::migrate_state(rt.store(), st, from_version).map_err(|e| {
+                e.downcast_subnet(ExitCode::USR_ILLEGAL_STATE, "failed to migrate state")
+            })?;
+            Ok(true)
+        })?;
+
+        Ok(None)
+    }
+
+    /// Tops up the stake of an address that already staked in the subnet.
+    ///
+    /// Unlike `join`, it doesn't go through validator qualification, the
+    /// validator set is left untouched. Stakers below the minimum validator
+    /// stake need to `join` to become validators.
+    pub fn add_stake<BS, RT>(rt: &mut RT) -> Result<Option<RawBytes>, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        let st: State = rt.state()?;
+        st.caller_policies.join.validate(rt)?;
+
+        let caller = rt.message().caller();
+        let amount = rt.message().value_received();
+        if amount == TokenAmount::zero() {
+            return Err(actor_error!(illegal_argument, "no stake to add"));
+        }
+
+        let mut msg = None;
+        let mut refund = None;
+        rt.transaction(|st: &mut State, rt| {
+            check_accepts_stake(st)?;
+            if st.collateral_token.is_some() {
+                return Err(actor_error!(
+                    illegal_argument,
+                    "the collateral of the subnet is denominated in tokens"
+                ));
+            }
+            let staked = st.capped_stake(rt.store(), &caller, &amount).map_err(|e| {
+                e.downcast_subnet(ExitCode::USR_ILLEGAL_ARGUMENT, "cannot top-up stake")
+            })?;
+            if staked < amount {
+                refund = Some(refund_msg(st, caller, &amount - &staked)?);
             }
 
+            st.top_up_stake(rt.store(), &caller, &caller, &staked)
+                .map_err(|e| {
+                    e.downcast_subnet(ExitCode::USR_ILLEGAL_ARGUMENT, "cannot top-up stake")
+                })?;
+
+            msg = stake_gateway_msg(st, &staked);
+
+            st.update_status(rt.curr_epoch(), rt.message().caller());
+
             Ok(true)
         })?;
 
-        // propagate to sca
         if let Some(p) = msg {
+            send_stake_msg(rt, p)?;
+        }
+        if let Some(p) = refund {
             rt.send(p.to, p.method, p.params, p.value)?;
         }
 
         Ok(None)
     }
-}
 
-impl ActorCode for Actor {
-    fn invoke_method<BS, RT>(
+    /// Adds `amount` of collateral from `caller` to the subnet, onboarding it
+    /// as a validator if it qualifies.
+    ///
+    /// `via_token` signals that the collateral was received through a transfer
+    /// of the collateral token of the subnet instead of native funds.
+    fn stake_collateral<BS, RT>(
         rt: &mut RT,
-        method: MethodNum,
-        params: &RawBytes,
-    ) -> Result<RawBytes, ActorError>
+        caller: Address,
+        amount: TokenAmount,
+        params: JoinParams,
+        via_token: bool,
+    ) -> Result<JoinReturn, ActorError>
     where
         BS: Blockstore,
         RT: Runtime<BS>,
     {
-        match FromPrimitive::from_u64(method) {
-            Some(Method::Constructor) => {
-                Self::constructor(rt, cbor::deserialize_params(params)?)?;
-                Ok(RawBytes::default())
+        check_net_addr(&params.validator_net_addr)?;
+
+        // stake is keyed by ID address
+        let caller = resolve_id_address(rt, &caller)?;
+
+        // multisigs can't sign, so they sign checkpoints with a worker key
+        let worker = match &params.worker {
+            Some(key) => {
+                check_validator_key(key)?;
+                Some((*key, resolve_id_address(rt, key)?))
             }
-            Some(Method::Join) => {
-                let res = Self::join(rt, cbor::deserialize_params(params)?)?;
-                Ok(RawBytes::serialize(res)?)
+            None => None,
+        };
+        let is_multisig = caller
+            .id()
+            .ok()
+            .and_then(|id| rt.get_actor_code_cid(&id))
+            .and_then(|code| rt.resolve_builtin_actor_type(&code))
+            == Some(Type::Multisig);
+
+        let commitment = params
+            .invitation
+            .as_ref()
+            .map(|secret| invitation_commitment(rt, secret, &caller));
+
+        let mut msg = None;
+        let mut refund = None;
+        let mut burn = None;
+        let ret = rt.transaction(|st: &mut State, rt| {
+            let fee = check_join(st, rt, &caller, &amount, via_token)?;
+            let amount = &amount - &fee;
+
+            let was_validator = st
+                .is_validator(rt.store(), &caller)
+                .map_err(|_| actor_error!(illegal_state, "cannot load validator set"))?;
+
+            // new validators of invitation-only subnets need to redeem an invitation
+            if let Some(commitment) = required_invitation(st, rt, &caller, commitment.as_ref())? {
+                st.redeem_invitation(rt.store(), commitment, &caller)
+                    .map_err(|e| {
+                        e.downcast_subnet(ExitCode::USR_FORBIDDEN, "cannot redeem invitation")
+                    })?;
             }
-            Some(Method::Leave) => {
-                let res = Self::leave(rt)?;
-                Ok(RawBytes::serialize(res)?)
+
+            let staked = st.capped_stake(rt.store(), &caller, &amount).map_err(|e| {
+                e.downcast_subnet(ExitCode::USR_ILLEGAL_ARGUMENT, "cannot stake collateral")
+            })?;
+            let excess = &amount - &staked;
+            if excess > TokenAmount::zero() {
+                refund = Some(refund_msg(st, caller, excess.clone())?);
             }
-            Some(Method::Kill) => {
-                let res = Self::kill(rt)?;
-                Ok(RawBytes::serialize(res)?)
+
+            // increase collateral
+            st.add_stake(
+                rt.store(),
+                &caller,
+                &caller,
+                params.validator_net_addr.as_str(),
+                &staked,
+            )
+            .map_err(|e| e.downcast_subnet(ExitCode::USR_ILLEGAL_STATE, "failed to load subnet"))?;
+            match &worker {
+                Some((key, id)) => {
+                    st.register_worker(rt.store(), &caller, *key, id)
+                        .map_err(|e| {
+                            e.downcast_subnet(
+                                ExitCode::USR_ILLEGAL_ARGUMENT,
+                                "cannot register worker key",
+                            )
+                        })?;
+                }
+                None if is_multisig => {
+                    let key = st
+                        .get_validator_key(rt.store(), &caller)
+                        .map_err(|_| actor_error!(illegal_state, "cannot load validator keys"))?;
+                    if key.is_none() {
+                        return Err(SubnetActorError::WorkerKeyRequired(caller).into());
+                    }
+                }
+                None => {}
             }
-            Some(Method::SubmitCheckpoint) => {
-                let res = Self::submit_checkpoint(rt, cbor::deserialize_params(params)?)?;
-                Ok(RawBytes::serialize(res)?)
+            if let Some(schedule) = &params.vesting {
+                if !schedule.is_valid() {
+                    return Err(actor_error!(illegal_argument, "invalid vesting schedule"));
+                }
+                st.add_vesting(
+                    rt.store(),
+                    &caller,
+                    &staked,
+                    rt.curr_epoch(),
+                    schedule.clone(),
+                )
+                .map_err(|e| e.downcast_subnet(ExitCode::USR_ILLEGAL_STATE, "cannot vest stake"))?;
+            }
+
+            msg = stake_gateway_msg(st, &staked);
+
+            if !fee.is_zero() {
+                match st.join_fee_policy {
+                    JoinFeePolicy::Treasury => st.collect_join_fee(&fee),
+                    JoinFeePolicy::Burn => burn = Some(fee),
+                }
             }
-            None => Err(actor_error!(unhandled_message; "Invalid method")),
+
+            st.stats.joins += 1;
+            st.update_status(rt.curr_epoch(), rt.message().caller());
+
+            let is_validator = st
+                .is_validator(rt.store(), &caller)
+                .map_err(|_| actor_error!(illegal_state, "cannot load validator set"))?;
+            let ticket = if !was_validator && is_validator {
+                st.get_membership_ticket(rt.store(), &caller)
+                    .map_err(|_| actor_error!(illegal_state, "cannot load membership ticket"))?
+            } else {
+                None
+            };
+            Ok(JoinReturn {
+                status: st.status,
+                total_stake: st.total_stake.clone(),
+                became_validator: !was_validator && is_validator,
+                refunded: excess,
+                ticket,
+            })
+        })?;
+
+        if let Some(p) = msg {
+            send_stake_msg(rt, p)?;
         }
+        if let Some(fee) = burn {
+            rt.send(
+                *BURNT_FUNDS_ACTOR_ADDR,
+                METHOD_SEND,
+                RawBytes::default(),
+                fee,
+            )?;
+        }
+        if let Some(p) = refund {
+            rt.send(p.to, p.method, p.params, p.value)?;
+        }
+
+        Ok(ret)
+    }
+
+    /// FRC-46 receiver hook, called by the collateral token of the subnet
+    /// when tokens are transferred to the subnet actor.
+    ///
+    /// Received tokens are staked on behalf of the sender, which is onboarded
+    /// as when joining with native funds. The `JoinParams` need to be passed
+    /// as the operator data of the transfer.
+    pub fn token_received<BS, RT>(
+        rt: &mut RT,
+        params: frc46::UniversalReceiverParams,
+    ) -> Result<JoinReturn, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        let st: State = rt.state()?;
+        let token = st
+            .collateral_token
+            .ok_or_else(|| actor_error!(forbidden, "the subnet doesn't accept token collateral"))?;
+        rt.validate_immediate_caller_is(std::iter::once(&token))?;
+
+        if params.type_ != frc46::FRC46_TOKEN_TYPE {
+            return Err(actor_error!(
+                illegal_argument,
+                format!("unsupported token type: {}", params.type_)
+            ));
+        }
+        let received: frc46::FRC46TokenReceived = params.payload.deserialize()?;
+        if Some(received.to) != rt.message().receiver().id().ok() {
+            return Err(actor_error!(
+                illegal_argument,
+                format!("tokens were received by {}, not the subnet", received.to)
+            ));
+        }
+        if received.amount == TokenAmount::zero() {
+            return Err(actor_error!(
+                illegal_argument,
+                "a minimum collateral is required to join the subnet"
+            ));
+        }
+        let join_params: JoinParams = received.operator_data.deserialize()?;
+
+        // the token is the immediate caller, the policy applies to the sender
+        let from = Address::new_id(received.from);
+        st.caller_policies.join.check(rt, &from)?;
+
+        Self::stake_collateral(rt, from, received.amount, join_params, true)
+    }
+
+    /// Submits a vote for a checkpoint that catches up with windows for
+    /// which no checkpoint was committed.
+    ///
+    /// Regular submissions are only accepted for the next expected window;
+    /// once those windows have passed, validators can explicitly vote for a
+    /// checkpoint of a later window covering all the skipped ones.
+    pub fn submit_catch_up_checkpoint<BS, RT>(
+        rt: &mut RT,
+        submission: CheckpointSubmission,
+    ) -> Result<Option<RawBytes>, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        Self::vote_checkpoint::<Self, BS, RT>(rt, submission, true)
+    }
+
+    /// Returns the epoch of the next checkpoint expected for the subnet.
+    pub fn next_checkpoint_epoch<BS, RT>(rt: &mut RT) -> Result<ChainEpoch, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+        let st: State = rt.state()?;
+        Ok(st.next_checkpoint_epoch())
+    }
+
+    /// Registers the vote of the caller for a checkpoint, committing it if
+    /// it reaches a majority.
+    ///
+    /// Its bottom-up messages are validated with the `validate_cross_msg`
+    /// of `A`, so custom subnet actors implement `submit_checkpoint` by
+    /// calling it with themselves as `A`.
+    pub fn vote_checkpoint<A, BS, RT>(
+        rt: &mut RT,
+        submission: CheckpointSubmission,
+        catch_up: bool,
+    ) -> Result<Option<RawBytes>, ActorError>
+    where
+        A: SubnetActor,
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        let state: State = rt.state()?;
+        state.caller_policies.submit_checkpoint.validate(rt)?;
+        // workers vote for the validator they sign for
+        let caller = state
+            .validator_for_caller(rt.store(), &rt.message().caller())
+            .map_err(|_| actor_error!(illegal_state, "cannot load workers"))?;
+
+        if !state
+            .is_active_validator(rt.store(), &caller)
+            .map_err(|_| actor_error!(illegal_state, "cannot load participation"))?
+        {
+            return Err(SubnetActorError::NotValidator(caller).into());
+        }
+
+        // submissions beyond the cap are penalized if penalties are
+        // enabled, as a rejection would revert the penalty
+        if state
+            .submissions_exhausted(rt.store(), &caller, rt.curr_epoch())
+            .map_err(|_| actor_error!(illegal_state, "cannot load submissions"))?
+        {
+            if state.econ.checkpoint_penalty_rate > 0 {
+                return Self::penalize_bad_checkpoint(rt, &state, &submission);
+            }
+            return Err(SubnetActorError::TooManySubmissions(caller).into());
+        }
+
+        if let Err(e) = state.check_checkpoint(rt, &submission, catch_up) {
+            let bad_submission = matches!(
+                e.downcast_ref::<SubnetActorError>(),
+                Some(
+                    SubnetActorError::WrongCheckpointSource
+                        | SubnetActorError::InconsistentPrevCheckpoint
+                )
+            );
+            if bad_submission && state.econ.checkpoint_penalty_rate > 0 {
+                return Self::penalize_bad_checkpoint(rt, &state, &submission);
+            }
+            return Err(e.downcast_subnet(ExitCode::USR_ILLEGAL_STATE, "checkpoint failed"));
+        }
+        state
+            .verify_caller_signature(rt, &submission)
+            .map_err(|e| e.downcast_subnet(ExitCode::USR_ILLEGAL_STATE, "checkpoint failed"))?;
+        let ch = &submission.checkpoint;
+        if let Some(meta) = &ch.data.cross_msgs {
+            A::validate_cross_msg(rt, ch, meta)?;
+        }
+        let ch_cid = submission
+            .digest()
+            .map_err(|_| actor_error!(illegal_argument, "cannot digest checkpoint"))?;
+
+        let mut msg = None;
+        let mut fee = TokenAmount::zero();
+
+        rt.transaction(|st: &mut State, rt| {
+            st.expire_votes(rt.store(), rt.curr_epoch())
+                .map_err(|_| actor_error!(illegal_state, "cannot expire votes"))?;
+
+            let mut found = false;
+            let mut votes = match st.get_votes(rt.store(), &ch_cid)? {
+                Some(v) => {
+                    found = true;
+                    v
+                }
+                None => Votes {
+                    validators: Vec::new(),
+                },
+            };
+
+            if votes.validators.iter().any(|x| x == &caller) {
+                return Err(SubnetActorError::AlreadyVoted(caller).into());
+            }
+            if st
+                .conflicting_vote(rt.store(), ch.epoch(), &ch_cid, &caller)
+                .map_err(|_| actor_error!(illegal_state, "cannot load candidates"))?
+                .is_some()
+            {
+                return Err(SubnetActorError::ConflictingVote(caller).into());
+            }
+
+            // add miner vote
+            votes.validators.push(caller);
+            st.record_submission(rt.store(), &caller, rt.curr_epoch())
+                .map_err(|_| actor_error!(illegal_state, "cannot record submission"))?;
+            st.record_seen(rt.store(), &caller, rt.curr_epoch())
+                .map_err(|_| actor_error!(illegal_state, "cannot record heartbeat"))?;
+
+            // votes are weighted with the weights at the start of the window
+            st.snapshot_weights(rt.store(), ch.epoch())
+                .map_err(|_| actor_error!(illegal_state, "cannot snapshot weights"))?;
+
+            // if has majority
+            if st.has_majority_vote(rt.store(), ch.epoch(), &votes)? {
+                // commit checkpoint
+                msg = commit_checkpoint(
+                    st,
+                    rt.store(),
+                    &submission,
+                    &votes.validators,
+                    rt.curr_epoch(),
+                )?;
+                fee = st.take_relayer_fee();
+
+                // remove votes used for commitment
+                if found {
+                    st.remove_votes(rt.store(), &ch_cid)?;
+                }
+            } else {
+                // if no majority store vote and return
+                st.set_votes(rt.store(), &ch_cid, votes)?;
+                if !found {
+                    st.add_candidate(rt.store(), ch.epoch(), &ch_cid, rt.curr_epoch())
+                        .map_err(|_| actor_error!(illegal_state, "cannot add candidate"))?;
+                }
+            }
+
+            Ok(true)
+        })?;
+
+        // propagate to sca
+        if let Some(p) = msg {
+            send_gateway_msg(rt, p, OnGatewayFailure::Abort)?;
+        }
+        pay_relayer(rt, caller, fee)?;
+
+        Ok(None)
+    }
+
+    /// Penalizes the caller for signing a checkpoint with a wrong source or
+    /// previous checkpoint, or beyond its submissions of the window. The
+    /// submission is recorded instead of failing, so the penalty isn't
+    /// reverted.
+    fn penalize_bad_checkpoint<BS, RT>(
+        rt: &mut RT,
+        state: &State,
+        submission: &CheckpointSubmission,
+    ) -> Result<Option<RawBytes>, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        // only checkpoints actually signed by the validator are penalized
+        state
+            .verify_caller_signature(rt, submission)
+            .map_err(|e| e.downcast_subnet(ExitCode::USR_ILLEGAL_STATE, "checkpoint failed"))?;
+
+        let caller = state
+            .validator_for_caller(rt.store(), &rt.message().caller())
+            .map_err(|_| actor_error!(illegal_state, "cannot load workers"))?;
+        let epoch = rt.curr_epoch();
+        rt.transaction(|st: &mut State, rt| {
+            st.record_submission(rt.store(), &caller, epoch)
+                .map_err(|_| actor_error!(illegal_state, "cannot record submission"))?;
+            st.penalize_bad_checkpoint(rt.store(), &caller, epoch)
+                .map_err(|e| {
+                    e.downcast_subnet(ExitCode::USR_ILLEGAL_STATE, "cannot penalize validator")
+                })?;
+            st.update_status(rt.curr_epoch(), rt.message().caller());
+            Ok(true)
+        })?;
+
+        Ok(None)
+    }
+
+    /// Releases the caller from jail, charging it a penalty of
+    /// 1/`UNJAIL_PENALTY_DIVISOR` of its stake. Validators the penalty
+    /// would leave below the minimum stake need to top up their stake first.
+    pub fn unjail<BS, RT>(rt: &mut RT) -> Result<Option<RawBytes>, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let caller = rt.message().caller();
+
+        rt.transaction(|st: &mut State, rt| {
+            st.unjail(rt.store(), &caller).map_err(|e| {
+                e.downcast_subnet(ExitCode::USR_ILLEGAL_STATE, "cannot unjail validator")
+            })?;
+            st.update_status(rt.curr_epoch(), rt.message().caller());
+            Ok(true)
+        })?;
+
+        Ok(None)
+    }
+
+    /// Updates the chain ID, description and URL record of the subnet.
+    /// Only the owner of the subnet can update its metadata.
+    pub fn set_metadata<BS, RT>(
+        rt: &mut RT,
+        params: SetMetadataParams,
+    ) -> Result<Option<RawBytes>, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        validate_owner(rt)?;
+
+        rt.transaction(|st: &mut State, _| {
+            st.chain_id = params.chain_id;
+            st.description = params.description;
+            st.metadata_url = params.metadata_url;
+            Ok(true)
+        })?;
+
+        Ok(None)
+    }
+
+    /// Returns the stake of an address, resolving robust addresses to the
+    /// ID address stake is keyed by. Addresses that can't be resolved yet
+    /// have no stake.
+    pub fn get_stake_by_robust_address<BS, RT>(
+        rt: &mut RT,
+        addr: Address,
+    ) -> Result<TokenAmount, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let id = match resolve_id_address(rt, &addr) {
+            Ok(id) => id,
+            Err(_) => return Ok(TokenAmount::zero()),
+        };
+
+        let st: State = rt.state()?;
+        let stake = st
+            .get_stake(rt.store(), &id)
+            .map_err(|e| e.downcast_subnet(ExitCode::USR_ILLEGAL_STATE, "failed to load stake"))?;
+
+        Ok(stake.unwrap_or_else(TokenAmount::zero))
+    }
+
+    /// Sets up the genesis validator set in a single call, staking on behalf
+    /// of each validator the collateral funded by the caller.
+    ///
+    /// It can only be called once by the owner of the subnet, before its
+    /// activation, and registers the subnet in the gateway if the genesis
+    /// validators gather enough collateral.
+    pub fn bootstrap_validators<BS, RT>(
+        rt: &mut RT,
+        params: BootstrapValidatorsParams,
+    ) -> Result<Option<RawBytes>, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        validate_owner(rt)?;
+
+        let mut total = TokenAmount::zero();
+        let mut validators = Vec::new();
+        for v in params.validators {
+            if v.stake <= TokenAmount::zero() {
+                return Err(actor_error!(
+                    illegal_argument,
+                    format!("no stake for genesis validator {}", v.addr)
+                ));
+            }
+            check_net_addr(&v.net_addr)?;
+            total += &v.stake;
+            validators.push((resolve_id_address(rt, &v.addr)?, v));
+        }
+        if rt.message().value_received() != total {
+            return Err(actor_error!(
+                illegal_argument,
+                "funds don't match the stake of the genesis validators"
+            ));
+        }
+
+        // the caller funds the stake of every genesis validator
+        let funder = rt.message().caller();
+        let mut msg = None;
+        rt.transaction(|st: &mut State, rt| {
+            if st.bootstrapped || st.status != Status::Instantiated {
+                return Err(SubnetActorError::AlreadyBootstrapped.into());
+            }
+            if st.collateral_token.is_some() {
+                return Err(actor_error!(
+                    illegal_argument,
+                    "the collateral of the subnet is denominated in tokens"
+                ));
+            }
+
+            for (addr, v) in &validators {
+                if st.capped_stake(rt.store(), addr, &v.stake).map_err(|e| {
+                    e.downcast_subnet(ExitCode::USR_ILLEGAL_STATE, "cannot stake collateral")
+                })? != v.stake
+                {
+                    return Err(SubnetActorError::StakeCapExceeded(*addr).into());
+                }
+                st.add_stake(rt.store(), addr, &funder, v.net_addr.as_str(), &v.stake)
+                    .map_err(|e| {
+                        e.downcast_subnet(ExitCode::USR_ILLEGAL_STATE, "failed to load subnet")
+                    })?;
+            }
+            st.bootstrapped = true;
+
+            // a single registration with the collateral of every genesis validator
+            msg = stake_gateway_msg(st, &total);
+
+            st.update_status(rt.curr_epoch(), rt.message().caller());
+
+            Ok(true)
+        })?;
+
+        if let Some(p) = msg {
+            send_stake_msg(rt, p)?;
+        }
+
+        Ok(None)
+    }
+
+    /// Returns a page of the addresses with stake in the subnet.
+    pub fn list_stakers<BS, RT>(
+        rt: &mut RT,
+        params: ListParams,
+    ) -> Result<ListStakersReturn, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let st: State = rt.state()?;
+        let (stakers, next_cursor) = st
+            .list_stakers(
+                rt.store(),
+                params.cursor,
+                std::cmp::min(params.limit, MAX_PAGE_LIMIT),
+            )
+            .map_err(|e| {
+                e.downcast_subnet(ExitCode::USR_ILLEGAL_STATE, "failed to list stakers")
+            })?;
+
+        Ok(ListStakersReturn {
+            stakers,
+            next_cursor,
+            total: st.stakers_count,
+        })
+    }
+
+    /// Returns a page of the committed checkpoints.
+    pub fn list_checkpoints<BS, RT>(
+        rt: &mut RT,
+        params: ListParams,
+    ) -> Result<ListCheckpointsReturn, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let st: State = rt.state()?;
+        let (checkpoints, next_cursor) = st
+            .list_checkpoints(
+                rt.store(),
+                params.cursor,
+                std::cmp::min(params.limit, MAX_PAGE_LIMIT),
+            )
+            .map_err(|e| {
+                e.downcast_subnet(ExitCode::USR_ILLEGAL_STATE, "failed to list checkpoints")
+            })?;
+
+        Ok(ListCheckpointsReturn {
+            checkpoints,
+            next_cursor,
+            total: st.checkpoints_count,
+        })
+    }
+
+    /// Returns the delegated address of the actor, if it was deployed
+    /// through `Exec4`.
+    pub fn get_delegated_address<BS, RT>(rt: &mut RT) -> Result<Option<Address>, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+        let st: State = rt.state()?;
+        Ok(st.delegated_addr)
+    }
+
+    /// Returns a summary of the status, stake and checkpoints of the subnet
+    /// in a single read of the state.
+    pub fn get_subnet_summary<BS, RT>(rt: &mut RT) -> Result<SubnetSummary, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+        let st: State = rt.state()?;
+        let last_committed_checkpoint = st
+            .get_checkpoint(rt.store(), &st.last_committed_epoch)
+            .map_err(|e| e.downcast_subnet(ExitCode::USR_ILLEGAL_STATE, "cannot load checkpoint"))?
+            .map(|ch| ch.cid());
+        Ok(SubnetSummary {
+            status: st.status,
+            consensus: st.consensus,
+            total_stake: st.total_stake.clone(),
+            validators: st.validators.len() as u64,
+            last_committed_epoch: st.last_committed_epoch,
+            last_committed_checkpoint,
+            next_checkpoint_epoch: st.next_checkpoint_epoch(),
+            econ: st.econ,
+        })
+    }
+
+    /// Returns the checkpoints committed in a range of epochs, in order of
+    /// epoch, counting them in `total`. Ranges with more than a page of
+    /// checkpoints are continued with `ListCheckpoints` from the returned
+    /// cursor.
+    pub fn list_checkpoints_in_range<BS, RT>(
+        rt: &mut RT,
+        params: CheckpointRangeParams,
+    ) -> Result<ListCheckpointsReturn, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let st: State = rt.state()?;
+        let windows = st
+            .get_checkpoint_windows(rt.store(), params.from, params.to)
+            .map_err(|e| {
+                e.downcast_subnet(ExitCode::USR_ILLEGAL_STATE, "failed to find checkpoints")
+            })?;
+        let end = std::cmp::min(windows.end, windows.start + MAX_PAGE_LIMIT);
+        let checkpoints = st
+            .get_checkpoints_by_windows(rt.store(), windows.start..end)
+            .map_err(|e| {
+                e.downcast_subnet(ExitCode::USR_ILLEGAL_STATE, "failed to list checkpoints")
+            })?;
+
+        Ok(ListCheckpointsReturn {
+            checkpoints,
+            next_cursor: (end < windows.end).then_some(end),
+            total: windows.end - windows.start,
+        })
+    }
+
+    /// Distributes the cross-net message fees forwarded by the gateway
+    /// among the validators, proportionally to their stake.
+    ///
+    /// The dust left by rounding, and the shares that can't be sent to
+    /// their validator, go to the reward pool.
+    pub fn distribute_fees<BS, RT>(rt: &mut RT) -> Result<Option<RawBytes>, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        let st: State = rt.state()?;
+        rt.validate_immediate_caller_is(std::iter::once(&st.ipc_gateway_addr))?;
+
+        let fees = rt.message().value_received();
+        let (shares, mut dust) = rt.transaction(|st: &mut State, rt| {
+            st.apply_commission_changes(rt.store(), rt.curr_epoch())
+                .map_err(|e| {
+                    e.downcast_subnet(ExitCode::USR_ILLEGAL_STATE, "cannot apply commissions")
+                })?;
+            st.fee_shares(rt.store(), &fees)
+                .map_err(|e| e.downcast_subnet(ExitCode::USR_ILLEGAL_STATE, "cannot split fees"))
+        })?;
+
+        for (addr, share) in shares {
+            if rt
+                .send(addr, METHOD_SEND, RawBytes::default(), share.clone())
+                .is_err()
+            {
+                dust += share;
+            }
+        }
+
+        rt.transaction(|st: &mut State, _| {
+            st.reward_pool += dust;
+            Ok(true)
+        })?;
+
+        Ok(None)
+    }
+
+    /// Returns the state of the subnet claimed by the last committed
+    /// checkpoint, for light clients to verify state proofs of the subnet.
+    pub fn get_last_state_commitment<BS, RT>(
+        rt: &mut RT,
+    ) -> Result<Option<StateCommitment>, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+        let st: State = rt.state()?;
+        Ok(st.last_state_commitment)
+    }
+
+    /// Relays to the gateway the committed checkpoints whose dispute period
+    /// has ended and whose epoch is final. Anyone can trigger it.
+    pub fn relay_checkpoints<BS, RT>(rt: &mut RT) -> Result<Option<RawBytes>, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let msgs = rt.transaction(|st: &mut State, rt| {
+            let curr_epoch = rt.curr_epoch();
+            let ready = st
+                .pending_checkpoints
+                .iter()
+                .take_while(|p| p.relay_at <= curr_epoch)
+                .count();
+
+            let mut msgs = Vec::new();
+            for p in st.pending_checkpoints.drain(..ready).collect::<Vec<_>>() {
+                let ch = st
+                    .get_checkpoint(rt.store(), &p.epoch)
+                    .map_err(|_| actor_error!(illegal_state, "cannot load checkpoint"))?
+                    .ok_or_else(|| actor_error!(illegal_state, "pending checkpoint not found"))?;
+                msgs.push(relay_checkpoint_msg(st, &ch)?);
+            }
+            Ok(msgs)
+        })?;
+
+        for msg in msgs {
+            send_gateway_msg(rt, msg, OnGatewayFailure::Abort)?;
+        }
+
+        Ok(None)
+    }
+
+    /// Challenges a committed checkpoint in its dispute period with a
+    /// conflicting checkpoint for the same window signed by a majority,
+    /// differing in the checkpoint or in the state it claims.
+    ///
+    /// On success the committed checkpoint, and any committed after it, are
+    /// reverted before being relayed, and the validators that signed both
+    /// checkpoints are slashed.
+    pub fn challenge_checkpoint<BS, RT>(
+        rt: &mut RT,
+        params: ChallengeCheckpointParams,
+    ) -> Result<Option<RawBytes>, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let state: State = rt.state()?;
+        let submission = params.submission;
+        let ch = &submission.checkpoint;
+        let epoch = ch.epoch();
+
+        let pending = state
+            .pending_checkpoints
+            .iter()
+            .find(|p| p.epoch == epoch && p.relay_at > rt.curr_epoch())
+            .cloned()
+            .ok_or(SubnetActorError::NotDisputable(epoch))?;
+        let committed = state
+            .get_checkpoint(rt.store(), &epoch)
+            .map_err(|_| actor_error!(illegal_state, "cannot load checkpoint"))?
+            .ok_or_else(|| actor_error!(illegal_state, "pending checkpoint not found"))?;
+        let same_commitment =
+            state.pending_state_commitment(epoch).as_ref() == Some(&submission.state_commitment);
+        if (ch.cid() == committed.cid() && same_commitment) || ch.source() != committed.source() {
+            return Err(actor_error!(
+                illegal_argument,
+                "checkpoint doesn't conflict with the committed one"
+            ));
+        }
+
+        let mut signers = Vec::new();
+        for s in &params.signatures {
+            let validator = resolve_id_address(rt, &s.validator)?;
+            let is_validator = state
+                .is_validator(rt.store(), &validator)
+                .map_err(|_| actor_error!(illegal_state, "cannot load validator set"))?;
+            if !is_validator && !pending.voters.contains(&validator) {
+                return Err(SubnetActorError::NotValidator(s.validator).into());
+            }
+            if signers.contains(&validator) {
+                return Err(actor_error!(
+                    illegal_argument,
+                    format!("duplicated signature from {}", s.validator)
+                ));
+            }
+            state
+                .verify_checkpoint_signature(rt, &validator, &s.signature, &submission)
+                .map_err(|_| actor_error!(illegal_argument, "invalid checkpoint signature"))?;
+            signers.push(validator);
+        }
+        let votes = Votes {
+            validators: signers,
+        };
+        if !state.has_majority_vote(rt.store(), epoch, &votes)? {
+            return Err(actor_error!(
+                illegal_argument,
+                "not enough voting power to challenge the checkpoint"
+            ));
+        }
+
+        rt.transaction(|st: &mut State, rt| {
+            st.revert_checkpoints(rt.store(), epoch).map_err(|e| {
+                e.downcast_subnet(ExitCode::USR_ILLEGAL_STATE, "cannot revert checkpoints")
+            })?;
+
+            // validators that signed both checkpoints equivocated
+            for v in votes
+                .validators
+                .iter()
+                .filter(|v| pending.voters.contains(v))
+            {
+                st.slash(rt.store(), v).map_err(|e| {
+                    e.downcast_subnet(ExitCode::USR_ILLEGAL_STATE, "cannot slash validator")
+                })?;
+            }
+
+            st.snapshot_weights(rt.store(), st.next_checkpoint_epoch())
+                .map_err(|_| actor_error!(illegal_state, "cannot snapshot weights"))?;
+            st.update_status(rt.curr_epoch(), rt.message().caller());
+            Ok(true)
+        })?;
+
+        Ok(None)
+    }
+
+    /// Slashes a validator with evidence of its misbehavior in the consensus
+    /// of the subnet, e.g. a double sign, verified by the consensus.
+    pub fn submit_evidence<BS, RT>(
+        rt: &mut RT,
+        params: SubmitEvidenceParams,
+    ) -> Result<Option<RawBytes>, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let validator = resolve_id_address(rt, &params.validator)?;
+        let state: State = rt.state()?;
+        let is_validator = state
+            .is_validator(rt.store(), &validator)
+            .map_err(|_| actor_error!(illegal_state, "cannot load validator set"))?;
+        if !is_validator {
+            return Err(SubnetActorError::NotValidator(params.validator).into());
+        }
+        let offence = state
+            .consensus
+            .verify(rt, &state, &validator, params.evidence.bytes())
+            .map_err(|e| e.downcast_subnet(ExitCode::USR_ILLEGAL_ARGUMENT, "invalid evidence"))?;
+
+        rt.transaction(|st: &mut State, rt| {
+            st.record_offence(rt.store(), offence, rt.curr_epoch())
+                .map_err(|e| {
+                    e.downcast_subnet(ExitCode::USR_ILLEGAL_STATE, "cannot record offence")
+                })?;
+            st.slash(rt.store(), &validator).map_err(|e| {
+                e.downcast_subnet(ExitCode::USR_ILLEGAL_STATE, "cannot slash validator")
+            })?;
+            st.snapshot_weights(rt.store(), st.next_checkpoint_epoch())
+                .map_err(|_| actor_error!(illegal_state, "cannot snapshot weights"))?;
+            st.update_status(rt.curr_epoch(), rt.message().caller());
+            Ok(true)
+        })?;
+
+        Ok(None)
+    }
+
+    /// Rotates the key the caller signs checkpoints with, from the next
+    /// checkpoint window on, without leaving the subnet.
+    pub fn rotate_validator_key<BS, RT>(
+        rt: &mut RT,
+        params: RotateValidatorKeyParams,
+    ) -> Result<Option<RawBytes>, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let caller = rt.message().caller();
+        check_validator_key(&params.key)?;
+        let st: State = rt.state()?;
+        let payload = key_rotation_payload(&caller, st.config_number)
+            .map_err(|_| actor_error!(illegal_argument, "cannot encode key rotation"))?;
+        st.sig_scheme
+            .verify(rt, &params.signature, &params.key, &payload)
+            .map_err(|_| actor_error!(illegal_argument, "invalid signature by the new key"))?;
+        // the account of the new key submits checkpoints for the validator
+        let worker = rt.resolve_address(&params.key).map(Address::new_id);
+
+        rt.transaction(|st: &mut State, rt| {
+            if !st
+                .is_validator(rt.store(), &caller)
+                .map_err(|_| actor_error!(illegal_state, "cannot load validator set"))?
+            {
+                return Err(SubnetActorError::NotValidator(caller).into());
+            }
+            st.rotate_validator_key(rt.store(), &caller, params.key, params.nonce)
+                .and_then(|_| st.set_worker(rt.store(), &caller, worker.as_ref()))
+                .map_err(|e| {
+                    e.downcast_subnet(ExitCode::USR_ILLEGAL_STATE, "cannot rotate validator key")
+                })?;
+            Ok(true)
+        })?;
+
+        Ok(None)
+    }
+
+    /// Returns the active validators with their stake scaled to a voting
+    /// power, as expected by Tendermint and Mir subnet nodes.
+    pub fn get_power_table<BS, RT>(rt: &mut RT) -> Result<PowerTable, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+        let st: State = rt.state()?;
+        st.power_table(rt.store())
+            .map_err(|e| e.downcast_subnet(ExitCode::USR_ILLEGAL_STATE, "cannot build power table"))
+    }
+
+    /// Removes the entries of addresses without stake left from the stake
+    /// table, for a page of the stakers in the order they first staked.
+    /// Anyone can trigger it.
+    pub fn compact_stake<BS, RT>(
+        rt: &mut RT,
+        params: ListParams,
+    ) -> Result<CompactStakeReturn, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+
+        rt.transaction(|st: &mut State, rt| {
+            let (removed, next_cursor) = st
+                .compact_stake(
+                    rt.store(),
+                    params.cursor,
+                    std::cmp::min(params.limit, MAX_PAGE_LIMIT),
+                )
+                .map_err(|e| {
+                    e.downcast_subnet(ExitCode::USR_ILLEGAL_STATE, "cannot compact stake table")
+                })?;
+            Ok(CompactStakeReturn {
+                removed,
+                next_cursor,
+            })
+        })
+    }
+
+    /// Registers the vote of the caller for the height of the parent chain
+    /// it considers final, committing it if it reaches a majority.
+    ///
+    /// Heights need to be at least `finality_threshold` epochs old, and
+    /// newer than the committed parent finality.
+    pub fn submit_parent_finality<BS, RT>(
+        rt: &mut RT,
+        finality: ParentFinality,
+    ) -> Result<Option<RawBytes>, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let caller = rt.message().caller();
+        rt.transaction(|st: &mut State, rt| {
+            if !st
+                .is_active_validator(rt.store(), &caller)
+                .map_err(|_| actor_error!(illegal_state, "cannot load participation"))?
+            {
+                return Err(SubnetActorError::NotValidator(caller).into());
+            }
+            st.check_parent_finality(&finality, rt.curr_epoch())
+                .map_err(|e| {
+                    e.downcast_subnet(ExitCode::USR_ILLEGAL_ARGUMENT, "invalid parent finality")
+                })?;
+            st.vote_parent_finality(rt.store(), &caller, finality)
+                .map_err(|e| {
+                    e.downcast_subnet(ExitCode::USR_ILLEGAL_STATE, "cannot vote parent finality")
+                })?;
+            Ok(true)
+        })?;
+
+        Ok(None)
+    }
+
+    /// Returns the last parent finality committed by the validators.
+    pub fn get_parent_finality<BS, RT>(rt: &mut RT) -> Result<Option<ParentFinality>, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+        let st: State = rt.state()?;
+        Ok(st.parent_finality)
+    }
+
+    /// Returns the checkpoints voted in the window at `epoch`, the weight
+    /// of their votes and the threshold needed to commit them.
+    pub fn get_checkpoint_voting_status<BS, RT>(
+        rt: &mut RT,
+        epoch: ChainEpoch,
+    ) -> Result<CheckpointVotingStatus, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+        let st: State = rt.state()?;
+        st.voting_status(rt.store(), epoch)
+    }
+
+    /// Freezes the methods mutating the state, e.g. while a vulnerability
+    /// is being fixed. Only the owner of the subnet can pause it.
+    pub fn pause<BS, RT>(rt: &mut RT) -> Result<Option<RawBytes>, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        validate_owner(rt)?;
+        rt.transaction(|st: &mut State, _| {
+            st.paused = true;
+            Ok(true)
+        })?;
+        Ok(None)
+    }
+
+    /// Resumes the methods frozen by [`Actor::pause`].
+    pub fn unpause<BS, RT>(rt: &mut RT) -> Result<Option<RawBytes>, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        validate_owner(rt)?;
+        rt.transaction(|st: &mut State, _| {
+            st.paused = false;
+            Ok(true)
+        })?;
+        Ok(None)
+    }
+
+    /// Delegates the value of the message to an active validator. The
+    /// delegated stake adds to the weight of the validator, and earns its
+    /// share of the fees minus the commission of the validator.
+    pub fn delegate<BS, RT>(
+        rt: &mut RT,
+        params: DelegateParams,
+    ) -> Result<Option<RawBytes>, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let caller = rt.message().caller();
+        let amount = rt.message().value_received();
+        if amount == TokenAmount::zero() {
+            return Err(actor_error!(illegal_argument, "no stake to delegate"));
+        }
+        let validator = resolve_id_address(rt, &params.validator)?;
+
+        let mut msg = None;
+        rt.transaction(|st: &mut State, rt| {
+            check_accepts_stake(st)?;
+            if st.collateral_token.is_some() {
+                return Err(actor_error!(
+                    illegal_argument,
+                    "stake can only be delegated in subnets with native collateral"
+                ));
+            }
+            st.delegate(rt.store(), &validator, &caller, &amount)
+                .map_err(|e| {
+                    e.downcast_subnet(ExitCode::USR_ILLEGAL_ARGUMENT, "cannot delegate stake")
+                })?;
+            msg = stake_gateway_msg(st, &amount);
+            st.update_status(rt.curr_epoch(), rt.message().caller());
+            Ok(true)
+        })?;
+
+        if let Some(p) = msg {
+            send_stake_msg(rt, p)?;
+        }
+
+        Ok(None)
+    }
+
+    /// Withdraws all the stake the caller delegated to a validator, and
+    /// refunds it to the caller. See `State::undelegate`.
+    pub fn undelegate<BS, RT>(
+        rt: &mut RT,
+        params: UndelegateParams,
+    ) -> Result<TokenAmount, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let caller = rt.message().caller();
+        let validator = resolve_id_address(rt, &params.validator)?;
+
+        let mut msgs = Vec::new();
+        let amount = rt.transaction(|st: &mut State, rt| {
+            let amount = st
+                .undelegate(rt.store(), &validator, &caller)
+                .map_err(|e| {
+                    e.downcast_subnet(ExitCode::USR_ILLEGAL_ARGUMENT, "cannot undelegate stake")
+                })?;
+            if amount.is_positive() {
+                msgs = release_stake_msg(st, caller, &amount)?;
+            }
+            st.update_status(rt.curr_epoch(), rt.message().caller());
+            Ok(amount)
+        })?;
+
+        for p in msgs {
+            send_stake_msg(rt, p)?;
+        }
+
+        Ok(amount)
+    }
+
+    /// Moves stake the caller delegated to a validator to another one,
+    /// without withdrawing it from the gateway. See `State::redelegate`.
+    pub fn redelegate<BS, RT>(
+        rt: &mut RT,
+        params: RedelegateParams,
+    ) -> Result<Option<RawBytes>, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+        if !params.amount.is_positive() {
+            return Err(actor_error!(
+                illegal_argument,
+                "the amount to redelegate needs to be positive"
+            ));
+        }
+
+        let caller = rt.message().caller();
+        let from = resolve_id_address(rt, &params.from)?;
+        let to = resolve_id_address(rt, &params.to)?;
+
+        rt.transaction(|st: &mut State, rt| {
+            check_accepts_stake(st)?;
+            st.redelegate(
+                rt.store(),
+                &caller,
+                &from,
+                &to,
+                &params.amount,
+                rt.curr_epoch(),
+            )
+            .map_err(|e| {
+                e.downcast_subnet(ExitCode::USR_ILLEGAL_ARGUMENT, "cannot redelegate stake")
+            })?;
+            st.update_status(rt.curr_epoch(), rt.message().caller());
+            Ok(true)
+        })?;
+        Ok(None)
+    }
+
+    /// Sets the commission the caller keeps from the fees earned by the
+    /// stake delegated to it, in basis points. Increases only apply after
+    /// `COMMISSION_CHANGE_DELAY_WINDOWS` checkpoint windows.
+    pub fn set_commission_rate<BS, RT>(
+        rt: &mut RT,
+        rate: u64,
+    ) -> Result<Option<RawBytes>, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let caller = rt.message().caller();
+        rt.transaction(|st: &mut State, rt| {
+            st.set_commission_rate(rt.store(), &caller, rate, rt.curr_epoch())
+                .map_err(|e| {
+                    e.downcast_subnet(ExitCode::USR_ILLEGAL_ARGUMENT, "cannot set commission")
+                })?;
+            Ok(true)
+        })?;
+
+        Ok(None)
+    }
+
+    /// Releases collateral of a validator on behalf of the IPC gateway,
+    /// e.g. when a dispute is resolved in the parent, and refunds it to the
+    /// funders of its stake. Native collateral is released by the gateway along with
+    /// the call, while token collateral is held by the subnet actor.
+    pub fn release_stake_for<BS, RT>(
+        rt: &mut RT,
+        params: ReleaseStakeForParams,
+    ) -> Result<Option<RawBytes>, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        let st: State = rt.state()?;
+        rt.validate_immediate_caller_is(std::iter::once(&st.ipc_gateway_addr))?;
+
+        let expected = match st.collateral_token {
+            Some(_) => TokenAmount::zero(),
+            None => params.amount.clone(),
+        };
+        if rt.message().value_received() != expected {
+            return Err(actor_error!(
+                illegal_argument,
+                "the gateway needs to send the released collateral: {}",
+                expected
+            ));
+        }
+
+        let validator = resolve_id_address(rt, &params.validator)?;
+        let msgs = rt.transaction(|st: &mut State, rt| {
+            let refunds = st
+                .release_stake_for(rt.store(), &validator, &params.amount)
+                .map_err(|e| {
+                    e.downcast_subnet(ExitCode::USR_ILLEGAL_ARGUMENT, "cannot release stake")
+                })?;
+            st.update_status(rt.curr_epoch(), rt.message().caller());
+            refunds
+                .into_iter()
+                .map(|(funder, amount)| refund_msg(st, funder, amount))
+                .collect::<Result<Vec<_>, _>>()
+        })?;
+
+        for msg in msgs {
+            rt.send(msg.to, msg.method, msg.params, msg.value)?;
+        }
+
+        Ok(None)
+    }
+
+    /// Transfers the ownership of the subnet. The owner presents the
+    /// current ownership nonce, so that the transfer can't be replayed.
+    pub fn transfer_ownership<BS, RT>(
+        rt: &mut RT,
+        params: TransferOwnershipParams,
+    ) -> Result<Option<RawBytes>, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        validate_owner(rt)?;
+
+        let new_owner = resolve_id_address(rt, &params.new_owner)?;
+        rt.transaction(|st: &mut State, _| {
+            if params.nonce != st.owner_nonce {
+                return Err(SubnetActorError::InvalidOwnerNonce(st.owner_nonce).into());
+            }
+            st.owner = Some(new_owner);
+            st.owner_nonce += 1;
+            Ok(true)
+        })?;
+
+        Ok(None)
+    }
+
+    /// Gives up the ownership of the subnet for good, leaving its
+    /// governance to the validators. Only bootstrapped subnets can be
+    /// renounced, as genesis validators are set by the owner.
+    pub fn renounce_ownership<BS, RT>(
+        rt: &mut RT,
+        nonce: u64,
+    ) -> Result<Option<RawBytes>, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        validate_owner(rt)?;
+
+        rt.transaction(|st: &mut State, _| {
+            if nonce != st.owner_nonce {
+                return Err(SubnetActorError::InvalidOwnerNonce(st.owner_nonce).into());
+            }
+            if st.status == Status::Instantiated {
+                return Err(actor_error!(
+                    illegal_state,
+                    "the subnet needs to be bootstrapped to renounce its ownership"
+                ));
+            }
+            st.owner = None;
+            st.owner_nonce += 1;
+            Ok(true)
+        })?;
+
+        Ok(None)
+    }
+
+    /// Adds the value of the message to the reward pool relayer fees are
+    /// paid from. Anyone can fund the pool until the subnet is killed,
+    /// which burns what is left in it.
+    pub fn fund_reward_pool<BS, RT>(rt: &mut RT) -> Result<Option<RawBytes>, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let amount = rt.message().value_received();
+        rt.transaction(|st: &mut State, _| {
+            check_accepts_stake(st)?;
+            st.reward_pool += amount;
+            Ok(true)
+        })?;
+
+        Ok(None)
+    }
+
+    /// Returns the activation of the subnet and its last status change.
+    pub fn get_genesis_epoch<BS, RT>(rt: &mut RT) -> Result<ActivationInfo, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+        let st: State = rt.state()?;
+        Ok(ActivationInfo {
+            activation: st.activation,
+            status: st.status,
+            status_changed_at: st.status_changed_at,
+        })
+    }
+
+    /// Returns the counters of the operations of the subnet.
+    pub fn get_stats<BS, RT>(rt: &mut RT) -> Result<Stats, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+        let st: State = rt.state()?;
+        Ok(st.stats)
+    }
+
+    /// Changes the checkpoint period of the subnet. Only the owner can
+    /// change it.
+    ///
+    /// The new period takes effect with the next committed checkpoint, see
+    /// `State::schedule_check_period`.
+    pub fn set_check_period<BS, RT>(
+        rt: &mut RT,
+        period: ChainEpoch,
+    ) -> Result<Option<RawBytes>, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        validate_owner(rt)?;
+
+        rt.transaction(|st: &mut State, _| {
+            st.schedule_check_period(period).map_err(|e| {
+                e.downcast_subnet(ExitCode::USR_ILLEGAL_ARGUMENT, "cannot change check period")
+            })?;
+            Ok(true)
+        })?;
+
+        Ok(None)
+    }
+
+    /// Returns the membership ticket of a validator, if it is one.
+    pub fn get_membership_ticket<BS, RT>(
+        rt: &mut RT,
+        validator: Address,
+    ) -> Result<Option<MembershipTicket>, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let validator = match resolve_id_address(rt, &validator) {
+            Ok(id) => id,
+            Err(_) => return Ok(None),
+        };
+
+        let st: State = rt.state()?;
+        st.get_membership_ticket(rt.store(), &validator)
+            .map_err(|_| actor_error!(illegal_state, "cannot load membership ticket"))
+    }
+
+    /// Returns the checkpoint committed for a window number along with the
+    /// blocks proving its inclusion from the state root of the actor, for
+    /// light clients on the parent to verify it.
+    pub fn get_checkpoint_proof<BS, RT>(
+        rt: &mut RT,
+        window: u64,
+    ) -> Result<Option<CheckpointProof>, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let st: State = rt.state()?;
+        let checkpoint = match st
+            .get_checkpoint_by_window(rt.store(), window)
+            .map_err(|_| actor_error!(illegal_state, "cannot load checkpoint"))?
+        {
+            Some(ch) => ch,
+            None => return Ok(None),
+        };
+
+        let root = rt.get_state_root()?;
+        let mut blocks = vec![proofs::load_block(rt.store(), &root)
+            .map_err(|_| actor_error!(illegal_state, "cannot load state"))?];
+        blocks.extend(
+            proofs::amt_path(rt.store(), &st.checkpoints.cid(), window)
+                .map_err(|_| actor_error!(illegal_state, "cannot build checkpoint proof"))?,
+        );
+
+        let config_number = st
+            .get_checkpoint_config(rt.store(), window)
+            .map_err(|_| actor_error!(illegal_state, "cannot load checkpoint config"))?;
+
+        Ok(Some(CheckpointProof {
+            window,
+            checkpoint,
+            config_number,
+            blocks,
+        }))
+    }
+
+    /// Records the current epoch as the last epoch the calling validator
+    /// was seen. Voting a checkpoint records it as well.
+    pub fn heartbeat<BS, RT>(rt: &mut RT) -> Result<Option<RawBytes>, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+        let caller = rt.message().caller();
+
+        rt.transaction(|st: &mut State, rt| {
+            if !st
+                .is_validator(rt.store(), &caller)
+                .map_err(|_| actor_error!(illegal_state, "cannot load validator set"))?
+            {
+                return Err(SubnetActorError::NotValidator(caller).into());
+            }
+            st.record_seen(rt.store(), &caller, rt.curr_epoch())
+                .map_err(|_| actor_error!(illegal_state, "cannot record heartbeat"))?;
+            Ok(true)
+        })?;
+
+        Ok(None)
+    }
+
+    /// Returns the validators seen in the last `window` epochs.
+    pub fn get_live_validators<BS, RT>(
+        rt: &mut RT,
+        window: ChainEpoch,
+    ) -> Result<Vec<Address>, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+        if window < 0 {
+            return Err(actor_error!(illegal_argument, "negative liveness window"));
+        }
+
+        let st: State = rt.state()?;
+        st.live_validators(rt.store(), rt.curr_epoch() - window)
+            .map_err(|_| actor_error!(illegal_state, "cannot load last seen epochs"))
+    }
+
+    /// Returns the finality rules applied by the subnet.
+    pub fn get_finality_config<BS, RT>(rt: &mut RT) -> Result<FinalityConfig, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+        let st: State = rt.state()?;
+        Ok(FinalityConfig {
+            finality_threshold: st.finality_threshold,
+            dispute_period: st.dispute_period,
+        })
+    }
+
+    /// Withdraws part of the stake of the caller, which stays in the
+    /// validator set as long as it keeps the minimum validator stake. The
+    /// released stake is propagated to the gateway as when leaving, and the
+    /// power table reflects the reduced stake right away.
+    pub fn withdraw_stake<BS, RT>(
+        rt: &mut RT,
+        params: WithdrawStakeParams,
+    ) -> Result<LeaveReturn, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        let st: State = rt.state()?;
+        st.caller_policies.leave.validate(rt)?;
+
+        let caller = rt.message().caller();
+        let mut msgs = Vec::new();
+        let ret = rt.transaction(|st: &mut State, rt| {
+            let refunds = st
+                .withdraw_stake(rt.store(), &caller, &params.amount, rt.curr_epoch())
+                .map_err(|e| {
+                    e.downcast_subnet(ExitCode::USR_ILLEGAL_ARGUMENT, "cannot withdraw stake")
+                })?;
+            let (released, release) = release_stake_msgs(st, &refunds)?;
+            msgs = release;
+
+            let remaining_stake = st
+                .get_stake(rt.store(), &caller)
+                .map_err(|e| {
+                    e.downcast_subnet(ExitCode::USR_ILLEGAL_STATE, "failed to load stake")
+                })?
+                .unwrap_or_else(TokenAmount::zero);
+            st.update_status(rt.curr_epoch(), rt.message().caller());
+
+            Ok(LeaveReturn {
+                refunded: released,
+                remaining_stake,
+                refunds,
+            })
+        })?;
+
+        for p in msgs {
+            send_stake_msg(rt, p)?;
+        }
+
+        Ok(ret)
+    }
+
+    /// Returns the validators of the configuration `config_number` of the
+    /// validator set, for anyone to check which validators were active in
+    /// the windows of a checkpoint committed in it.
+    pub fn get_validator_set_at<BS, RT>(
+        rt: &mut RT,
+        config_number: u64,
+    ) -> Result<Option<ValidatorSetAt>, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let st: State = rt.state()?;
+        let set = st
+            .get_validator_set_at(rt.store(), config_number)
+            .map_err(|_| actor_error!(illegal_state, "cannot load validator set"))?;
+        Ok(set.map(|(digest, validators)| ValidatorSetAt {
+            config_number,
+            digest,
+            validators,
+        }))
+    }
+
+    /// Enables or disables the notification of the changes of the power
+    /// table to the IPC gateway. Only the owner of the subnet can set it,
+    /// once the gateway implements `ValidatorSetUpdated`.
+    pub fn set_validator_set_notifications<BS, RT>(
+        rt: &mut RT,
+        enabled: bool,
+    ) -> Result<Option<RawBytes>, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        validate_owner(rt)?;
+
+        rt.transaction(|st: &mut State, _| {
+            st.notify_validator_set = enabled;
+            // the gateway gets the full power table when re-enabled
+            st.notified_power_table = None;
+            Ok(true)
+        })?;
+
+        Ok(None)
+    }
+
+    /// Returns the fields of the checkpoint expected for the next window,
+    /// so validators don't need to rebuild them off-chain.
+    pub fn get_checkpoint_template<BS, RT>(rt: &mut RT) -> Result<CheckpointTemplate, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+        let st: State = rt.state()?;
+        st.checkpoint_template(rt.store(), rt.message().receiver())
+            .map_err(|_| actor_error!(illegal_state, "cannot load last checkpoint"))
+    }
+
+    /// Sends the calls to the IPC gateway queued after it rejected them, in
+    /// order, until it rejects one again. Returns the number of calls sent.
+    /// Anyone can trigger it.
+    pub fn retry_gateway_calls<BS, RT>(rt: &mut RT) -> Result<u64, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let st: State = rt.state()?;
+        let mut sent = 0;
+        for call in st.pending_gateway_calls {
+            if call.method == GatewayMethod::Register.method_num() {
+                check_parent(rt)?;
+            }
+            if rt
+                .send(call.to, call.method, call.params, call.value)
+                .is_err()
+            {
+                break;
+            }
+            sent += 1;
+        }
+
+        rt.transaction(|st: &mut State, _| {
+            st.pending_gateway_calls.drain(..sent);
+            Ok(true)
+        })?;
+
+        Ok(sent as u64)
+    }
+
+    /// Returns the position of a validator in the exit queue, or None if
+    /// it isn't waiting to leave.
+    pub fn get_exit_queue_position<BS, RT>(
+        rt: &mut RT,
+        validator: Address,
+    ) -> Result<Option<u64>, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+        let st: State = rt.state()?;
+        Ok(st.exit_queue_position(&validator))
+    }
+
+    /// Proposes to spend funds of the treasury, approving the proposal.
+    /// Only active validators can propose. Returns the CID identifying the
+    /// proposal.
+    pub fn propose_spend<BS, RT>(rt: &mut RT, params: SpendParams) -> Result<Cid, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+        if !params.amount.is_positive() {
+            return Err(actor_error!(
+                illegal_argument,
+                "the amount to spend needs to be positive"
+            ));
+        }
+
+        let caller = rt.message().caller();
+        let mut cid = None;
+        rt.transaction(|st: &mut State, rt| {
+            if !st
+                .is_active_validator(rt.store(), &caller)
+                .map_err(|_| actor_error!(illegal_state, "cannot load participation"))?
+            {
+                return Err(SubnetActorError::NotValidator(caller).into());
+            }
+            let proposal = SpendProposal {
+                to: params.to,
+                amount: params.amount,
+                proposer: caller,
+                proposed_at: rt.curr_epoch(),
+            };
+            cid = Some(st.propose_spend(rt.store(), proposal).map_err(|e| {
+                e.downcast_subnet(ExitCode::USR_ILLEGAL_ARGUMENT, "cannot propose spend")
+            })?);
+            Ok(true)
+        })?;
+
+        cid.ok_or_else(|| actor_error!(illegal_state, "spend proposal not recorded"))
+    }
+
+    /// Approves a spend proposal. Once approved by a majority of the stake,
+    /// its funds are paid from the treasury, releasing the part locked in
+    /// the gateway first if the collateral is native. Returns whether the
+    /// proposal was paid.
+    pub fn approve_spend<BS, RT>(rt: &mut RT, cid: Cid) -> Result<bool, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let caller = rt.message().caller();
+        let mut approved = None;
+        rt.transaction(|st: &mut State, rt| {
+            if !st
+                .is_active_validator(rt.store(), &caller)
+                .map_err(|_| actor_error!(illegal_state, "cannot load participation"))?
+            {
+                return Err(SubnetActorError::NotValidator(caller).into());
+            }
+            approved = st.approve_spend(rt.store(), &caller, &cid).map_err(|e| {
+                e.downcast_subnet(ExitCode::USR_ILLEGAL_ARGUMENT, "cannot approve spend")
+            })?;
+            Ok(true)
+        })?;
+
+        let (proposal, locked) = match approved {
+            Some(p) => p,
+            None => return Ok(false),
+        };
+        let st: State = rt.state()?;
+        if st.collateral_token.is_none() && st.status != Status::Terminating && !locked.is_zero() {
+            let release = CrossActorPayload::new(
+                st.ipc_gateway_addr,
+                GatewayMethod::ReleaseStake.method_num(),
+                compat::fund_params(&locked)
+                    .map_err(|_| actor_error!(illegal_state, "cannot encode fund params"))?,
+                TokenAmount::zero(),
+            );
+            send_gateway_msg(rt, release, OnGatewayFailure::Abort)?;
+        }
+        let p = refund_msg(&st, proposal.to, proposal.amount)?;
+        rt.send(p.to, p.method, p.params, p.value)?;
+
+        Ok(true)
+    }
+
+    /// Returns the spend proposals pending approval.
+    pub fn list_spend_proposals<BS, RT>(rt: &mut RT) -> Result<Vec<SpendProposalInfo>, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+        let st: State = rt.state()?;
+        st.list_spend_proposals(rt.store())
+            .map_err(|_| actor_error!(illegal_state, "cannot load spend proposals"))
+    }
+
+    /// Returns a page of the membership history of the validator set, in
+    /// the order the events happened.
+    pub fn get_membership_history<BS, RT>(
+        rt: &mut RT,
+        params: ListParams,
+    ) -> Result<MembershipHistoryReturn, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let st: State = rt.state()?;
+        let (events, next_cursor) = st
+            .list_membership_history(
+                rt.store(),
+                params.cursor,
+                std::cmp::min(params.limit, MAX_PAGE_LIMIT),
+            )
+            .map_err(|e| {
+                e.downcast_subnet(ExitCode::USR_ILLEGAL_STATE, "failed to list membership")
+            })?;
+
+        Ok(MembershipHistoryReturn {
+            events,
+            next_cursor,
+            total: st.membership_events,
+        })
+    }
+
+    /// Returns the checkpoint windows of the subnet, for nodes to share
+    /// the window math of the actor through the `checkpoint` module.
+    pub fn get_checkpoint_windows<BS, RT>(rt: &mut RT) -> Result<CheckpointWindows, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+        let st: State = rt.state()?;
+        Ok(st.checkpoint_windows())
+    }
+
+    /// Dry run of `Join`, checking whether `validator` can join with
+    /// `amount` of collateral, and the invitation of invitation-only
+    /// subnets, without changing the state.
+    pub fn can_join<BS, RT>(rt: &mut RT, params: CanJoinParams) -> Result<DryRunReturn, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+        let st: State = rt.state()?;
+        Ok(Self::check_can_join(rt, &st, &params).into())
+    }
+
+    fn check_can_join<BS, RT>(rt: &RT, st: &State, params: &CanJoinParams) -> Result<(), ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        st.caller_policies.join.check(rt, &params.validator)?;
+        if params.amount.is_zero() {
+            return Err(actor_error!(
+                illegal_argument,
+                "a minimum collateral is required to join the subnet"
+            ));
+        }
+        let validator = resolve_id_address(rt, &params.validator)?;
+        let via_token = st.collateral_token.is_some();
+        let fee = check_join(st, rt, &validator, &params.amount, via_token)?;
+        let commitment = params
+            .invitation
+            .as_ref()
+            .map(|secret| invitation_commitment(rt, secret, &validator));
+        required_invitation(st, rt, &validator, commitment.as_ref())?;
+        st.capped_stake(rt.store(), &validator, &(&params.amount - &fee))
+            .map_err(|e| {
+                e.downcast_subnet(ExitCode::USR_ILLEGAL_ARGUMENT, "cannot stake collateral")
+            })?;
+        Ok(())
+    }
+
+    /// Dry run of `Leave`, or of `WithdrawStake` of `amount`, checking
+    /// whether `validator` can withdraw its stake without changing the
+    /// state. Leaving beyond the exit or churn limits passes, as the
+    /// validator is queued.
+    pub fn can_leave<BS, RT>(
+        rt: &mut RT,
+        params: CanLeaveParams,
+    ) -> Result<DryRunReturn, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+        let st: State = rt.state()?;
+        Ok(Self::check_can_leave(rt, &st, &params).into())
+    }
+
+    fn check_can_leave<BS, RT>(
+        rt: &RT,
+        st: &State,
+        params: &CanLeaveParams,
+    ) -> Result<(), ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        st.caller_policies.leave.check(rt, &params.validator)?;
+        let validator = resolve_id_address(rt, &params.validator)?;
+        match &params.amount {
+            Some(amount) => {
+                st.check_withdraw_stake(rt.store(), &validator, amount, rt.curr_epoch())
+                    .map_err(|e| {
+                        e.downcast_subnet(ExitCode::USR_ILLEGAL_ARGUMENT, "cannot withdraw stake")
+                    })?;
+            }
+            None => {
+                leaving_stake(st, rt.store(), &validator, rt.curr_epoch())?;
+                if st.exit_queue_position(&validator).is_some() {
+                    return Err(SubnetActorError::ExitPending(validator).into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Dry run of `SubmitCheckpoint`, or of `SubmitCatchUpCheckpoint` for
+    /// windows after the next one, checking whether `validator` can submit
+    /// a checkpoint for the window ending at `epoch`. The contents of the
+    /// checkpoint and its signature aren't checked.
+    pub fn can_submit_checkpoint<BS, RT>(
+        rt: &mut RT,
+        params: CanSubmitCheckpointParams,
+    ) -> Result<DryRunReturn, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+        let st: State = rt.state()?;
+        Ok(Self::check_can_submit_checkpoint(rt, &st, &params).into())
+    }
+
+    fn check_can_submit_checkpoint<BS, RT>(
+        rt: &RT,
+        st: &State,
+        params: &CanSubmitCheckpointParams,
+    ) -> Result<(), ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        st.caller_policies
+            .submit_checkpoint
+            .check(rt, &params.validator)?;
+        let validator = resolve_id_address(rt, &params.validator)?;
+        let validator = st
+            .validator_for_caller(rt.store(), &validator)
+            .map_err(|_| actor_error!(illegal_state, "cannot load workers"))?;
+        if !st
+            .is_active_validator(rt.store(), &validator)
+            .map_err(|_| actor_error!(illegal_state, "cannot load participation"))?
+        {
+            return Err(SubnetActorError::NotValidator(validator).into());
+        }
+        // submissions beyond the cap fail or are penalized, either way
+        // they shouldn't be sent
+        if st
+            .submissions_exhausted(rt.store(), &validator, rt.curr_epoch())
+            .map_err(|_| actor_error!(illegal_state, "cannot load submissions"))?
+        {
+            return Err(SubnetActorError::TooManySubmissions(validator).into());
+        }
+
+        let catch_up = params.epoch > st.next_checkpoint_epoch();
+        st.check_checkpoint_epoch(rt.store(), params.epoch, rt.curr_epoch(), catch_up)
+            .map_err(|e| e.downcast_subnet(ExitCode::USR_ILLEGAL_STATE, "checkpoint failed"))?;
+
+        // no checkpoint has the default CID, so any vote of the validator
+        // in the window conflicts with it, unless the votes expired
+        if !st.votes_expired(params.epoch, rt.curr_epoch())
+            && st
+                .conflicting_vote(rt.store(), params.epoch, &Cid::default(), &validator)
+                .map_err(|_| actor_error!(illegal_state, "cannot load candidates"))?
+                .is_some()
+        {
+            return Err(SubnetActorError::AlreadyVoted(validator).into());
+        }
+        Ok(())
+    }
+
+    /// Sets the code CIDs the IPC gateway can be moved to. Only the owner
+    /// can set them.
+    pub fn set_gateway_codes<BS, RT>(
+        rt: &mut RT,
+        codes: Vec<Cid>,
+    ) -> Result<Option<RawBytes>, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        validate_owner(rt)?;
+
+        rt.transaction(|st: &mut State, _| {
+            st.gateway_codes = codes;
+            Ok(true)
+        })?;
+
+        Ok(None)
+    }
+
+    /// Sets the code CIDs the subnet actor can be upgraded to. Only the IPC
+    /// gateway can commit them, for all the subnets of the parent.
+    pub fn set_upgrade_codes<BS, RT>(
+        rt: &mut RT,
+        codes: Vec<Cid>,
+    ) -> Result<Option<RawBytes>, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        let st: State = rt.state()?;
+        rt.validate_immediate_caller_is(std::iter::once(&st.ipc_gateway_addr))?;
+
+        rt.transaction(|st: &mut State, _| {
+            st.upgrade_codes = codes;
+            Ok(true)
+        })?;
+
+        Ok(None)
+    }
+
+    /// Upgrades the subnet actor to `code`, one of the codes committed by
+    /// the IPC gateway with `SetUpgradeCodes`, migrating its state to the
+    /// schema of the new code. Only the owner can upgrade the actor.
+    ///
+    /// The runtime doesn't let actors replace their own code, which is
+    /// swapped by the network in place, keeping the address the subnet is
+    /// registered with. The upgrade completes the swap once the actor runs
+    /// `code`, so the state is only migrated to allowed codes.
+    pub fn upgrade<BS, RT>(rt: &mut RT, code: Cid) -> Result<Option<RawBytes>, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        validate_owner(rt)?;
+
+        let st: State = rt.state()?;
+        if !st.upgrade_codes.contains(&code) {
+            return Err(SubnetActorError::UpgradeCodeNotAllowed(code).into());
+        }
+        let running = rt
+            .message()
+            .receiver()
+            .id()
+            .ok()
+            .and_then(|id| rt.get_actor_code_cid(&id));
+        if running != Some(code) {
+            return Err(actor_error!(
+                illegal_state,
+                "the actor doesn't run code {} yet",
+                code
+            ));
+        }
+
+        rt.transaction(|st: &mut State, rt| {
+            let from_version = st.version;
+            migrations::migrate_state(rt.store(), st, from_version).map_err(|e| {
+                e.downcast_subnet(ExitCode::USR_ILLEGAL_STATE, "failed to migrate state")
+            })?;
+            Ok(true)
+        })?;
+
+        Ok(None)
+    }
+
+    /// Points the subnet to the IPC gateway redeployed at `gateway`. Only
+    /// the owner can move it, to a gateway running one of the codes set
+    /// with `SetGatewayCodes`.
+    ///
+    /// A registered subnet registers again in the new gateway, with the
+    /// collateral it accounts. The collateral locked in the old gateway
+    /// can't be released from it, so the owner sends it along, less the
+    /// stake of the calls queued for the old gateway, which the actor still
+    /// holds and which are dropped. The other queued calls are retried with
+    /// the new gateway. Subnets not registered yet register in the new
+    /// gateway when they gather enough collateral. The new gateway is
+    /// notified of the power table again, and needs to be deployed on the
+    /// parent.
+    pub fn update_gateway_addr<BS, RT>(
+        rt: &mut RT,
+        gateway: Address,
+    ) -> Result<Option<RawBytes>, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        validate_owner(rt)?;
+
+        let gateway_id = resolve_id_address(rt, &gateway)?;
+        let st: State = rt.state()?;
+        if gateway_id == st.ipc_gateway_addr {
+            return Err(actor_error!(
+                illegal_argument,
+                "the subnet already points to the gateway"
+            ));
+        }
+        let code = gateway_id
+            .id()
+            .ok()
+            .and_then(|id| rt.get_actor_code_cid(&id));
+        if !code.map_or(false, |c| st.gateway_codes.contains(&c)) {
+            return Err(SubnetActorError::GatewayCodeNotAllowed(gateway).into());
+        }
+        let parent_checked = check_gateway_network(rt, gateway_id, &st.parent_id)?;
+
+        let value = rt.message().value_received();
+        let msg = rt.transaction(|st: &mut State, _| {
+            st.ipc_gateway_addr = gateway_id;
+            st.parent_checked_gateway = parent_checked.then_some(gateway_id);
+            st.ipc_gateway_robust_addr = if gateway.protocol() == Protocol::ID {
+                None
+            } else {
+                Some(gateway)
+            };
+            st.notified_power_table = None;
+
+            let mut held = TokenAmount::zero();
+            let mut calls = Vec::new();
+            for mut call in std::mem::take(&mut st.pending_gateway_calls) {
+                if call.method == GatewayMethod::Register.method_num()
+                    || call.method == GatewayMethod::AddStake.method_num()
+                {
+                    held += call.value;
+                } else {
+                    call.to = gateway_id;
+                    calls.push(call);
+                }
+            }
+            st.pending_gateway_calls = calls;
+
+            let locked = if !st.registered_with_gateway {
+                TokenAmount::zero()
+            } else if st.collateral_token.is_some() {
+                stake_math::checked_sub(&st.registration_bond, &held)?
+            } else {
+                stake_math::checked_sub(&st.total_stake, &held)?
+            };
+            if value != locked {
+                return Err(actor_error!(
+                    illegal_argument,
+                    "the collateral locked in the old gateway needs to be sent: {}",
+                    locked
+                ));
+            }
+            st.registered_with_gateway = false;
+            Ok(stake_gateway_msg(st, &TokenAmount::zero()))
+        })?;
+
+        if let Some(p) = msg {
+            send_gateway_msg(rt, p, OnGatewayFailure::Abort)?;
+        }
+        notify_validator_set(rt)?;
+
+        Ok(None)
+    }
+
+    /// Returns the economic parameters of the subnet.
+    pub fn get_econ_params<BS, RT>(rt: &mut RT) -> Result<EconParams, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+        let st: State = rt.state()?;
+        Ok(st.econ)
+    }
+
+    /// Votes for new economic parameters of the subnet, replacing the
+    /// current ones once voted by a majority of the stake. Only active
+    /// validators can vote. Returns whether the parameters were applied.
+    ///
+    /// The status of the subnet follows a new minimum total collateral,
+    /// registering it in the gateway if it now gathers enough collateral.
+    pub fn propose_econ_params<BS, RT>(rt: &mut RT, params: EconParams) -> Result<bool, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let caller = rt.message().caller();
+        let mut applied = false;
+        let mut msg = None;
+        rt.transaction(|st: &mut State, rt| {
+            params
+                .validate(st.collateral_token.as_ref())
+                .map_err(|e| actor_error!(illegal_argument, "{}", e))?;
+            if !st
+                .is_active_validator(rt.store(), &caller)
+                .map_err(|_| actor_error!(illegal_state, "cannot load participation"))?
+            {
+                return Err(SubnetActorError::NotValidator(caller).into());
+            }
+            applied = st
+                .vote_econ_params(rt.store(), &caller, params)
+                .map_err(|e| {
+                    e.downcast_subnet(
+                        ExitCode::USR_ILLEGAL_ARGUMENT,
+                        "cannot vote economic parameters",
+                    )
+                })?;
+            if applied {
+                if !st.registered_with_gateway {
+                    msg = stake_gateway_msg(st, &TokenAmount::zero());
+                }
+                st.update_status(rt.curr_epoch(), caller);
+            }
+            Ok(true)
+        })?;
+
+        if let Some(p) = msg {
+            send_stake_msg(rt, p)?;
+        }
+
+        Ok(applied)
+    }
+}
+
+/// Returns the message notifying the IPC gateway about `amount` of new stake
+/// in the subnet: the subnet is registered once it gathers enough collateral,
+/// and stake is added to the gateway from then on, even if the subnet went
+/// inactive in between.
+fn stake_gateway_msg(st: &mut State, amount: &TokenAmount) -> Option<CrossActorPayload> {
+    // token collateral is held by the subnet actor and not locked in the
+    // gateway, which holds the registration bond of the subnet instead
+    let via_token = st.collateral_token.is_some();
+    if st.registered_with_gateway {
+        if via_token {
+            return None;
+        }
+        return Some(CrossActorPayload::new(
+            st.ipc_gateway_addr,
+            GatewayMethod::AddStake.method_num(),
+            RawBytes::default(),
+            amount.clone(),
+        ));
+    }
+    if st.total_stake < st.econ.min_total_collateral {
+        return None;
+    }
+    // token subnets constructed before registration bonds can't register
+    if via_token && st.registration_bond.is_zero() {
+        return None;
+    }
+    st.registered_with_gateway = true;
+    let collateral = if via_token {
+        st.registration_bond.clone()
+    } else {
+        st.total_stake.clone()
+    };
+    Some(CrossActorPayload::new(
+        st.ipc_gateway_addr,
+        GatewayMethod::Register.method_num(),
+        RawBytes::default(),
+        collateral,
+    ))
+}
+
+/// Sends a message syncing the stake of the subnet with the IPC gateway.
+///
+/// A call rejected by the gateway doesn't revert the change of stake that
+/// triggered it: it is queued in the state, along with the calls following
+/// it to keep their order, until `RetryGatewayCalls` gets them through.
+/// Releases of stake are the exception, as the refunds following them need
+/// the released collateral. Messages to other actors, like refunds, are
+/// sent right away.
+fn send_stake_msg<BS, RT>(rt: &mut RT, p: CrossActorPayload) -> Result<(), ActorError>
+where
+    BS: Blockstore,
+    RT: Runtime<BS>,
+{
+    let st: State = rt.state()?;
+    if p.to != st.ipc_gateway_addr {
+        rt.send(p.to, p.method, p.params, p.value)?;
+        return Ok(());
+    }
+    let on_failure = if p.method == GatewayMethod::ReleaseStake.method_num() {
+        OnGatewayFailure::Abort
+    } else {
+        OnGatewayFailure::Record
+    };
+    send_gateway_msg(rt, p, on_failure)
+}
+
+/// What to do with a message when the call to the IPC gateway it makes
+/// fails.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum OnGatewayFailure {
+    /// Aborts the message with the [`SubnetActorError`] the failure maps
+    /// to, reverting its changes.
+    Abort,
+    /// Keeps the changes of the message, queueing the call to be retried
+    /// with `RetryGatewayCalls`.
+    Record,
+}
+
+/// Sends a message to the IPC gateway, handling its failure as
+/// `on_failure` says. Recorded calls are also queued while earlier ones
+/// wait to be retried, so they reach the gateway in order. Registrations
+/// are followed by the genesis checkpoint of subnets anchoring it.
+fn send_gateway_msg<BS, RT>(
+    rt: &mut RT,
+    p: CrossActorPayload,
+    on_failure: OnGatewayFailure,
+) -> Result<(), ActorError>
+where
+    BS: Blockstore,
+    RT: Runtime<BS>,
+{
+    let register = p.method == GatewayMethod::Register.method_num();
+    if register {
+        check_parent(rt)?;
+    }
+    deliver_gateway_msg(rt, p, on_failure)?;
+    // the genesis checkpoint follows the registration, queued after it if
+    // the registration was
+    if register {
+        anchor_genesis(rt, on_failure)?;
+    }
+    Ok(())
+}
+
+/// Sends a message to the IPC gateway, or queues it for retry, see
+/// `send_gateway_msg`.
+fn deliver_gateway_msg<BS, RT>(
+    rt: &mut RT,
+    p: CrossActorPayload,
+    on_failure: OnGatewayFailure,
+) -> Result<(), ActorError>
+where
+    BS: Blockstore,
+    RT: Runtime<BS>,
+{
+    let st: State = rt.state()?;
+    let queued = !st.pending_gateway_calls.is_empty();
+    if !(queued && on_failure == OnGatewayFailure::Record) {
+        let err = match rt.send(p.to, p.method, p.params.clone(), p.value.clone()) {
+            Ok(_) => return Ok(()),
+            Err(e) => e,
+        };
+        if on_failure == OnGatewayFailure::Abort {
+            let mapped = SubnetActorError::from_gateway(p.method, err.exit_code());
+            return Err(ActorError::unchecked(
+                mapped.exit_code(),
+                format!("{}: {}", mapped, err.msg()),
+            ));
+        }
+    }
+    rt.transaction(|st: &mut State, _| {
+        if st.pending_gateway_calls.len() >= MAX_PENDING_GATEWAY_CALLS {
+            return Err(SubnetActorError::GatewayQueueFull(p.method).into());
+        }
+        st.pending_gateway_calls.push(GatewayCall {
+            to: p.to,
+            method: p.method,
+            params: p.params,
+            value: p.value,
+        });
+        Ok(true)
+    })?;
+    Ok(())
+}
+
+/// Commits the genesis checkpoint of subnets anchoring their genesis, once
+/// they register in the gateway. See `State::build_genesis_checkpoint`.
+fn anchor_genesis<BS, RT>(rt: &mut RT, on_failure: OnGatewayFailure) -> Result<(), ActorError>
+where
+    BS: Blockstore,
+    RT: Runtime<BS>,
+{
+    let st: State = rt.state()?;
+    if !st.anchor_genesis || st.genesis_checkpoint.is_some() {
+        return Ok(());
+    }
+    let subnet = compat::subnet_id(&st.parent_id, rt.message().receiver())
+        .map_err(|_| actor_error!(illegal_state, "cannot build subnet ID"))?;
+    let msg = rt.transaction(|st: &mut State, rt| {
+        let genesis = st
+            .build_genesis_checkpoint(rt.store(), subnet)
+            .map_err(|e| {
+                e.downcast_subnet(
+                    ExitCode::USR_ILLEGAL_STATE,
+                    "cannot build genesis checkpoint",
+                )
+            })?;
+        let msg = relay_checkpoint_msg(st, &genesis.checkpoint)?;
+        st.genesis_checkpoint = Some(genesis.checkpoint);
+        st.last_state_commitment = Some(genesis.state_commitment);
+        Ok(msg)
+    })?;
+    deliver_gateway_msg(rt, msg, on_failure)
+}
+
+/// Checks the parent of the subnet is the network the IPC gateway reports
+/// being deployed on, before registering with it, so the subnet can't be
+/// registered with the gateway of another IPC deployment. Each gateway is
+/// only asked once.
+fn check_parent<BS, RT>(rt: &mut RT) -> Result<(), ActorError>
+where
+    BS: Blockstore,
+    RT: Runtime<BS>,
+{
+    let st: State = rt.state()?;
+    if st.parent_checked_gateway == Some(st.ipc_gateway_addr) {
+        return Ok(());
+    }
+    if check_gateway_network(rt, st.ipc_gateway_addr, &st.parent_id)? {
+        rt.transaction(|st: &mut State, _| {
+            st.parent_checked_gateway = Some(st.ipc_gateway_addr);
+            Ok(true)
+        })?;
+    }
+    Ok(())
+}
+
+/// Fails if `gateway` reports being deployed on a network other than
+/// `parent`. Returns whether it reported its network: gateways predating
+/// `NetworkName` are trusted.
+fn check_gateway_network<BS, RT>(
+    rt: &mut RT,
+    gateway: Address,
+    parent: &SubnetID,
+) -> Result<bool, ActorError>
+where
+    BS: Blockstore,
+    RT: Runtime<BS>,
+{
+    let ret = match rt.send(
+        gateway,
+        gateway::NETWORK_NAME_METHOD,
+        RawBytes::default(),
+        TokenAmount::zero(),
+    ) {
+        Ok(ret) => ret,
+        Err(e) if e.exit_code() == ExitCode::USR_UNHANDLED_MESSAGE => return Ok(false),
+        Err(e) => return Err(e),
+    };
+    let network: SubnetID = ret.deserialize()?;
+    if network != *parent {
+        return Err(SubnetActorError::ParentMismatch(network).into());
+    }
+    Ok(true)
+}
+
+/// Notifies the IPC gateway of a change of the power table, if the subnet
+/// opted in. A failed notification doesn't revert the change: the power
+/// table is notified again after the next one.
+fn notify_validator_set<BS, RT>(rt: &mut RT) -> Result<(), ActorError>
+where
+    BS: Blockstore,
+    RT: Runtime<BS>,
+{
+    let st: State = rt.state()?;
+    if !st.notify_validator_set || !st.registered_with_gateway {
+        return Ok(());
+    }
+    let table = st
+        .power_table(rt.store())
+        .map_err(|e| e.downcast_subnet(ExitCode::USR_ILLEGAL_STATE, "cannot build power table"))?;
+    let link = TCid::new_link(rt.store(), &table.validators)
+        .map_err(|_| actor_error!(illegal_state, "cannot store power table"))?;
+    if st.notified_power_table.as_ref().map(|l| l.cid()) == Some(link.cid()) {
+        return Ok(());
+    }
+
+    let params = RawBytes::serialize(gateway::ValidatorSetUpdatedParams {
+        config_number: st.config_number,
+        power_table: link.cid(),
+    })?;
+    if rt
+        .send(
+            st.ipc_gateway_addr,
+            gateway::VALIDATOR_SET_UPDATED_METHOD,
+            params,
+            TokenAmount::zero(),
+        )
+        .is_err()
+    {
+        return Ok(());
+    }
+    rt.transaction(|st: &mut State, _| {
+        st.notified_power_table = Some(link);
+        Ok(true)
+    })?;
+    Ok(())
+}
+
+/// Pays the relayer fee to whoever committed a checkpoint. Fees that can't
+/// be sent to the relayer go back to the reward pool.
+fn pay_relayer<BS, RT>(rt: &mut RT, relayer: Address, fee: TokenAmount) -> Result<(), ActorError>
+where
+    BS: Blockstore,
+    RT: Runtime<BS>,
+{
+    if fee.is_zero() {
+        return Ok(());
+    }
+    if rt
+        .send(relayer, METHOD_SEND, RawBytes::default(), fee.clone())
+        .is_err()
+    {
+        rt.transaction(|st: &mut State, _| {
+            st.reward_pool += fee;
+            Ok(true)
+        })?;
+    }
+    Ok(())
+}
+
+/// Checks that the subnet can still accept collateral, so that funds sent
+/// to a terminating or killed subnet are refused instead of getting stuck.
+fn check_accepts_stake(st: &State) -> Result<(), ActorError> {
+    match st.status {
+        Status::Terminating => Err(SubnetActorError::SubnetTerminating.into()),
+        Status::Killed => Err(SubnetActorError::SubnetKilled.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Returns the messages releasing `amount` of stake withdrawn by `to`, and
+/// refunding it with `refund_msg`. Token collateral is held by the subnet
+/// actor, while native collateral is first released by the gateway to the
+/// subnet actor, unless the subnet is terminating and it already was.
+fn release_stake_msg(
+    st: &State,
+    to: Address,
+    amount: &TokenAmount,
+) -> Result<Vec<CrossActorPayload>, ActorError> {
+    let mut msgs = Vec::new();
+    if st.collateral_token.is_none() && st.status != Status::Terminating {
+        msgs.push(CrossActorPayload::new(
+            st.ipc_gateway_addr,
+            GatewayMethod::ReleaseStake.method_num(),
+            compat::fund_params(amount)
+                .map_err(|_| actor_error!(illegal_state, "cannot encode fund params"))?,
+            TokenAmount::zero(),
+        ));
+    }
+    msgs.push(refund_msg(st, to, amount.clone())?);
+    Ok(msgs)
+}
+
+/// Returns the total of the stake released to each funder in `refunds`,
+/// and the messages releasing it, see `release_stake_msg`.
+fn release_stake_msgs(
+    st: &State,
+    refunds: &[(Address, TokenAmount)],
+) -> Result<(TokenAmount, Vec<CrossActorPayload>), ActorError> {
+    let mut total = TokenAmount::zero();
+    let mut msgs = Vec::new();
+    for (funder, amount) in refunds {
+        total += amount;
+        msgs.extend(release_stake_msg(st, *funder, amount)?);
+    }
+    Ok((total, msgs))
+}
+
+/// Rejects malformed network addresses of validators, which would end up
+/// in the power table read by the nodes of the subnet.
+fn check_net_addr(addr: &NetAddress) -> Result<(), ActorError> {
+    addr.validate().map_err(|e| {
+        actor_error!(
+            illegal_argument,
+            "invalid validator network address {:?}: {}",
+            addr.as_str(),
+            e
+        )
+    })
+}
+
+/// Returns the message refunding `amount` of collateral to `to`,
+/// in the collateral asset of the subnet.
+fn refund_msg(
+    st: &State,
+    to: Address,
+    amount: TokenAmount,
+) -> Result<CrossActorPayload, ActorError> {
+    match st.collateral_token {
+        Some(token) => Ok(CrossActorPayload::new(
+            token,
+            frc46::TRANSFER_METHOD,
+            RawBytes::serialize(frc46::TransferParams {
+                to,
+                amount,
+                operator_data: RawBytes::default(),
+            })?,
+            TokenAmount::zero(),
+        )),
+        None => Ok(CrossActorPayload::new(
+            to,
+            METHOD_SEND,
+            RawBytes::default(),
+            amount,
+        )),
+    }
+}
+
+/// Commits a checkpoint that gathered a majority of votes, and returns
+/// the message propagating it to the IPC gateway.
+///
+/// If the subnet has a dispute period, the checkpoint is queued instead,
+/// and relayed by `relay_checkpoints` once the period ends.
+fn commit_checkpoint<BS: Blockstore>(
+    st: &mut State,
+    store: &BS,
+    submission: &CheckpointSubmission,
+    voters: &[Address],
+    curr_epoch: ChainEpoch,
+) -> Result<Option<CrossActorPayload>, ActorError> {
+    let ch = &submission.checkpoint;
+    let expected = st.next_checkpoint_epoch();
+    let pending = PendingCheckpoint {
+        epoch: ch.epoch(),
+        relay_at: st.relay_epoch(ch.epoch(), curr_epoch),
+        voters: voters.to_vec(),
+        prev_committed_epoch: st.last_committed_epoch,
+        prev_state_commitment: st.last_state_commitment.clone(),
+    };
+    st.flush_checkpoint(store, ch, &submission.state_commitment)
+        .map_err(|_| actor_error!(illegal_state, "cannot flush checkpoint"))?;
+    st.stats.committed_checkpoints += 1;
+    st.record_participation(store, voters)
+        .map_err(|_| actor_error!(illegal_state, "cannot record participation"))?;
+    st.remove_cross_msgs(store, &ch.epoch())
+        .map_err(|_| actor_error!(illegal_state, "cannot remove cross-msgs"))?;
+
+    // the next window starts with the commitment of this one, windows
+    // skipped by a catch-up checkpoint are closed as well
+    for epoch in [expected, ch.epoch()] {
+        st.remove_weight_snapshot(store, epoch)
+            .map_err(|_| actor_error!(illegal_state, "cannot remove weight snapshot"))?;
+    }
+    st.remove_closed_candidates(store, ch.epoch())
+        .map_err(|_| actor_error!(illegal_state, "cannot remove candidates"))?;
+    // a pending change of the checkpoint period starts with the next window
+    st.apply_check_period(ch.epoch());
+    // changes queued by the churn limit go into the next window
+    st.apply_pending_changes(store)
+        .map_err(|_| actor_error!(illegal_state, "cannot apply validator changes"))?;
+    st.process_exit_queue(store)
+        .map_err(|_| actor_error!(illegal_state, "cannot process exit queue"))?;
+    st.snapshot_weights(store, st.next_checkpoint_epoch())
+        .map_err(|_| actor_error!(illegal_state, "cannot snapshot weights"))?;
+
+    if pending.relay_at > curr_epoch {
+        st.pending_checkpoints.push(pending);
+        return Ok(None);
+    }
+    Ok(Some(relay_checkpoint_msg(st, ch)?))
+}
+
+/// Message propagating a committed checkpoint to the IPC gateway.
+fn relay_checkpoint_msg(st: &State, ch: &Checkpoint) -> Result<CrossActorPayload, ActorError> {
+    Ok(CrossActorPayload::new(
+        st.ipc_gateway_addr,
+        GatewayMethod::CommitChildCheckpoint.method_num(),
+        RawBytes::serialize(ch)?,
+        TokenAmount::zero(),
+    ))
+}
+
+/// Checks that the immediate caller is the owner of the subnet.
+fn validate_owner<BS, RT>(rt: &mut RT) -> Result<(), ActorError>
+where
+    BS: Blockstore,
+    RT: Runtime<BS>,
+{
+    let st: State = rt.state()?;
+    match st.owner {
+        Some(owner) => rt.validate_immediate_caller_is(std::iter::once(&owner)),
+        None => Err(actor_error!(forbidden, "the subnet has no owner")),
+    }
+}
+
+/// Checks a join of `amount` of collateral by `caller`, returning the join
+/// fee it owes. Invitations are checked when redeemed.
+fn check_join<BS, RT>(
+    st: &State,
+    rt: &RT,
+    caller: &Address,
+    amount: &TokenAmount,
+    via_token: bool,
+) -> Result<TokenAmount, ActorError>
+where
+    BS: Blockstore,
+    RT: Runtime<BS>,
+{
+    check_accepts_stake(st)?;
+    let fee = join_fee(st, rt, caller)?;
+    if *amount <= fee {
+        return Err(SubnetActorError::JoinFeeNotCovered(fee).into());
+    }
+    if amount - &fee < st.econ.min_join_amount {
+        return Err(SubnetActorError::JoinAmountTooLow.into());
+    }
+    if st.collateral_token.is_some() != via_token {
+        return Err(actor_error!(
+            illegal_argument,
+            "collateral not denominated in the collateral asset of the subnet"
+        ));
+    }
+    Ok(fee)
+}
+
+/// Stake of `caller` and the part of it withdrawn by leaving at `epoch`,
+/// before the leaving penalty: the stake delegated to them stays locked.
+///
+/// Leaving removes the validator, so it is rejected while part of its stake
+/// is still vesting. The vested stake is withdrawn with `WithdrawStake`
+/// meanwhile.
+fn leaving_stake<BS: Blockstore>(
+    st: &State,
+    store: &BS,
+    caller: &Address,
+    epoch: ChainEpoch,
+) -> Result<(TokenAmount, TokenAmount), ActorError> {
+    let stake = st
+        .get_stake(store, caller)
+        .map_err(|e| e.downcast_subnet(ExitCode::USR_ILLEGAL_STATE, "failed to load stake"))?;
+    let stake = match stake {
+        Some(stake) if !stake.is_zero() => stake,
+        _ => return Err(actor_error!(illegal_state, "caller has no stake in subnet")),
+    };
+
+    let locked = st
+        .locked_stake(store, caller, epoch)
+        .map_err(|e| e.downcast_subnet(ExitCode::USR_ILLEGAL_STATE, "failed to load vesting"))?;
+    if locked.is_positive() {
+        return Err(SubnetActorError::StakeStillVesting(*caller).into());
+    }
+    let delegated = st.delegated_stake(store, caller).map_err(|e| {
+        e.downcast_subnet(ExitCode::USR_ILLEGAL_STATE, "failed to load delegations")
+    })?;
+    if stake <= delegated {
+        return Err(SubnetActorError::NoVestedStake(*caller).into());
+    }
+    let withdrawn = &stake - delegated;
+    Ok((stake, withdrawn))
+}
+
+/// Join fee owed by `caller`: only addresses without stake pay it, unless
+/// they are exempted from it.
+fn join_fee<BS, RT>(st: &State, rt: &RT, caller: &Address) -> Result<TokenAmount, ActorError>
+where
+    BS: Blockstore,
+    RT: Runtime<BS>,
+{
+    if st.econ.join_fee.is_zero() {
+        return Ok(TokenAmount::zero());
+    }
+    let stake = st
+        .get_stake(rt.store(), caller)
+        .map_err(|e| e.downcast_subnet(ExitCode::USR_ILLEGAL_STATE, "failed to load stake"))?;
+    if stake.map_or(false, |s| !s.is_zero()) {
+        return Ok(TokenAmount::zero());
+    }
+    // exempt addresses may not have existed when the subnet was created
+    let exempt = st.join_fee_exempt.iter().any(|addr| {
+        addr == caller || rt.resolve_address(addr).map(Address::new_id) == Some(*caller)
+    });
+    if exempt {
+        return Ok(TokenAmount::zero());
+    }
+    Ok(st.econ.join_fee.clone())
+}
+
+/// Checks that `key` is the address of a key validators can sign
+/// checkpoints with.
+fn check_validator_key(key: &Address) -> Result<(), ActorError> {
+    if !matches!(key.protocol(), Protocol::Secp256k1 | Protocol::BLS) && !eth::is_eth_address(key) {
+        return Err(actor_error!(
+            illegal_argument,
+            "validator key needs to be a secp256k1, BLS or f410 address"
+        ));
+    }
+    Ok(())
+}
+
+/// Checks that `validator` presents the invitation it needs to join an
+/// invitation-only subnet, with `commitment`. Validators already staking
+/// don't need one. Returns the commitment of the invitation to redeem, if
+/// any.
+fn required_invitation<'a, BS, RT>(
+    st: &State,
+    rt: &RT,
+    validator: &Address,
+    commitment: Option<&'a Vec<u8>>,
+) -> Result<Option<&'a Vec<u8>>, ActorError>
+where
+    BS: Blockstore,
+    RT: Runtime<BS>,
+{
+    if !st.invitation_only {
+        return Ok(None);
+    }
+    let stake = st
+        .get_stake(rt.store(), validator)
+        .map_err(|e| e.downcast_subnet(ExitCode::USR_ILLEGAL_STATE, "failed to load stake"))?;
+    if !stake.unwrap_or_else(TokenAmount::zero).is_zero() {
+        return Ok(None);
+    }
+    let commitment = commitment
+        .ok_or_else(|| actor_error!(forbidden, "an invitation is required to join the subnet"))?;
+    st.check_invitation(rt.store(), commitment)
+        .map_err(|e| e.downcast_subnet(ExitCode::USR_FORBIDDEN, "cannot redeem invitation"))?;
+    Ok(Some(commitment))
+}
+
+/// Commitment of an invitation of `invitee` with `secret`: the blake2b-256
+/// hash of the secret followed by the ID address of the invitee. Binding
+/// the invitee keeps others from redeeming the secret seen in its join.
+pub fn invitation_commitment<BS, RT>(rt: &RT, secret: &[u8], invitee: &Address) -> Vec<u8>
+where
+    BS: Blockstore,
+    RT: Runtime<BS>,
+{
+    let mut preimage = secret.to_vec();
+    preimage.extend(invitee.to_bytes());
+    rt.hash_blake2b(&preimage).to_vec()
+}
+
+/// Payload the new key of `validator` signs to rotate to it: the CBOR
+/// encoding of the validator and the configuration number of the validator
+/// set, so the signature can't be reused for another validator nor once the
+/// validator set changes.
+pub fn key_rotation_payload(validator: &Address, config_number: u64) -> anyhow::Result<Vec<u8>> {
+    Ok(fvm_ipld_encoding::to_vec(&(validator, config_number))?)
+}
+
+/// Resolves `addr` to the ID address of the actor, which is the canonical
+/// address used to key stake and votes in the state.
+pub fn resolve_id_address<BS, RT>(rt: &RT, addr: &Address) -> Result<Address, ActorError>
+where
+    BS: Blockstore,
+    RT: Runtime<BS>,
+{
+    if addr.protocol() == Protocol::ID {
+        return Ok(*addr);
+    }
+    rt.resolve_address(addr)
+        .map(Address::new_id)
+        .ok_or_else(|| actor_error!(illegal_argument, format!("cannot resolve address {}", addr)))
+}
+
+/// Dispatches the methods of the [`SubnetActor`] trait to `A`.
+///
+/// Custom subnet actors can call it from their `invoke_method` to route the
+/// methods they don't handle themselves, or get their whole `ActorCode`
+/// from [`impl_subnet_actor_code`].
+pub fn route_method<A, BS, RT>(
+    rt: &mut RT,
+    method: MethodNum,
+    params: &RawBytes,
+) -> Result<RawBytes, ActorError>
+where
+    A: SubnetActor,
+    BS: Blockstore,
+    RT: Runtime<BS>,
+{
+    match FromPrimitive::from_u64(method) {
+        Some(Method::Constructor) => {
+            A::constructor(rt, cbor::deserialize_params(params)?)?;
+            Ok(RawBytes::default())
+        }
+        Some(Method::Join) => {
+            let res = A::join(rt, cbor::deserialize_params(params)?)?;
+            Ok(RawBytes::serialize(res)?)
+        }
+        Some(Method::Leave) => {
+            let res = A::leave(rt)?;
+            Ok(RawBytes::serialize(res)?)
+        }
+        Some(Method::Kill) => {
+            let res = A::kill(rt)?;
+            Ok(RawBytes::serialize(res)?)
+        }
+        Some(Method::SubmitCheckpoint) => {
+            let res = A::submit_checkpoint(rt, cbor::deserialize_params(params)?)?;
+            Ok(RawBytes::serialize(res)?)
+        }
+        _ => Err(actor_error!(unhandled_message; "Invalid method")),
+    }
+}
+
+/// Implements `ActorCode` for a type implementing [`SubnetActor`],
+/// routing its methods through [`route_method`].
+///
+/// ```ignore
+/// struct MyActor;
+/// impl SubnetActor for MyActor { ... }
+/// ipc_subnet_actor::impl_subnet_actor_code!(MyActor);
+/// ```
+#[macro_export]
+macro_rules! impl_subnet_actor_code {
+    ($actor:ty) => {
+        impl $crate::__private::ActorCode for $actor {
+            fn invoke_method<BS, RT>(
+                rt: &mut RT,
+                method: $crate::__private::MethodNum,
+                params: &$crate::__private::RawBytes,
+            ) -> Result<$crate::__private::RawBytes, $crate::__private::ActorError>
+            where
+                BS: $crate::__private::Blockstore,
+                RT: $crate::__private::Runtime<BS>,
+            {
+                $crate::route_method::<Self, BS, RT>(rt, method, params)
+            }
+        }
+    };
+}
+
+/// Re-exports used by [`impl_subnet_actor_code`].
+#[doc(hidden)]
+pub mod __private {
+    pub use fil_actors_runtime::runtime::{ActorCode, Runtime};
+    pub use fil_actors_runtime::ActorError;
+    pub use fvm_ipld_blockstore::Blockstore;
+    pub use fvm_ipld_encoding::RawBytes;
+    pub use fvm_shared::MethodNum;
+}
+
+#[cfg(feature = "fil-actor")]
+impl ActorCode for Actor {
+    fn invoke_method<BS, RT>(
+        rt: &mut RT,
+        method: MethodNum,
+        params: &RawBytes,
+    ) -> Result<RawBytes, ActorError>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        let method_id: Option<Method> = FromPrimitive::from_u64(method);
+        if method_id.is_some_and(|m| m.is_pausable()) {
+            let st: State = rt.state()?;
+            if st.paused {
+                return Err(SubnetActorError::Paused.into());
+            }
+        }
+
+        let ret = match method_id {
+            Some(Method::IssueInvitations) => {
+                let res = Self::issue_invitations(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::RegisterCrossMsg) => {
+                let res = Self::register_cross_msg(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::PutCrossMsgBatch) => {
+                let res = Self::put_cross_msg_batch(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::SubmitAggregatedCheckpoint) => {
+                let res =
+                    Self::submit_aggregated_checkpoint(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::MigrateState) => {
+                let res = Self::migrate_state(rt)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::AddStake) => {
+                let res = Self::add_stake(rt)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::Receive) => {
+                let res = Self::token_received(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::SubmitCatchUpCheckpoint) => {
+                let res = Self::submit_catch_up_checkpoint(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::NextCheckpointEpoch) => {
+                let res = Self::next_checkpoint_epoch(rt)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::Unjail) => {
+                let res = Self::unjail(rt)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::SetMetadata) => {
+                let res = Self::set_metadata(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::GetStakeByRobustAddress) => {
+                let res = Self::get_stake_by_robust_address(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::BootstrapValidators) => {
+                let res = Self::bootstrap_validators(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::ListStakers) => {
+                let res = Self::list_stakers(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::ListCheckpoints) => {
+                let res = Self::list_checkpoints(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::DistributeFees) => {
+                let res = Self::distribute_fees(rt)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::GetLastStateCommitment) => {
+                let res = Self::get_last_state_commitment(rt)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::RelayCheckpoints) => {
+                let res = Self::relay_checkpoints(rt)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::ChallengeCheckpoint) => {
+                let res = Self::challenge_checkpoint(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::RotateValidatorKey) => {
+                let res = Self::rotate_validator_key(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::GetPowerTable) => {
+                let res = Self::get_power_table(rt)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::CompactStake) => {
+                let res = Self::compact_stake(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::SubmitParentFinality) => {
+                let res = Self::submit_parent_finality(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::GetParentFinality) => {
+                let res = Self::get_parent_finality(rt)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::GetCheckpointVotingStatus) => {
+                let res =
+                    Self::get_checkpoint_voting_status(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::Pause) => {
+                let res = Self::pause(rt)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::Unpause) => {
+                let res = Self::unpause(rt)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::Delegate) => {
+                let res = Self::delegate(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::Undelegate) => {
+                let res = Self::undelegate(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::SetCommissionRate) => {
+                let res = Self::set_commission_rate(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::ReleaseStakeFor) => {
+                let res = Self::release_stake_for(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::TransferOwnership) => {
+                let res = Self::transfer_ownership(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::RenounceOwnership) => {
+                let res = Self::renounce_ownership(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::FundRewardPool) => {
+                let res = Self::fund_reward_pool(rt)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::GetGenesisEpoch) => {
+                let res = Self::get_genesis_epoch(rt)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::GetStats) => {
+                let res = Self::get_stats(rt)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::SetCheckPeriod) => {
+                let res = Self::set_check_period(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::GetMembershipTicket) => {
+                let res = Self::get_membership_ticket(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::GetCheckpointProof) => {
+                let res = Self::get_checkpoint_proof(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::Heartbeat) => {
+                let res = Self::heartbeat(rt)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::GetLiveValidators) => {
+                let res = Self::get_live_validators(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::GetFinalityConfig) => {
+                let res = Self::get_finality_config(rt)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::WithdrawStake) => {
+                let res = Self::withdraw_stake(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::GetValidatorSetAt) => {
+                let res = Self::get_validator_set_at(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::SetValidatorSetNotifications) => {
+                let res =
+                    Self::set_validator_set_notifications(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::GetCheckpointTemplate) => {
+                let res = Self::get_checkpoint_template(rt)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::RetryGatewayCalls) => {
+                let res = Self::retry_gateway_calls(rt)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::GetExitQueuePosition) => {
+                let res = Self::get_exit_queue_position(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::ProposeSpend) => {
+                let res = Self::propose_spend(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::ApproveSpend) => {
+                let res = Self::approve_spend(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::ListSpendProposals) => {
+                let res = Self::list_spend_proposals(rt)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::GetMembershipHistory) => {
+                let res = Self::get_membership_history(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::GetCheckpointWindows) => {
+                let res = Self::get_checkpoint_windows(rt)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::Redelegate) => {
+                let res = Self::redelegate(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::CanJoin) => {
+                let res = Self::can_join(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::CanLeave) => {
+                let res = Self::can_leave(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::CanSubmitCheckpoint) => {
+                let res = Self::can_submit_checkpoint(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::SetGatewayCodes) => {
+                let res = Self::set_gateway_codes(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::UpdateGatewayAddr) => {
+                let res = Self::update_gateway_addr(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::GetEconParams) => {
+                let res = Self::get_econ_params(rt)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::ProposeEconParams) => {
+                let res = Self::propose_econ_params(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::ListCheckpointsInRange) => {
+                let res = Self::list_checkpoints_in_range(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::GetDelegatedAddress) => {
+                let res = Self::get_delegated_address(rt)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::SubmitEvidence) => {
+                let res = Self::submit_evidence(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::GetSubnetSummary) => {
+                let res = Self::get_subnet_summary(rt)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::SetUpgradeCodes) => {
+                let res = Self::set_upgrade_codes(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            Some(Method::Upgrade) => {
+                let res = Self::upgrade(rt, cbor::deserialize_params(params)?)?;
+                Ok(RawBytes::serialize(res)?)
+            }
+            _ => route_method::<Self, BS, RT>(rt, method, params),
+        };
+
+        if ret.is_ok() && method_id.is_some_and(|m| m.changes_validator_set()) {
+            notify_validator_set(rt)?;
+        }
+
+        // catch inconsistencies where they are introduced in debug builds
+        #[cfg(debug_assertions)]
+        if ret.is_ok() {
+            let st: State = rt.state()?;
+            let report = st.check_invariants(rt.store()).map_err(|e| {
+                e.downcast_subnet(ExitCode::USR_ILLEGAL_STATE, "cannot check state")
+            })?;
+            if !report.is_ok() {
+                return Err(actor_error!(illegal_state, report.to_string()));
+            }
+        }
+
+        ret
     }
 }
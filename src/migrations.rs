@@ -0,0 +1,586 @@
+use anyhow::anyhow;
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::RawBytes;
+use fvm_ipld_hamt::BytesKey;
+use fvm_shared::address::Address;
+use fvm_shared::econ::TokenAmount;
+use num_traits::Zero;
+use primitives::TCid;
+
+use crate::compat::{Checkpoint, MIN_COLLATERAL_AMOUNT};
+use crate::state::{keys, State};
+use crate::types::{
+    Funding, GatewayCall, MembershipEventKind, MembershipTicket, ParentFinality, Status,
+};
+use crate::validator_set::ValidatorSet;
+use crate::voting::Voting;
+
+/// Version of the state schema of the current code. It needs to be
+/// bumped, and a migration from the previous version added to
+/// `migrate_state`, whenever a change in the schema of the state can't
+/// be handled by defaulting the new fields.
+pub const STATE_VERSION: u64 = 34;
+
+/// Migrates the state from `from_version` to [`STATE_VERSION`] applying
+/// every intermediate migration in order.
+///
+/// Migrations need to be deterministic, as they are run on-chain as part
+/// of the execution of the actor.
+pub fn migrate_state<BS: Blockstore>(
+    store: &BS,
+    st: &mut State,
+    from_version: u64,
+) -> anyhow::Result<()> {
+    if from_version > STATE_VERSION {
+        return Err(anyhow!(
+            "state version {} is newer than the supported version {}",
+            from_version,
+            STATE_VERSION
+        ));
+    }
+
+    // earlier migrations read the validator set, so the one written before
+    // version 34 is indexed before running them
+    index_legacy_validators(store, st)?;
+
+    for version in from_version..STATE_VERSION {
+        match version {
+            0 => migrate_v0_to_v1(store, st)?,
+            1 => migrate_v1_to_v2(store, st)?,
+            2 => migrate_v2_to_v3(store, st)?,
+            3 => migrate_v3_to_v4(store, st)?,
+            4 => migrate_v4_to_v5(store, st)?,
+            5 => migrate_v5_to_v6(store, st)?,
+            6 => migrate_v6_to_v7(store, st)?,
+            7 => migrate_v7_to_v8(store, st)?,
+            8 => migrate_v8_to_v9(store, st)?,
+            9 => migrate_v9_to_v10(store, st)?,
+            10 => migrate_v10_to_v11(store, st)?,
+            11 => migrate_v11_to_v12(store, st)?,
+            12 => migrate_v12_to_v13(store, st)?,
+            13 => migrate_v13_to_v14(store, st)?,
+            14 => migrate_v14_to_v15(store, st)?,
+            15 => migrate_v15_to_v16(store, st)?,
+            16 => migrate_v16_to_v17(store, st)?,
+            17 => migrate_v17_to_v18(store, st)?,
+            18 => migrate_v18_to_v19(store, st)?,
+            19 => migrate_v19_to_v20(store, st)?,
+            20 => migrate_v20_to_v21(store, st)?,
+            21 => migrate_v21_to_v22(store, st)?,
+            22 => migrate_v22_to_v23(store, st)?,
+            23 => migrate_v23_to_v24(store, st)?,
+            24 => migrate_v24_to_v25(store, st)?,
+            25 => migrate_v25_to_v26(store, st)?,
+            26 => migrate_v26_to_v27(store, st)?,
+            27 => migrate_v27_to_v28(store, st)?,
+            28 => migrate_v28_to_v29(store, st)?,
+            29 => migrate_v29_to_v30(store, st)?,
+            30 => migrate_v30_to_v31(store, st)?,
+            31 => migrate_v31_to_v32(store, st)?,
+            32 => migrate_v32_to_v33(store, st)?,
+            33 => migrate_v33_to_v34(store, st)?,
+            v => return Err(anyhow!("no migration available from version {}", v)),
+        }
+    }
+    st.version = STATE_VERSION;
+
+    Ok(())
+}
+
+/// Version 0 states were deployed before invitations and bottom-up
+/// message batches were introduced, so their HAMTs need to be created.
+fn migrate_v0_to_v1<BS: Blockstore>(store: &BS, st: &mut State) -> anyhow::Result<()> {
+    if st.invitations.cid() == Cid::default() {
+        st.invitations = TCid::new_hamt(store)?;
+    }
+    if st.cross_msgs.cid() == Cid::default() {
+        st.cross_msgs = TCid::new_hamt(store)?;
+    }
+    Ok(())
+}
+
+/// Version 2 tracks the epoch of the last committed checkpoint instead of
+/// walking back checkpoint windows, so it is recovered from the committed
+/// checkpoints.
+fn migrate_v1_to_v2<BS: Blockstore>(store: &BS, st: &mut State) -> anyhow::Result<()> {
+    let checkpoints = legacy_checkpoints(store, st)?;
+    st.last_committed_epoch = checkpoints.iter().map(|ch| ch.epoch()).max().unwrap_or(0);
+    Ok(())
+}
+
+/// Version 3 tracks the participation of validators in checkpoint windows.
+fn migrate_v2_to_v3<BS: Blockstore>(store: &BS, st: &mut State) -> anyhow::Result<()> {
+    if st.participation.cid() == Cid::default() {
+        st.participation = TCid::new_hamt(store)?;
+    }
+    Ok(())
+}
+
+/// Version 4 keys checkpoints and bottom-up message batches by the canonical
+/// big-endian epoch encoding of [`keys::epoch_key`] instead of the native
+/// byte order, so their HAMTs are rebuilt with the new keys.
+fn migrate_v3_to_v4<BS: Blockstore>(store: &BS, st: &mut State) -> anyhow::Result<()> {
+    let checkpoints = legacy_checkpoints(store, st)?;
+    let mut rebuilt = TCid::new_hamt(store)?;
+    rebuilt.modify(store, |hamt| {
+        for ch in checkpoints {
+            hamt.set(keys::epoch_key(ch.epoch()), ch)?;
+        }
+        Ok(true)
+    })?;
+    st.legacy_checkpoints = Some(rebuilt);
+
+    let mut batches = Vec::new();
+    st.cross_msgs.load(store)?.for_each(|k, msgs| {
+        batches.push((keys::parse_legacy_epoch_key(&k.0)?, msgs.clone()));
+        Ok(())
+    })?;
+    st.cross_msgs = TCid::new_hamt(store)?;
+    st.cross_msgs.modify(store, |hamt| {
+        for (epoch, msgs) in batches {
+            hamt.set(keys::epoch_key(epoch), msgs)?;
+        }
+        Ok(true)
+    })?;
+
+    Ok(())
+}
+
+/// Version 5 snapshots the weights of the validators for each window.
+fn migrate_v4_to_v5<BS: Blockstore>(store: &BS, st: &mut State) -> anyhow::Result<()> {
+    if st.weight_snapshots.cid() == Cid::default() {
+        st.weight_snapshots = TCid::new_hamt(store)?;
+    }
+    Ok(())
+}
+
+/// Version 6 keeps count of the stakers and committed checkpoints, which
+/// are counted once from the existing entries.
+fn migrate_v5_to_v6<BS: Blockstore>(store: &BS, st: &mut State) -> anyhow::Result<()> {
+    let mut stakers = 0;
+    st.stake.load(store)?.for_each(|_, stake| {
+        if !stake.is_zero() {
+            stakers += 1;
+        }
+        Ok(())
+    })?;
+    st.stakers_count = stakers;
+
+    st.checkpoints_count = legacy_checkpoints(store, st)?.len() as u64;
+
+    Ok(())
+}
+
+/// Version 7 lets validators rotate their signing key.
+fn migrate_v6_to_v7<BS: Blockstore>(store: &BS, st: &mut State) -> anyhow::Result<()> {
+    if st.validator_keys.cid() == Cid::default() {
+        st.validator_keys = TCid::new_hamt(store)?;
+    }
+    Ok(())
+}
+
+/// Version 8 lets validators vest their stake.
+fn migrate_v7_to_v8<BS: Blockstore>(store: &BS, st: &mut State) -> anyhow::Result<()> {
+    if st.vesting.cid() == Cid::default() {
+        st.vesting = TCid::new_hamt(store)?;
+    }
+    Ok(())
+}
+
+/// Version 9 lets validators vote the finality of the parent. Their votes
+/// are kept in `finality_votes` since version 31, created by its migration.
+fn migrate_v8_to_v9<BS: Blockstore>(_store: &BS, _st: &mut State) -> anyhow::Result<()> {
+    Ok(())
+}
+
+/// Version 10 penalizes validators submitting invalid checkpoints.
+fn migrate_v9_to_v10<BS: Blockstore>(store: &BS, st: &mut State) -> anyhow::Result<()> {
+    if st.penalties.cid() == Cid::default() {
+        st.penalties = TCid::new_hamt(store)?;
+    }
+    Ok(())
+}
+
+/// Version 11 indexes the checkpoints voted in each window.
+fn migrate_v10_to_v11<BS: Blockstore>(store: &BS, st: &mut State) -> anyhow::Result<()> {
+    if st.candidates.cid() == Cid::default() {
+        st.candidates = TCid::new_hamt(store)?;
+    }
+    Ok(())
+}
+
+/// Version 12 tracks the registration in the gateway, which subnets that
+/// gathered the minimum collateral already went through.
+fn migrate_v11_to_v12<BS: Blockstore>(_store: &BS, st: &mut State) -> anyhow::Result<()> {
+    st.registered_with_gateway = st.status != Status::Instantiated;
+    Ok(())
+}
+
+/// Version 13 lets stakers delegate stake to validators.
+fn migrate_v12_to_v13<BS: Blockstore>(store: &BS, st: &mut State) -> anyhow::Result<()> {
+    if st.delegations.cid() == Cid::default() {
+        st.delegations = TCid::new_hamt(store)?;
+    }
+    Ok(())
+}
+
+/// Version 14 keeps the validator set in a HAMT keyed by address, so the
+/// validators of the legacy list are moved to it in the same order.
+fn migrate_v13_to_v14<BS: Blockstore>(store: &BS, st: &mut State) -> anyhow::Result<()> {
+    if st.validators.cid() == Cid::default() {
+        st.validators = ValidatorSet::new(store)?;
+    }
+    for v in std::mem::take(&mut st.legacy_validator_set) {
+        let stake = st
+            .get_stake(store, &v.addr)?
+            .unwrap_or_else(TokenAmount::zero);
+        st.validators.insert(store, v, stake)?;
+    }
+    Ok(())
+}
+
+/// Version 15 issues membership tickets to validators when admitted, so
+/// the validators of the current configuration get theirs, effective
+/// since the last committed checkpoint.
+fn migrate_v14_to_v15<BS: Blockstore>(store: &BS, st: &mut State) -> anyhow::Result<()> {
+    if st.membership_tickets.cid() == Cid::default() {
+        st.membership_tickets = TCid::new_hamt(store)?;
+    }
+    let validators = st.validators.addrs().copied().collect::<Vec<_>>();
+    let (config_number, effective_epoch) = (st.config_number, st.last_committed_epoch);
+    st.membership_tickets.modify(store, |hamt| {
+        for validator in validators {
+            let ticket = MembershipTicket {
+                validator,
+                config_number,
+                effective_epoch,
+            };
+            hamt.set(BytesKey::from(validator.to_bytes()), ticket)?;
+        }
+        Ok(true)
+    })?;
+    Ok(())
+}
+
+/// Version 16 logs committed checkpoints by window number, so the existing
+/// ones are logged in order of epoch.
+fn migrate_v15_to_v16<BS: Blockstore>(store: &BS, st: &mut State) -> anyhow::Result<()> {
+    let mut checkpoints = legacy_checkpoints(store, st)?;
+    checkpoints.sort_by_key(|ch| ch.epoch());
+
+    st.checkpoints = TCid::new_amt(store)?;
+    st.checkpoints.modify(store, |amt| {
+        for (window, ch) in checkpoints.into_iter().enumerate() {
+            amt.set(window as u64, ch)?;
+        }
+        Ok(true)
+    })?;
+    Ok(())
+}
+
+/// Version 17 records the last epoch at which validators were seen.
+fn migrate_v16_to_v17<BS: Blockstore>(store: &BS, st: &mut State) -> anyhow::Result<()> {
+    if st.last_seen.cid() == Cid::default() {
+        st.last_seen = TCid::new_hamt(store)?;
+    }
+    Ok(())
+}
+
+/// Version 18 records the validators of each configuration of the
+/// validator set, and the configuration checkpoints are committed in.
+/// Earlier configurations are unknown, so only the current one is
+/// recorded.
+fn migrate_v17_to_v18<BS: Blockstore>(store: &BS, st: &mut State) -> anyhow::Result<()> {
+    if st.validator_sets.cid() == Cid::default() {
+        st.validator_sets = TCid::new_amt(store)?;
+    }
+    if st.checkpoint_configs.cid() == Cid::default() {
+        st.checkpoint_configs = TCid::new_amt(store)?;
+    }
+    st.record_validator_set(store)
+}
+
+/// Version 19 separates the total collateral activating the subnet from
+/// the minimum stake of validators. Subnets were activated by the
+/// `MIN_COLLATERAL_AMOUNT` of the gateway until then.
+fn migrate_v18_to_v19<BS: Blockstore>(_store: &BS, st: &mut State) -> anyhow::Result<()> {
+    if st
+        .legacy_min_total_collateral
+        .as_ref()
+        .map_or(true, Zero::is_zero)
+    {
+        st.legacy_min_total_collateral = Some(TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT));
+    }
+    Ok(())
+}
+
+/// Version 20 counts the checkpoints submitted by each validator in the
+/// open window.
+fn migrate_v19_to_v20<BS: Blockstore>(store: &BS, st: &mut State) -> anyhow::Result<()> {
+    if st.submissions.cid() == Cid::default() {
+        st.submissions = TCid::new_hamt(store)?;
+    }
+    Ok(())
+}
+
+/// Version 21 adds the treasury, funded with the stake penalized until then.
+fn migrate_v20_to_v21<BS: Blockstore>(store: &BS, st: &mut State) -> anyhow::Result<()> {
+    if st.spend_proposals.cid() == Cid::default() {
+        st.spend_proposals = TCid::new_hamt(store)?;
+    }
+    if st.spend_votes.cid() == Cid::default() {
+        st.spend_votes = Voting::new(store)?;
+    }
+    st.treasury = st.penalized_stake.clone();
+    Ok(())
+}
+
+/// Version 22 logs the changes of the membership of the validator set.
+/// Earlier changes are unknown, so the log starts with the current
+/// validators joining, sorted by address.
+fn migrate_v21_to_v22<BS: Blockstore>(store: &BS, st: &mut State) -> anyhow::Result<()> {
+    if st.membership_log.cid() == Cid::default() {
+        st.membership_log = TCid::new_amt(store)?;
+    }
+    let mut validators = st.validators.addrs().copied().collect::<Vec<_>>();
+    validators.sort_by_key(|a| a.to_bytes());
+    for addr in validators {
+        st.log_membership_event(store, MembershipEventKind::Join, &addr)?;
+    }
+    Ok(())
+}
+
+/// Version 23 records the redelegations of delegators for their cooldown.
+fn migrate_v22_to_v23<BS: Blockstore>(store: &BS, st: &mut State) -> anyhow::Result<()> {
+    if st.redelegations.cid() == Cid::default() {
+        st.redelegations = TCid::new_hamt(store)?;
+    }
+    Ok(())
+}
+
+/// Version 24 indexes the validators by the worker submitting their
+/// checkpoints.
+fn migrate_v23_to_v24<BS: Blockstore>(store: &BS, st: &mut State) -> anyhow::Result<()> {
+    if st.workers.cid() == Cid::default() {
+        st.workers = TCid::new_hamt(store)?;
+    }
+    Ok(())
+}
+
+/// Version 25 keeps the economic parameters of the subnet in `econ`, so
+/// they are moved from the top-level fields they were written to, and
+/// lets the validators vote for new ones.
+fn migrate_v24_to_v25<BS: Blockstore>(store: &BS, st: &mut State) -> anyhow::Result<()> {
+    if st.econ_votes.cid() == Cid::default() {
+        st.econ_votes = Voting::new(store)?;
+    }
+    if let Some(stake) = st.legacy_min_validator_stake.take() {
+        st.econ.min_validator_stake = stake;
+    }
+    if let Some(collateral) = st.legacy_min_total_collateral.take() {
+        st.econ.min_total_collateral = collateral;
+    }
+    if let Some(amount) = st.legacy_min_join_amount.take() {
+        st.econ.min_join_amount = amount;
+    }
+    if let Some(stake) = st.legacy_max_validator_stake.take() {
+        st.econ.max_validator_stake = stake;
+    }
+    if let Some(fee) = st.legacy_join_fee.take() {
+        st.econ.join_fee = fee;
+    }
+    if let Some(fee) = st.legacy_relayer_fee.take() {
+        st.econ.relayer_fee = fee;
+    }
+    if let Some(rate) = st.legacy_checkpoint_penalty_rate.take() {
+        st.econ.checkpoint_penalty_rate = rate;
+    }
+    if let Some(coeff) = st.legacy_leaving_coeff.take() {
+        st.econ.leaving_coeff = coeff;
+    }
+    Ok(())
+}
+
+/// Version 26 only keeps the committed checkpoints logged by window number
+/// since version 16, so the HAMT keying them by epoch is dropped.
+fn migrate_v25_to_v26<BS: Blockstore>(_: &BS, st: &mut State) -> anyhow::Result<()> {
+    st.legacy_checkpoints = None;
+    Ok(())
+}
+
+/// Version 27 keeps the funding ledger of each staker. Stake delegated to
+/// a staker is funded by its delegators, up to its stake, and the rest by
+/// the staker itself.
+fn migrate_v26_to_v27<BS: Blockstore>(store: &BS, st: &mut State) -> anyhow::Result<()> {
+    if st.funding.cid() != Cid::default() {
+        return Ok(());
+    }
+    let mut stakes = Vec::new();
+    st.stake.load(store)?.for_each(|k, stake| {
+        if !stake.is_zero() {
+            stakes.push((Address::from_bytes(&k.0)?, stake.clone()));
+        }
+        Ok(())
+    })?;
+
+    let mut ledgers = Vec::new();
+    for (addr, stake) in stakes {
+        let mut ledger = Vec::new();
+        let mut left = stake;
+        for d in st
+            .get_delegations(store, &addr)?
+            .unwrap_or_default()
+            .delegators
+        {
+            let amount = std::cmp::min(d.amount, left.clone());
+            if !amount.is_zero() {
+                left -= &amount;
+                ledger.push(Funding {
+                    funder: d.delegator,
+                    amount,
+                });
+            }
+        }
+        if !left.is_zero() {
+            ledger.push(Funding {
+                funder: addr,
+                amount: left,
+            });
+        }
+        ledgers.push((addr, ledger));
+    }
+
+    st.funding = TCid::new_hamt(store)?;
+    st.funding.modify(store, |hamt| {
+        for (addr, ledger) in ledgers {
+            hamt.set(BytesKey::from(addr.to_bytes()), ledger)?;
+        }
+        Ok(true)
+    })?;
+    Ok(())
+}
+
+/// Version 28 tracks the validators registering batches of bottom-up
+/// messages. Batches registered before it aren't counted against anyone.
+fn migrate_v27_to_v28<BS: Blockstore>(store: &BS, st: &mut State) -> anyhow::Result<()> {
+    if st.cross_msg_registrants.cid() == Cid::default() {
+        st.cross_msg_registrants = TCid::new_hamt(store)?;
+    }
+    Ok(())
+}
+
+/// Version 29 indexes the stakers to list them from a cursor. Existing
+/// stakers are indexed in the iteration order of the stake HAMT.
+fn migrate_v28_to_v29<BS: Blockstore>(store: &BS, st: &mut State) -> anyhow::Result<()> {
+    if st.staker_index.cid() != Cid::default() {
+        return Ok(());
+    }
+    st.staker_index = TCid::new_amt(store)?;
+    st.staker_positions = TCid::new_hamt(store)?;
+    st.staker_index_len = 0;
+
+    let mut stakers = Vec::new();
+    st.stake.load(store)?.for_each(|k, stake| {
+        if !stake.is_zero() {
+            stakers.push(Address::from_bytes(&k.0)?);
+        }
+        Ok(())
+    })?;
+    for addr in stakers {
+        st.index_staker(store, &addr)?;
+    }
+    Ok(())
+}
+
+/// Version 30 adds the fees that couldn't be distributed to the reward
+/// pool instead of keeping them aside.
+fn migrate_v29_to_v30<BS: Blockstore>(_store: &BS, st: &mut State) -> anyhow::Result<()> {
+    if let Some(dust) = st.legacy_fee_dust.take() {
+        st.reward_pool += dust;
+    }
+    Ok(())
+}
+
+/// Version 31 keeps the votes for parent finalities in a `Voting`, keyed
+/// by the CID of the finality instead of its CBOR encoding.
+fn migrate_v30_to_v31<BS: Blockstore>(store: &BS, st: &mut State) -> anyhow::Result<()> {
+    if st.finality_votes.cid() == Cid::default() {
+        st.finality_votes = Voting::new(store)?;
+    }
+    let legacy = match st.legacy_finality_votes.take() {
+        Some(legacy) => legacy,
+        None => return Ok(()),
+    };
+    let mut votes = Vec::new();
+    legacy.load(store)?.for_each(|k, v| {
+        let finality: ParentFinality = RawBytes::deserialize(&k.0.clone().into())?;
+        votes.push((finality, v.clone()));
+        Ok(())
+    })?;
+    for (finality, v) in votes {
+        let cid = TCid::new_link(store, &finality)?.cid();
+        st.finality_votes.set(store, &cid, v)?;
+    }
+    Ok(())
+}
+
+/// Version 32 keeps the gateway each queued call is addressed to, which
+/// was always the current one before.
+fn migrate_v31_to_v32<BS: Blockstore>(_store: &BS, st: &mut State) -> anyhow::Result<()> {
+    if let Some(legacy) = st.legacy_pending_gateway_calls.take() {
+        st.pending_gateway_calls = legacy
+            .into_iter()
+            .map(|c| GatewayCall {
+                to: st.ipc_gateway_addr,
+                method: c.method,
+                params: c.params,
+                value: c.value,
+            })
+            .collect();
+    }
+    Ok(())
+}
+
+/// Version 33 records the offences slashed on evidence of misbehavior.
+fn migrate_v32_to_v33<BS: Blockstore>(store: &BS, st: &mut State) -> anyhow::Result<()> {
+    if st.slashed_offences.cid() == Cid::default() {
+        st.slashed_offences = TCid::new_hamt(store)?;
+    }
+    Ok(())
+}
+
+/// Version 34 caches the stake of each validator in the index of the
+/// validator set, to move a single validator when its stake changes. The
+/// index is built by `index_legacy_validators` before any migration.
+fn migrate_v33_to_v34<BS: Blockstore>(_store: &BS, _st: &mut State) -> anyhow::Result<()> {
+    Ok(())
+}
+
+/// Indexes the validator set of versions 14 to 33 with the stake of its
+/// validators, ordered by it.
+fn index_legacy_validators<BS: Blockstore>(store: &BS, st: &mut State) -> anyhow::Result<()> {
+    let legacy = match st.legacy_validators.take() {
+        Some(legacy) => legacy,
+        None => return Ok(()),
+    };
+    let stakes = st.stake.load(store)?;
+    st.validators = ValidatorSet::from_legacy(legacy, |addr| {
+        let stake = stakes.get(&BytesKey::from(addr.to_bytes()))?;
+        Ok(stake.cloned().unwrap_or_else(TokenAmount::zero))
+    })?;
+    Ok(())
+}
+
+/// Committed checkpoints in the HAMT keyed by epoch of versions before 26,
+/// in its iteration order.
+fn legacy_checkpoints<BS: Blockstore>(store: &BS, st: &State) -> anyhow::Result<Vec<Checkpoint>> {
+    let mut checkpoints = Vec::new();
+    if let Some(hamt) = &st.legacy_checkpoints {
+        hamt.load(store)?.for_each(|_, ch| {
+            checkpoints.push(ch.clone());
+            Ok(())
+        })?;
+    }
+    Ok(checkpoints)
+}
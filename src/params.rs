@@ -0,0 +1,354 @@
+//! Construction of the parameters of the actor outside of it, for the IPC
+//! agent and deployment scripts.
+//!
+//! ```ignore
+//! let params = ConstructParamsBuilder::new(parent, "subnet", gateway)
+//!     .with_consensus(ConsensusType::Mir)
+//!     .with_owner(owner)
+//!     .build()?;
+//! ```
+use cid::Cid;
+use fvm_ipld_encoding::RawBytes;
+use fvm_shared::address::{Address, Protocol};
+use fvm_shared::clock::ChainEpoch;
+use fvm_shared::econ::TokenAmount;
+use multihash::{Code, MultihashDigest};
+use num_traits::Zero;
+use thiserror::Error;
+
+use crate::caller_policy::CallerPolicies;
+use crate::compat::{SubnetID, DEFAULT_CHECKPOINT_PERIOD, MIN_COLLATERAL_AMOUNT};
+use crate::ext::init::Exec4Params;
+use crate::sig::SigScheme;
+use crate::stake_math::StakeRatio;
+use crate::types::{
+    ConsensusType, ConstructParams, EconParams, ExcessStakePolicy, JoinFeePolicy,
+    PENALTY_RATE_DENOMINATOR,
+};
+
+/// Length of the subaddress of the delegated address of subnet actors.
+pub const SUBADDRESS_LEN: usize = 20;
+
+/// Reasons for construction parameters to be rejected.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ConstructParamsError {
+    #[error("the IPC gateway address needs to be an actor address")]
+    GatewayNotActor,
+    #[error("the relayer fee can't be negative")]
+    NegativeRelayerFee,
+    #[error("the minimum validator stake can't be negative")]
+    NegativeMinValidatorStake,
+    #[error(
+        "the minimum total collateral can't be below the {} required by the gateway",
+        TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT)
+    )]
+    MinTotalCollateralTooLow,
+    #[error(
+        "the checkpoint penalty rate can't exceed {} basis points",
+        PENALTY_RATE_DENOMINATOR
+    )]
+    PenaltyRateTooHigh,
+    #[error("the checkpoint period can't be below {}", DEFAULT_CHECKPOINT_PERIOD)]
+    CheckPeriodTooShort,
+    #[error("the maximum validator stake is below the minimum validator stake")]
+    MaxValidatorStakeTooLow,
+    #[error("the join fee can't be negative")]
+    NegativeJoinFee,
+    #[error("join fees aren't supported with a collateral token")]
+    JoinFeeWithCollateralToken,
+    #[error("the leaving coefficient needs to be in [0, 1]")]
+    InvalidLeavingCoeff,
+    #[error("checkpoint signatures can only be skipped with the `testing` feature")]
+    AcceptAllSigScheme,
+    #[error("the maximum voting power of a validator needs to be in (0, 100] percent")]
+    InvalidMaxVotingPower,
+    #[error("subnets with a collateral token need an owner to refund their registration bond")]
+    TokenCollateralWithoutOwner,
+}
+
+impl ConstructParams {
+    /// Checks the parameters rejected by the constructor of the actor.
+    pub fn validate(&self) -> Result<(), ConstructParamsError> {
+        if matches!(
+            self.ipc_gateway_addr.protocol(),
+            Protocol::Secp256k1 | Protocol::BLS
+        ) {
+            return Err(ConstructParamsError::GatewayNotActor);
+        }
+        self.econ_params()
+            .validate(self.collateral_token.as_ref())?;
+        if self.sig_scheme == SigScheme::AcceptAll && !cfg!(feature = "testing") {
+            return Err(ConstructParamsError::AcceptAllSigScheme);
+        }
+        if self
+            .max_voting_power_pct
+            .map_or(false, |pct| pct == 0 || pct > 100)
+        {
+            return Err(ConstructParamsError::InvalidMaxVotingPower);
+        }
+        if self.collateral_token.is_some() && self.owner.is_none() {
+            return Err(ConstructParamsError::TokenCollateralWithoutOwner);
+        }
+        Ok(())
+    }
+
+    /// Economic parameters the subnet is constructed with, replacing the
+    /// zero minimum stakes by the `MIN_COLLATERAL_AMOUNT` of the gateway.
+    pub fn econ_params(&self) -> EconParams {
+        let or_min_collateral = |amount: &TokenAmount| {
+            if amount.is_zero() {
+                TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT)
+            } else {
+                amount.clone()
+            }
+        };
+        EconParams {
+            min_validator_stake: or_min_collateral(&self.min_validator_stake),
+            min_total_collateral: or_min_collateral(&self.min_total_collateral),
+            min_join_amount: self.min_join_amount.clone(),
+            max_validator_stake: self.max_validator_stake.clone(),
+            join_fee: self.join_fee.clone(),
+            relayer_fee: self.relayer_fee.clone(),
+            checkpoint_penalty_rate: self.checkpoint_penalty_rate,
+            leaving_coeff: self.leaving_coeff.clone(),
+        }
+    }
+
+    /// Subaddress of the delegated address of the subnet actor: the first
+    /// 20 bytes of the Blake2b-256 hash of the CBOR encoding of its parent
+    /// and name, which are unique among the subnets of the parent.
+    pub fn subaddress(&self) -> anyhow::Result<Vec<u8>> {
+        let bytes = fvm_ipld_encoding::to_vec(&(&self.parent, &self.name))?;
+        Ok(Code::Blake2b256.digest(&bytes).digest()[..SUBADDRESS_LEN].to_vec())
+    }
+
+    /// Delegated address the subnet actor gets when deployed through
+    /// `Exec4` by the actor of `delegated_namespace`, known before the
+    /// deployment.
+    pub fn delegated_address(&self) -> anyhow::Result<Option<Address>> {
+        let namespace = match self.delegated_namespace {
+            Some(namespace) => namespace,
+            None => return Ok(None),
+        };
+        Ok(Some(Address::new_delegated(
+            namespace,
+            &self.subaddress()?,
+        )?))
+    }
+
+    /// Parameters of the `Exec4` call to the init actor deploying the
+    /// subnet actor of code `code_cid` with its delegated address. It
+    /// needs to be sent by the actor of `delegated_namespace`.
+    pub fn exec4_params(&self, code_cid: Cid) -> anyhow::Result<Exec4Params> {
+        Ok(Exec4Params {
+            code_cid,
+            constructor_params: RawBytes::serialize(self)?,
+            subaddress: RawBytes::new(self.subaddress()?),
+        })
+    }
+}
+
+impl EconParams {
+    /// Checks the parameters of a subnet with the given collateral token,
+    /// at construction and when they are proposed.
+    pub fn validate(&self, collateral_token: Option<&Address>) -> Result<(), ConstructParamsError> {
+        if self.relayer_fee.is_negative() {
+            return Err(ConstructParamsError::NegativeRelayerFee);
+        }
+        if self.min_validator_stake.is_negative() {
+            return Err(ConstructParamsError::NegativeMinValidatorStake);
+        }
+        if self.min_total_collateral < TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT) {
+            return Err(ConstructParamsError::MinTotalCollateralTooLow);
+        }
+        if self.checkpoint_penalty_rate > PENALTY_RATE_DENOMINATOR {
+            return Err(ConstructParamsError::PenaltyRateTooHigh);
+        }
+        if !self.max_validator_stake.is_zero()
+            && self.max_validator_stake < self.min_validator_stake
+        {
+            return Err(ConstructParamsError::MaxValidatorStakeTooLow);
+        }
+        if self.join_fee.is_negative() {
+            return Err(ConstructParamsError::NegativeJoinFee);
+        }
+        if !self.join_fee.is_zero() && collateral_token.is_some() {
+            return Err(ConstructParamsError::JoinFeeWithCollateralToken);
+        }
+        if StakeRatio::new(self.leaving_coeff.numer, self.leaving_coeff.denom).is_err() {
+            return Err(ConstructParamsError::InvalidLeavingCoeff);
+        }
+        Ok(())
+    }
+}
+
+/// Default minimum number of validators of a consensus. BFT consensuses
+/// need 4 validators to tolerate a faulty one.
+fn default_min_validators(consensus: ConsensusType) -> u64 {
+    match consensus {
+        ConsensusType::Tendermint | ConsensusType::Mir => 4,
+        _ => 1,
+    }
+}
+
+/// Default epochs for a block of the parent to be considered final by a
+/// consensus. BFT consensuses are final right away, while probabilistic
+/// ones need to wait for forks to settle.
+fn default_finality_threshold(consensus: ConsensusType) -> ChainEpoch {
+    match consensus {
+        ConsensusType::FilecoinEC => 900,
+        ConsensusType::PoW => 100,
+        _ => 0,
+    }
+}
+
+/// Builds [`ConstructParams`] from defaults, checking them as the actor
+/// would before they are sent.
+///
+/// Unlike the constructor, which silently replaces some values, the
+/// builder sets the replacement values explicitly, and rejects a
+/// checkpoint period below the `DEFAULT_CHECKPOINT_PERIOD` of the gateway
+/// instead of raising it.
+pub struct ConstructParamsBuilder {
+    params: ConstructParams,
+    min_validators: Option<u64>,
+    finality_threshold: Option<ChainEpoch>,
+}
+
+impl ConstructParamsBuilder {
+    /// Starts from a subnet with Mir consensus, the minimum collateral of
+    /// the gateway and its default checkpoint period. The minimum number
+    /// of validators and the finality threshold default to those of the
+    /// consensus.
+    pub fn new(parent: SubnetID, name: impl Into<String>, ipc_gateway_addr: Address) -> Self {
+        Self {
+            params: ConstructParams {
+                parent,
+                name: name.into(),
+                ipc_gateway_addr,
+                consensus: ConsensusType::Mir,
+                min_validator_stake: TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT),
+                min_validators: 0,
+                finality_threshold: 0,
+                check_period: DEFAULT_CHECKPOINT_PERIOD,
+                genesis: Vec::new(),
+                owner: None,
+                invitation_only: false,
+                collateral_token: None,
+                max_missed_windows: 0,
+                chain_id: 0,
+                description: String::new(),
+                metadata_url: None,
+                max_validator_stake: TokenAmount::zero(),
+                excess_stake_policy: ExcessStakePolicy::default(),
+                dispute_period: 0,
+                min_join_amount: TokenAmount::zero(),
+                caller_policies: CallerPolicies::default(),
+                checkpoint_penalty_rate: 0,
+                churn_limit: 0,
+                relayer_fee: TokenAmount::zero(),
+                min_total_collateral: TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT),
+                max_submissions_per_window: 0,
+                max_exits_per_window: 0,
+                join_fee: TokenAmount::zero(),
+                join_fee_policy: JoinFeePolicy::default(),
+                join_fee_exempt: Vec::new(),
+                leaving_coeff: StakeRatio::default(),
+                sig_scheme: SigScheme::default(),
+                max_voting_power_pct: None,
+                delegated_namespace: None,
+                anchor_genesis: false,
+            },
+            min_validators: None,
+            finality_threshold: None,
+        }
+    }
+
+    pub fn with_consensus(mut self, consensus: ConsensusType) -> Self {
+        self.params.consensus = consensus;
+        self
+    }
+
+    pub fn with_min_validators(mut self, min_validators: u64) -> Self {
+        self.min_validators = Some(min_validators);
+        self
+    }
+
+    pub fn with_finality_threshold(mut self, finality_threshold: ChainEpoch) -> Self {
+        self.finality_threshold = Some(finality_threshold);
+        self
+    }
+
+    pub fn with_check_period(mut self, check_period: ChainEpoch) -> Self {
+        self.params.check_period = check_period;
+        self
+    }
+
+    /// Sets the minimum stake of validators, raising the total collateral
+    /// activating the subnet if it is lower.
+    pub fn with_min_validator_stake(mut self, stake: TokenAmount) -> Self {
+        if self.params.min_total_collateral < stake {
+            self.params.min_total_collateral = stake.clone();
+        }
+        self.params.min_validator_stake = stake;
+        self
+    }
+
+    pub fn with_min_total_collateral(mut self, collateral: TokenAmount) -> Self {
+        self.params.min_total_collateral = collateral;
+        self
+    }
+
+    pub fn with_owner(mut self, owner: Address) -> Self {
+        self.params.owner = Some(owner);
+        self
+    }
+
+    pub fn with_genesis(mut self, genesis: Vec<u8>) -> Self {
+        self.params.genesis = genesis;
+        self
+    }
+
+    /// Anchors the genesis in the parent with a genesis checkpoint, see
+    /// `ConstructParams::anchor_genesis`.
+    pub fn with_genesis_anchor(mut self) -> Self {
+        self.params.anchor_genesis = true;
+        self
+    }
+
+    /// Takes the collateral of the subnet in `token`. These subnets need an
+    /// owner, and a registration bond in native funds sent along with the
+    /// construction.
+    pub fn with_collateral_token(mut self, token: Address) -> Self {
+        self.params.collateral_token = Some(token);
+        self
+    }
+
+    pub fn with_chain_id(mut self, chain_id: u64) -> Self {
+        self.params.chain_id = chain_id;
+        self
+    }
+
+    /// Overrides any other parameter.
+    pub fn with_params(mut self, f: impl FnOnce(&mut ConstructParams)) -> Self {
+        f(&mut self.params);
+        self
+    }
+
+    /// Fills the defaults of the consensus and checks the parameters.
+    pub fn build(self) -> Result<ConstructParams, ConstructParamsError> {
+        let mut params = self.params;
+        params.min_validators = self
+            .min_validators
+            .unwrap_or_else(|| default_min_validators(params.consensus));
+        params.finality_threshold = self
+            .finality_threshold
+            .unwrap_or_else(|| default_finality_threshold(params.consensus));
+
+        params.validate()?;
+        if params.check_period < DEFAULT_CHECKPOINT_PERIOD {
+            return Err(ConstructParamsError::CheckPeriodTooShort);
+        }
+        Ok(params)
+    }
+}
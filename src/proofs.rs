@@ -0,0 +1,95 @@
+//! Inclusion proofs of the data of the actor, for light clients on the
+//! parent to verify it against the state root of the actor.
+//!
+//! A proof is the list of IPLD blocks on the path from a root to the block
+//! holding the data. Clients check that each block hashes to its CID and
+//! links to the next one.
+use anyhow::anyhow;
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::tuple::{Deserialize_tuple, Serialize_tuple};
+use fvm_ipld_encoding::{BytesDe, RawBytes};
+use serde::de::IgnoredAny;
+
+/// IPLD block along with its CID.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct IpldBlock {
+    pub cid: Cid,
+    pub data: RawBytes,
+}
+
+/// Root of an AMT, as encoded by `fvm_ipld_amt`.
+#[derive(Deserialize_tuple)]
+struct AmtRoot {
+    bit_width: u32,
+    height: u32,
+    _count: u64,
+    node: AmtNode,
+}
+
+/// Node of an AMT, as encoded by `fvm_ipld_amt`. Only the links are
+/// needed to walk down the tree.
+#[derive(Deserialize_tuple)]
+struct AmtNode {
+    bitmap: BytesDe,
+    links: Vec<Cid>,
+    _values: IgnoredAny,
+}
+
+/// Loads the block of `cid` from the store.
+pub fn load_block<BS: Blockstore>(store: &BS, cid: &Cid) -> anyhow::Result<IpldBlock> {
+    let data = store
+        .get(cid)?
+        .ok_or_else(|| anyhow!("block {} not found", cid))?;
+    Ok(IpldBlock {
+        cid: *cid,
+        data: RawBytes::new(data),
+    })
+}
+
+/// Returns the blocks on the path from the root of the AMT at `root` to
+/// the leaf holding the value at `index`, starting with the root.
+pub fn amt_path<BS: Blockstore>(
+    store: &BS,
+    root: &Cid,
+    index: u64,
+) -> anyhow::Result<Vec<IpldBlock>> {
+    let block = load_block(store, root)?;
+    let root: AmtRoot = fvm_ipld_encoding::from_slice(block.data.bytes())?;
+    let mut blocks = vec![block];
+
+    let width = 1u64
+        .checked_shl(root.bit_width)
+        .ok_or_else(|| anyhow!("invalid AMT bit width: {}", root.bit_width))?;
+    let mut node = root.node;
+    let mut index = index;
+    for height in (1..=root.height).rev() {
+        // each link of a node at `height` covers `span` values
+        let span = width
+            .checked_pow(height)
+            .ok_or_else(|| anyhow!("invalid AMT height: {}", root.height))?;
+        let slot = index / span;
+        index %= span;
+        if !is_set(&node.bitmap.0, slot) {
+            return Err(anyhow!("no value at index"));
+        }
+        let pos = (0..slot).filter(|i| is_set(&node.bitmap.0, *i)).count();
+        let link = node
+            .links
+            .get(pos)
+            .ok_or_else(|| anyhow!("AMT node missing link {}", pos))?;
+        let block = load_block(store, link)?;
+        node = fvm_ipld_encoding::from_slice(block.data.bytes())?;
+        blocks.push(block);
+    }
+    if !is_set(&node.bitmap.0, index) {
+        return Err(anyhow!("no value at index"));
+    }
+    Ok(blocks)
+}
+
+fn is_set(bitmap: &[u8], i: u64) -> bool {
+    bitmap
+        .get((i / 8) as usize)
+        .map_or(false, |b| b & (1 << (i % 8)) != 0)
+}
@@ -0,0 +1,87 @@
+//! Quorum policies deciding when the votes of the validators are enough to
+//! accept a proposal, selected by the consensus of the subnet.
+//!
+//! Votes are weighed by [`Tally::with_policy`], and a new consensus type
+//! only needs a policy here instead of changes to the voting code.
+use fvm_shared::econ::TokenAmount;
+use lazy_static::lazy_static;
+use num::rational::Ratio;
+use num::BigInt;
+use num_traits::Zero;
+
+use crate::types::{ConsensusType, WeightSnapshot};
+use crate::voting::Tally;
+
+lazy_static! {
+    /// Fraction of the stake a proposal needs with [`StakeWeighted`].
+    static ref STAKE_THRESHOLD: Ratio<BigInt> = Ratio::new(BigInt::from(2), BigInt::from(3));
+}
+
+pub trait QuorumPolicy: Sync {
+    /// Weight of the vote of a validator with `stake`.
+    fn weight(&self, stake: &TokenAmount) -> TokenAmount;
+    /// Weight of all the validators of `snapshot`.
+    fn total_weight(&self, snapshot: &WeightSnapshot) -> TokenAmount;
+    /// Whether the votes of `tally` reach the quorum.
+    fn has_quorum(&self, tally: &Tally) -> bool;
+    /// Minimum weight reaching the quorum out of the total of `tally`.
+    fn threshold(&self, tally: &Tally) -> TokenAmount;
+}
+
+/// Two thirds of the stake of the subnet, for BFT consensuses.
+pub struct StakeWeighted;
+
+impl QuorumPolicy for StakeWeighted {
+    fn weight(&self, stake: &TokenAmount) -> TokenAmount {
+        stake.clone()
+    }
+
+    fn total_weight(&self, snapshot: &WeightSnapshot) -> TokenAmount {
+        snapshot.total.clone()
+    }
+
+    fn has_quorum(&self, tally: &Tally) -> bool {
+        if tally.total <= TokenAmount::zero() {
+            return false;
+        }
+        Ratio::new(tally.weight.atto().clone(), tally.total.atto().clone()) >= *STAKE_THRESHOLD
+    }
+
+    fn threshold(&self, tally: &Tally) -> TokenAmount {
+        let (numer, denom) = (STAKE_THRESHOLD.numer(), STAKE_THRESHOLD.denom());
+        TokenAmount::from_atto((tally.total.atto() * numer + denom - 1) / denom)
+    }
+}
+
+/// More than half of the validators regardless of their stake, for
+/// consensuses where stake doesn't give voting power. Each validator
+/// weighs one atto.
+pub struct CountMajority;
+
+impl QuorumPolicy for CountMajority {
+    fn weight(&self, _stake: &TokenAmount) -> TokenAmount {
+        TokenAmount::from_atto(1)
+    }
+
+    fn total_weight(&self, snapshot: &WeightSnapshot) -> TokenAmount {
+        TokenAmount::from_atto(snapshot.weights.len())
+    }
+
+    fn has_quorum(&self, tally: &Tally) -> bool {
+        tally.total > TokenAmount::zero() && tally.weight >= self.threshold(tally)
+    }
+
+    fn threshold(&self, tally: &Tally) -> TokenAmount {
+        TokenAmount::from_atto(tally.total.atto() / 2 + 1)
+    }
+}
+
+impl ConsensusType {
+    /// Quorum policy of the votes of the validators of the consensus.
+    pub fn quorum_policy(&self) -> &'static dyn QuorumPolicy {
+        match self {
+            ConsensusType::PoW => &CountMajority,
+            _ => &StakeWeighted,
+        }
+    }
+}
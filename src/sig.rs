@@ -0,0 +1,143 @@
+//! Verification of the signatures of checkpoints.
+//!
+//! Each subnet picks the [`SigScheme`] its validators sign checkpoints
+//! with at construction. Schemes implement [`CheckpointSigVerifier`], so a
+//! new one, e.g. Ed25519 for subnets derived from Tendermint, only needs a
+//! verifier and a variant of `SigScheme`.
+use anyhow::anyhow;
+use fil_actors_runtime::runtime::Runtime;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::repr::*;
+use fvm_shared::address::{Address, Protocol};
+use fvm_shared::crypto::signature::{Signature, SignatureType};
+
+use crate::eth;
+
+/// Verifies the signature of a checkpoint by the key of a validator.
+pub trait CheckpointSigVerifier {
+    /// Verifies `sig` of `plaintext` by `key`, the key the validator signs
+    /// checkpoints with.
+    fn verify<BS, RT>(
+        &self,
+        rt: &RT,
+        sig: &Signature,
+        key: &Address,
+        plaintext: &[u8],
+    ) -> anyhow::Result<()>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>;
+}
+
+/// Verifies secp256k1 signatures, by secp256k1 keys or f410 addresses.
+pub struct Secp256k1Verifier;
+
+impl CheckpointSigVerifier for Secp256k1Verifier {
+    fn verify<BS, RT>(
+        &self,
+        rt: &RT,
+        sig: &Signature,
+        key: &Address,
+        plaintext: &[u8],
+    ) -> anyhow::Result<()>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        if sig.sig_type != SignatureType::Secp256k1 {
+            return Err(anyhow!("checkpoints need to be signed with secp256k1"));
+        }
+        // the runtime doesn't verify signatures by f410 addresses
+        if eth::is_eth_address(key) {
+            return eth::verify_signature(rt, sig, key, plaintext);
+        }
+        if key.protocol() != Protocol::Secp256k1 {
+            return Err(anyhow!("not a secp256k1 key: {}", key));
+        }
+        rt.verify_signature(sig, key, plaintext)?;
+        Ok(())
+    }
+}
+
+/// Verifies BLS signatures by BLS keys.
+pub struct BlsVerifier;
+
+impl CheckpointSigVerifier for BlsVerifier {
+    fn verify<BS, RT>(
+        &self,
+        rt: &RT,
+        sig: &Signature,
+        key: &Address,
+        plaintext: &[u8],
+    ) -> anyhow::Result<()>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        if sig.sig_type != SignatureType::BLS {
+            return Err(anyhow!("checkpoints need to be signed with BLS"));
+        }
+        if key.protocol() != Protocol::BLS {
+            return Err(anyhow!("not a BLS key: {}", key));
+        }
+        rt.verify_signature(sig, key, plaintext)?;
+        Ok(())
+    }
+}
+
+/// Accepts any signature. Only for tests, see [`SigScheme::AcceptAll`].
+pub struct AcceptAllVerifier;
+
+impl CheckpointSigVerifier for AcceptAllVerifier {
+    fn verify<BS, RT>(&self, _: &RT, _: &Signature, _: &Address, _: &[u8]) -> anyhow::Result<()>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        Ok(())
+    }
+}
+
+/// Signature scheme of the checkpoints of a subnet, set at construction.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default, Deserialize_repr, Serialize_repr)]
+#[repr(u64)]
+pub enum SigScheme {
+    /// Secp256k1 or BLS, whichever the key of each validator is, and
+    /// secp256k1 for f410 addresses.
+    #[default]
+    Native,
+    Secp256k1,
+    Bls,
+    /// Accepts any signature, to test subnets without signing their
+    /// checkpoints. Only accepted at construction with the `testing`
+    /// feature.
+    AcceptAll,
+}
+
+impl CheckpointSigVerifier for SigScheme {
+    fn verify<BS, RT>(
+        &self,
+        rt: &RT,
+        sig: &Signature,
+        key: &Address,
+        plaintext: &[u8],
+    ) -> anyhow::Result<()>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        match self {
+            SigScheme::Native if eth::is_eth_address(key) => {
+                eth::verify_signature(rt, sig, key, plaintext)
+            }
+            // the runtime checks the signature matches the type of the key
+            SigScheme::Native => {
+                rt.verify_signature(sig, key, plaintext)?;
+                Ok(())
+            }
+            SigScheme::Secp256k1 => Secp256k1Verifier.verify(rt, sig, key, plaintext),
+            SigScheme::Bls => BlsVerifier.verify(rt, sig, key, plaintext),
+            SigScheme::AcceptAll => AcceptAllVerifier.verify(rt, sig, key, plaintext),
+        }
+    }
+}
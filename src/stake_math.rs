@@ -0,0 +1,152 @@
+//! Checked arithmetic on stake amounts.
+//!
+//! Token amounts are arbitrary-precision integers, so they can't overflow,
+//! but taking more stake than available silently leaves negative balances
+//! behind. These helpers turn such accounting bugs into typed errors that
+//! abort the message instead.
+use fvm_ipld_encoding::tuple::{Deserialize_tuple, Serialize_tuple};
+use fvm_shared::econ::TokenAmount;
+use num_traits::Zero;
+
+use crate::error::SubnetActorError;
+
+/// Returns `a + b`. Fails if `b` is negative.
+pub fn checked_add(a: &TokenAmount, b: &TokenAmount) -> Result<TokenAmount, SubnetActorError> {
+    if b.is_negative() {
+        return Err(SubnetActorError::NegativeStakeAmount);
+    }
+    Ok(a + b)
+}
+
+/// Returns `a - b`. Fails if `b` is negative or larger than `a`.
+pub fn checked_sub(a: &TokenAmount, b: &TokenAmount) -> Result<TokenAmount, SubnetActorError> {
+    if b.is_negative() {
+        return Err(SubnetActorError::NegativeStakeAmount);
+    }
+    if b > a {
+        return Err(SubnetActorError::StakeUnderflow);
+    }
+    Ok(a - b)
+}
+
+/// Splits `amount` across `shares` in proportion to them. Parts are rounded
+/// down and the remainder is handed out in order, without any part going
+/// above its share. Fails if `amount` exceeds the sum of the shares.
+pub fn split_pro_rata(
+    amount: &TokenAmount,
+    shares: &[TokenAmount],
+) -> Result<Vec<TokenAmount>, SubnetActorError> {
+    if amount.is_negative() {
+        return Err(SubnetActorError::NegativeStakeAmount);
+    }
+    let total = shares.iter().fold(TokenAmount::zero(), |sum, s| sum + s);
+    if *amount > total {
+        return Err(SubnetActorError::StakeUnderflow);
+    }
+    if amount.is_zero() {
+        return Ok(vec![TokenAmount::zero(); shares.len()]);
+    }
+
+    let mut parts: Vec<TokenAmount> = shares
+        .iter()
+        .map(|s| TokenAmount::from_atto(s.atto() * amount.atto() / total.atto()))
+        .collect();
+    let mut left = parts.iter().fold(amount.clone(), |left, p| left - p);
+    for (part, share) in parts.iter_mut().zip(shares) {
+        if left.is_zero() {
+            break;
+        }
+        let extra = std::cmp::min(share - &*part, left.clone());
+        left -= &extra;
+        *part += extra;
+    }
+    Ok(parts)
+}
+
+/// Caps the voting power of `stakes` so none exceeds `max_pct` percent of
+/// the total voting power, `others` being the power of the rest of the
+/// subnet. Stake above the cap still counts as collateral, but not as
+/// voting power, and shrinks the total the cap is taken from.
+///
+/// When there are too few stakes for each to stay under `max_pct`, they
+/// all get the power of the smallest one.
+pub fn cap_voting_power(
+    stakes: &[TokenAmount],
+    others: &TokenAmount,
+    max_pct: u64,
+) -> Vec<TokenAmount> {
+    if max_pct == 0 || max_pct >= 100 || stakes.is_empty() {
+        return stakes.to_vec();
+    }
+    let mut sorted: Vec<&TokenAmount> = stakes.iter().collect();
+    sorted.sort_by(|a, b| b.cmp(a));
+
+    // capping the `k` largest stakes leaves each of them `max_pct` of the
+    // total with a cap of `max_pct * rest / (100 - max_pct * k)`, `rest`
+    // being the power of the others
+    let mut rest = stakes.iter().fold(others.clone(), |sum, s| sum + s);
+    let mut cap = sorted[sorted.len() - 1].clone();
+    for (k, stake) in sorted.iter().enumerate() {
+        let denom = 100_u64.saturating_sub(max_pct.saturating_mul(k as u64));
+        if denom == 0 {
+            break;
+        }
+        let c = TokenAmount::from_atto(rest.atto() * max_pct / denom);
+        if **stake <= c {
+            cap = c;
+            break;
+        }
+        rest -= *stake;
+    }
+    stakes
+        .iter()
+        .map(|s| if *s > cap { cap.clone() } else { s.clone() })
+        .collect()
+}
+
+/// Fraction of a stake amount, at most one.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct StakeRatio {
+    pub numer: u64,
+    pub denom: u64,
+}
+
+impl StakeRatio {
+    pub fn new(numer: u64, denom: u64) -> anyhow::Result<Self> {
+        if denom == 0 || numer > denom {
+            return Err(anyhow::anyhow!(
+                "invalid stake ratio {}/{}, it needs to be in [0, 1]",
+                numer,
+                denom
+            ));
+        }
+        Ok(Self { numer, denom })
+    }
+
+    /// Returns the fraction of `amount`, rounded down.
+    pub fn apply(&self, amount: &TokenAmount) -> TokenAmount {
+        if self.denom == 0 {
+            return TokenAmount::zero();
+        }
+        TokenAmount::from_atto(amount.atto() * self.numer / self.denom)
+    }
+
+    /// Splits `amount` into its fraction and the rest. The rest is rounded
+    /// down, so the fraction keeps the remainder of the division.
+    pub fn split(&self, amount: &TokenAmount) -> (TokenAmount, TokenAmount) {
+        if self.denom == 0 {
+            return (TokenAmount::zero(), amount.clone());
+        }
+        let rest = TokenAmount::from_atto(
+            amount.atto() * self.denom.saturating_sub(self.numer) / self.denom,
+        );
+        (amount - &rest, rest)
+    }
+}
+
+impl Default for StakeRatio {
+    /// The whole amount.
+    fn default() -> Self {
+        Self { numer: 1, denom: 1 }
+    }
+}
@@ -1,49 +1,460 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::Range;
+
 use anyhow::anyhow;
 use cid::Cid;
 use fil_actors_runtime::runtime::fvm::resolve_secp_bls;
 use fil_actors_runtime::runtime::Runtime;
 use fil_actors_runtime::{actor_error, ActorError};
-use fvm_ipld_blockstore::Blockstore;
-use fvm_ipld_encoding::{Cbor, RawBytes};
+use fvm_ipld_blockstore::{Block, Blockstore};
+use fvm_ipld_encoding::{Cbor, CborStore, RawBytes, DAG_CBOR};
 use fvm_ipld_hamt::BytesKey;
-use fvm_shared::address::Address;
+use fvm_shared::address::{Address, Protocol};
 use fvm_shared::bigint::Zero;
 use fvm_shared::clock::ChainEpoch;
+use fvm_shared::crypto::signature::Signature;
 use fvm_shared::econ::TokenAmount;
-use ipc_gateway::{Checkpoint, SubnetID, DEFAULT_CHECKPOINT_PERIOD, MIN_COLLATERAL_AMOUNT};
-use lazy_static::lazy_static;
-use num::rational::Ratio;
+use multihash::Code;
 use num::BigInt;
-use primitives::{TCid, THamt};
+use num_traits::ToPrimitive;
+use primitives::{TAmt, TCid, THamt, TLink};
 use serde::{Deserialize, Serialize};
 
+use crate::caller_policy::CallerPolicies;
+use crate::checkpoint::CheckpointWindows;
+use crate::compat::{self, Checkpoint, SubnetID, DEFAULT_CHECKPOINT_PERIOD, MIN_COLLATERAL_AMOUNT};
+use crate::error::SubnetActorError;
+use crate::eth;
+use crate::migrations::STATE_VERSION;
+use crate::sig::{CheckpointSigVerifier, SigScheme};
+use crate::stake_math::{self, StakeRatio};
 use crate::types::*;
+use crate::validator_set::{LegacyValidatorSet, ValidatorSet};
+use crate::voting::{Tally, Voting};
 
-lazy_static! {
-    static ref VOTING_THRESHOLD: Ratio<BigInt> = Ratio::new(
-        TokenAmount::from_atto(2).atto().clone(),
-        TokenAmount::from_atto(3).atto().clone()
-    );
-}
+#[cfg(not(target_arch = "wasm32"))]
+mod diff;
+#[cfg(all(feature = "dump", not(target_arch = "wasm32")))]
+mod dump;
+pub mod keys;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use diff::{diff, diff_states, StakeChange, StateDiff};
+
+#[cfg(all(feature = "dump", not(target_arch = "wasm32")))]
+pub use dump::dump;
 
 /// The state object.
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct State {
     pub name: String,
     pub parent_id: SubnetID,
+    /// ID address of the IPC gateway.
     pub ipc_gateway_addr: Address,
     pub consensus: ConsensusType,
-    pub min_validator_stake: TokenAmount,
+    /// Economic parameters of the subnet.
+    #[serde(default)]
+    pub econ: EconParams,
     pub total_stake: TokenAmount,
     pub stake: TCid<THamt<Cid, TokenAmount>>,
     pub status: Status,
+    /// Epoch of the last status change.
+    #[serde(default)]
+    pub status_changed_at: ChainEpoch,
+    /// Activation of the subnet, unknown for subnets activated before it
+    /// was recorded.
+    #[serde(default)]
+    pub activation: Option<Activation>,
     pub genesis: Vec<u8>,
+    /// Epochs a height needs to be behind the current epoch to be
+    /// considered final, see `FinalityConfig`.
     pub finality_threshold: ChainEpoch,
     pub check_period: ChainEpoch,
-    pub checkpoints: TCid<THamt<Cid, Checkpoint>>,
-    pub window_checks: TCid<THamt<Cid, Votes>>,
-    pub validator_set: Vec<Validator>,
+    /// Change of `check_period` taking effect at the next committed
+    /// checkpoint.
+    #[serde(default)]
+    pub pending_check_period: Option<CheckPeriodChange>,
+    /// Changes of `check_period` applied so far, oldest first. Windows are
+    /// counted from the effective epoch of the last one.
+    #[serde(default)]
+    pub check_period_changes: Vec<CheckPeriodChange>,
+    /// Committed checkpoints indexed by window number, the number of
+    /// checkpoints committed before them. Windows are committed in order of
+    /// epoch, so checkpoints are found by epoch bisecting the windows.
+    #[serde(default, rename = "checkpoint_log")]
+    pub checkpoints: TCid<TAmt<Checkpoint>>,
+    /// Committed checkpoints keyed by epoch, written by state versions
+    /// before 26 and dropped by the migration.
+    #[serde(
+        default,
+        rename = "checkpoints",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub legacy_checkpoints: Option<TCid<THamt<Cid, Checkpoint>>>,
+    /// Configuration number of the validator set when each checkpoint of
+    /// `checkpoints` was committed, by window number.
+    #[serde(default)]
+    pub checkpoint_configs: TCid<TAmt<u64>>,
+    pub window_checks: Voting<Checkpoint>,
+    /// Validator set written by state versions before 14, moved to
+    /// `validators` by the migration.
+    #[serde(
+        default,
+        rename = "validator_set",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub legacy_validator_set: Vec<Validator>,
+    /// Validator set written by state versions before 34, indexed with the
+    /// stake of its validators into `validators` by the migration.
+    #[serde(
+        default,
+        rename = "validators",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub legacy_validators: Option<LegacyValidatorSet>,
+    #[serde(default, rename = "ordered_validators")]
+    pub validators: ValidatorSet,
+    /// Configuration of the validator set, increased on every change of
+    /// its members.
+    #[serde(default)]
+    pub config_number: u64,
+    /// Validators of each configuration of the validator set, sorted by
+    /// address, by configuration number. The CIDs of the links are the
+    /// digests of the configurations.
+    #[serde(default)]
+    pub validator_sets: TCid<TAmt<TCid<TLink<Vec<Validator>>>>>,
+    /// Append-only log of the changes of the membership of the validator
+    /// set, to replay it.
+    #[serde(default)]
+    pub membership_log: TCid<TAmt<MembershipEvent>>,
+    /// Number of events in `membership_log`.
+    #[serde(default)]
+    pub membership_events: u64,
+    /// Membership tickets of the validators, issued when admitted.
+    #[serde(default)]
+    pub membership_tickets: TCid<THamt<Address, MembershipTicket>>,
     pub min_validators: u64,
+    #[serde(default)]
+    pub owner: Option<Address>,
+    #[serde(default)]
+    pub invitation_only: bool,
+    #[serde(default)]
+    pub invitations: TCid<THamt<Vec<u8>, Invitation>>,
+    #[serde(default)]
+    pub cross_msgs: TCid<THamt<ChainEpoch, Vec<Cid>>>,
+    /// Validators that registered each batch of `cross_msgs`, in the same
+    /// order, to bound the batches a validator registers in a window.
+    #[serde(default)]
+    pub cross_msg_registrants: TCid<THamt<ChainEpoch, Vec<Address>>>,
+    #[serde(default)]
+    pub collateral_token: Option<Address>,
+    /// Epoch of the last committed checkpoint.
+    #[serde(default)]
+    pub last_committed_epoch: ChainEpoch,
+    /// Number of consecutive windows a validator can miss before being
+    /// jailed. Zero disables jailing.
+    #[serde(default)]
+    pub max_missed_windows: u64,
+    #[serde(default)]
+    pub participation: TCid<THamt<Address, Participation>>,
+    /// Last epoch at which validators were seen, by sending a heartbeat
+    /// or voting a checkpoint.
+    #[serde(default)]
+    pub last_seen: TCid<THamt<Address, ChainEpoch>>,
+    /// Stake charged to validators as penalties.
+    #[serde(default)]
+    pub penalized_stake: TokenAmount,
+    /// Fees that couldn't be distributed to validators, written by state
+    /// versions before 30 and moved to `reward_pool` by the migration.
+    #[serde(default, rename = "fee_dust", skip_serializing_if = "Option::is_none")]
+    pub legacy_fee_dust: Option<TokenAmount>,
+    /// Penalized stake not spent yet, spent by the validators through
+    /// spend proposals.
+    #[serde(default)]
+    pub treasury: TokenAmount,
+    /// Part of the treasury held by the actor instead of being locked in
+    /// the gateway, like join fees. It is spent first, and burnt when the
+    /// subnet is killed.
+    #[serde(default)]
+    pub treasury_held: TokenAmount,
+    #[serde(default)]
+    pub join_fee_policy: JoinFeePolicy,
+    /// Addresses joining without the join fee.
+    #[serde(default)]
+    pub join_fee_exempt: Vec<Address>,
+    /// Spend proposals pending approval.
+    #[serde(default)]
+    pub spend_proposals: TCid<THamt<Cid, SpendProposal>>,
+    /// Approvals of the spend proposals.
+    #[serde(default)]
+    pub spend_votes: Voting<SpendProposal>,
+    /// Votes of the validators for new economic parameters.
+    #[serde(default)]
+    pub econ_votes: Voting<EconParams>,
+    /// Counters of the operations of the subnet.
+    #[serde(default)]
+    pub stats: Stats,
+    /// Funds held by the actor to pay relayer fees.
+    #[serde(default)]
+    pub reward_pool: TokenAmount,
+    /// Number of addresses with stake in the subnet.
+    #[serde(default)]
+    pub stakers_count: u64,
+    /// Addresses that staked in the subnet, in order of their first stake,
+    /// to list the stakers from a cursor.
+    #[serde(default)]
+    pub staker_index: TCid<TAmt<Address>>,
+    /// Number of addresses in `staker_index`.
+    #[serde(default)]
+    pub staker_index_len: u64,
+    /// Position of each address of `staker_index`.
+    #[serde(default)]
+    pub staker_positions: TCid<THamt<Address, u64>>,
+    /// Number of committed checkpoints.
+    #[serde(default)]
+    pub checkpoints_count: u64,
+    /// Whether the genesis validator set has been bootstrapped.
+    #[serde(default)]
+    pub bootstrapped: bool,
+    /// Weights of the validators at the start of each open window.
+    #[serde(default)]
+    pub weight_snapshots: TCid<THamt<ChainEpoch, WeightSnapshot>>,
+    /// Stakers with enough collateral to replace the delegate of a subnet
+    /// with delegated consensus, in order of promotion.
+    #[serde(default)]
+    pub standby: Vec<Validator>,
+    /// Last promotion of a standby staker to delegate.
+    #[serde(default)]
+    pub last_failover: Option<DelegateFailover>,
+    /// State of the subnet claimed by the last committed checkpoint.
+    #[serde(default)]
+    pub last_state_commitment: Option<StateCommitment>,
+    /// Epochs committed checkpoints can be challenged before they are
+    /// relayed to the gateway.
+    #[serde(default)]
+    pub dispute_period: ChainEpoch,
+    /// Committed checkpoints waiting to be relayed, in order of epoch.
+    #[serde(default)]
+    pub pending_checkpoints: Vec<PendingCheckpoint>,
+    /// Robust address of the IPC gateway, if it was given at construction,
+    /// which stays the same across networks where its ID differs.
+    #[serde(default)]
+    pub ipc_gateway_robust_addr: Option<Address>,
+    /// Signature scheme of the checkpoints of the subnet.
+    #[serde(default)]
+    pub sig_scheme: SigScheme,
+    /// Code CIDs the IPC gateway can be moved to with `UpdateGatewayAddr`.
+    #[serde(default)]
+    pub gateway_codes: Vec<Cid>,
+    /// Code CIDs the actor can be upgraded to with `Upgrade`, committed by
+    /// the IPC gateway.
+    #[serde(default)]
+    pub upgrade_codes: Vec<Cid>,
+    /// Signing keys of the validators that rotated their key.
+    #[serde(default)]
+    pub validator_keys: TCid<THamt<Address, ValidatorKey>>,
+    /// Validators by the ID address of the worker key submitting their
+    /// checkpoints, for validators owned by actors that can't sign, like
+    /// multisigs.
+    #[serde(default)]
+    pub workers: TCid<THamt<Address, Address>>,
+    /// Stake of validators vesting with a schedule.
+    #[serde(default)]
+    pub vesting: TCid<THamt<Address, Vesting>>,
+    /// Calls queued by state versions before 32, moved to
+    /// `pending_gateway_calls` by the migration.
+    #[serde(
+        default,
+        rename = "pending_gateway_calls",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub legacy_pending_gateway_calls: Option<Vec<LegacyGatewayCall>>,
+    /// Calls syncing the stake with the IPC gateway rejected by it, oldest
+    /// first, to be retried with `RetryGatewayCalls`. At most
+    /// `MAX_PENDING_GATEWAY_CALLS`.
+    #[serde(default, rename = "queued_gateway_calls")]
+    pub pending_gateway_calls: Vec<GatewayCall>,
+    /// Whether the IPC gateway is notified of the changes of the power
+    /// table, see `ext::gateway`.
+    #[serde(default)]
+    pub notify_validator_set: bool,
+    /// Validators of the power table last notified to the IPC gateway.
+    #[serde(default)]
+    pub notified_power_table: Option<TCid<TLink<Vec<PowerTableEntry>>>>,
+    /// Last parent finality committed by a majority of validators.
+    #[serde(default)]
+    pub parent_finality: Option<ParentFinality>,
+    /// Actor types allowed to call the validator methods.
+    #[serde(default)]
+    pub caller_policies: CallerPolicies,
+    /// Whether the owner froze the methods mutating the state.
+    #[serde(default)]
+    pub paused: bool,
+    /// Number of ownership transfers and renouncements, which ownership
+    /// changes need to present so they can't be replayed.
+    #[serde(default)]
+    pub owner_nonce: u64,
+    /// Stake delegated to each validator.
+    #[serde(default)]
+    pub delegations: TCid<THamt<Address, Delegations>>,
+    /// Epoch of the last redelegation of each delegator.
+    #[serde(default)]
+    pub redelegations: TCid<THamt<Address, ChainEpoch>>,
+    /// Commission rate increases waiting for their delay, at most one per
+    /// validator.
+    #[serde(default)]
+    pub commission_changes: Vec<CommissionChange>,
+    /// Funding ledger of each staker, with the stake each funder put up for
+    /// it, the staker itself included. Ledgers sum to the stake of their
+    /// staker, and withdrawn stake is refunded to its funders pro-rata.
+    #[serde(default)]
+    pub funding: TCid<THamt<Address, Vec<Funding>>>,
+    /// Maximum number of validator set changes per checkpoint window.
+    #[serde(default)]
+    pub churn_limit: u64,
+    /// Window the validator set changes in `churn` were done in.
+    #[serde(default)]
+    pub churn_window: ChainEpoch,
+    /// Validator set changes done in `churn_window`.
+    #[serde(default)]
+    pub churn: u64,
+    /// Validator set changes beyond the churn limit, applied in order
+    /// when windows are committed.
+    #[serde(default)]
+    pub pending_changes: Vec<ValidatorChange>,
+    /// Maximum number of validators leaving per checkpoint window.
+    #[serde(default)]
+    pub max_exits_per_window: u64,
+    /// Window the exits in `exits` were done in.
+    #[serde(default)]
+    pub exit_window: ChainEpoch,
+    /// Validators that left in `exit_window`.
+    #[serde(default)]
+    pub exits: u64,
+    /// Validators waiting to leave beyond the exit limit, removed in
+    /// order when windows are committed. They remain in the validator
+    /// set, with their stake locked, until then.
+    #[serde(default)]
+    pub exit_queue: Vec<ExitRequest>,
+    /// Whether the subnet was registered in the IPC gateway, after which
+    /// new collateral is added to the registered subnet.
+    #[serde(default)]
+    pub registered_with_gateway: bool,
+    /// Native funds subnets with a collateral token register with in the
+    /// gateway, which only takes native collateral. Sent along with the
+    /// construction of the subnet and refunded to the owner when killed.
+    #[serde(default)]
+    pub registration_bond: TokenAmount,
+    /// Votes for parent finalities keyed by their CBOR encoding, written by
+    /// state versions before 31 and moved to `finality_votes` by the
+    /// migration.
+    #[serde(
+        default,
+        rename = "finality_votes",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub legacy_finality_votes: Option<TCid<THamt<Vec<u8>, Votes>>>,
+    /// Votes for parent finalities, keyed by the CID of the finality.
+    #[serde(default, rename = "parent_finality_votes")]
+    pub finality_votes: Voting<ParentFinality>,
+    /// Checkpoints voted in each open window.
+    #[serde(default)]
+    pub candidates: TCid<THamt<ChainEpoch, Vec<Cid>>>,
+    /// Expiry of the votes of each window with candidates, oldest first.
+    #[serde(default)]
+    pub vote_expiry: Vec<VoteExpiry>,
+    /// Invalid checkpoints submitted by each validator.
+    #[serde(default)]
+    pub penalties: TCid<THamt<Address, PenaltyRecord>>,
+    /// Maximum number of checkpoints a validator can submit in a window.
+    /// Zero means no limit.
+    #[serde(default)]
+    pub max_submissions_per_window: u64,
+    /// Checkpoints submitted by each validator in the open window.
+    #[serde(default)]
+    pub submissions: TCid<THamt<Address, SubmissionCount>>,
+    #[serde(default)]
+    pub chain_id: u64,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub metadata_url: Option<String>,
+    #[serde(default)]
+    pub excess_stake_policy: ExcessStakePolicy,
+    /// Maximum share of the voting power of a single validator, in
+    /// percent, if capped.
+    #[serde(default)]
+    pub max_voting_power_pct: Option<u64>,
+    /// Delegated address of the actor, if deployed through `Exec4`, for
+    /// the gateway to check registrations against.
+    #[serde(default)]
+    pub delegated_addr: Option<Address>,
+    /// IPC gateway that reported being deployed on `parent_id`, checked
+    /// before registering with it.
+    #[serde(default)]
+    pub parent_checked_gateway: Option<Address>,
+    /// Whether a genesis checkpoint is committed when registering in the
+    /// gateway, see `ConstructParams::anchor_genesis`.
+    #[serde(default)]
+    pub anchor_genesis: bool,
+    /// Genesis checkpoint committed on registration, the first link of the
+    /// chain of checkpoints.
+    #[serde(default)]
+    pub genesis_checkpoint: Option<Checkpoint>,
+    /// Digests of the offences slashed on evidence of misbehavior, with
+    /// the epoch they were slashed at.
+    #[serde(default)]
+    pub slashed_offences: TCid<THamt<Cid, ChainEpoch>>,
+    // economic parameters written by state versions before 25, moved to
+    // `econ` by the migration
+    #[serde(
+        default,
+        rename = "min_validator_stake",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub legacy_min_validator_stake: Option<TokenAmount>,
+    #[serde(
+        default,
+        rename = "min_total_collateral",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub legacy_min_total_collateral: Option<TokenAmount>,
+    #[serde(
+        default,
+        rename = "min_join_amount",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub legacy_min_join_amount: Option<TokenAmount>,
+    #[serde(
+        default,
+        rename = "max_validator_stake",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub legacy_max_validator_stake: Option<TokenAmount>,
+    #[serde(default, rename = "join_fee", skip_serializing_if = "Option::is_none")]
+    pub legacy_join_fee: Option<TokenAmount>,
+    #[serde(
+        default,
+        rename = "relayer_fee",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub legacy_relayer_fee: Option<TokenAmount>,
+    #[serde(
+        default,
+        rename = "checkpoint_penalty_rate",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub legacy_checkpoint_penalty_rate: Option<u64>,
+    #[serde(
+        default,
+        rename = "leaving_coeff",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub legacy_leaving_coeff: Option<StakeRatio>,
+    /// Version of the state schema, see [`crate::migrations`].
+    #[serde(default)]
+    pub version: u64,
 }
 
 impl Cbor for State {}
@@ -53,19 +464,14 @@ impl Cbor for State {}
 /// StateObject trait (i.e. impl StateObject for State).
 impl State {
     pub fn new<BS: Blockstore>(store: &BS, params: ConstructParams) -> anyhow::Result<State> {
-        let min_stake = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
-
-        let state = State {
+        let delegated_addr = params.delegated_address()?;
+        let mut state = State {
             name: params.name,
             parent_id: params.parent,
-            ipc_gateway_addr: Address::new_id(params.ipc_gateway_addr),
+            ipc_gateway_addr: params.ipc_gateway_addr,
             consensus: params.consensus,
+            econ: params.econ_params(),
             total_stake: TokenAmount::zero(),
-            min_validator_stake: if params.min_validator_stake < min_stake {
-                min_stake
-            } else {
-                params.min_validator_stake
-            },
             min_validators: params.min_validators,
             finality_threshold: params.finality_threshold,
             check_period: if params.check_period < DEFAULT_CHECKPOINT_PERIOD {
@@ -75,11 +481,117 @@ impl State {
             },
             genesis: params.genesis,
             status: Status::Instantiated,
-            checkpoints: TCid::new_hamt(store)?,
+            checkpoints: TCid::new_amt(store)?,
+            legacy_checkpoints: None,
+            checkpoint_configs: TCid::new_amt(store)?,
             stake: TCid::new_hamt(store)?,
-            window_checks: TCid::new_hamt(store)?,
-            validator_set: Vec::new(),
+            window_checks: Voting::new(store)?,
+            validators: ValidatorSet::new(store)?,
+            config_number: 0,
+            validator_sets: TCid::new_amt(store)?,
+            membership_log: TCid::new_amt(store)?,
+            membership_events: 0,
+            membership_tickets: TCid::new_hamt(store)?,
+            legacy_validator_set: Vec::new(),
+            legacy_validators: None,
+            owner: params.owner,
+            invitation_only: params.invitation_only,
+            invitations: TCid::new_hamt(store)?,
+            cross_msgs: TCid::new_hamt(store)?,
+            cross_msg_registrants: TCid::new_hamt(store)?,
+            collateral_token: params.collateral_token,
+            last_committed_epoch: 0,
+            max_missed_windows: params.max_missed_windows,
+            participation: TCid::new_hamt(store)?,
+            last_seen: TCid::new_hamt(store)?,
+            penalized_stake: TokenAmount::zero(),
+            legacy_fee_dust: None,
+            treasury: TokenAmount::zero(),
+            treasury_held: TokenAmount::zero(),
+            spend_proposals: TCid::new_hamt(store)?,
+            spend_votes: Voting::new(store)?,
+            econ_votes: Voting::new(store)?,
+            reward_pool: TokenAmount::zero(),
+            stats: Stats::default(),
+            pending_check_period: None,
+            check_period_changes: Vec::new(),
+            status_changed_at: 0,
+            activation: None,
+            stakers_count: 0,
+            staker_index: TCid::new_amt(store)?,
+            staker_index_len: 0,
+            staker_positions: TCid::new_hamt(store)?,
+            checkpoints_count: 0,
+            bootstrapped: false,
+            weight_snapshots: TCid::new_hamt(store)?,
+            standby: Vec::new(),
+            last_failover: None,
+            last_state_commitment: None,
+            dispute_period: params.dispute_period,
+            pending_checkpoints: Vec::new(),
+            ipc_gateway_robust_addr: if params.ipc_gateway_addr.protocol() == Protocol::ID {
+                None
+            } else {
+                Some(params.ipc_gateway_addr)
+            },
+            gateway_codes: Vec::new(),
+            upgrade_codes: Vec::new(),
+            sig_scheme: params.sig_scheme,
+            validator_keys: TCid::new_hamt(store)?,
+            workers: TCid::new_hamt(store)?,
+            vesting: TCid::new_hamt(store)?,
+            legacy_pending_gateway_calls: None,
+            pending_gateway_calls: Vec::new(),
+            notify_validator_set: false,
+            notified_power_table: None,
+            parent_finality: None,
+            legacy_finality_votes: None,
+            finality_votes: Voting::new(store)?,
+            candidates: TCid::new_hamt(store)?,
+            vote_expiry: Vec::new(),
+            penalties: TCid::new_hamt(store)?,
+            max_submissions_per_window: params.max_submissions_per_window,
+            submissions: TCid::new_hamt(store)?,
+            caller_policies: params.caller_policies,
+            paused: false,
+            owner_nonce: 0,
+            registered_with_gateway: false,
+            registration_bond: TokenAmount::zero(),
+            delegations: TCid::new_hamt(store)?,
+            redelegations: TCid::new_hamt(store)?,
+            commission_changes: Vec::new(),
+            funding: TCid::new_hamt(store)?,
+            churn_limit: params.churn_limit,
+            churn_window: 0,
+            churn: 0,
+            pending_changes: Vec::new(),
+            max_exits_per_window: params.max_exits_per_window,
+            exit_window: 0,
+            exits: 0,
+            exit_queue: Vec::new(),
+            join_fee_policy: params.join_fee_policy,
+            join_fee_exempt: params.join_fee_exempt,
+            chain_id: params.chain_id,
+            description: params.description,
+            metadata_url: params.metadata_url,
+            excess_stake_policy: params.excess_stake_policy,
+            max_voting_power_pct: params.max_voting_power_pct,
+            delegated_addr,
+            parent_checked_gateway: None,
+            anchor_genesis: params.anchor_genesis,
+            genesis_checkpoint: None,
+            slashed_offences: TCid::new_hamt(store)?,
+            legacy_min_validator_stake: None,
+            legacy_min_total_collateral: None,
+            legacy_min_join_amount: None,
+            legacy_max_validator_stake: None,
+            legacy_join_fee: None,
+            legacy_relayer_fee: None,
+            legacy_checkpoint_penalty_rate: None,
+            legacy_leaving_coeff: None,
+            version: STATE_VERSION,
         };
+        state.record_validator_set(store)?;
 
         Ok(state)
     }
@@ -89,14 +601,9 @@ impl State {
         store: &BS,
         cid: &Cid,
     ) -> Result<Option<Votes>, ActorError> {
-        let hamt = self
-            .window_checks
-            .load(store)
-            .map_err(|_| actor_error!(illegal_state, "cannot load votes hamt"))?;
-        let votes = hamt
-            .get(&BytesKey::from(cid.to_bytes()))
-            .map_err(|_| actor_error!(illegal_state, "cannot read votes"))?;
-        Ok(votes.cloned())
+        self.window_checks
+            .get(store, cid)
+            .map_err(|_| actor_error!(illegal_state, "cannot read votes"))
     }
 
     pub fn remove_votes<BS: Blockstore>(
@@ -105,14 +612,8 @@ impl State {
         cid: &Cid,
     ) -> Result<(), ActorError> {
         self.window_checks
-            .modify(store, |hamt| {
-                hamt.delete(&BytesKey::from(cid.to_bytes()))
-                    .map_err(|_| actor_error!(illegal_state, "cannot remove votes from hamt"))?;
-                Ok(true)
-            })
-            .map_err(|_| actor_error!(illegal_state, "cannot modify window checks"))?;
-
-        Ok(())
+            .remove(store, std::slice::from_ref(cid))
+            .map_err(|_| actor_error!(illegal_state, "cannot remove votes from hamt"))
     }
 
     pub fn set_votes<BS: Blockstore>(
@@ -122,13 +623,8 @@ impl State {
         votes: Votes,
     ) -> Result<(), ActorError> {
         self.window_checks
-            .modify(store, |hamt| {
-                hamt.set(BytesKey::from(cid.to_bytes()), votes)
-                    .map_err(|_| actor_error!(illegal_state, "cannot set votes in hamt"))?;
-                Ok(true)
-            })
-            .map_err(|_| actor_error!(illegal_state, "cannot modify window checks"))?;
-        Ok(())
+            .set(store, cid, votes)
+            .map_err(|_| actor_error!(illegal_state, "cannot set votes in hamt"))
     }
 
     /// Get the stake of an address.
@@ -142,246 +638,3387 @@ impl State {
         Ok(amount.cloned())
     }
 
-    /// Adds stake from a validator
+    /// Adds stake from a validator, put up by `funder`
     pub(crate) fn add_stake<BS: Blockstore>(
         &mut self,
         store: &BS,
         addr: &Address,
+        funder: &Address,
         net_addr: &str,
         amount: &TokenAmount,
     ) -> anyhow::Result<()> {
         // update miner stake
+        let mut updated_stake = TokenAmount::zero();
+        let mut new_staker = false;
         self.stake.modify(store, |hamt| {
             // Note that when trying to get stake, if it is not found in the
             // hamt, that means it's the first time adding stake and we just
             // give default stake amount 0.
             let key = BytesKey::from(addr.to_bytes());
             let stake = hamt.get(&key)?.unwrap_or(&TokenAmount::zero()).clone();
-            let updated_stake = stake + amount;
+            if stake == TokenAmount::zero() && *amount > TokenAmount::zero() {
+                self.stakers_count += 1;
+                new_staker = true;
+            }
+            updated_stake = stake_math::checked_add(&stake, amount)?;
 
             hamt.set(key, updated_stake.clone())?;
 
             // update total collateral
-            self.total_stake += amount;
+            self.total_stake = stake_math::checked_add(&self.total_stake, amount)?;
+
+            Ok(true)
+        })?;
+        if new_staker {
+            self.index_staker(store, addr)?;
+        }
+        self.credit_funding(store, addr, funder, amount)?;
 
-            // check if the miner has collateral to become a validator
-            if updated_stake >= self.min_validator_stake
-                && (self.consensus != ConsensusType::Delegated || self.validator_set.is_empty())
-            {
-                self.validator_set.push(Validator {
-                    addr: *addr,
-                    net_addr: String::from(net_addr),
-                });
+        // check if the miner has collateral to become a validator
+        // (and it is not a validator already). Delegated subnets have a
+        // single validator, the rest of stakers are kept on standby.
+        if updated_stake >= self.econ.min_validator_stake && !self.is_validator(store, addr)? {
+            let validator = Validator {
+                addr: *addr,
+                net_addr: String::from(net_addr),
+            };
+            if self.consensus != ConsensusType::Delegated || self.validators.is_empty() {
+                if self.consume_churn() {
+                    self.admit_validator(store, validator)?;
+                } else {
+                    self.queue_validator_change(validator, true);
+                }
+            } else if !self.standby.iter().any(|v| v.addr == *addr) {
+                self.standby.push(validator);
             }
+        }
+
+        self.validators.update_stake(addr, updated_stake);
+        Ok(())
+    }
+
+    /// Tops up the stake of an address that has already staked in the subnet,
+    /// with `amount` put up by `funder`, without updating the validator set.
+    pub(crate) fn top_up_stake<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        addr: &Address,
+        funder: &Address,
+        amount: &TokenAmount,
+    ) -> anyhow::Result<()> {
+        let mut updated_stake = TokenAmount::zero();
+        self.stake.modify(store, |hamt| {
+            let key = BytesKey::from(addr.to_bytes());
+            let stake = match hamt.get(&key)? {
+                Some(s) if *s != TokenAmount::zero() => s.clone(),
+                _ => return Err(SubnetActorError::NotEnoughStake(*addr).into()),
+            };
+            updated_stake = stake_math::checked_add(&stake, amount)?;
+            hamt.set(key, updated_stake.clone())?;
+
+            // update total collateral
+            self.total_stake = stake_math::checked_add(&self.total_stake, amount)?;
 
             Ok(true)
         })?;
+        self.credit_funding(store, addr, funder, amount)?;
 
+        self.validators.update_stake(addr, updated_stake);
         Ok(())
     }
 
+    /// Returns the part of `amount` that `addr` can stake without going above
+    /// `max_validator_stake`. Fails if there is an excess and the policy of
+    /// the subnet is to reject it.
+    pub(crate) fn capped_stake<BS: Blockstore>(
+        &self,
+        store: &BS,
+        addr: &Address,
+        amount: &TokenAmount,
+    ) -> anyhow::Result<TokenAmount> {
+        if self.econ.max_validator_stake == TokenAmount::zero() {
+            return Ok(amount.clone());
+        }
+
+        let stake = self
+            .get_stake(store, addr)?
+            .unwrap_or_else(TokenAmount::zero);
+        let room = if stake < self.econ.max_validator_stake {
+            &self.econ.max_validator_stake - &stake
+        } else {
+            TokenAmount::zero()
+        };
+        if *amount <= room {
+            return Ok(amount.clone());
+        }
+
+        match self.excess_stake_policy {
+            ExcessStakePolicy::Reject => Err(SubnetActorError::StakeCapExceeded(*addr).into()),
+            ExcessStakePolicy::Refund if room == TokenAmount::zero() => {
+                Err(SubnetActorError::StakeAtCap(*addr).into())
+            }
+            ExcessStakePolicy::Refund => Ok(room),
+        }
+    }
+
+    /// Removes `amount` from the stake of `addr` as it leaves the subnet,
+    /// which can't include the stake not vested yet at `epoch`. Only the
+    /// leaving coefficient of `amount` is released, the rest is forfeited
+    /// to the treasury. Returns the stake released to each funder of the
+    /// own stake of `addr`, pro-rata to their funding.
     pub fn rm_stake<BS: Blockstore>(
         &mut self,
         store: &BS,
         addr: &Address,
         amount: &TokenAmount,
-    ) -> anyhow::Result<()> {
+        epoch: ChainEpoch,
+    ) -> anyhow::Result<Vec<(Address, TokenAmount)>> {
+        let locked = self.locked_stake(store, addr, epoch)?;
+        let mut withdrawn_all = false;
+
         // update miner stake
         self.stake.modify(store, |hamt| {
             // Note that when trying to get stake, if it is not found in the
             // hamt, that means it's the first time adding stake and we just
             // give default stake amount 0.
             let key = BytesKey::from(addr.to_bytes());
-            let mut stake = hamt.get(&key)?.unwrap_or(&TokenAmount::zero()).clone();
-            stake = stake.div_floor(LEAVING_COEFF);
+            let stake = hamt.get(&key)?.unwrap_or(&TokenAmount::zero()).clone();
 
-            if stake.lt(amount) {
-                return Err(anyhow!(format!(
-                    "address not enough stake to withdraw: {:?}",
-                    addr
-                )));
+            if stake.lt(amount) || &stake - amount < locked {
+                return Err(SubnetActorError::NotEnoughStake(*addr).into());
             }
 
-            hamt.set(key, stake - amount)?;
+            let updated_stake = stake_math::checked_sub(&stake, amount)?;
+            if updated_stake == TokenAmount::zero() && *amount > TokenAmount::zero() {
+                decrement(&mut self.stakers_count, "stakers")?;
+                withdrawn_all = true;
+            }
+            hamt.set(key, updated_stake)?;
 
             // update total collateral
-            self.total_stake -= amount;
+            self.total_stake = stake_math::checked_sub(&self.total_stake, amount)?;
+
+            Ok(true)
+        })?;
+
+        // the penalty only applies to the stake withdrawn, the stake left
+        // behind is penalized when it is withdrawn in turn
+        let refunds = self.release_funding(store, addr, amount)?;
+
+        if withdrawn_all {
+            self.vesting.modify(store, |hamt| {
+                hamt.delete(&BytesKey::from(addr.to_bytes()))?;
+                Ok(true)
+            })?;
+        }
+
+        // remove miner from list of validators, validators withdrawing part
+        // of their stake go through `withdraw_stake` instead
+        self.remove_validator(store, addr)?;
+        Ok(refunds)
+    }
 
-            // remove miner from list of validators
-            // NOTE: We currently only support full recovery of collateral.
-            // And additional check will be needed here if we consider part-recoveries.
-            self.validator_set.retain(|x| x.addr != *addr);
+    /// Withdraws `amount` of the unlocked, own stake of `addr`, keeping it
+    /// in the validator set. Validators need to keep `min_validator_stake`,
+    /// and only get the leaving coefficient of the amount back, as when
+    /// leaving. The weights of the validator set change with the stake, so
+    /// they are recorded in a new configuration. Returns the stake released
+    /// to each funder, as `rm_stake`.
+    pub(crate) fn withdraw_stake<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        addr: &Address,
+        amount: &TokenAmount,
+        epoch: ChainEpoch,
+    ) -> anyhow::Result<Vec<(Address, TokenAmount)>> {
+        let remaining = self.check_withdraw_stake(store, addr, amount, epoch)?;
+        let is_validator = self.is_validator(store, addr)?;
 
+        self.stake.modify(store, |hamt| {
+            let key = BytesKey::from(addr.to_bytes());
+            if remaining.is_zero() {
+                hamt.delete(&key)?;
+            } else {
+                hamt.set(key, remaining.clone())?;
+            }
             Ok(true)
         })?;
+        self.total_stake = stake_math::checked_sub(&self.total_stake, amount)?;
+        let refunds = self.release_funding(store, addr, amount)?;
+        if remaining.is_zero() {
+            decrement(&mut self.stakers_count, "stakers")?;
+            self.vesting.modify(store, |hamt| {
+                hamt.delete(&BytesKey::from(addr.to_bytes()))?;
+                Ok(true)
+            })?;
+        }
+        // the power table follows the new stake
+        self.validators.update_stake(addr, remaining);
+        if is_validator {
+            self.config_number += 1;
+            self.record_validator_set(store)?;
+        }
 
-        Ok(())
+        Ok(refunds)
     }
 
-    pub fn has_majority_vote<BS: Blockstore>(
+    /// Checks that `addr` can withdraw `amount` of its stake with
+    /// `withdraw_stake` at `epoch`. Returns the stake it keeps.
+    pub fn check_withdraw_stake<BS: Blockstore>(
         &self,
         store: &BS,
-        votes: &Votes,
-    ) -> Result<bool, ActorError> {
-        let mut sum = TokenAmount::zero();
-        for v in &votes.validators {
-            let stake = self
-                .get_stake(store, v)
-                .map_err(|_| actor_error!(illegal_state, "cannot load stake from hamt"))?;
-            sum += stake.unwrap_or_else(TokenAmount::zero);
+        addr: &Address,
+        amount: &TokenAmount,
+        epoch: ChainEpoch,
+    ) -> anyhow::Result<TokenAmount> {
+        let stake = self
+            .get_stake(store, addr)?
+            .unwrap_or_else(TokenAmount::zero);
+        let locked = self.locked_stake(store, addr, epoch)?;
+        let delegated = self.delegated_stake(store, addr)?;
+        if !amount.is_positive() || *amount > &stake - &locked - &delegated {
+            return Err(SubnetActorError::NotEnoughStake(*addr).into());
+        }
+        let remaining = stake_math::checked_sub(&stake, amount)?;
+        if self.is_validator(store, addr)? && remaining < self.econ.min_validator_stake {
+            return Err(SubnetActorError::WithdrawalBelowMinStake(*addr).into());
         }
-        let ftotal = Ratio::from_integer(self.total_stake.atto().clone());
-        Ok(Ratio::from_integer(sum.atto().clone()) / ftotal >= *VOTING_THRESHOLD)
+        Ok(remaining)
     }
 
-    pub fn mutate_state(&mut self) {
-        match self.status {
-            Status::Instantiated => {
-                if self.total_stake >= TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT) {
-                    self.status = Status::Active
-                }
+    pub fn get_vesting<BS: Blockstore>(
+        &self,
+        store: &BS,
+        addr: &Address,
+    ) -> anyhow::Result<Option<Vesting>> {
+        let hamt = self.vesting.load(store)?;
+        let vesting = hamt.get(&BytesKey::from(addr.to_bytes()))?;
+        Ok(vesting.cloned())
+    }
+
+    /// Stake of `addr` not vested yet at `epoch`.
+    pub fn locked_stake<BS: Blockstore>(
+        &self,
+        store: &BS,
+        addr: &Address,
+        epoch: ChainEpoch,
+    ) -> anyhow::Result<TokenAmount> {
+        Ok(self
+            .get_vesting(store, addr)?
+            .map_or_else(TokenAmount::zero, |v| v.locked(epoch)))
+    }
+
+    /// Vests `amount` of the stake of `addr` from `epoch` with `schedule`.
+    pub(crate) fn add_vesting<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        addr: &Address,
+        amount: &TokenAmount,
+        epoch: ChainEpoch,
+        schedule: VestingSchedule,
+    ) -> anyhow::Result<()> {
+        self.vesting.modify(store, |hamt| {
+            let key = BytesKey::from(addr.to_bytes());
+            if hamt.contains_key(&key)? {
+                return Err(SubnetActorError::AlreadyVesting(*addr).into());
             }
-            Status::Active => {
-                if self.total_stake < TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT) {
-                    self.status = Status::Inactive
-                }
+            hamt.set(
+                key,
+                Vesting {
+                    amount: amount.clone(),
+                    start: epoch,
+                    schedule,
+                },
+            )?;
+            Ok(true)
+        })?;
+        Ok(())
+    }
+
+    /// First epoch a checkpoint for `epoch` committed at `curr_epoch` can be
+    /// relayed to the gateway at: once its dispute period ends and `epoch`
+    /// is `finality_threshold` epochs old.
+    pub fn relay_epoch(&self, epoch: ChainEpoch, curr_epoch: ChainEpoch) -> ChainEpoch {
+        std::cmp::max(
+            curr_epoch.saturating_add(self.dispute_period),
+            epoch.saturating_add(self.finality_threshold),
+        )
+    }
+
+    /// Checks that `finality` is final with the `finality_threshold` of the
+    /// subnet at `curr_epoch`, and newer than the committed finality.
+    pub fn check_parent_finality(
+        &self,
+        finality: &ParentFinality,
+        curr_epoch: ChainEpoch,
+    ) -> anyhow::Result<()> {
+        let committed = self.parent_finality.as_ref().map_or(0, |f| f.height);
+        if finality.height > curr_epoch - self.finality_threshold || finality.height <= committed {
+            return Err(SubnetActorError::InvalidParentFinality(finality.height).into());
+        }
+        Ok(())
+    }
+
+    /// Records the vote of `addr` for a parent finality, committing it if it
+    /// reaches a majority. Returns whether it was committed.
+    pub(crate) fn vote_parent_finality<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        addr: &Address,
+        finality: ParentFinality,
+    ) -> anyhow::Result<bool> {
+        let cid = TCid::new_link(store, &finality)?.cid();
+        let mut votes = self.finality_votes.get(store, &cid)?.unwrap_or(Votes {
+            validators: Vec::new(),
+        });
+        if votes.validators.contains(addr) {
+            return Err(SubnetActorError::AlreadyVoted(*addr).into());
+        }
+        votes.validators.push(*addr);
+
+        if !self.has_current_majority(store, &votes)? {
+            self.finality_votes.set(store, &cid, votes)?;
+            return Ok(false);
+        }
+
+        // votes for finalities up to the committed one are not needed anymore
+        let mut stale = Vec::new();
+        for proposal in self.finality_votes.proposals(store)? {
+            let f: ParentFinality = store
+                .get_cbor(&proposal)?
+                .ok_or_else(|| anyhow!("parent finality {} not found", proposal))?;
+            if f.height <= finality.height {
+                stale.push(proposal);
             }
-            Status::Inactive => {
-                if self.total_stake >= TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT) {
-                    self.status = Status::Active
-                }
+        }
+        self.finality_votes.remove(store, &stale)?;
+        self.parent_finality = Some(finality);
+        Ok(true)
+    }
+
+    /// Removes the entries of the stake table of addresses without stake
+    /// left, among up to `limit` stakers of the staker index from `cursor`.
+    ///
+    /// Returns the number of entries removed, and the cursor to compact the
+    /// next page from, if any.
+    pub(crate) fn compact_stake<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        cursor: u64,
+        limit: u64,
+    ) -> anyhow::Result<(u64, Option<u64>)> {
+        let index = self.staker_index.load(store)?;
+        let stakes = self.stake.load(store)?;
+        let end = std::cmp::min(cursor.saturating_add(limit), self.staker_index_len);
+        let mut empty = Vec::new();
+        for i in cursor..end {
+            let key = match index.get(i)? {
+                Some(addr) => BytesKey::from(addr.to_bytes()),
+                None => continue,
+            };
+            if stakes.get(&key)?.map_or(false, |stake| stake.is_zero()) {
+                empty.push(key);
             }
-            // if no total_stake and current_balance left (except if we are testing where the funds
-            // are never leaving the actor)
-            Status::Terminating => {
-                if self.total_stake == TokenAmount::zero() {
-                    self.status = Status::Killed
+        }
+        if !empty.is_empty() {
+            self.stake.modify(store, |hamt| {
+                for k in &empty {
+                    hamt.delete(k)?;
                 }
-            }
-            _ => {}
+                Ok(true)
+            })?;
         }
+        let next = (end < self.staker_index_len).then_some(end);
+        Ok((empty.len() as u64, next))
     }
 
-    fn get_checkpoint<BS: Blockstore>(
-        &self,
-        store: &BS,
-        epoch: &ChainEpoch,
-    ) -> anyhow::Result<Option<Checkpoint>> {
-        let hamt = self
-            .checkpoints
-            .load(store)
-            .map_err(|e| anyhow!("failed to load checkpoints: {}", e))?;
-        let checkpoint = hamt
-            .get(&BytesKey::from(epoch.to_ne_bytes().to_vec()))
-            .map_err(|e| anyhow!("failed to get checkpoint for id {}: {:?}", epoch, e))?
-            .cloned();
-        Ok(checkpoint)
+    /// Seed of the leader rotation in the current checkpoint window: the
+    /// digest of the CID of the last committed checkpoint, empty until the
+    /// first checkpoint is committed.
+    pub fn rotation_seed<BS: Blockstore>(&self, store: &BS) -> anyhow::Result<Vec<u8>> {
+        Ok(self
+            .get_checkpoint(store, &self.last_committed_epoch)?
+            .map(|ch| ch.cid().hash().digest().to_vec())
+            .unwrap_or_default())
     }
 
-    pub fn is_validator(&self, addr: &Address) -> bool {
-        self.validator_set.iter().any(|x| x.addr == *addr)
+    /// Counts a change of the validator set against the churn limit of the
+    /// current window. Returns false if the limit was already reached, in
+    /// which case the change needs to be queued. Subnets are not limited
+    /// until they are bootstrapped.
+    pub(crate) fn consume_churn(&mut self) -> bool {
+        if self.churn_limit == 0 || self.status == Status::Instantiated {
+            return true;
+        }
+        let window = self.next_checkpoint_epoch();
+        if self.churn_window != window {
+            self.churn_window = window;
+            self.churn = 0;
+        }
+        if self.churn >= self.churn_limit {
+            return false;
+        }
+        self.churn += 1;
+        true
     }
 
-    /// Do not call this function in transaction
-    pub fn verify_checkpoint<BS, RT>(&self, rt: &mut RT, ch: &Checkpoint) -> anyhow::Result<()>
-    where
-        BS: Blockstore,
-        RT: Runtime<BS>,
-    {
-        // check that subnet is active
-        if self.status != Status::Active {
-            return Err(anyhow!(
-                "submitting checkpoints is not allowed while subnet is not active"
-            ));
+    /// Queues a change of the validator set to the next windows, replacing
+    /// any change already queued for the validator.
+    pub(crate) fn queue_validator_change(&mut self, validator: Validator, join: bool) {
+        self.pending_changes
+            .retain(|c| c.validator.addr != validator.addr);
+        self.pending_changes
+            .push(ValidatorChange { validator, join });
+    }
+
+    /// Counts the exit of a validator against the exit limit of the current
+    /// window. Returns false if the limit was already reached, in which
+    /// case the exit needs to wait in the exit queue. Subnets are not
+    /// limited until they are bootstrapped.
+    pub(crate) fn consume_exit(&mut self) -> bool {
+        if self.max_exits_per_window == 0 || self.status == Status::Instantiated {
+            return true;
         }
+        let window = self.next_checkpoint_epoch();
+        if self.exit_window != window {
+            self.exit_window = window;
+            self.exits = 0;
+        }
+        if self.exits >= self.max_exits_per_window {
+            return false;
+        }
+        self.exits += 1;
+        true
+    }
 
-        // check that a checkpoint for the epoch doesn't exist already.
-        if self.get_checkpoint(rt.store(), &ch.epoch())?.is_some() {
-            return Err(anyhow!("cannot submit checkpoint for epoch"));
-        };
+    /// Position of `addr` in the exit queue.
+    pub fn exit_queue_position(&self, addr: &Address) -> Option<u64> {
+        self.exit_queue
+            .iter()
+            .position(|e| e.validator == *addr)
+            .map(|p| p as u64)
+    }
 
-        // check that the epoch is correct
-        if ch.epoch() % self.check_period != 0 {
-            return Err(anyhow!(
-                "epoch in checkpoint doesn't correspond with a signing window"
-            ));
+    /// Removes the validators of the exit queue that fit in the exit limit
+    /// of the current window, in order. Their removal still counts against
+    /// the churn limit. Validators removed from the validator set while
+    /// queued, e.g. slashed below the minimum stake, are dropped.
+    pub(crate) fn process_exit_queue<BS: Blockstore>(&mut self, store: &BS) -> anyhow::Result<()> {
+        let queue = std::mem::take(&mut self.exit_queue);
+        for exit in queue {
+            let validator = match self.validators.get(store, &exit.validator)? {
+                Some(v) => v,
+                None => continue,
+            };
+            if !self.consume_exit() {
+                self.exit_queue.push(exit);
+                continue;
+            }
+            if self.consume_churn() {
+                self.remove_validator(store, &exit.validator)?;
+            } else {
+                self.queue_validator_change(validator, false);
+            }
         }
+        Ok(())
+    }
 
-        // check the source is correct
-        if *ch.source() != SubnetID::new(&self.parent_id, rt.message().receiver()) {
-            return Err(anyhow!("submitting checkpoint with the wrong source"));
+    /// Applies the queued changes of the validator set that fit in the
+    /// churn limit of the current window. Changes that don't apply anymore,
+    /// like joins of stakers that withdrew their stake, are dropped.
+    pub(crate) fn apply_pending_changes<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+    ) -> anyhow::Result<()> {
+        let pending = std::mem::take(&mut self.pending_changes);
+        for change in pending {
+            let addr = change.validator.addr;
+            let applies = if change.join {
+                let stake = self
+                    .get_stake(store, &addr)?
+                    .unwrap_or_else(TokenAmount::zero);
+                stake >= self.econ.min_validator_stake && !self.is_validator(store, &addr)?
+            } else {
+                self.is_validator(store, &addr)?
+            };
+            if !applies {
+                continue;
+            }
+            if !self.consume_churn() {
+                self.pending_changes.push(change);
+                continue;
+            }
+            if change.join {
+                self.admit_validator(store, change.validator)?;
+            } else {
+                self.remove_validator(store, &addr)?;
+            }
         }
+        Ok(())
+    }
 
-        // check previous checkpoint
-        if self.prev_checkpoint_cid(rt.store(), &ch.epoch())? != ch.prev_check().cid() {
-            return Err(anyhow!(
-                "previous checkpoint not consistent with previously committed"
-            ));
+    /// Removes `addr` from the validator set and the standby list. If it was
+    /// the delegate of a subnet with delegated consensus, the first staker
+    /// on standby is promoted to replace it.
+    pub(crate) fn remove_validator<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        addr: &Address,
+    ) -> anyhow::Result<()> {
+        let was_validator = self.validators.remove(store, addr)?;
+        self.standby.retain(|x| x.addr != *addr);
+        if was_validator {
+            self.config_number += 1;
+            self.record_validator_set(store)?;
+            self.log_membership_event(store, MembershipEventKind::Leave, addr)?;
+            self.membership_tickets.modify(store, |hamt| {
+                hamt.delete(&BytesKey::from(addr.to_bytes()))?;
+                Ok(true)
+            })?;
         }
 
-        // check signature
-        let caller = rt.message().caller();
-        let pkey = resolve_secp_bls(rt, &caller)?;
+        if !was_validator
+            || self.consensus != ConsensusType::Delegated
+            || !self.validators.is_empty()
+        {
+            return Ok(());
+        }
 
-        rt.verify_signature(
-            &RawBytes::deserialize(&ch.signature().clone().into())?,
-            &pkey,
-            &ch.cid().to_bytes(),
-        )?;
+        let delegate = if self.standby.is_empty() {
+            None
+        } else {
+            Some(self.standby.remove(0))
+        };
+        self.last_failover = Some(DelegateFailover {
+            nonce: self.last_failover.as_ref().map_or(1, |f| f.nonce + 1),
+            prev: *addr,
+            delegate: delegate.as_ref().map(|v| v.addr),
+        });
+        if let Some(v) = delegate {
+            self.admit_validator(store, v)?;
+        }
+        Ok(())
+    }
 
+    /// Adds a validator to the validator set, issuing its membership ticket
+    /// for the new configuration. Validators vote from the window after the
+    /// current one, whose weights are already snapshotted.
+    fn admit_validator<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        validator: Validator,
+    ) -> anyhow::Result<()> {
+        let addr = validator.addr;
+        let stake = self
+            .get_stake(store, &addr)?
+            .unwrap_or_else(TokenAmount::zero);
+        self.validators.insert(store, validator, stake)?;
+        self.config_number += 1;
+        self.record_validator_set(store)?;
+        self.log_membership_event(store, MembershipEventKind::Join, &addr)?;
+        let ticket = MembershipTicket {
+            validator: addr,
+            config_number: self.config_number,
+            effective_epoch: self.next_checkpoint_epoch(),
+        };
+        self.membership_tickets.modify(store, |hamt| {
+            hamt.set(BytesKey::from(addr.to_bytes()), ticket)?;
+            Ok(true)
+        })?;
         Ok(())
     }
 
-    fn prev_checkpoint_cid<BS: Blockstore>(
-        &self,
+    /// Records the validators of the current configuration of the
+    /// validator set, sorted by address so the digest doesn't depend on the
+    /// order of the set.
+    pub(crate) fn record_validator_set<BS: Blockstore>(
+        &mut self,
         store: &BS,
-        epoch: &ChainEpoch,
-    ) -> anyhow::Result<Cid> {
-        let mut epoch = epoch - self.check_period;
-        while epoch >= 0 {
-            match self.get_checkpoint(store, &epoch)? {
-                Some(ch) => return Ok(ch.cid()),
-                None => {
-                    epoch -= self.check_period;
-                }
-            }
-        }
-        Ok(Cid::default())
+    ) -> anyhow::Result<()> {
+        let mut validators = self.validators.list(store)?;
+        validators.sort_by_key(|v| v.addr.to_bytes());
+        let link = TCid::new_link(store, &validators)?;
+        let config_number = self.config_number;
+        self.validator_sets.modify(store, |amt| {
+            amt.set(config_number, link)?;
+            Ok(true)
+        })?;
+        Ok(())
     }
 
-    pub fn flush_checkpoint<BS: Blockstore>(
+    /// Appends an event to the membership log, in the current window and
+    /// configuration.
+    pub(crate) fn log_membership_event<BS: Blockstore>(
         &mut self,
         store: &BS,
-        ch: &Checkpoint,
+        kind: MembershipEventKind,
+        validator: &Address,
     ) -> anyhow::Result<()> {
-        let epoch = ch.epoch();
-        self.checkpoints.modify(store, |hamt| {
-            hamt.set(BytesKey::from(epoch.to_ne_bytes().to_vec()), ch.clone())
-                .map_err(|e| anyhow!("failed to set checkpoint: {:?}", e))?;
+        let event = MembershipEvent {
+            kind,
+            validator: *validator,
+            epoch: self.next_checkpoint_epoch(),
+            config_number: self.config_number,
+        };
+        let index = self.membership_events;
+        self.membership_log.modify(store, |amt| {
+            amt.set(index, event)?;
             Ok(true)
         })?;
+        self.membership_events += 1;
         Ok(())
     }
-}
 
-impl Default for State {
-    fn default() -> Self {
-        Self {
-            name: String::new(),
-            parent_id: SubnetID::default(),
-            ipc_gateway_addr: Address::new_id(0),
-            consensus: ConsensusType::Delegated,
-            min_validator_stake: TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT),
+    /// Page of `limit` events of the membership log from `cursor`, and the
+    /// cursor of the next page.
+    pub fn list_membership_history<BS: Blockstore>(
+        &self,
+        store: &BS,
+        cursor: u64,
+        limit: u64,
+    ) -> anyhow::Result<(Vec<MembershipEvent>, Option<u64>)> {
+        let amt = self.membership_log.load(store)?;
+        let end = std::cmp::min(cursor.saturating_add(limit), self.membership_events);
+        let mut events = Vec::new();
+        for index in cursor..end {
+            if let Some(event) = amt.get(index)? {
+                events.push(event.clone());
+            }
+        }
+        let next = (end < self.membership_events).then_some(end);
+        Ok((events, next))
+    }
+
+    /// Digest and validators of the configuration `config_number` of the
+    /// validator set, if it was recorded.
+    pub fn get_validator_set_at<BS: Blockstore>(
+        &self,
+        store: &BS,
+        config_number: u64,
+    ) -> anyhow::Result<Option<(Cid, Vec<Validator>)>> {
+        let amt = self.validator_sets.load(store)?;
+        let link = match amt.get(config_number)? {
+            Some(link) => link.clone(),
+            None => return Ok(None),
+        };
+        let validators = link.load(store)?.to_vec();
+        Ok(Some((link.cid(), validators)))
+    }
+
+    pub fn get_membership_ticket<BS: Blockstore>(
+        &self,
+        store: &BS,
+        addr: &Address,
+    ) -> anyhow::Result<Option<MembershipTicket>> {
+        let hamt = self.membership_tickets.load(store)?;
+        let ticket = hamt.get(&BytesKey::from(addr.to_bytes()))?;
+        Ok(ticket.cloned())
+    }
+
+    /// Checks if the votes for the checkpoint of the window at `epoch` reach
+    /// the voting threshold.
+    ///
+    /// Votes are weighted with the snapshot of the weights taken for the
+    /// window, so validators joining or leaving during the window don't
+    /// change the quorum. The weight of jailed validators is excluded, and
+    /// the quorum is that of the policy of the consensus.
+    pub fn has_majority_vote<BS: Blockstore>(
+        &self,
+        store: &BS,
+        epoch: ChainEpoch,
+        votes: &Votes,
+    ) -> Result<bool, ActorError> {
+        let snapshot = self.window_weights(store, epoch)?;
+        self.has_majority(store, snapshot, votes)
+    }
+
+    /// Weights votes in the window at `epoch` are weighted with.
+    fn window_weights<BS: Blockstore>(
+        &self,
+        store: &BS,
+        epoch: ChainEpoch,
+    ) -> Result<WeightSnapshot, ActorError> {
+        match self
+            .get_weight_snapshot(store, epoch)
+            .map_err(|_| actor_error!(illegal_state, "cannot load weight snapshot"))?
+        {
+            Some(snapshot) => Ok(snapshot),
+            None => self
+                .current_weights(store)
+                .map_err(|_| actor_error!(illegal_state, "cannot load stake from hamt")),
+        }
+    }
+
+    /// Checks if the votes reach the voting threshold with the current
+    /// weights of the validators.
+    pub fn has_current_majority<BS: Blockstore>(
+        &self,
+        store: &BS,
+        votes: &Votes,
+    ) -> Result<bool, ActorError> {
+        let weights = self
+            .current_weights(store)
+            .map_err(|_| actor_error!(illegal_state, "cannot load stake from hamt"))?;
+        self.has_majority(store, weights, votes)
+    }
+
+    fn has_majority<BS: Blockstore>(
+        &self,
+        store: &BS,
+        snapshot: WeightSnapshot,
+        votes: &Votes,
+    ) -> Result<bool, ActorError> {
+        let tally = self.tally(store, &snapshot, votes)?;
+        Ok(self.consensus.quorum_policy().has_quorum(&tally))
+    }
+
+    /// Weighs the votes with the quorum policy of the consensus, leaving
+    /// jailed validators out of the quorum.
+    fn tally<BS: Blockstore>(
+        &self,
+        store: &BS,
+        snapshot: &WeightSnapshot,
+        votes: &Votes,
+    ) -> Result<Tally, ActorError> {
+        Tally::with_policy(self.consensus.quorum_policy(), snapshot, votes, |addr| {
+            self.is_jailed(store, addr)
+        })
+        .map_err(|_| actor_error!(illegal_state, "cannot load participation"))
+    }
+
+    /// Current weights of the validators, their stake capped to
+    /// `max_voting_power_pct` of the total.
+    fn current_weights<BS: Blockstore>(&self, store: &BS) -> anyhow::Result<WeightSnapshot> {
+        let mut collateral = Vec::new();
+        for addr in self.validators.addrs() {
+            let stake = self
+                .get_stake(store, addr)?
+                .unwrap_or_else(TokenAmount::zero);
+            collateral.push((*addr, stake));
+        }
+        let stakes: Vec<TokenAmount> = collateral.iter().map(|(_, s)| s.clone()).collect();
+        let powers = self.cap_voting_power(&stakes, &self.total_stake);
+        let excess = stakes
+            .iter()
+            .zip(&powers)
+            .fold(TokenAmount::zero(), |sum, (s, p)| sum + s - p);
+        Ok(WeightSnapshot {
+            weights: collateral
+                .iter()
+                .map(|(addr, _)| *addr)
+                .zip(powers)
+                .collect(),
+            total: &self.total_stake - &excess,
+            config_number: self.config_number,
+            collateral,
+        })
+    }
+
+    /// Voting power of `stakes` out of a `total` stake, capped by
+    /// `max_voting_power_pct`.
+    fn cap_voting_power(&self, stakes: &[TokenAmount], total: &TokenAmount) -> Vec<TokenAmount> {
+        match self.max_voting_power_pct {
+            Some(pct) => {
+                let sum = stakes.iter().fold(TokenAmount::zero(), |sum, s| sum + s);
+                let others = if *total > sum {
+                    total - &sum
+                } else {
+                    TokenAmount::zero()
+                };
+                stake_math::cap_voting_power(stakes, &others, pct)
+            }
+            None => stakes.to_vec(),
+        }
+    }
+
+    pub fn get_weight_snapshot<BS: Blockstore>(
+        &self,
+        store: &BS,
+        epoch: ChainEpoch,
+    ) -> anyhow::Result<Option<WeightSnapshot>> {
+        let hamt = self.weight_snapshots.load(store)?;
+        let snapshot = hamt.get(&keys::epoch_key(epoch))?;
+        Ok(snapshot.cloned())
+    }
+
+    /// Configuration of the validator set the window at `epoch` is voted
+    /// with: the one of its weight snapshot, or the current one until the
+    /// window gets its first vote.
+    pub fn window_config_number<BS: Blockstore>(
+        &self,
+        store: &BS,
+        epoch: ChainEpoch,
+    ) -> anyhow::Result<u64> {
+        Ok(self
+            .get_weight_snapshot(store, epoch)?
+            .map_or(self.config_number, |s| s.config_number))
+    }
+
+    /// Snapshots the current weights of the validators for the window at
+    /// `epoch`, unless they have already been snapshotted.
+    pub(crate) fn snapshot_weights<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        epoch: ChainEpoch,
+    ) -> anyhow::Result<()> {
+        if self.get_weight_snapshot(store, epoch)?.is_some() {
+            return Ok(());
+        }
+        let snapshot = self.current_weights(store)?;
+        self.weight_snapshots.modify(store, |hamt| {
+            hamt.set(keys::epoch_key(epoch), snapshot)?;
+            Ok(true)
+        })?;
+        Ok(())
+    }
+
+    pub(crate) fn remove_weight_snapshot<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        epoch: ChainEpoch,
+    ) -> anyhow::Result<()> {
+        self.weight_snapshots.modify(store, |hamt| {
+            hamt.delete(&keys::epoch_key(epoch))?;
+            Ok(true)
+        })?;
+        Ok(())
+    }
+
+    pub fn get_participation<BS: Blockstore>(
+        &self,
+        store: &BS,
+        addr: &Address,
+    ) -> anyhow::Result<Option<Participation>> {
+        let hamt = self.participation.load(store)?;
+        let participation = hamt.get(&BytesKey::from(addr.to_bytes()))?;
+        Ok(participation.cloned())
+    }
+
+    pub fn is_jailed<BS: Blockstore>(&self, store: &BS, addr: &Address) -> anyhow::Result<bool> {
+        Ok(self
+            .get_participation(store, addr)?
+            .map_or(false, |p| p.jailed))
+    }
+
+    /// Checks if the address is a validator that is not jailed.
+    pub fn is_active_validator<BS: Blockstore>(
+        &self,
+        store: &BS,
+        addr: &Address,
+    ) -> anyhow::Result<bool> {
+        Ok(self.is_validator(store, addr)? && !self.is_jailed(store, addr)?)
+    }
+
+    /// Records that the validator was seen at `epoch`.
+    pub(crate) fn record_seen<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        addr: &Address,
+        epoch: ChainEpoch,
+    ) -> anyhow::Result<()> {
+        self.last_seen.modify(store, |hamt| {
+            hamt.set(BytesKey::from(addr.to_bytes()), epoch)?;
+            Ok(true)
+        })?;
+        Ok(())
+    }
+
+    /// Last epoch at which the validator was seen.
+    pub fn get_last_seen<BS: Blockstore>(
+        &self,
+        store: &BS,
+        addr: &Address,
+    ) -> anyhow::Result<Option<ChainEpoch>> {
+        let hamt = self.last_seen.load(store)?;
+        let epoch = hamt.get(&BytesKey::from(addr.to_bytes()))?;
+        Ok(epoch.copied())
+    }
+
+    /// Validators seen at `since` or later, in the order of the validator
+    /// set.
+    pub fn live_validators<BS: Blockstore>(
+        &self,
+        store: &BS,
+        since: ChainEpoch,
+    ) -> anyhow::Result<Vec<Address>> {
+        let hamt = self.last_seen.load(store)?;
+        let mut live = Vec::new();
+        for addr in self.validators.addrs() {
+            if let Some(epoch) = hamt.get(&BytesKey::from(addr.to_bytes()))? {
+                if *epoch >= since {
+                    live.push(*addr);
+                }
+            }
+        }
+        Ok(live)
+    }
+
+    /// Records the participation of validators in a committed checkpoint.
+    ///
+    /// Validators that didn't vote for it miss the window, and are jailed
+    /// after missing `max_missed_windows` consecutive windows. A jailed
+    /// delegate is replaced by the first staker on standby.
+    pub(crate) fn record_participation<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        voters: &[Address],
+    ) -> anyhow::Result<()> {
+        let max_missed = self.max_missed_windows;
+        let validators = self.validators.addrs().copied().collect::<Vec<_>>();
+        let mut jailed = Vec::new();
+        self.participation.modify(store, |hamt| {
+            for addr in validators {
+                let key = BytesKey::from(addr.to_bytes());
+                let mut p = hamt.get(&key)?.cloned().unwrap_or_default();
+                if voters.contains(&addr) {
+                    p.missed_windows = 0;
+                } else {
+                    p.missed_windows += 1;
+                    if max_missed > 0 && p.missed_windows >= max_missed && !p.jailed {
+                        p.jailed = true;
+                        jailed.push(addr);
+                    }
+                }
+                hamt.set(key, p)?;
+            }
+            Ok(true)
+        })?;
+
+        for addr in &jailed {
+            self.log_membership_event(store, MembershipEventKind::Jail, addr)?;
+        }
+        if self.consensus == ConsensusType::Delegated {
+            for addr in jailed {
+                self.remove_validator(store, &addr)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Releases a validator from jail, charging it a penalty on its stake.
+    /// Returns the penalty charged.
+    pub(crate) fn unjail<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        addr: &Address,
+    ) -> anyhow::Result<TokenAmount> {
+        if !self.is_jailed(store, addr)? {
+            return Err(SubnetActorError::NotJailed(*addr).into());
+        }
+
+        let stake = self
+            .get_stake(store, addr)?
+            .unwrap_or_else(TokenAmount::zero);
+        let penalty = stake.div_floor(UNJAIL_PENALTY_DIVISOR);
+        let remaining = stake_math::checked_sub(&stake, &penalty)?;
+        // the penalty can't evict a validator on its own: validators it
+        // would leave below the minimum stake need to top up first
+        if remaining < self.econ.min_validator_stake
+            && stake >= self.econ.min_validator_stake
+            && self.is_validator(store, addr)?
+        {
+            return Err(SubnetActorError::UnjailBelowMinStake(*addr).into());
+        }
+        self.stake.modify(store, |hamt| {
+            hamt.set(BytesKey::from(addr.to_bytes()), remaining.clone())?;
+            Ok(true)
+        })?;
+        self.total_stake = stake_math::checked_sub(&self.total_stake, &penalty)?;
+        self.forfeit(&penalty);
+        self.debit_funding(store, addr, &penalty, false)?;
+        self.penalize_delegations(store, addr, &stake, &penalty)?;
+
+        // validators already below the minimum stake need to join again, as
+        // do jailed delegates, which were replaced when jailed
+        if remaining < self.econ.min_validator_stake {
+            self.remove_validator(store, addr)?;
+        }
+
+        self.participation.modify(store, |hamt| {
+            hamt.set(
+                BytesKey::from(addr.to_bytes()),
+                Participation {
+                    missed_windows: 0,
+                    jailed: false,
+                },
+            )?;
+            Ok(true)
+        })?;
+        self.log_membership_event(store, MembershipEventKind::Unjail, addr)?;
+
+        self.validators.update_stake(addr, remaining);
+        Ok(penalty)
+    }
+
+    /// Takes the relayer fee of a committed checkpoint from the reward
+    /// pool, or whatever is left in the pool if it can't cover the fee.
+    pub(crate) fn take_relayer_fee(&mut self) -> TokenAmount {
+        let fee = std::cmp::min(&self.econ.relayer_fee, &self.reward_pool).clone();
+        self.reward_pool -= &fee;
+        fee
+    }
+
+    /// Updates the status of the subnet with [`State::mutate_state`],
+    /// recording the epoch it changed at and, the first time the subnet
+    /// becomes active, its activation.
+    pub(crate) fn update_status(&mut self, epoch: ChainEpoch, caller: Address) {
+        let prev = self.status;
+        self.mutate_state();
+        if self.status == prev {
+            return;
+        }
+        self.status_changed_at = epoch;
+        if self.status == Status::Active && self.activation.is_none() {
+            self.activation = Some(Activation {
+                epoch,
+                sender: caller,
+            });
+        }
+    }
+
+    pub fn mutate_state(&mut self) {
+        match self.status {
+            Status::Instantiated => {
+                if self.total_stake >= self.econ.min_total_collateral {
+                    self.status = Status::Active
+                }
+            }
+            Status::Active => {
+                if self.total_stake < self.econ.min_total_collateral {
+                    self.status = Status::Inactive
+                }
+            }
+            Status::Inactive => {
+                if self.total_stake >= self.econ.min_total_collateral {
+                    self.status = Status::Active
+                }
+            }
+            // if no total_stake and current_balance left (except if we are testing where the funds
+            // are never leaving the actor)
+            Status::Terminating => {
+                if self.total_stake == TokenAmount::zero() {
+                    self.status = Status::Killed
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Checks the structural consistency of the state, for tests and
+    /// migrations. Errors are only returned when the state can't be read.
+    pub fn check_invariants<BS: Blockstore>(&self, store: &BS) -> anyhow::Result<InvariantReport> {
+        let mut report = InvariantReport::default();
+
+        let mut staked = TokenAmount::zero();
+        self.stake.load(store)?.for_each(|_, stake| {
+            staked += stake;
+            Ok(())
+        })?;
+        report.check(staked == self.total_stake, || {
+            format!(
+                "total stake {} doesn't match the stake table {}",
+                self.total_stake, staked
+            )
+        });
+
+        // funding ledgers sum to the stake of their staker
+        let mut stakers = Vec::new();
+        self.stake.load(store)?.for_each(|k, stake| {
+            if !stake.is_zero() {
+                stakers.push((Address::from_bytes(&k.0)?, stake.clone()));
+            }
+            Ok(())
+        })?;
+        for (addr, stake) in &stakers {
+            let funded = self
+                .get_funding(store, addr)?
+                .iter()
+                .fold(TokenAmount::zero(), |sum, f| sum + &f.amount);
+            report.check(funded == *stake, || {
+                format!(
+                    "funding of {} doesn't match its stake {}: {}",
+                    addr, stake, funded
+                )
+            });
+        }
+        let mut ledgers = 0;
+        self.funding.load(store)?.for_each(|_, _| {
+            ledgers += 1;
+            Ok(())
+        })?;
+        report.check(ledgers == stakers.len(), || {
+            format!("{} funding ledgers for {} stakers", ledgers, stakers.len())
+        });
+
+        for entry in self.validators.entries() {
+            let addr = &entry.addr;
+            report.check(self.validators.contains(store, addr)?, || {
+                format!("validator {} indexed but not in the validator set", addr)
+            });
+            let stake = self
+                .get_stake(store, addr)?
+                .unwrap_or_else(TokenAmount::zero);
+            report.check(stake >= self.econ.min_validator_stake, || {
+                format!("validator {} below the minimum stake: {}", addr, stake)
+            });
+            report.check(entry.stake == stake, || {
+                format!(
+                    "validator {} indexed with stake {}, has {}",
+                    addr, entry.stake, stake
+                )
+            });
+        }
+        let order = self
+            .validators
+            .entries()
+            .windows(2)
+            .all(|w| w[0].precedes(&w[1]));
+        report.check(order, || "validator set not ordered by stake".to_string());
+
+        // votes are only kept for the candidates of windows not committed yet
+        let mut candidates = Vec::new();
+        self.candidates.load(store)?.for_each(|k, cids| {
+            let epoch = keys::parse_epoch_key(&k.0)?;
+            report.check(epoch > self.last_committed_epoch, || {
+                format!("candidates kept for committed epoch {}", epoch)
+            });
+            candidates.extend_from_slice(cids);
+            Ok(())
+        })?;
+        for cid in self.window_checks.proposals(store)? {
+            report.check(candidates.contains(&cid), || {
+                format!("votes kept for checkpoint {} of no open window", cid)
+            });
+        }
+
+        let active = self.total_stake >= self.econ.min_total_collateral;
+        let consistent = match self.status {
+            Status::Instantiated | Status::Inactive => !active,
+            Status::Active => active,
+            Status::Terminating => true,
+            Status::Killed => self.total_stake.is_zero(),
+        };
+        report.check(consistent, || {
+            format!(
+                "status {:?} inconsistent with total stake {}",
+                self.status, self.total_stake
+            )
+        });
+
+        Ok(report)
+    }
+
+    pub(crate) fn get_checkpoint<BS: Blockstore>(
+        &self,
+        store: &BS,
+        epoch: &ChainEpoch,
+    ) -> anyhow::Result<Option<Checkpoint>> {
+        let window = self.find_checkpoint_window(store, *epoch)?;
+        match window {
+            Ok(window) => self.get_checkpoint_by_window(store, window),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Bisects the committed windows for the checkpoint at `epoch`.
+    ///
+    /// Returns the window number of the checkpoint, or the error with the
+    /// window number of the first checkpoint after `epoch` if none was
+    /// committed at it, like [`slice::binary_search`].
+    fn find_checkpoint_window<BS: Blockstore>(
+        &self,
+        store: &BS,
+        epoch: ChainEpoch,
+    ) -> anyhow::Result<Result<u64, u64>> {
+        let amt = self
+            .checkpoints
+            .load(store)
+            .map_err(|e| anyhow!("failed to load checkpoints: {}", e))?;
+        let (mut low, mut high) = (0, self.checkpoints_count);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            let ch = amt
+                .get(mid)?
+                .ok_or_else(|| anyhow!("missing checkpoint of window {}", mid))?;
+            match ch.epoch().cmp(&epoch) {
+                Ordering::Equal => return Ok(Ok(mid)),
+                Ordering::Less => low = mid + 1,
+                Ordering::Greater => high = mid,
+            }
+        }
+        Ok(Err(low))
+    }
+
+    /// Registers new invitation commitments issued by the owner.
+    pub fn add_invitations<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        commitments: &[Vec<u8>],
+    ) -> anyhow::Result<()> {
+        self.invitations.modify(store, |hamt| {
+            for c in commitments {
+                if c.len() != INVITATION_COMMITMENT_LEN {
+                    return Err(anyhow!(
+                        "invitation commitments need to be {} bytes",
+                        INVITATION_COMMITMENT_LEN
+                    ));
+                }
+                let key = BytesKey::from(c.clone());
+                if hamt.contains_key(&key)? {
+                    return Err(SubnetActorError::InvitationAlreadyIssued.into());
+                }
+                hamt.set(key, Invitation { redeemed_by: None })?;
+            }
+            Ok(true)
+        })?;
+        Ok(())
+    }
+
+    pub fn get_invitation<BS: Blockstore>(
+        &self,
+        store: &BS,
+        commitment: &[u8],
+    ) -> anyhow::Result<Option<Invitation>> {
+        let hamt = self.invitations.load(store)?;
+        let invitation = hamt.get(&BytesKey::from(commitment.to_vec()))?;
+        Ok(invitation.cloned())
+    }
+
+    /// Marks the invitation for the commitment as redeemed by `addr`.
+    /// Invitations are single-use, so redeeming an invitation twice fails.
+    pub(crate) fn redeem_invitation<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        commitment: &[u8],
+        addr: &Address,
+    ) -> anyhow::Result<()> {
+        self.check_invitation(store, commitment)?;
+        self.invitations.modify(store, |hamt| {
+            hamt.set(
+                BytesKey::from(commitment.to_vec()),
+                Invitation {
+                    redeemed_by: Some(*addr),
+                },
+            )?;
+            Ok(true)
+        })?;
+        Ok(())
+    }
+
+    /// Checks that the invitation of `commitment` was issued and is yet to
+    /// be redeemed.
+    pub fn check_invitation<BS: Blockstore>(
+        &self,
+        store: &BS,
+        commitment: &[u8],
+    ) -> anyhow::Result<()> {
+        let hamt = self.invitations.load(store)?;
+        match hamt.get(&BytesKey::from(commitment.to_vec()))? {
+            None => Err(SubnetActorError::InvalidInvitation.into()),
+            Some(inv) if inv.redeemed_by.is_some() => {
+                Err(SubnetActorError::InvitationAlreadyRedeemed.into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Registers the CID of a batch of bottom-up messages by `registrant`
+    /// for the checkpoint of the window starting at `epoch`.
+    ///
+    /// Batches can only be registered for the next window or, while
+    /// catching up, for a window that has already closed, and each
+    /// validator registers at most `MAX_CROSS_MSG_BATCHES_PER_VALIDATOR`
+    /// batches in a window.
+    pub(crate) fn register_cross_msgs<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        epoch: &ChainEpoch,
+        msgs_cid: &Cid,
+        registrant: &Address,
+        current_epoch: ChainEpoch,
+    ) -> anyhow::Result<()> {
+        if !self.is_window_epoch(*epoch) {
+            return Err(SubnetActorError::NotSigningWindow.into());
+        }
+        if self.get_checkpoint(store, epoch)?.is_some() {
+            return Err(SubnetActorError::CheckpointAlreadyCommitted.into());
+        }
+        let windows = self.checkpoint_windows();
+        if *epoch != windows.next_epoch && !windows.is_catch_up_open(*epoch, current_epoch) {
+            return Err(SubnetActorError::WindowNotOpen(*epoch).into());
+        }
+
+        let key = keys::epoch_key(*epoch);
+        let mut batches = self
+            .cross_msgs
+            .load(store)?
+            .get(&key)?
+            .cloned()
+            .unwrap_or_default();
+        if batches.contains(msgs_cid) {
+            return Ok(());
+        }
+        let mut registrants = self
+            .cross_msg_registrants
+            .load(store)?
+            .get(&key)?
+            .cloned()
+            .unwrap_or_default();
+        if registrants.iter().filter(|a| *a == registrant).count()
+            >= MAX_CROSS_MSG_BATCHES_PER_VALIDATOR
+        {
+            return Err(SubnetActorError::TooManyCrossMsgBatches(*registrant).into());
+        }
+        batches.push(*msgs_cid);
+        registrants.push(*registrant);
+
+        self.cross_msgs.modify(store, |hamt| {
+            hamt.set(key.clone(), batches)?;
+            Ok(true)
+        })?;
+        self.cross_msg_registrants.modify(store, |hamt| {
+            hamt.set(key, registrants)?;
+            Ok(true)
+        })?;
+        Ok(())
+    }
+
+    /// Puts a batch of bottom-up messages in the store and registers it for
+    /// the window ending at `epoch`, so checkpoints too large to carry it
+    /// can reference it by CID. Returns the CID of the batch.
+    pub(crate) fn put_cross_msg_batch<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        epoch: &ChainEpoch,
+        batch: &[u8],
+        registrant: &Address,
+        current_epoch: ChainEpoch,
+    ) -> anyhow::Result<Cid> {
+        if batch.len() > MAX_CROSS_MSG_BATCH_SIZE {
+            return Err(SubnetActorError::CrossMsgBatchTooLarge(batch.len()).into());
+        }
+        // reject anything the nodes couldn't decode from the CID
+        fvm_ipld_encoding::from_slice::<serde::de::IgnoredAny>(batch)?;
+
+        let msgs_cid = store.put(Code::Blake2b256, &Block::new(DAG_CBOR, batch))?;
+        self.register_cross_msgs(store, epoch, &msgs_cid, registrant, current_epoch)?;
+        Ok(msgs_cid)
+    }
+
+    /// Checks if a batch of bottom-up messages has been registered for the window.
+    pub fn is_cross_msgs_registered<BS: Blockstore>(
+        &self,
+        store: &BS,
+        epoch: &ChainEpoch,
+        msgs_cid: &Cid,
+    ) -> anyhow::Result<bool> {
+        let hamt = self.cross_msgs.load(store)?;
+        let batches = hamt.get(&keys::epoch_key(*epoch))?;
+        Ok(batches.is_some_and(|b| b.contains(msgs_cid)))
+    }
+
+    /// Removes the batches registered for a window once its checkpoint is committed.
+    pub(crate) fn remove_cross_msgs<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        epoch: &ChainEpoch,
+    ) -> anyhow::Result<()> {
+        self.cross_msgs.modify(store, |hamt| {
+            hamt.delete(&keys::epoch_key(*epoch))?;
+            Ok(true)
+        })?;
+        self.cross_msg_registrants.modify(store, |hamt| {
+            hamt.delete(&keys::epoch_key(*epoch))?;
+            Ok(true)
+        })?;
+        Ok(())
+    }
+
+    pub fn is_validator<BS: Blockstore>(&self, store: &BS, addr: &Address) -> anyhow::Result<bool> {
+        self.validators.contains(store, addr)
+    }
+
+    /// Do not call this function in transaction
+    pub fn verify_checkpoint<BS, RT>(
+        &self,
+        rt: &mut RT,
+        submission: &CheckpointSubmission,
+        catch_up: bool,
+    ) -> anyhow::Result<()>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        self.check_checkpoint(rt, submission, catch_up)?;
+        self.verify_caller_signature(rt, submission)
+    }
+
+    /// Verifies that the submission is signed by the caller, or by the
+    /// signing key of the validator the caller is the worker of, with the
+    /// signature carried by its checkpoint.
+    ///
+    /// Do not call this function in transaction
+    pub fn verify_caller_signature<BS, RT>(
+        &self,
+        rt: &mut RT,
+        submission: &CheckpointSubmission,
+    ) -> anyhow::Result<()>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        let caller = self.validator_for_caller(rt.store(), &rt.message().caller())?;
+        let sig: Signature =
+            RawBytes::deserialize(&submission.checkpoint.signature().clone().into())?;
+        self.verify_checkpoint_signature(rt, &caller, &sig, submission)
+    }
+
+    /// Checks that the checkpoint is valid for the next window of the subnet
+    /// without verifying its signature.
+    ///
+    /// With `catch_up`, checkpoints for a later window covering the windows
+    /// skipped since the last committed checkpoint are accepted.
+    pub fn check_checkpoint<BS, RT>(
+        &self,
+        rt: &RT,
+        submission: &CheckpointSubmission,
+        catch_up: bool,
+    ) -> anyhow::Result<()>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        let ch = &submission.checkpoint;
+        self.check_checkpoint_epoch(rt.store(), ch.epoch(), rt.curr_epoch(), catch_up)?;
+
+        // check the size before anything is stored or voted for it
+        let size = fvm_ipld_encoding::to_vec(ch)?.len();
+        if size > MAX_CHECKPOINT_SIZE {
+            return Err(SubnetActorError::CheckpointTooLarge(size).into());
+        }
+
+        // check the source is correct
+        if *ch.source() != compat::subnet_id(&self.parent_id, rt.message().receiver())? {
+            return Err(SubnetActorError::WrongCheckpointSource.into());
+        }
+
+        // check previous checkpoint
+        if self.prev_checkpoint_cid(rt.store())? != ch.prev_check().cid() {
+            return Err(SubnetActorError::InconsistentPrevCheckpoint.into());
+        }
+
+        // check that the batch of bottom-up messages was registered in the window
+        if let Some(meta) = &ch.data.cross_msgs {
+            if !self.is_cross_msgs_registered(rt.store(), &ch.epoch(), &meta.msgs_cid.cid())? {
+                return Err(SubnetActorError::CrossMsgsNotRegistered.into());
+            }
+        }
+
+        // check that the claimed state is not older than the last committed
+        // one, nor ahead of the window
+        let commitment = &submission.state_commitment;
+        let last_height = self.last_state_commitment.as_ref().map_or(0, |c| c.height);
+        if commitment.height > ch.epoch() || commitment.height < last_height {
+            return Err(SubnetActorError::InvalidStateCommitment.into());
+        }
+
+        // check the checkpoint was produced under the validator set its
+        // window is voted with. Configurations from before validator sets
+        // were recorded are only checked by number.
+        let config_number = self.window_config_number(rt.store(), ch.epoch())?;
+        let digest = self
+            .get_validator_set_at(rt.store(), config_number)?
+            .map(|(digest, _)| digest);
+        if commitment.config_number != config_number
+            || digest.is_some_and(|d| d != commitment.validator_set)
+        {
+            return Err(SubnetActorError::ValidatorSetMismatch(config_number).into());
+        }
+
+        Ok(())
+    }
+
+    /// Checks that a checkpoint for the window ending at `epoch` can be
+    /// submitted at `current_epoch`, regardless of its contents.
+    pub fn check_checkpoint_epoch<BS: Blockstore>(
+        &self,
+        store: &BS,
+        epoch: ChainEpoch,
+        current_epoch: ChainEpoch,
+        catch_up: bool,
+    ) -> anyhow::Result<()> {
+        // check that subnet is active
+        if self.status != Status::Active {
+            return Err(SubnetActorError::SubnetNotActive.into());
+        }
+
+        // check that a checkpoint for the epoch doesn't exist already.
+        if self.get_checkpoint(store, &epoch)?.is_some() {
+            return Err(SubnetActorError::CheckpointAlreadyCommitted.into());
+        };
+
+        // check that the epoch is correct
+        let windows = self.checkpoint_windows();
+        if !windows.is_window_end(epoch) {
+            return Err(SubnetActorError::NotSigningWindow.into());
+        }
+
+        // check that the checkpoint is for the next window, or for a window
+        // that has already closed when catching up with skipped windows
+        let next_epoch = windows.next_epoch;
+        if catch_up {
+            if !windows.is_catch_up_open(epoch, current_epoch) {
+                return Err(SubnetActorError::NoSkippedWindows.into());
+            }
+        } else if epoch != next_epoch {
+            return Err(SubnetActorError::NotNextWindow(next_epoch).into());
+        }
+        Ok(())
+    }
+
+    /// Verifies the signature of a checkpoint submission by `signer`, over
+    /// the digest of the submission.
+    ///
+    /// Do not call this function in transaction
+    pub fn verify_checkpoint_signature<BS, RT>(
+        &self,
+        rt: &mut RT,
+        signer: &Address,
+        sig: &Signature,
+        submission: &CheckpointSubmission,
+    ) -> anyhow::Result<()>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        let plaintext = submission.digest()?.to_bytes();
+        let epoch = submission.checkpoint.epoch();
+        self.verify_validator_signature(rt, signer, sig, &plaintext, epoch)
+    }
+
+    /// Verifies the signature of `plaintext` by `signer`, with the key it
+    /// signs checkpoints with at `epoch`.
+    ///
+    /// Do not call this function in transaction
+    pub fn verify_validator_signature<BS, RT>(
+        &self,
+        rt: &mut RT,
+        signer: &Address,
+        sig: &Signature,
+        plaintext: &[u8],
+        epoch: ChainEpoch,
+    ) -> anyhow::Result<()>
+    where
+        BS: Blockstore,
+        RT: Runtime<BS>,
+    {
+        let pkey = match self.signing_key(rt.store(), signer, epoch)? {
+            Some(key) => key,
+            None if eth::is_eth_address(signer) => *signer,
+            None => resolve_secp_bls(rt, signer)?,
+        };
+        self.sig_scheme.verify(rt, sig, &pkey, plaintext)
+    }
+
+    pub fn get_validator_key<BS: Blockstore>(
+        &self,
+        store: &BS,
+        addr: &Address,
+    ) -> anyhow::Result<Option<ValidatorKey>> {
+        let hamt = self.validator_keys.load(store)?;
+        let key = hamt.get(&BytesKey::from(addr.to_bytes()))?;
+        Ok(key.cloned())
+    }
+
+    /// Key `addr` signs the checkpoint of the window at `epoch` with, if it
+    /// rotated away from the key of its account.
+    pub fn signing_key<BS: Blockstore>(
+        &self,
+        store: &BS,
+        addr: &Address,
+        epoch: ChainEpoch,
+    ) -> anyhow::Result<Option<Address>> {
+        Ok(self.get_validator_key(store, addr)?.and_then(|k| {
+            if epoch >= k.effective_at {
+                Some(k.key)
+            } else {
+                k.prev
+            }
+        }))
+    }
+
+    /// Rotates the signing key of a validator from the next checkpoint
+    /// window. `nonce` needs to be the number of rotations done so far.
+    pub(crate) fn rotate_validator_key<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        addr: &Address,
+        key: Address,
+        nonce: u64,
+    ) -> anyhow::Result<()> {
+        let current = self.get_validator_key(store, addr)?;
+        let expected = current.as_ref().map_or(0, |k| k.nonce);
+        if nonce != expected {
+            return Err(SubnetActorError::InvalidKeyNonce(expected).into());
+        }
+
+        let effective_at = self.next_checkpoint_epoch();
+        let prev = match current {
+            // the previous rotation is not in effect yet
+            Some(k) if k.effective_at >= effective_at => k.prev,
+            Some(k) => Some(k.key),
+            None => None,
+        };
+        self.validator_keys.modify(store, |hamt| {
+            hamt.set(
+                BytesKey::from(addr.to_bytes()),
+                ValidatorKey {
+                    key,
+                    effective_at,
+                    prev,
+                    nonce: nonce + 1,
+                },
+            )?;
+            Ok(true)
+        })?;
+        self.log_membership_event(store, MembershipEventKind::Rotate, addr)
+    }
+
+    /// Registers the worker key of `owner`, which signs its checkpoints from
+    /// now on. `worker_id` is the ID address of the worker, which submits
+    /// checkpoints on behalf of `owner`.
+    pub(crate) fn register_worker<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        owner: &Address,
+        key: Address,
+        worker_id: &Address,
+    ) -> anyhow::Result<()> {
+        if self.get_validator_key(store, owner)?.is_some() {
+            return Err(anyhow!(
+                "{} already has a signing key, it needs to rotate it instead",
+                owner
+            ));
+        }
+        self.set_worker(store, owner, Some(worker_id))?;
+        self.validator_keys.modify(store, |hamt| {
+            hamt.set(
+                BytesKey::from(owner.to_bytes()),
+                ValidatorKey {
+                    key,
+                    effective_at: 0,
+                    prev: None,
+                    nonce: 0,
+                },
+            )?;
+            Ok(true)
+        })?;
+        Ok(())
+    }
+
+    /// Replaces the worker submitting the checkpoints of `owner`.
+    pub(crate) fn set_worker<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        owner: &Address,
+        worker_id: Option<&Address>,
+    ) -> anyhow::Result<()> {
+        if let Some(worker) = worker_id {
+            let current = self
+                .workers
+                .load(store)?
+                .get(&BytesKey::from(worker.to_bytes()))?
+                .cloned();
+            let has_stake = self.get_stake(store, worker)?.is_some_and(|s| !s.is_zero());
+            if current.is_some_and(|o| o != *owner) || worker == owner || has_stake {
+                return Err(SubnetActorError::WorkerKeyInUse(*worker).into());
+            }
+        }
+        self.workers.modify(store, |hamt| {
+            let mut prev = Vec::new();
+            hamt.for_each(|k, v| {
+                if v == owner {
+                    prev.push(k.clone());
+                }
+                Ok(())
+            })?;
+            for k in prev {
+                hamt.delete(&k)?;
+            }
+            if let Some(worker) = worker_id {
+                hamt.set(BytesKey::from(worker.to_bytes()), *owner)?;
+            }
+            Ok(true)
+        })?;
+        Ok(())
+    }
+
+    /// Validator a message from `caller` acts for: `caller` itself if it is
+    /// a validator, or the validator it is the worker of.
+    pub fn validator_for_caller<BS: Blockstore>(
+        &self,
+        store: &BS,
+        caller: &Address,
+    ) -> anyhow::Result<Address> {
+        if self.is_validator(store, caller)? {
+            return Ok(*caller);
+        }
+        let hamt = self.workers.load(store)?;
+        let owner = hamt.get(&BytesKey::from(caller.to_bytes()))?;
+        Ok(owner.cloned().unwrap_or(*caller))
+    }
+
+    /// Epoch of the next checkpoint expected for the subnet.
+    pub fn next_checkpoint_epoch(&self) -> ChainEpoch {
+        self.last_committed_epoch + self.check_period
+    }
+
+    /// Whether `epoch` closes a checkpoint window.
+    ///
+    /// While a change of the checkpoint period is pending, the windows of
+    /// both periods are valid: the current ones until the change takes
+    /// effect, and the new ones after it.
+    pub fn is_window_epoch(&self, epoch: ChainEpoch) -> bool {
+        self.checkpoint_windows().is_window_end(epoch)
+    }
+
+    /// Checkpoint windows of the subnet, with the window math checkpoints
+    /// are validated with.
+    pub fn checkpoint_windows(&self) -> CheckpointWindows {
+        CheckpointWindows {
+            anchor: self
+                .check_period_changes
+                .last()
+                .map_or(0, |c| c.effective_epoch),
+            period: self.check_period,
+            pending: self.pending_check_period.clone(),
+            next_epoch: self.next_checkpoint_epoch(),
+        }
+    }
+
+    /// Checkpoint period of the window following the checkpoint at `epoch`.
+    fn check_period_after(&self, epoch: ChainEpoch) -> ChainEpoch {
+        self.check_period_changes
+            .iter()
+            .find(|c| c.effective_epoch > epoch)
+            .map_or(self.check_period, |c| c.old_period)
+    }
+
+    /// Schedules a change of the checkpoint period.
+    ///
+    /// Changing the period in the middle of a window would break the chain
+    /// of checkpoints, so the change only takes effect with the next
+    /// committed checkpoint, and later windows are counted from it.
+    pub(crate) fn schedule_check_period(&mut self, period: ChainEpoch) -> anyhow::Result<()> {
+        if period < DEFAULT_CHECKPOINT_PERIOD {
+            return Err(SubnetActorError::InvalidCheckPeriod(period).into());
+        }
+        self.pending_check_period = (period != self.check_period).then(|| CheckPeriodChange {
+            effective_epoch: self.next_checkpoint_epoch(),
+            old_period: self.check_period,
+            new_period: period,
+        });
+        Ok(())
+    }
+
+    /// Applies the pending change of the checkpoint period, if any, when
+    /// committing the checkpoint at `epoch`.
+    pub(crate) fn apply_check_period(&mut self, epoch: ChainEpoch) {
+        match self.pending_check_period.take() {
+            Some(mut c) if epoch >= c.effective_epoch => {
+                c.effective_epoch = epoch;
+                self.check_period = c.new_period;
+                self.check_period_changes.push(c);
+            }
+            pending => self.pending_check_period = pending,
+        }
+    }
+
+    /// Fields of the checkpoint expected for the next window, checked by
+    /// `verify_checkpoint`. `subnet_actor` is the address of this actor.
+    pub fn checkpoint_template<BS: Blockstore>(
+        &self,
+        store: &BS,
+        subnet_actor: Address,
+    ) -> anyhow::Result<CheckpointTemplate> {
+        let epoch = self.next_checkpoint_epoch();
+        let config_number = self.window_config_number(store, epoch)?;
+        let validator_set = self
+            .get_validator_set_at(store, config_number)?
+            .map(|(digest, _)| digest)
+            .unwrap_or_default();
+        Ok(CheckpointTemplate {
+            epoch,
+            source: compat::subnet_id(&self.parent_id, subnet_actor)?,
+            prev_check: self.prev_checkpoint_cid(store)?,
+            config_number,
+            validator_set,
+        })
+    }
+
+    /// CID of the last committed checkpoint, which needs to be referenced by
+    /// the next one.
+    fn prev_checkpoint_cid<BS: Blockstore>(&self, store: &BS) -> anyhow::Result<Cid> {
+        // the chain starts at the genesis checkpoint, if the genesis is anchored
+        Ok(self
+            .get_checkpoint(store, &self.last_committed_epoch)?
+            .or_else(|| self.genesis_checkpoint.clone())
+            .map(|ch| ch.cid())
+            .unwrap_or_default())
+    }
+
+    /// Builds the genesis checkpoint of `subnet`, at epoch 0, along with its
+    /// commitment to the CID of the genesis and the validator set at
+    /// registration.
+    pub fn build_genesis_checkpoint<BS: Blockstore>(
+        &self,
+        store: &BS,
+        subnet: SubnetID,
+    ) -> anyhow::Result<CheckpointSubmission> {
+        let genesis: TCid<TLink<RawBytes>> =
+            TCid::new_link(store, &RawBytes::new(self.genesis.clone()))?;
+        let validator_set = self
+            .get_validator_set_at(store, self.config_number)?
+            .map(|(digest, _)| digest)
+            .unwrap_or_default();
+        Ok(CheckpointSubmission {
+            checkpoint: Checkpoint::new(subnet, 0),
+            state_commitment: StateCommitment {
+                state_root: genesis.cid(),
+                height: 0,
+                config_number: self.config_number,
+                validator_set,
+            },
+        })
+    }
+
+    /// Splits `fees` among the validators proportionally to their stake.
+    ///
+    /// Returns the share of each validator and the dust left by rounding
+    /// down the shares, which is all the fees if there are no validators.
+    pub fn fee_shares<BS: Blockstore>(
+        &self,
+        store: &BS,
+        fees: &TokenAmount,
+    ) -> anyhow::Result<(Vec<(Address, TokenAmount)>, TokenAmount)> {
+        let mut stakes = Vec::new();
+        let mut total = TokenAmount::zero();
+        for addr in self.validators.addrs() {
+            let stake = self
+                .get_stake(store, addr)?
+                .unwrap_or_else(TokenAmount::zero);
+            total += &stake;
+            stakes.push((*addr, stake));
+        }
+        if total.is_zero() {
+            return Ok((Vec::new(), fees.clone()));
+        }
+
+        let mut dust = fees.clone();
+        let mut shares = Vec::new();
+        for (addr, stake) in stakes {
+            let share = TokenAmount::from_atto(fees.atto() * stake.atto() / total.atto());
+            if !share.is_positive() {
+                continue;
+            }
+            for (to, amount) in self.split_commission(store, &addr, &stake, &share)? {
+                dust -= &amount;
+                shares.push((to, amount));
+            }
+        }
+        Ok((shares, dust))
+    }
+
+    /// Splits the fee share of a validator between the validator and its
+    /// delegators. The part earned by the delegated stake goes to the
+    /// delegators pro-rata, minus the commission of the validator.
+    fn split_commission<BS: Blockstore>(
+        &self,
+        store: &BS,
+        validator: &Address,
+        stake: &TokenAmount,
+        share: &TokenAmount,
+    ) -> anyhow::Result<Vec<(Address, TokenAmount)>> {
+        let delegations = match self.get_delegations(store, validator)? {
+            Some(d) if d.total.is_positive() => d,
+            _ => return Ok(vec![(*validator, share.clone())]),
+        };
+
+        let delegated =
+            TokenAmount::from_atto(share.atto() * delegations.total.atto() / stake.atto());
+        let commission = TokenAmount::from_atto(
+            delegated.atto() * delegations.commission_rate / COMMISSION_RATE_DENOMINATOR,
+        );
+        let rewards = &delegated - &commission;
+
+        let mut shares = vec![(*validator, share - &delegated + &commission)];
+        for d in &delegations.delegators {
+            let amount =
+                TokenAmount::from_atto(rewards.atto() * d.amount.atto() / delegations.total.atto());
+            if amount.is_positive() {
+                shares.push((d.delegator, amount));
+            }
+        }
+        Ok(shares)
+    }
+
+    pub fn get_delegations<BS: Blockstore>(
+        &self,
+        store: &BS,
+        validator: &Address,
+    ) -> anyhow::Result<Option<Delegations>> {
+        let hamt = self.delegations.load(store)?;
+        let delegations = hamt.get(&BytesKey::from(validator.to_bytes()))?;
+        Ok(delegations.cloned())
+    }
+
+    fn set_delegations<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        validator: &Address,
+        delegations: Delegations,
+    ) -> anyhow::Result<()> {
+        self.delegations.modify(store, |hamt| {
+            let key = BytesKey::from(validator.to_bytes());
+            if delegations.total.is_zero() && delegations.commission_rate == 0 {
+                hamt.delete(&key)?;
+            } else {
+                hamt.set(key, delegations)?;
+            }
+            Ok(true)
+        })?;
+        Ok(())
+    }
+
+    /// Stake delegated to `validator`, which the validator can't withdraw.
+    pub fn delegated_stake<BS: Blockstore>(
+        &self,
+        store: &BS,
+        validator: &Address,
+    ) -> anyhow::Result<TokenAmount> {
+        Ok(self
+            .get_delegations(store, validator)?
+            .map_or_else(TokenAmount::zero, |d| d.total))
+    }
+
+    /// Adds `amount` delegated by `delegator` to the stake of `validator`.
+    pub(crate) fn delegate<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        validator: &Address,
+        delegator: &Address,
+        amount: &TokenAmount,
+    ) -> anyhow::Result<()> {
+        if !self.is_validator(store, validator)? {
+            return Err(SubnetActorError::NotValidator(*validator).into());
+        }
+        if self.capped_stake(store, validator, amount)? != *amount {
+            return Err(SubnetActorError::StakeCapExceeded(*validator).into());
+        }
+        self.top_up_stake(store, validator, delegator, amount)?;
+
+        let mut delegations = self.get_delegations(store, validator)?.unwrap_or_default();
+        delegations.total += amount;
+        match delegations
+            .delegators
+            .iter_mut()
+            .find(|d| d.delegator == *delegator)
+        {
+            Some(d) => d.amount += amount,
+            None => delegations.delegators.push(Delegation {
+                delegator: *delegator,
+                amount: amount.clone(),
+            }),
+        }
+        self.set_delegations(store, validator, delegations)
+    }
+
+    /// Removes the stake `delegator` delegated to `validator`, returning the
+    /// amount removed, at most the funding of the delegator. The validator
+    /// stays in the validator set as long as it keeps the minimum stake.
+    pub(crate) fn undelegate<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        validator: &Address,
+        delegator: &Address,
+    ) -> anyhow::Result<TokenAmount> {
+        let mut delegations = self.get_delegations(store, validator)?.unwrap_or_default();
+        let delegated = match delegations
+            .delegators
+            .iter()
+            .position(|d| d.delegator == *delegator)
+        {
+            Some(i) => delegations.delegators.remove(i).amount,
+            None => return Err(SubnetActorError::NotEnoughStake(*delegator).into()),
+        };
+        delegations.total -= &delegated;
+        self.set_delegations(store, validator, delegations)?;
+        self.remove_delegated_stake(store, validator, delegator, &delegated)
+    }
+
+    /// Moves `amount` of the stake `delegator` delegated to `from` to `to`
+    /// without leaving the subnet, along with its funding, which can be
+    /// less after penalties. The weights of both validators change
+    /// from the next window, as votes of the open one are weighted with its
+    /// snapshot. Delegators can only redelegate once every
+    /// `REDELEGATION_COOLDOWN_WINDOWS` checkpoint windows.
+    pub(crate) fn redelegate<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        delegator: &Address,
+        from: &Address,
+        to: &Address,
+        amount: &TokenAmount,
+        epoch: ChainEpoch,
+    ) -> anyhow::Result<()> {
+        if from == to {
+            return Err(anyhow!(
+                "stake can only be redelegated to another validator"
+            ));
+        }
+        if let Some(until) = self.redelegation_cooldown_end(store, delegator)? {
+            if epoch < until {
+                return Err(SubnetActorError::RedelegationCooldown(until).into());
+            }
+        }
+
+        let mut delegations = self.get_delegations(store, from)?.unwrap_or_default();
+        let i = delegations
+            .delegators
+            .iter()
+            .position(|d| d.delegator == *delegator && d.amount >= *amount)
+            .ok_or(SubnetActorError::NotEnoughStake(*delegator))?;
+        delegations.delegators[i].amount -= amount;
+        if delegations.delegators[i].amount.is_zero() {
+            delegations.delegators.remove(i);
+        }
+        delegations.total -= amount;
+        self.set_delegations(store, from, delegations)?;
+        let moved = self.remove_delegated_stake(store, from, delegator, amount)?;
+
+        self.delegate(store, to, delegator, &moved)?;
+        self.redelegations.modify(store, |hamt| {
+            hamt.set(BytesKey::from(delegator.to_bytes()), epoch)?;
+            Ok(true)
+        })?;
+        Ok(())
+    }
+
+    /// Epoch from which `delegator` can redelegate again, if it redelegated
+    /// before.
+    pub fn redelegation_cooldown_end<BS: Blockstore>(
+        &self,
+        store: &BS,
+        delegator: &Address,
+    ) -> anyhow::Result<Option<ChainEpoch>> {
+        let hamt = self.redelegations.load(store)?;
+        let last = hamt.get(&BytesKey::from(delegator.to_bytes()))?;
+        Ok(last.map(|epoch| epoch + REDELEGATION_COOLDOWN_WINDOWS * self.check_period))
+    }
+
+    /// Takes the part of a `penalty` on the `stake` of `validator` that
+    /// falls on the stake delegated to it from its delegators, pro-rata to
+    /// their delegations, the same way it is debited from their funding.
+    fn penalize_delegations<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        validator: &Address,
+        stake: &TokenAmount,
+        penalty: &TokenAmount,
+    ) -> anyhow::Result<()> {
+        let mut delegations = match self.get_delegations(store, validator)? {
+            Some(d) if d.total.is_positive() && penalty.is_positive() => d,
+            _ => return Ok(()),
+        };
+        let delegated =
+            TokenAmount::from_atto(penalty.atto() * delegations.total.atto() / stake.atto());
+        let amounts: Vec<TokenAmount> = delegations
+            .delegators
+            .iter()
+            .map(|d| d.amount.clone())
+            .collect();
+        for (d, part) in delegations
+            .delegators
+            .iter_mut()
+            .zip(stake_math::split_pro_rata(&delegated, &amounts)?)
+        {
+            d.amount -= part;
+        }
+        delegations.delegators.retain(|d| !d.amount.is_zero());
+        delegations.total = stake_math::checked_sub(&delegations.total, &delegated)?;
+        self.set_delegations(store, validator, delegations)
+    }
+
+    /// Removes `amount` of stake delegated by `delegator` from the stake of
+    /// `validator`, capped at the funding of the delegator, and returns the
+    /// amount removed. The validator stays in the validator set as long as
+    /// it keeps the minimum stake.
+    fn remove_delegated_stake<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        validator: &Address,
+        delegator: &Address,
+        amount: &TokenAmount,
+    ) -> anyhow::Result<TokenAmount> {
+        let amount = self.debit_delegator_funding(store, validator, delegator, amount)?;
+        let stake = self
+            .get_stake(store, validator)?
+            .unwrap_or_else(TokenAmount::zero);
+        let remaining = stake_math::checked_sub(&stake, &amount)?;
+        self.stake.modify(store, |hamt| {
+            hamt.set(BytesKey::from(validator.to_bytes()), remaining.clone())?;
+            Ok(true)
+        })?;
+        self.total_stake = stake_math::checked_sub(&self.total_stake, &amount)?;
+        if remaining.is_zero() {
+            decrement(&mut self.stakers_count, "stakers")?;
+        }
+        if remaining < self.econ.min_validator_stake {
+            self.remove_validator(store, validator)?;
+        }
+        self.validators.update_stake(validator, remaining);
+        Ok(amount)
+    }
+
+    /// Releases `amount` of the own stake of `validator`, regardless of its
+    /// vesting, removing it from the validator set if it is left without
+    /// enough collateral. Returns the part of each funder of its own stake.
+    pub(crate) fn release_stake_for<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        validator: &Address,
+        amount: &TokenAmount,
+    ) -> anyhow::Result<Vec<(Address, TokenAmount)>> {
+        let stake = self
+            .get_stake(store, validator)?
+            .unwrap_or_else(TokenAmount::zero);
+        let delegated = self.delegated_stake(store, validator)?;
+        if amount.is_zero() || *amount > &stake - &delegated {
+            return Err(SubnetActorError::NotEnoughStake(*validator).into());
+        }
+
+        let remaining = stake_math::checked_sub(&stake, amount)?;
+        self.stake.modify(store, |hamt| {
+            hamt.set(BytesKey::from(validator.to_bytes()), remaining.clone())?;
+            Ok(true)
+        })?;
+        self.total_stake = stake_math::checked_sub(&self.total_stake, amount)?;
+        let released = self.debit_funding(store, validator, amount, true)?;
+        if remaining.is_zero() {
+            decrement(&mut self.stakers_count, "stakers")?;
+            self.vesting.modify(store, |hamt| {
+                hamt.delete(&BytesKey::from(validator.to_bytes()))?;
+                Ok(true)
+            })?;
+        }
+        if remaining < self.econ.min_validator_stake {
+            self.remove_validator(store, validator)?;
+        }
+        self.validators.update_stake(validator, remaining);
+        Ok(released)
+    }
+
+    /// Sets the commission `validator` keeps from the fees earned by the
+    /// stake delegated to it. Decreases apply right away, replacing any
+    /// pending increase, while increases apply after
+    /// `COMMISSION_CHANGE_DELAY_WINDOWS` checkpoint windows.
+    pub(crate) fn set_commission_rate<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        validator: &Address,
+        rate: u64,
+        epoch: ChainEpoch,
+    ) -> anyhow::Result<()> {
+        if rate > COMMISSION_RATE_DENOMINATOR {
+            return Err(anyhow!(
+                "the commission rate can't exceed {} basis points",
+                COMMISSION_RATE_DENOMINATOR
+            ));
+        }
+        self.apply_commission_changes(store, epoch)?;
+        self.commission_changes
+            .retain(|c| c.validator != *validator);
+
+        let mut delegations = self.get_delegations(store, validator)?.unwrap_or_default();
+        if rate > delegations.commission_rate {
+            self.commission_changes.push(CommissionChange {
+                validator: *validator,
+                rate,
+                effective_at: epoch + COMMISSION_CHANGE_DELAY_WINDOWS * self.check_period,
+            });
+            return Ok(());
+        }
+        delegations.commission_rate = rate;
+        self.set_delegations(store, validator, delegations)
+    }
+
+    /// Applies the commission rate increases due by `epoch`.
+    pub(crate) fn apply_commission_changes<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        epoch: ChainEpoch,
+    ) -> anyhow::Result<()> {
+        let (due, pending): (Vec<_>, Vec<_>) = std::mem::take(&mut self.commission_changes)
+            .into_iter()
+            .partition(|c| c.effective_at <= epoch);
+        self.commission_changes = pending;
+        for change in due {
+            let mut delegations = self
+                .get_delegations(store, &change.validator)?
+                .unwrap_or_default();
+            delegations.commission_rate = change.rate;
+            self.set_delegations(store, &change.validator, delegations)?;
+        }
+        Ok(())
+    }
+
+    /// Funding ledger of `staker`, empty if it has no stake.
+    pub fn get_funding<BS: Blockstore>(
+        &self,
+        store: &BS,
+        staker: &Address,
+    ) -> anyhow::Result<Vec<Funding>> {
+        let hamt = self.funding.load(store)?;
+        let funding = hamt.get(&BytesKey::from(staker.to_bytes()))?;
+        Ok(funding.cloned().unwrap_or_default())
+    }
+
+    fn set_funding<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        staker: &Address,
+        funding: Vec<Funding>,
+    ) -> anyhow::Result<()> {
+        self.funding.modify(store, |hamt| {
+            let key = BytesKey::from(staker.to_bytes());
+            if funding.is_empty() {
+                hamt.delete(&key)?;
+            } else {
+                hamt.set(key, funding)?;
+            }
+            Ok(true)
+        })?;
+        Ok(())
+    }
+
+    /// Records `amount` of the stake of `staker` as put up by `funder`.
+    fn credit_funding<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        staker: &Address,
+        funder: &Address,
+        amount: &TokenAmount,
+    ) -> anyhow::Result<()> {
+        if amount.is_zero() {
+            return Ok(());
+        }
+        let mut funding = self.get_funding(store, staker)?;
+        match funding.iter_mut().find(|f| f.funder == *funder) {
+            Some(f) => f.amount += amount,
+            None => funding.push(Funding {
+                funder: *funder,
+                amount: amount.clone(),
+            }),
+        }
+        self.set_funding(store, staker, funding)
+    }
+
+    /// Debits `amount` taken from the stake of `staker` from its funders
+    /// pro-rata, returning the part of each. With `own`, only the own stake
+    /// of the staker is debited, leaving the funding of the stake delegated
+    /// to it to its delegators.
+    fn debit_funding<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        staker: &Address,
+        amount: &TokenAmount,
+        own: bool,
+    ) -> anyhow::Result<Vec<(Address, TokenAmount)>> {
+        let mut funding = self.get_funding(store, staker)?;
+        let mut shares: Vec<TokenAmount> = funding.iter().map(|f| f.amount.clone()).collect();
+        if own {
+            let delegations = self.get_delegations(store, staker)?.unwrap_or_default();
+            let own_shares: Vec<TokenAmount> = funding
+                .iter()
+                .map(|f| {
+                    let delegated = delegations
+                        .delegators
+                        .iter()
+                        .find(|d| d.delegator == f.funder)
+                        .map_or_else(TokenAmount::zero, |d| d.amount.clone());
+                    if f.amount > delegated {
+                        &f.amount - &delegated
+                    } else {
+                        TokenAmount::zero()
+                    }
+                })
+                .collect();
+            // penalties are debited from the delegators too, so their
+            // funding can fall below their delegations
+            let own_total = own_shares
+                .iter()
+                .fold(TokenAmount::zero(), |sum, s| sum + s);
+            if own_total >= *amount {
+                shares = own_shares;
+            }
+        }
+
+        let parts = stake_math::split_pro_rata(amount, &shares)?;
+        let mut debited = Vec::new();
+        for (f, part) in funding.iter_mut().zip(parts) {
+            if part.is_zero() {
+                continue;
+            }
+            f.amount -= &part;
+            debited.push((f.funder, part));
+        }
+        funding.retain(|f| !f.amount.is_zero());
+        self.set_funding(store, staker, funding)?;
+        Ok(debited)
+    }
+
+    /// Debits `amount` withdrawn from the own stake of `staker` from its
+    /// funders, forfeiting the leaving penalty of each part. Returns the
+    /// stake released to each funder.
+    fn release_funding<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        staker: &Address,
+        amount: &TokenAmount,
+    ) -> anyhow::Result<Vec<(Address, TokenAmount)>> {
+        let mut released = Vec::new();
+        for (funder, part) in self.debit_funding(store, staker, amount, true)? {
+            let (refund, forfeited) = self.econ.leaving_coeff.split(&part);
+            self.forfeit(&forfeited);
+            released.push((funder, refund));
+        }
+        Ok(released)
+    }
+
+    /// Debits `amount` of the stake `delegator` delegated to `validator`
+    /// from the funding of the delegator, returning the amount debited. The
+    /// funding of the delegator can be below its delegation after penalties
+    /// taken before `penalize_delegations`, in which case only its funding
+    /// is debited, as the rest of the funders don't owe the difference.
+    fn debit_delegator_funding<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        validator: &Address,
+        delegator: &Address,
+        amount: &TokenAmount,
+    ) -> anyhow::Result<TokenAmount> {
+        let mut funding = self.get_funding(store, validator)?;
+        let debited = match funding.iter_mut().find(|f| f.funder == *delegator) {
+            Some(f) => {
+                let part = std::cmp::min(f.amount.clone(), amount.clone());
+                f.amount -= &part;
+                part
+            }
+            None => TokenAmount::zero(),
+        };
+        funding.retain(|f| !f.amount.is_zero());
+        self.set_funding(store, validator, funding)?;
+        Ok(debited)
+    }
+
+    /// Builds the power table of the active validators, those not jailed
+    /// and with at least `min_validator_stake`, scaling their stake capped
+    /// by `max_voting_power_pct` so the total voting power stays below
+    /// [`MAX_TOTAL_VOTING_POWER`]. Every active validator has a voting
+    /// power of at least 1.
+    pub fn power_table<BS: Blockstore>(&self, store: &BS) -> anyhow::Result<PowerTable> {
+        let mut stakes = Vec::new();
+        for v in self.validators.list(store)? {
+            if self.is_jailed(store, &v.addr)? {
+                continue;
+            }
+            let stake = self
+                .get_stake(store, &v.addr)?
+                .unwrap_or_else(TokenAmount::zero);
+            if stake < self.econ.min_validator_stake {
+                continue;
+            }
+            stakes.push((v, stake));
+        }
+        let capped = self.cap_voting_power(
+            &stakes.iter().map(|(_, s)| s.clone()).collect::<Vec<_>>(),
+            &TokenAmount::zero(),
+        );
+        let total = capped.iter().fold(TokenAmount::zero(), |sum, p| sum + p);
+
+        // leave room for the validators rounded up to 1
+        let target = BigInt::from(MAX_TOTAL_VOTING_POWER - stakes.len() as u64);
+        let mut validators = Vec::new();
+        for ((v, stake), capped) in stakes.into_iter().zip(capped) {
+            let power = if total.is_zero() {
+                1
+            } else {
+                (capped.atto() * &target / total.atto())
+                    .to_u64()
+                    .ok_or_else(|| anyhow!("voting power out of range"))?
+                    .max(1)
+            };
+            validators.push(PowerTableEntry {
+                addr: v.addr,
+                net_addr: v.net_addr,
+                power,
+                collateral: stake,
+            });
+        }
+        validators.sort_by(|a, b| {
+            b.power
+                .cmp(&a.power)
+                .then_with(|| a.addr.to_bytes().cmp(&b.addr.to_bytes()))
+        });
+
+        Ok(PowerTable {
+            total_power: validators.iter().map(|v| v.power).sum(),
+            validators,
+            window: self.next_checkpoint_epoch(),
+            rotation_seed: self.rotation_seed(store)?,
+        })
+    }
+
+    /// Appends `addr` to the staker index, unless it is already in it.
+    pub(crate) fn index_staker<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        addr: &Address,
+    ) -> anyhow::Result<()> {
+        let index = self.staker_index_len;
+        let mut added = false;
+        self.staker_positions.modify(store, |hamt| {
+            added = hamt.set_if_absent(BytesKey::from(addr.to_bytes()), index)?;
+            Ok(added)
+        })?;
+        if added {
+            self.staker_index.modify(store, |amt| {
+                amt.set(index, *addr)?;
+                Ok(true)
+            })?;
+            self.staker_index_len += 1;
+        }
+        Ok(())
+    }
+
+    /// Lists the addresses with stake in the subnet among the `limit`
+    /// entries of the staker index from `cursor`. Addresses keep their
+    /// entry after withdrawing all their stake, so pages can hold fewer
+    /// than `limit` stakers.
+    ///
+    /// Returns the cursor to request the next page from, if any.
+    pub fn list_stakers<BS: Blockstore>(
+        &self,
+        store: &BS,
+        cursor: u64,
+        limit: u64,
+    ) -> anyhow::Result<(Vec<(Address, TokenAmount)>, Option<u64>)> {
+        let index = self.staker_index.load(store)?;
+        let stakes = self.stake.load(store)?;
+        let end = std::cmp::min(cursor.saturating_add(limit), self.staker_index_len);
+        let mut stakers = Vec::new();
+        for i in cursor..end {
+            let addr = match index.get(i)? {
+                Some(addr) => *addr,
+                None => continue,
+            };
+            if let Some(stake) = stakes.get(&BytesKey::from(addr.to_bytes()))? {
+                if !stake.is_zero() {
+                    stakers.push((addr, stake.clone()));
+                }
+            }
+        }
+        let next = (end < self.staker_index_len).then_some(end);
+        Ok((stakers, next))
+    }
+
+    /// Lists up to `limit` committed checkpoints in order of epoch, from
+    /// the window number `cursor`.
+    ///
+    /// Returns the cursor to request the next page from, if any.
+    pub fn list_checkpoints<BS: Blockstore>(
+        &self,
+        store: &BS,
+        cursor: u64,
+        limit: u64,
+    ) -> anyhow::Result<(Vec<Checkpoint>, Option<u64>)> {
+        let end = std::cmp::min(cursor.saturating_add(limit), self.checkpoints_count);
+        let checkpoints = self.get_checkpoints_by_windows(store, cursor..end)?;
+        let next = (end < self.checkpoints_count).then_some(end);
+        Ok((checkpoints, next))
+    }
+
+    /// Committed checkpoints of the given range of window numbers, up to
+    /// the last committed one.
+    pub fn get_checkpoints_by_windows<BS: Blockstore>(
+        &self,
+        store: &BS,
+        windows: Range<u64>,
+    ) -> anyhow::Result<Vec<Checkpoint>> {
+        let amt = self.checkpoints.load(store)?;
+        let end = std::cmp::min(windows.end, self.checkpoints_count);
+        let mut checkpoints = Vec::new();
+        for window in windows.start..end {
+            let ch = amt
+                .get(window)?
+                .ok_or_else(|| anyhow!("missing checkpoint of window {}", window))?;
+            checkpoints.push(ch.clone());
+        }
+        Ok(checkpoints)
+    }
+
+    /// Range of window numbers of the checkpoints committed at epochs from
+    /// `from` to `to`, both included.
+    pub fn get_checkpoint_windows<BS: Blockstore>(
+        &self,
+        store: &BS,
+        from: ChainEpoch,
+        to: ChainEpoch,
+    ) -> anyhow::Result<Range<u64>> {
+        if from > to {
+            return Ok(0..0);
+        }
+        let start = self
+            .find_checkpoint_window(store, from)?
+            .unwrap_or_else(|w| w);
+        let end = match self.find_checkpoint_window(store, to)? {
+            Ok(window) => window + 1,
+            Err(window) => window,
+        };
+        Ok(start..end)
+    }
+
+    pub fn flush_checkpoint<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        ch: &Checkpoint,
+        commitment: &StateCommitment,
+    ) -> anyhow::Result<()> {
+        let epoch = ch.epoch();
+        self.last_state_commitment = Some(commitment.clone());
+        if let Ok(window) = self.find_checkpoint_window(store, epoch)? {
+            // committed again, keeping its window
+            self.checkpoints.modify(store, |amt| {
+                amt.set(window, ch.clone())?;
+                Ok(true)
+            })?;
+        } else {
+            let window = self.checkpoints_count;
+            self.checkpoints.modify(store, |amt| {
+                amt.set(window, ch.clone())?;
+                Ok(true)
+            })?;
+            let config_number = self.config_number;
+            self.checkpoint_configs.modify(store, |amt| {
+                amt.set(window, config_number)?;
+                Ok(true)
+            })?;
+            self.checkpoints_count += 1;
+        }
+        if epoch > self.last_committed_epoch {
+            self.last_committed_epoch = epoch;
+        }
+        Ok(())
+    }
+
+    /// Committed checkpoint of the given window number.
+    pub fn get_checkpoint_by_window<BS: Blockstore>(
+        &self,
+        store: &BS,
+        window: u64,
+    ) -> anyhow::Result<Option<Checkpoint>> {
+        let amt = self.checkpoints.load(store)?;
+        let ch = amt.get(window)?;
+        Ok(ch.cloned())
+    }
+
+    /// Configuration number of the validator set when the checkpoint of the
+    /// given window number was committed. Checkpoints committed before
+    /// state version 18 have none.
+    pub fn get_checkpoint_config<BS: Blockstore>(
+        &self,
+        store: &BS,
+        window: u64,
+    ) -> anyhow::Result<Option<u64>> {
+        let amt = self.checkpoint_configs.load(store)?;
+        let config_number = amt.get(window)?;
+        Ok(config_number.copied())
+    }
+
+    /// State commitment of the pending checkpoint at `epoch`, kept as the
+    /// previous commitment of the next pending one, or as the last one.
+    pub fn pending_state_commitment(&self, epoch: ChainEpoch) -> Option<StateCommitment> {
+        let i = self
+            .pending_checkpoints
+            .iter()
+            .position(|p| p.epoch == epoch)?;
+        match self.pending_checkpoints.get(i + 1) {
+            Some(next) => next.prev_state_commitment.clone(),
+            None => self.last_state_commitment.clone(),
+        }
+    }
+
+    /// Reverts the pending checkpoint at `epoch` and the ones committed
+    /// after it, which build on it. Returns the reverted checkpoints.
+    ///
+    /// The votes, participation and bottom-up messages of the reverted
+    /// windows are not restored, validators need to register and vote
+    /// them again.
+    pub(crate) fn revert_checkpoints<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        epoch: ChainEpoch,
+    ) -> anyhow::Result<Vec<PendingCheckpoint>> {
+        let i = self
+            .pending_checkpoints
+            .iter()
+            .position(|p| p.epoch == epoch)
+            .ok_or(SubnetActorError::NotDisputable(epoch))?;
+        let reverted = self.pending_checkpoints.split_off(i);
+
+        // the reverted checkpoints are the last ones committed
+        let windows = self.get_checkpoint_windows(store, reverted[0].epoch, ChainEpoch::MAX)?;
+        self.checkpoints_count = windows.start;
+        self.checkpoints.modify(store, |amt| {
+            for window in windows.clone() {
+                amt.delete(window)?;
+            }
+            Ok(true)
+        })?;
+        self.checkpoint_configs.modify(store, |amt| {
+            for window in windows {
+                amt.delete(window)?;
+            }
+            Ok(true)
+        })?;
+        // windows after the reverted ones are not open anymore
+        for p in &reverted {
+            self.remove_weight_snapshot(store, p.epoch + self.check_period_after(p.epoch))?;
+        }
+        self.last_committed_epoch = reverted[0].prev_committed_epoch;
+        self.last_state_commitment = reverted[0].prev_state_commitment.clone();
+
+        // changes of the checkpoint period applied by the reverted
+        // checkpoints are pending again, unless a later one was scheduled
+        let mut period = None;
+        while let Some(c) = self.check_period_changes.pop() {
+            if c.effective_epoch <= self.last_committed_epoch {
+                self.check_period_changes.push(c);
+                break;
+            }
+            self.check_period = c.old_period;
+            period.get_or_insert(c.new_period);
+        }
+        if let Some(c) = &self.pending_check_period {
+            period = Some(c.new_period);
+        }
+        if let Some(period) = period {
+            self.schedule_check_period(period)?;
+        }
+
+        Ok(reverted)
+    }
+
+    /// Records the offence with digest `digest` as slashed at `epoch`,
+    /// failing if it already was.
+    pub(crate) fn record_offence<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        digest: Cid,
+        epoch: ChainEpoch,
+    ) -> anyhow::Result<()> {
+        let mut added = false;
+        self.slashed_offences.modify(store, |hamt| {
+            added = hamt.set_if_absent(BytesKey::from(digest.to_bytes()), epoch)?;
+            Ok(added)
+        })?;
+        if !added {
+            return Err(SubnetActorError::EvidenceAlreadyUsed.into());
+        }
+        Ok(())
+    }
+
+    /// Slashes the whole stake of a validator, the stake delegated to it
+    /// included, removing it from the validator set. Returns the amount
+    /// slashed.
+    pub(crate) fn slash<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        addr: &Address,
+    ) -> anyhow::Result<TokenAmount> {
+        let stake = self
+            .get_stake(store, addr)?
+            .unwrap_or_else(TokenAmount::zero);
+        if !stake.is_zero() {
+            self.stake.modify(store, |hamt| {
+                hamt.set(BytesKey::from(addr.to_bytes()), TokenAmount::zero())?;
+                Ok(true)
+            })?;
+            decrement(&mut self.stakers_count, "stakers")?;
+            self.total_stake = stake_math::checked_sub(&self.total_stake, &stake)?;
+            self.forfeit(&stake);
+            self.set_funding(store, addr, Vec::new())?;
+        }
+        // delegated stake is part of the stake forfeited, along with the
+        // funding of its delegators
+        self.delegations.modify(store, |hamt| {
+            hamt.delete(&BytesKey::from(addr.to_bytes()))?;
+            Ok(true)
+        })?;
+        self.commission_changes.retain(|c| c.validator != *addr);
+        self.stats.slashes += 1;
+        self.log_membership_event(store, MembershipEventKind::Slash, addr)?;
+        self.remove_validator(store, addr)?;
+        Ok(stake)
+    }
+
+    /// Checkpoints voted in the window at `epoch`.
+    pub fn get_candidates<BS: Blockstore>(
+        &self,
+        store: &BS,
+        epoch: ChainEpoch,
+    ) -> anyhow::Result<Vec<Cid>> {
+        let hamt = self.candidates.load(store)?;
+        let candidates = hamt.get(&keys::epoch_key(epoch))?;
+        Ok(candidates.cloned().unwrap_or_default())
+    }
+
+    /// Returns the candidate other than `cid` voted by `addr` in the window
+    /// at `epoch`, if any.
+    ///
+    /// Validators with diverging views of the subnet may submit competing
+    /// checkpoints for a window, each collecting its own votes. Each
+    /// validator can only vote one of them, so at most one can reach the
+    /// voting threshold; if the votes are split so that none can, the
+    /// window is left to a catch-up checkpoint.
+    pub fn conflicting_vote<BS: Blockstore>(
+        &self,
+        store: &BS,
+        epoch: ChainEpoch,
+        cid: &Cid,
+        addr: &Address,
+    ) -> anyhow::Result<Option<Cid>> {
+        for candidate in self.get_candidates(store, epoch)? {
+            if candidate == *cid {
+                continue;
+            }
+            if let Some(votes) = self.window_checks.get(store, &candidate)? {
+                if votes.validators.contains(addr) {
+                    return Ok(Some(candidate));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    pub(crate) fn add_candidate<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        epoch: ChainEpoch,
+        cid: &Cid,
+        current_epoch: ChainEpoch,
+    ) -> anyhow::Result<()> {
+        let mut candidates = self.get_candidates(store, epoch)?;
+        if candidates.is_empty() {
+            // votes can be cast for a window until the one after the
+            // window they were first cast in closes
+            let (_, end) = self.checkpoint_windows().window_for_epoch(current_epoch);
+            self.vote_expiry.push(VoteExpiry {
+                epoch,
+                expires_at: end.max(epoch) + self.check_period,
+            });
+        }
+        if !candidates.contains(cid) {
+            candidates.push(*cid);
+            self.candidates.modify(store, |hamt| {
+                hamt.set(keys::epoch_key(epoch), candidates)?;
+                Ok(true)
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Removes the candidates of a closed window along with their votes.
+    pub(crate) fn remove_candidates<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        epoch: ChainEpoch,
+    ) -> anyhow::Result<()> {
+        self.vote_expiry.retain(|e| e.epoch != epoch);
+        let candidates = self.get_candidates(store, epoch)?;
+        if candidates.is_empty() {
+            return Ok(());
+        }
+        self.window_checks.remove(store, &candidates)?;
+        self.candidates.modify(store, |hamt| {
+            hamt.delete(&keys::epoch_key(epoch))?;
+            Ok(true)
+        })?;
+        Ok(())
+    }
+
+    /// Removes the candidates of the windows closed by the checkpoint at
+    /// `epoch` along with their votes.
+    pub(crate) fn remove_closed_candidates<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        epoch: ChainEpoch,
+    ) -> anyhow::Result<()> {
+        let mut closed = Vec::new();
+        self.candidates.load(store)?.for_each(|k, _| {
+            let e = keys::parse_epoch_key(&k.0)?;
+            if e <= epoch {
+                closed.push(e);
+            }
+            Ok(())
+        })?;
+        for e in closed {
+            self.remove_candidates(store, e)?;
+        }
+        Ok(())
+    }
+
+    /// Whether the votes of the window at `epoch` expired by
+    /// `current_epoch` without reaching quorum.
+    pub fn votes_expired(&self, epoch: ChainEpoch, current_epoch: ChainEpoch) -> bool {
+        self.vote_expiry
+            .iter()
+            .any(|e| e.epoch == epoch && current_epoch >= e.expires_at)
+    }
+
+    /// Removes the candidates of the windows whose votes expired by
+    /// `current_epoch` along with their votes, so validators can vote
+    /// them again.
+    pub(crate) fn expire_votes<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        current_epoch: ChainEpoch,
+    ) -> anyhow::Result<()> {
+        let expired: Vec<ChainEpoch> = self
+            .vote_expiry
+            .iter()
+            .filter(|e| current_epoch >= e.expires_at)
+            .map(|e| e.epoch)
+            .collect();
+        for epoch in expired {
+            self.remove_candidates(store, epoch)?;
+            self.reset_submissions(store, epoch)?;
+            self.stats.expired_windows += 1;
+        }
+        Ok(())
+    }
+
+    /// Drops the checkpoints counted in the window at `epoch`, whose votes
+    /// expired, so validators capped by `max_submissions_per_window` can
+    /// vote it again.
+    fn reset_submissions<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        epoch: ChainEpoch,
+    ) -> anyhow::Result<()> {
+        let mut counted = Vec::new();
+        self.submissions.load(store)?.for_each(|k, c| {
+            if c.window == epoch {
+                counted.push(k.clone());
+            }
+            Ok(())
+        })?;
+        if counted.is_empty() {
+            return Ok(());
+        }
+        self.submissions.modify(store, |hamt| {
+            for k in &counted {
+                hamt.delete(k)?;
+            }
+            Ok(true)
+        })?;
+        Ok(())
+    }
+
+    /// Returns the candidates voted in the window at `epoch` with the
+    /// weight of their votes and the threshold they need to reach.
+    pub fn voting_status<BS: Blockstore>(
+        &self,
+        store: &BS,
+        epoch: ChainEpoch,
+    ) -> Result<CheckpointVotingStatus, ActorError> {
+        let committed = self
+            .get_checkpoint(store, &epoch)
+            .map_err(|_| actor_error!(illegal_state, "cannot load checkpoint"))?
+            .is_some();
+        let snapshot = self.window_weights(store, epoch)?;
+        let cids = self
+            .get_candidates(store, epoch)
+            .map_err(|_| actor_error!(illegal_state, "cannot load candidates"))?;
+
+        let no_votes = Votes {
+            validators: Vec::new(),
+        };
+        let quorum = self.tally(store, &snapshot, &no_votes)?;
+
+        let mut candidates = Vec::new();
+        let mut voted: Vec<Address> = Vec::new();
+        for cid in cids {
+            let votes = match self.get_votes(store, &cid)? {
+                Some(votes) => votes,
+                None => continue,
+            };
+            let weight = self.tally(store, &snapshot, &votes)?.weight;
+            for v in &votes.validators {
+                if !voted.contains(v) {
+                    voted.push(*v);
+                }
+            }
+            candidates.push(CheckpointCandidate {
+                cid,
+                validators: votes.validators,
+                weight,
+            });
+        }
+
+        Ok(CheckpointVotingStatus {
+            epoch,
+            committed,
+            candidates,
+            voted,
+            threshold: self.consensus.quorum_policy().threshold(&quorum),
+            total_weight: quorum.total,
+        })
+    }
+
+    pub fn get_penalties<BS: Blockstore>(
+        &self,
+        store: &BS,
+        addr: &Address,
+    ) -> anyhow::Result<Option<PenaltyRecord>> {
+        let hamt = self.penalties.load(store)?;
+        let record = hamt.get(&BytesKey::from(addr.to_bytes()))?;
+        Ok(record.cloned())
+    }
+
+    /// Number of checkpoints submitted by `addr` in the open window since
+    /// its votes last expired, at `current_epoch`.
+    pub fn submissions_in_window<BS: Blockstore>(
+        &self,
+        store: &BS,
+        addr: &Address,
+        current_epoch: ChainEpoch,
+    ) -> anyhow::Result<u64> {
+        let window = self.next_checkpoint_epoch();
+        // the votes are expired on the next vote, resetting the counts
+        if self.votes_expired(window, current_epoch) {
+            return Ok(0);
+        }
+        let hamt = self.submissions.load(store)?;
+        Ok(match hamt.get(&BytesKey::from(addr.to_bytes()))? {
+            Some(c) if c.window == window => c.count,
+            _ => 0,
+        })
+    }
+
+    /// Whether `addr` submitted as many checkpoints in the open window as
+    /// allowed by `max_submissions_per_window`, at `current_epoch`.
+    pub fn submissions_exhausted<BS: Blockstore>(
+        &self,
+        store: &BS,
+        addr: &Address,
+        current_epoch: ChainEpoch,
+    ) -> anyhow::Result<bool> {
+        Ok(self.max_submissions_per_window > 0
+            && self.submissions_in_window(store, addr, current_epoch)?
+                >= self.max_submissions_per_window)
+    }
+
+    /// Counts a checkpoint submitted by `addr` in the open window at
+    /// `current_epoch`. The count of a previous window is overwritten, so
+    /// there is a single entry per validator.
+    pub(crate) fn record_submission<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        addr: &Address,
+        current_epoch: ChainEpoch,
+    ) -> anyhow::Result<()> {
+        let count = SubmissionCount {
+            window: self.next_checkpoint_epoch(),
+            count: self.submissions_in_window(store, addr, current_epoch)? + 1,
+        };
+        self.submissions.modify(store, |hamt| {
+            hamt.set(BytesKey::from(addr.to_bytes()), count)?;
+            Ok(true)
+        })?;
+        Ok(())
+    }
+
+    /// Accounts stake taken from a validator, which goes to the treasury.
+    pub(crate) fn forfeit(&mut self, amount: &TokenAmount) {
+        self.penalized_stake += amount;
+        self.treasury += amount;
+    }
+
+    /// Accounts a join fee kept in the treasury, held by the actor.
+    pub(crate) fn collect_join_fee(&mut self, fee: &TokenAmount) {
+        self.treasury += fee;
+        self.treasury_held += fee;
+    }
+
+    /// Records a spend proposal, approved by its proposer. Returns its CID.
+    pub(crate) fn propose_spend<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        proposal: SpendProposal,
+    ) -> anyhow::Result<Cid> {
+        if proposal.amount > self.treasury {
+            return Err(SubnetActorError::InsufficientTreasury(proposal.amount).into());
+        }
+        let proposer = proposal.proposer;
+        let cid = TCid::new_link(store, &proposal)?.cid();
+        if self.get_spend_proposal(store, &cid)?.is_some() {
+            return Err(anyhow!("spend proposal {} already exists", cid));
+        }
+        self.spend_proposals.modify(store, |hamt| {
+            hamt.set(BytesKey::from(cid.to_bytes()), proposal)?;
+            Ok(true)
+        })?;
+        self.spend_votes.set(
+            store,
+            &cid,
+            Votes {
+                validators: vec![proposer],
+            },
+        )?;
+        Ok(cid)
+    }
+
+    pub fn get_spend_proposal<BS: Blockstore>(
+        &self,
+        store: &BS,
+        cid: &Cid,
+    ) -> anyhow::Result<Option<SpendProposal>> {
+        let hamt = self.spend_proposals.load(store)?;
+        let proposal = hamt.get(&BytesKey::from(cid.to_bytes()))?;
+        Ok(proposal.cloned())
+    }
+
+    /// Spend proposals pending approval, with their approvals.
+    pub fn list_spend_proposals<BS: Blockstore>(
+        &self,
+        store: &BS,
+    ) -> anyhow::Result<Vec<SpendProposalInfo>> {
+        let mut proposals = Vec::new();
+        self.spend_proposals.load(store)?.for_each(|k, proposal| {
+            let cid = Cid::try_from(k.0.as_slice())?;
+            proposals.push(SpendProposalInfo {
+                cid,
+                proposal: proposal.clone(),
+                approvals: Vec::new(),
+            });
+            Ok(())
+        })?;
+        for p in proposals.iter_mut() {
+            if let Some(votes) = self.spend_votes.get(store, &p.cid)? {
+                p.approvals = votes.validators;
+            }
+        }
+        Ok(proposals)
+    }
+
+    /// Records the approval of `addr` for a spend proposal. Once approved by
+    /// a majority of the current stake, the proposal is removed and its
+    /// amount taken from the treasury, and it is returned to be paid along
+    /// with the part of its amount to release from the gateway.
+    pub(crate) fn approve_spend<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        addr: &Address,
+        cid: &Cid,
+    ) -> anyhow::Result<Option<(SpendProposal, TokenAmount)>> {
+        let proposal = self
+            .get_spend_proposal(store, cid)?
+            .ok_or_else(|| anyhow!("unknown spend proposal {}", cid))?;
+        let mut votes = self.spend_votes.get(store, cid)?.unwrap_or(Votes {
+            validators: Vec::new(),
+        });
+        if votes.validators.contains(addr) {
+            return Err(SubnetActorError::AlreadyVoted(*addr).into());
+        }
+        votes.validators.push(*addr);
+
+        if !self.has_current_majority(store, &votes)? {
+            self.spend_votes.set(store, cid, votes)?;
+            return Ok(None);
+        }
+
+        if proposal.amount > self.treasury {
+            return Err(SubnetActorError::InsufficientTreasury(proposal.amount).into());
+        }
+        self.treasury -= &proposal.amount;
+        let held = std::cmp::min(&proposal.amount, &self.treasury_held).clone();
+        self.treasury_held -= &held;
+        self.spend_proposals.modify(store, |hamt| {
+            hamt.delete(&BytesKey::from(cid.to_bytes()))?;
+            Ok(true)
+        })?;
+        self.spend_votes.remove(store, &[*cid])?;
+        let locked = &proposal.amount - held;
+        Ok(Some((proposal, locked)))
+    }
+
+    /// Records the vote of `addr` for new economic parameters. Once voted by
+    /// a majority of the current stake they replace the current ones, the
+    /// votes for any other parameters are dropped, and validators below the
+    /// new minimum stake leave the validator set. Stakers above a lowered
+    /// minimum join it on their next change of stake. Returns whether the
+    /// parameters were applied.
+    pub(crate) fn vote_econ_params<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        addr: &Address,
+        params: EconParams,
+    ) -> anyhow::Result<bool> {
+        let cid = TCid::new_link(store, &params)?.cid();
+        let mut votes = self.econ_votes.get(store, &cid)?.unwrap_or(Votes {
+            validators: Vec::new(),
+        });
+        if votes.validators.contains(addr) {
+            return Err(SubnetActorError::AlreadyVoted(*addr).into());
+        }
+        votes.validators.push(*addr);
+
+        if !self.has_current_majority(store, &votes)? {
+            self.econ_votes.set(store, &cid, votes)?;
+            return Ok(false);
+        }
+
+        let proposals = self.econ_votes.proposals(store)?;
+        self.econ_votes.remove(store, &proposals)?;
+        self.econ = params;
+        for addr in self.validators.addrs().copied().collect::<Vec<_>>() {
+            let stake = self
+                .get_stake(store, &addr)?
+                .unwrap_or_else(TokenAmount::zero);
+            if stake < self.econ.min_validator_stake {
+                self.remove_validator(store, &addr)?;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Records an invalid checkpoint signed by `addr`, penalizing
+    /// `checkpoint_penalty_rate` basis points of its stake.
+    pub(crate) fn penalize_bad_checkpoint<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        addr: &Address,
+        epoch: ChainEpoch,
+    ) -> anyhow::Result<TokenAmount> {
+        let stake = self
+            .get_stake(store, addr)?
+            .unwrap_or_else(TokenAmount::zero);
+        let penalty = TokenAmount::from_atto(
+            stake.atto() * self.econ.checkpoint_penalty_rate / PENALTY_RATE_DENOMINATOR,
+        );
+        if !penalty.is_zero() {
+            let remaining = stake_math::checked_sub(&stake, &penalty)?;
+            self.stake.modify(store, |hamt| {
+                hamt.set(BytesKey::from(addr.to_bytes()), remaining.clone())?;
+                Ok(true)
+            })?;
+            self.total_stake = stake_math::checked_sub(&self.total_stake, &penalty)?;
+            self.forfeit(&penalty);
+            self.debit_funding(store, addr, &penalty, false)?;
+            self.penalize_delegations(store, addr, &stake, &penalty)?;
+
+            if remaining.is_zero() {
+                decrement(&mut self.stakers_count, "stakers")?;
+                self.vesting.modify(store, |hamt| {
+                    hamt.delete(&BytesKey::from(addr.to_bytes()))?;
+                    Ok(true)
+                })?;
+            }
+            if remaining < self.econ.min_validator_stake {
+                self.remove_validator(store, addr)?;
+            }
+            self.validators.update_stake(addr, remaining);
+        }
+
+        let mut record = self.get_penalties(store, addr)?.unwrap_or_default();
+        record.bad_submissions += 1;
+        self.stats.failed_submissions += 1;
+        record.penalized += &penalty;
+        record.last_epoch = epoch;
+        self.penalties.modify(store, |hamt| {
+            hamt.set(BytesKey::from(addr.to_bytes()), record)?;
+            Ok(true)
+        })?;
+
+        Ok(penalty)
+    }
+}
+
+/// Inconsistencies found by [`State::check_invariants`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InvariantReport {
+    pub violations: Vec<String>,
+}
+
+impl InvariantReport {
+    /// Whether the state is consistent.
+    pub fn is_ok(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    fn check(&mut self, holds: bool, violation: impl FnOnce() -> String) {
+        if !holds {
+            self.violations.push(violation());
+        }
+    }
+}
+
+impl fmt::Display for InvariantReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_ok() {
+            return write!(f, "state is consistent");
+        }
+        write!(
+            f,
+            "state invariants violated: {}",
+            self.violations.join("; ")
+        )
+    }
+}
+
+/// Decrements a counter of the state. Going below zero means the state is
+/// inconsistent, which aborts the message instead of wrapping around.
+fn decrement(count: &mut u64, what: &'static str) -> Result<(), SubnetActorError> {
+    *count = count
+        .checked_sub(1)
+        .ok_or(SubnetActorError::CountUnderflow(what))?;
+    Ok(())
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            parent_id: SubnetID::default(),
+            ipc_gateway_addr: Address::new_id(0),
+            consensus: ConsensusType::Delegated,
+            econ: EconParams {
+                min_validator_stake: TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT),
+                min_total_collateral: TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT),
+                ..EconParams::default()
+            },
             total_stake: TokenAmount::zero(),
             finality_threshold: 5,
             check_period: 10,
             genesis: Vec::new(),
             status: Status::Instantiated,
             checkpoints: TCid::default(),
+            legacy_checkpoints: None,
+            checkpoint_configs: TCid::default(),
             stake: TCid::default(),
-            window_checks: TCid::default(),
-            validator_set: Vec::new(),
+            window_checks: Voting::default(),
+            validators: ValidatorSet::default(),
+            config_number: 0,
+            validator_sets: TCid::default(),
+            membership_log: TCid::default(),
+            membership_events: 0,
+            membership_tickets: TCid::default(),
+            legacy_validator_set: Vec::new(),
+            legacy_validators: None,
             min_validators: 0,
+            owner: None,
+            invitation_only: false,
+            invitations: TCid::default(),
+            cross_msgs: TCid::default(),
+            cross_msg_registrants: TCid::default(),
+            collateral_token: None,
+            last_committed_epoch: 0,
+            max_missed_windows: 0,
+            participation: TCid::default(),
+            last_seen: TCid::default(),
+            penalized_stake: TokenAmount::zero(),
+            legacy_fee_dust: None,
+            treasury: TokenAmount::zero(),
+            treasury_held: TokenAmount::zero(),
+            spend_proposals: TCid::default(),
+            spend_votes: Voting::default(),
+            econ_votes: Voting::default(),
+            reward_pool: TokenAmount::zero(),
+            stats: Stats::default(),
+            pending_check_period: None,
+            check_period_changes: Vec::new(),
+            status_changed_at: 0,
+            activation: None,
+            stakers_count: 0,
+            staker_index: TCid::default(),
+            staker_index_len: 0,
+            staker_positions: TCid::default(),
+            checkpoints_count: 0,
+            bootstrapped: false,
+            weight_snapshots: TCid::default(),
+            standby: Vec::new(),
+            last_failover: None,
+            last_state_commitment: None,
+            dispute_period: 0,
+            pending_checkpoints: Vec::new(),
+            ipc_gateway_robust_addr: None,
+            gateway_codes: Vec::new(),
+            upgrade_codes: Vec::new(),
+            sig_scheme: SigScheme::default(),
+            validator_keys: TCid::default(),
+            workers: TCid::default(),
+            vesting: TCid::default(),
+            legacy_pending_gateway_calls: None,
+            pending_gateway_calls: Vec::new(),
+            notify_validator_set: false,
+            notified_power_table: None,
+            parent_finality: None,
+            legacy_finality_votes: None,
+            finality_votes: Voting::default(),
+            candidates: TCid::default(),
+            vote_expiry: Vec::new(),
+            penalties: TCid::default(),
+            max_submissions_per_window: 0,
+            submissions: TCid::default(),
+            caller_policies: CallerPolicies::default(),
+            paused: false,
+            owner_nonce: 0,
+            registered_with_gateway: false,
+            registration_bond: TokenAmount::zero(),
+            delegations: TCid::default(),
+            redelegations: TCid::default(),
+            commission_changes: Vec::new(),
+            funding: TCid::default(),
+            churn_limit: 0,
+            churn_window: 0,
+            churn: 0,
+            pending_changes: Vec::new(),
+            max_exits_per_window: 0,
+            exit_window: 0,
+            exits: 0,
+            exit_queue: Vec::new(),
+            join_fee_policy: JoinFeePolicy::Treasury,
+            join_fee_exempt: Vec::new(),
+            chain_id: 0,
+            description: String::new(),
+            metadata_url: None,
+            excess_stake_policy: ExcessStakePolicy::Reject,
+            max_voting_power_pct: None,
+            delegated_addr: None,
+            parent_checked_gateway: None,
+            anchor_genesis: false,
+            genesis_checkpoint: None,
+            slashed_offences: TCid::default(),
+            legacy_min_validator_stake: None,
+            legacy_min_total_collateral: None,
+            legacy_min_join_amount: None,
+            legacy_max_validator_stake: None,
+            legacy_join_fee: None,
+            legacy_relayer_fee: None,
+            legacy_checkpoint_penalty_rate: None,
+            legacy_leaving_coeff: None,
+            version: STATE_VERSION,
         }
     }
 }
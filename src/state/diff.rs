@@ -0,0 +1,136 @@
+//! Structured diff between two states of the actor, for tooling monitoring
+//! a subnet and for tests asserting the exact effect of a message.
+//!
+//! ```ignore
+//! let before = rt.get_state_root()?;
+//! // ... apply a message ...
+//! let diff = diff(store, &before, &rt.get_state_root()?)?;
+//! assert_eq!(diff.validators_added, vec![validator]);
+//! ```
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::anyhow;
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::CborStore;
+use fvm_shared::address::Address;
+use fvm_shared::clock::ChainEpoch;
+use fvm_shared::econ::TokenAmount;
+use num_traits::Zero;
+
+use super::State;
+use crate::compat::Checkpoint;
+use crate::types::{Status, Validator};
+
+/// Change of the stake of an address, zero when it had none.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StakeChange {
+    pub addr: Address,
+    pub before: TokenAmount,
+    pub after: TokenAmount,
+}
+
+/// Changes from a state of the actor to a later one.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StateDiff {
+    /// Status before and after, if it changed.
+    pub status: Option<(Status, Status)>,
+    /// Total stake before and after, if it changed.
+    pub total_stake: Option<(TokenAmount, TokenAmount)>,
+    /// Stake changes, ordered by address.
+    pub stake: Vec<StakeChange>,
+    /// Validators that entered the validator set, or changed their network
+    /// address, in the order of the validator set.
+    pub validators_added: Vec<Validator>,
+    /// Validators that left the validator set.
+    pub validators_removed: Vec<Address>,
+    /// Checkpoints committed in between, ordered by epoch.
+    pub new_checkpoints: Vec<(ChainEpoch, Checkpoint)>,
+}
+
+impl StateDiff {
+    pub fn is_empty(&self) -> bool {
+        *self == StateDiff::default()
+    }
+}
+
+/// Diffs the states at the `before` and `after` roots in `store`.
+pub fn diff<BS: Blockstore>(store: &BS, before: &Cid, after: &Cid) -> anyhow::Result<StateDiff> {
+    let load = |root: &Cid| -> anyhow::Result<State> {
+        store
+            .get_cbor(root)?
+            .ok_or_else(|| anyhow!("state not found: {}", root))
+    };
+    diff_states(store, &load(before)?, &load(after)?)
+}
+
+/// Diffs `before` and `after`, loading their contents from `store`.
+pub fn diff_states<BS: Blockstore>(
+    store: &BS,
+    before: &State,
+    after: &State,
+) -> anyhow::Result<StateDiff> {
+    let mut diff = StateDiff::default();
+    if before.status != after.status {
+        diff.status = Some((before.status, after.status));
+    }
+    if before.total_stake != after.total_stake {
+        diff.total_stake = Some((before.total_stake.clone(), after.total_stake.clone()));
+    }
+
+    // unchanged tables share their root
+    if before.stake.cid() != after.stake.cid() {
+        let (old, new) = (stake_table(store, before)?, stake_table(store, after)?);
+        let mut addrs: Vec<&Vec<u8>> = old.keys().chain(new.keys()).collect();
+        addrs.sort();
+        addrs.dedup();
+        for addr in addrs {
+            let stake_before = old.get(addr).cloned().unwrap_or_else(TokenAmount::zero);
+            let stake_after = new.get(addr).cloned().unwrap_or_else(TokenAmount::zero);
+            if stake_before != stake_after {
+                diff.stake.push(StakeChange {
+                    addr: Address::from_bytes(addr)?,
+                    before: stake_before,
+                    after: stake_after,
+                });
+            }
+        }
+    }
+
+    let old = before.validators.list(store)?;
+    let new = after.validators.list(store)?;
+    diff.validators_added = new.iter().filter(|v| !old.contains(v)).cloned().collect();
+    diff.validators_removed = old
+        .iter()
+        .filter(|v| !new.iter().any(|n| n.addr == v.addr))
+        .map(|v| v.addr)
+        .collect();
+
+    if before.checkpoints.cid() != after.checkpoints.cid() {
+        let old: BTreeSet<ChainEpoch> = before
+            .get_checkpoints_by_windows(store, 0..before.checkpoints_count)?
+            .iter()
+            .map(|ch| ch.epoch())
+            .collect();
+        diff.new_checkpoints = after
+            .get_checkpoints_by_windows(store, 0..after.checkpoints_count)?
+            .into_iter()
+            .filter(|ch| !old.contains(&ch.epoch()))
+            .map(|ch| (ch.epoch(), ch))
+            .collect();
+    }
+
+    Ok(diff)
+}
+
+fn stake_table<BS: Blockstore>(
+    store: &BS,
+    st: &State,
+) -> anyhow::Result<BTreeMap<Vec<u8>, TokenAmount>> {
+    let mut stake = BTreeMap::new();
+    st.stake.load(store)?.for_each(|k, amount| {
+        stake.insert(k.0.clone(), amount.clone());
+        Ok(())
+    })?;
+    Ok(stake)
+}
@@ -0,0 +1,75 @@
+//! JSON snapshot of the state of the actor, for tooling that needs to
+//! inspect it without walking its IPLD structures.
+use fvm_ipld_blockstore::Blockstore;
+use fvm_shared::address::Address;
+use fvm_shared::econ::TokenAmount;
+use serde_json::{json, Map, Value};
+
+use super::State;
+
+/// Dumps `st` along with the contents of its stake table, committed
+/// checkpoints and checkpoint votes, loaded from `store`.
+///
+/// Token amounts are given in attoFIL, as strings, and checkpoints are
+/// sorted by epoch.
+pub fn dump<BS: Blockstore>(st: &State, store: &BS) -> anyhow::Result<Value> {
+    let mut stake = Map::new();
+    st.stake.load(store)?.for_each(|k, amount| {
+        stake.insert(Address::from_bytes(&k.0)?.to_string(), atto(amount));
+        Ok(())
+    })?;
+
+    let checkpoints: Vec<Value> = st
+        .get_checkpoints_by_windows(store, 0..st.checkpoints_count)?
+        .iter()
+        .map(|ch| {
+            json!({
+                "epoch": ch.epoch(),
+                "cid": ch.cid().to_string(),
+                "prev_check": ch.prev_check().cid().to_string(),
+            })
+        })
+        .collect();
+
+    let mut votes = Map::new();
+    for cid in st.window_checks.proposals(store)? {
+        let validators = st
+            .window_checks
+            .get(store, &cid)?
+            .map(|v| v.validators)
+            .unwrap_or_default();
+        votes.insert(cid.to_string(), addresses(&validators));
+    }
+
+    let validators: Vec<Value> = st
+        .validators
+        .list(store)?
+        .iter()
+        .map(|v| json!({ "addr": v.addr.to_string(), "net_addr": v.net_addr }))
+        .collect();
+
+    Ok(json!({
+        "name": st.name,
+        "parent_id": st.parent_id.to_string(),
+        "ipc_gateway_addr": st.ipc_gateway_addr.to_string(),
+        "owner": st.owner.map(|a| a.to_string()),
+        "status": format!("{:?}", st.status),
+        "check_period": st.check_period,
+        "last_committed_epoch": st.last_committed_epoch,
+        "min_validator_stake": atto(&st.econ.min_validator_stake),
+        "total_stake": atto(&st.total_stake),
+        "validators": validators,
+        "stake": stake,
+        "checkpoints": checkpoints,
+        "votes": votes,
+        "version": st.version,
+    }))
+}
+
+fn atto(amount: &TokenAmount) -> Value {
+    Value::String(amount.atto().to_string())
+}
+
+fn addresses(addrs: &[Address]) -> Value {
+    Value::Array(addrs.iter().map(|a| Value::String(a.to_string())).collect())
+}
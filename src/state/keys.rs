@@ -0,0 +1,31 @@
+//! Canonical encoding of the keys of the HAMTs in the state.
+//!
+//! Keys need to be encoded the same way in every architecture the actor
+//! runs on, otherwise the state root depends on where it was computed.
+use anyhow::anyhow;
+use fvm_ipld_hamt::BytesKey;
+use fvm_shared::clock::ChainEpoch;
+
+/// Encodes an epoch as the big-endian bytes of the epoch, so that keys
+/// sort in the same order as epochs.
+pub fn epoch_key(epoch: ChainEpoch) -> BytesKey {
+    BytesKey::from(epoch.to_be_bytes().to_vec())
+}
+
+/// Decodes an epoch encoded with [`epoch_key`].
+pub fn parse_epoch_key(key: &[u8]) -> anyhow::Result<ChainEpoch> {
+    let bytes: [u8; 8] = key
+        .try_into()
+        .map_err(|_| anyhow!("invalid epoch key length: {}", key.len()))?;
+    Ok(ChainEpoch::from_be_bytes(bytes))
+}
+
+/// Decodes an epoch key written by state versions before 4, which used the
+/// native byte order. The actor only runs on wasm32, so these keys were
+/// written in little-endian.
+pub(crate) fn parse_legacy_epoch_key(key: &[u8]) -> anyhow::Result<ChainEpoch> {
+    let bytes: [u8; 8] = key
+        .try_into()
+        .map_err(|_| anyhow!("invalid epoch key length: {}", key.len()))?;
+    Ok(ChainEpoch::from_le_bytes(bytes))
+}
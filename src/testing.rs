@@ -0,0 +1,118 @@
+//! Builders to set up the state of the actor in unit tests of the state
+//! logic, without going through the `MockRuntime`.
+//!
+//! ```ignore
+//! let (store, st) = StateBuilder::new()
+//!     .with_validators(&[Address::new_id(10), Address::new_id(20)])
+//!     .with_stake(Address::new_id(30), TokenAmount::from_atto(1))
+//!     .build();
+//! ```
+use std::str::FromStr;
+
+use fvm_ipld_blockstore::MemoryBlockstore;
+use fvm_shared::address::Address;
+use fvm_shared::econ::TokenAmount;
+use num_traits::Zero;
+
+use crate::caller_policy::CallerPolicies;
+use crate::compat::{SubnetID, MIN_COLLATERAL_AMOUNT};
+use crate::sig::SigScheme;
+use crate::stake_math::StakeRatio;
+use crate::state::State;
+use crate::types::{ConsensusType, ConstructParams, ExcessStakePolicy, JoinFeePolicy};
+
+/// ID of the gateway the subnets built are registered in.
+pub const GATEWAY_ID: u64 = 1024;
+
+/// Builds a [`State`] backed by a [`MemoryBlockstore`].
+pub struct StateBuilder {
+    params: ConstructParams,
+    stakes: Vec<(Address, TokenAmount)>,
+}
+
+impl Default for StateBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StateBuilder {
+    /// Starts from a `/root` child subnet with dummy consensus and no
+    /// stakers.
+    pub fn new() -> Self {
+        Self {
+            params: ConstructParams {
+                parent: SubnetID::from_str("/root").unwrap(),
+                name: String::from("test"),
+                ipc_gateway_addr: Address::new_id(GATEWAY_ID),
+                consensus: ConsensusType::Dummy,
+                min_validator_stake: TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT),
+                min_validators: 0,
+                finality_threshold: 0,
+                check_period: 0,
+                genesis: Vec::new(),
+                owner: None,
+                invitation_only: false,
+                collateral_token: None,
+                max_missed_windows: 0,
+                chain_id: 0,
+                description: String::new(),
+                metadata_url: None,
+                max_validator_stake: TokenAmount::zero(),
+                excess_stake_policy: ExcessStakePolicy::Reject,
+                dispute_period: 0,
+                min_join_amount: TokenAmount::zero(),
+                caller_policies: CallerPolicies::default(),
+                checkpoint_penalty_rate: 0,
+                churn_limit: 0,
+                relayer_fee: TokenAmount::zero(),
+                min_total_collateral: TokenAmount::zero(),
+                max_submissions_per_window: 0,
+                max_exits_per_window: 0,
+                join_fee: TokenAmount::zero(),
+                join_fee_policy: JoinFeePolicy::Treasury,
+                join_fee_exempt: Vec::new(),
+                leaving_coeff: StakeRatio::default(),
+                sig_scheme: SigScheme::default(),
+                max_voting_power_pct: None,
+                delegated_namespace: None,
+                anchor_genesis: false,
+            },
+            stakes: Vec::new(),
+        }
+    }
+
+    /// Overrides the construction parameters of the subnet.
+    pub fn with_params(mut self, f: impl FnOnce(&mut ConstructParams)) -> Self {
+        f(&mut self.params);
+        self
+    }
+
+    /// Adds validators staking the minimum collateral.
+    pub fn with_validators(mut self, validators: &[Address]) -> Self {
+        for v in validators {
+            self.stakes
+                .push((*v, TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT)));
+        }
+        self
+    }
+
+    /// Adds `amount` to the stake of `addr`, which becomes a validator if
+    /// its stake reaches the minimum collateral.
+    pub fn with_stake(mut self, addr: Address, amount: TokenAmount) -> Self {
+        self.stakes.push((addr, amount));
+        self
+    }
+
+    /// Creates the state, adding the stakes in the order they were given.
+    pub fn build(self) -> (MemoryBlockstore, State) {
+        let store = MemoryBlockstore::new();
+        let mut st = State::new(&store, self.params).unwrap();
+        for (addr, amount) in self.stakes {
+            st.add_stake(&store, &addr, &addr, &addr.to_string(), &amount)
+                .unwrap();
+        }
+        st.mutate_state();
+        (store, st)
+    }
+}
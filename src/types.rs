@@ -1,19 +1,70 @@
+use cid::multihash::{Code, MultihashDigest};
+use cid::Cid;
+use fil_actors_runtime::ActorError;
 use fvm_ipld_encoding::repr::*;
 use fvm_ipld_encoding::tuple::{Deserialize_tuple, Serialize_tuple};
-use fvm_ipld_encoding::{Cbor, RawBytes};
+use fvm_ipld_encoding::{Cbor, RawBytes, DAG_CBOR};
 use fvm_shared::address::Address;
 use fvm_shared::clock::ChainEpoch;
+use fvm_shared::crypto::signature::Signature;
 use fvm_shared::econ::TokenAmount;
-use fvm_shared::MethodNum;
-use ipc_gateway::SubnetID;
-
-/// Optional leaving coefficient to penalize
-/// validators leaving the subnet.
-// It should be a float between 0-1 but
-// setting it to 1_u64 for now for convenience.
-// This will change once we figure out the econ model.
-pub const LEAVING_COEFF: u64 = 1;
+use fvm_shared::error::ExitCode;
+use fvm_shared::{ActorID, MethodNum};
+use num_traits::Zero;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::caller_policy::CallerPolicies;
+use crate::compat::{Checkpoint, SubnetID};
+use crate::proofs::IpldBlock;
+use crate::sig::SigScheme;
+use crate::stake_math::StakeRatio;
+
 pub const TESTING_ID: u64 = 339;
+/// Fraction of the stake charged to jailed validators
+/// to be released (1/UNJAIL_PENALTY_DIVISOR).
+pub const UNJAIL_PENALTY_DIVISOR: u64 = 100;
+/// Maximum number of entries returned in a page by list methods.
+pub const MAX_PAGE_LIMIT: u64 = 100;
+/// Maximum total voting power accepted by Tendermint, which the voting
+/// power of the power table is scaled to.
+pub const MAX_TOTAL_VOTING_POWER: u64 = (i64::MAX / 8) as u64;
+/// Denominator of penalty rates, which are given in basis points.
+pub const PENALTY_RATE_DENOMINATOR: u64 = 10_000;
+/// Denominator of commission rates, which are given in basis points.
+pub const COMMISSION_RATE_DENOMINATOR: u64 = 10_000;
+/// Maximum length in bytes of the network address of a validator.
+pub const MAX_NET_ADDR_LEN: usize = 256;
+/// Length in bytes of invitation commitments, blake2b-256 hashes.
+pub const INVITATION_COMMITMENT_LEN: usize = 32;
+/// Checkpoint windows a delegator waits between redelegations, so its
+/// stake can't hop between validators to shift the quorum of windows.
+pub const REDELEGATION_COOLDOWN_WINDOWS: ChainEpoch = 2;
+/// Checkpoint windows before an increase of the commission rate of a
+/// validator applies, so its delegators can undelegate before paying it.
+pub const COMMISSION_CHANGE_DELAY_WINDOWS: ChainEpoch = 2;
+/// Maximum size in bytes of an encoded checkpoint, signature included.
+/// Larger batches of bottom-up messages are referenced by CID instead, see
+/// `PutCrossMsgBatch`.
+pub const MAX_CHECKPOINT_SIZE: usize = 64 << 10;
+/// Maximum size in bytes of a batch of bottom-up messages put in the
+/// store of the actor.
+pub const MAX_CROSS_MSG_BATCH_SIZE: usize = 1 << 20;
+/// Maximum number of batches of bottom-up messages a validator registers
+/// for a window.
+pub const MAX_CROSS_MSG_BATCHES_PER_VALIDATOR: usize = 8;
+/// Maximum number of calls to the IPC gateway queued for retry. Further
+/// calls fail instead of growing the state while the gateway rejects them.
+pub const MAX_PENDING_GATEWAY_CALLS: usize = 32;
+/// Checkpoint windows behind the last committed checkpoint after which
+/// evidence of misbehavior can't slash anymore, the unbonding period of
+/// the stake it is against.
+pub const EVIDENCE_MAX_AGE_WINDOWS: ChainEpoch = 10;
+
+/// Exit code returned when collateral is sent to a terminating subnet.
+pub const ERR_SUBNET_TERMINATING: ExitCode = ExitCode::new(32);
+/// Exit code returned when collateral is sent to a killed subnet.
+pub const ERR_SUBNET_KILLED: ExitCode = ExitCode::new(33);
 
 #[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
 pub struct Validator {
@@ -21,6 +72,202 @@ pub struct Validator {
     pub net_addr: String,
 }
 
+/// Activation of the subnet, when it first gathered enough collateral to
+/// be registered in the IPC gateway.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct Activation {
+    /// Epoch of the parent the subnet was activated at.
+    pub epoch: ChainEpoch,
+    /// Sender of the message that activated the subnet.
+    pub sender: Address,
+}
+
+/// Proof of membership of a validator in a configuration of the validator
+/// set, recorded in the state so its subnet node can show peers it is
+/// entitled to take part in consensus from `effective_epoch`.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct MembershipTicket {
+    pub validator: Address,
+    /// Configuration of the validator set the validator was admitted in.
+    pub config_number: u64,
+    /// First epoch of the checkpoint window the validator votes in.
+    pub effective_epoch: ChainEpoch,
+}
+
+/// Committed checkpoint along with the proof of its inclusion in the state
+/// of the actor, returned by `GetCheckpointProof`.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct CheckpointProof {
+    /// Window number of the checkpoint, see `State::checkpoints`.
+    pub window: u64,
+    pub checkpoint: Checkpoint,
+    /// Configuration number of the validator set the checkpoint was
+    /// committed in, `None` for checkpoints committed before it was
+    /// recorded.
+    pub config_number: Option<u64>,
+    /// Blocks from the state root of the actor to the leaf of
+    /// `checkpoints` holding the checkpoint: the state, followed by the
+    /// path in the AMT.
+    pub blocks: Vec<IpldBlock>,
+}
+
+/// Call to the IPC gateway rejected by it, queued to be retried.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct GatewayCall {
+    /// Gateway the call was addressed to, which it is retried on even if
+    /// the subnet moves to another gateway in between.
+    pub to: Address,
+    pub method: MethodNum,
+    pub params: RawBytes,
+    pub value: TokenAmount,
+}
+
+/// Call queued by state versions before 32, without the gateway it was
+/// addressed to.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct LegacyGatewayCall {
+    pub method: MethodNum,
+    pub params: RawBytes,
+    pub value: TokenAmount,
+}
+
+/// Fields of the checkpoint expected for the next window, returned by
+/// `GetCheckpointTemplate` for validators and relayers to build the
+/// checkpoint they sign from the state of the actor.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct CheckpointTemplate {
+    pub epoch: ChainEpoch,
+    pub source: SubnetID,
+    /// CID of the last committed checkpoint, the default CID if there is
+    /// none.
+    pub prev_check: Cid,
+    /// Configuration of the validator set voting the checkpoint.
+    pub config_number: u64,
+    /// Digest of the validator set of `config_number`, to be committed in
+    /// the state commitment of the checkpoint.
+    pub validator_set: Cid,
+}
+
+/// Validators of a configuration of the validator set, returned by
+/// `GetValidatorSetAt`.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct ValidatorSetAt {
+    pub config_number: u64,
+    /// CID of the validators, sorted by address, as recorded in
+    /// `State::validator_sets`.
+    pub digest: Cid,
+    pub validators: Vec<Validator>,
+}
+
+/// Returned by `GetGenesisEpoch`, for subnet nodes to align their
+/// checkpoint windows with the activation of the subnet.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct ActivationInfo {
+    pub activation: Option<Activation>,
+    pub status: Status,
+    /// Epoch of the last status change.
+    pub status_changed_at: ChainEpoch,
+}
+
+/// Counters of the operations of the subnet, for explorers and dashboards
+/// to show its health without replaying its history.
+#[derive(Clone, Debug, Default, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct Stats {
+    pub joins: u64,
+    pub leaves: u64,
+    pub committed_checkpoints: u64,
+    /// Checkpoints recorded as invalid submissions. Submissions that abort
+    /// revert every change to the state, so they can't be counted.
+    pub failed_submissions: u64,
+    pub slashes: u64,
+    /// Windows whose votes expired without reaching quorum.
+    #[serde(default)]
+    pub expired_windows: u64,
+}
+
+/// Change of the checkpoint period of the subnet.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct CheckPeriodChange {
+    /// Epoch of the checkpoint from which windows are `new_period` long.
+    /// For a pending change, the earliest epoch it can take effect.
+    pub effective_epoch: ChainEpoch,
+    pub old_period: ChainEpoch,
+    pub new_period: ChainEpoch,
+}
+
+/// Change of the validator set queued by the churn limit.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct ValidatorChange {
+    pub validator: Validator,
+    /// Whether the validator joins the validator set, or leaves it.
+    pub join: bool,
+}
+
+/// Kind of change of the membership of the validator set.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Deserialize_repr, Serialize_repr)]
+#[repr(u64)]
+pub enum MembershipEventKind {
+    Join,
+    Leave,
+    Slash,
+    Jail,
+    Unjail,
+    Rotate,
+}
+
+/// Entry of the membership history of the validator set.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct MembershipEvent {
+    pub kind: MembershipEventKind,
+    pub validator: Address,
+    /// Epoch closing the checkpoint window the event happened in.
+    pub epoch: ChainEpoch,
+    /// Configuration of the validator set after the event.
+    pub config_number: u64,
+}
+
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct MembershipHistoryReturn {
+    pub events: Vec<MembershipEvent>,
+    /// Cursor of the next page, `None` if this is the last one.
+    pub next_cursor: Option<u64>,
+    pub total: u64,
+}
+impl Cbor for MembershipHistoryReturn {}
+
+/// Spending of the treasury proposed to the validators.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct SpendParams {
+    pub to: Address,
+    pub amount: TokenAmount,
+}
+impl Cbor for SpendParams {}
+
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct SpendProposal {
+    pub to: Address,
+    pub amount: TokenAmount,
+    pub proposer: Address,
+    pub proposed_at: ChainEpoch,
+}
+impl Cbor for SpendProposal {}
+
+/// Spend proposal pending approval, with the validators that approved it.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct SpendProposalInfo {
+    pub cid: Cid,
+    pub proposal: SpendProposal,
+    pub approvals: Vec<Address>,
+}
+
+/// Exit of a validator waiting in the exit queue.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct ExitRequest {
+    pub validator: Address,
+    /// Epoch the validator asked to leave at.
+    pub requested_at: ChainEpoch,
+}
+
 #[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
 pub struct Votes {
     pub validators: Vec<Address>,
@@ -28,6 +275,242 @@ pub struct Votes {
 
 impl Cbor for Votes {}
 
+/// Epoch at which the votes of a window left without quorum expire.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct VoteExpiry {
+    /// End of the window.
+    pub epoch: ChainEpoch,
+    pub expires_at: ChainEpoch,
+}
+
+/// Updates the metadata used to discover the subnet.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct SetMetadataParams {
+    pub chain_id: u64,
+    pub description: String,
+    pub metadata_url: Option<String>,
+}
+impl Cbor for SetMetadataParams {}
+
+/// Participation of a validator in checkpoint windows.
+#[derive(Clone, Debug, Default, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct Participation {
+    /// Consecutive committed windows the validator didn't vote for.
+    pub missed_windows: u64,
+    /// Jailed validators are excluded from the quorum.
+    pub jailed: bool,
+}
+
+/// Checkpoint voted in a window and the weight of its votes.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct CheckpointCandidate {
+    pub cid: Cid,
+    pub validators: Vec<Address>,
+    pub weight: TokenAmount,
+}
+
+/// Voting status of the checkpoint window at `epoch`, letting relayers
+/// decide whether their vote is still needed.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct CheckpointVotingStatus {
+    pub epoch: ChainEpoch,
+    /// Whether a checkpoint was already committed for the window.
+    pub committed: bool,
+    pub candidates: Vec<CheckpointCandidate>,
+    /// Validators that voted for any of the candidates.
+    pub voted: Vec<Address>,
+    /// Weight of the validators that can vote in the window. Weights are
+    /// those of the quorum policy of the consensus, one atto per validator
+    /// for count-based policies.
+    pub total_weight: TokenAmount,
+    /// Weight a candidate needs to be committed.
+    pub threshold: TokenAmount,
+}
+impl Cbor for CheckpointVotingStatus {}
+
+/// Invalid checkpoints submitted by a validator and the stake it was
+/// penalized for them.
+#[derive(Clone, Debug, Default, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct PenaltyRecord {
+    /// Number of invalid checkpoints submitted.
+    pub bad_submissions: u64,
+    /// Total stake penalized.
+    pub penalized: TokenAmount,
+    /// Epoch of the last invalid submission.
+    pub last_epoch: ChainEpoch,
+}
+
+/// Checkpoints submitted by a validator in a checkpoint window.
+#[derive(Clone, Debug, Default, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct SubmissionCount {
+    /// Epoch closing the window.
+    pub window: ChainEpoch,
+    /// Number of checkpoints submitted.
+    pub count: u64,
+}
+
+/// Weights of the validators at the start of a checkpoint window.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct WeightSnapshot {
+    /// Voting power of the validators, their stake capped by
+    /// `max_voting_power_pct`.
+    pub weights: Vec<(Address, TokenAmount)>,
+    /// Total voting power in the subnet when the snapshot was taken.
+    pub total: TokenAmount,
+    /// Configuration of the validator set when the snapshot was taken.
+    #[serde(default)]
+    pub config_number: u64,
+    /// Stake of the validators, counted as their collateral in full.
+    /// Empty for snapshots taken before voting power was capped.
+    #[serde(default)]
+    pub collateral: Vec<(Address, TokenAmount)>,
+}
+
+/// Promotion of a standby staker after the delegate of a subnet with
+/// delegated consensus left or was jailed. Subnet nodes watch the nonce
+/// to reconfigure their consensus.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct DelegateFailover {
+    /// Increases with every failover of the subnet.
+    pub nonce: u64,
+    /// Delegate that was removed.
+    pub prev: Address,
+    /// Promoted delegate, if there was any staker on standby.
+    pub delegate: Option<Address>,
+}
+
+/// State of the subnet claimed by a checkpoint, submitted along with it in
+/// a [`CheckpointSubmission`].
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct StateCommitment {
+    /// Root of the state of the subnet at `height`.
+    pub state_root: Cid,
+    /// Subnet block height the state root belongs to.
+    pub height: ChainEpoch,
+    /// Configuration of the validator set the checkpoint was produced
+    /// under, the one the votes of its window are weighted with.
+    #[serde(default)]
+    pub config_number: u64,
+    /// Digest of the validator set of `config_number`, see
+    /// `GetValidatorSetAt`.
+    #[serde(default)]
+    pub validator_set: Cid,
+}
+impl Cbor for StateCommitment {}
+
+/// Checkpoint voted by a validator along with the state of the subnet it
+/// claims. The fields of the checkpoint are left to the gateway, so the
+/// commitment travels next to it.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct CheckpointSubmission {
+    pub checkpoint: Checkpoint,
+    pub state_commitment: StateCommitment,
+}
+impl Cbor for CheckpointSubmission {}
+
+impl CheckpointSubmission {
+    /// CID the votes of the submission are keyed by, and what validators
+    /// sign: the digest of the CID of the checkpoint, which leaves out its
+    /// signature, and of the state commitment.
+    pub fn digest(&self) -> anyhow::Result<Cid> {
+        let bytes = fvm_ipld_encoding::to_vec(&(self.checkpoint.cid(), &self.state_commitment))?;
+        Ok(Cid::new_v1(DAG_CBOR, Code::Blake2b256.digest(&bytes)))
+    }
+}
+
+/// Committed checkpoint waiting for its dispute period to end, and for its
+/// epoch to be final, before being relayed to the gateway.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct PendingCheckpoint {
+    /// Epoch of the checkpoint.
+    pub epoch: ChainEpoch,
+    /// First epoch the checkpoint can be relayed at.
+    pub relay_at: ChainEpoch,
+    /// Validators that voted for the checkpoint.
+    pub voters: Vec<Address>,
+    /// Epoch of the last committed checkpoint before this one.
+    pub prev_committed_epoch: ChainEpoch,
+    /// State commitment of the last committed checkpoint before this one.
+    pub prev_state_commitment: Option<StateCommitment>,
+}
+
+/// Finality rules of the subnet, returned by `GetFinalityConfig`.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct FinalityConfig {
+    /// Epochs a height needs to be behind the current epoch to be final:
+    /// parent heights voted as parent finality, and subnet epochs of
+    /// checkpoints relayed to the gateway.
+    pub finality_threshold: ChainEpoch,
+    /// Epochs committed checkpoints can be challenged before they are
+    /// relayed to the gateway.
+    pub dispute_period: ChainEpoch,
+}
+
+/// Key a validator signs checkpoints with, when rotated away from the key
+/// of its account.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct ValidatorKey {
+    /// Public key address of the key in use from `effective_at`.
+    pub key: Address,
+    /// Epoch of the first checkpoint window signed with `key`.
+    pub effective_at: ChainEpoch,
+    /// Key used for windows before `effective_at`, the key of the account
+    /// of the validator if not set.
+    pub prev: Option<Address>,
+    /// Number of rotations, to protect them against replays.
+    pub nonce: u64,
+}
+
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct RotateValidatorKeyParams {
+    /// Public key address (secp256k1 or BLS) of the new key, or the f410
+    /// address of an Ethereum key. Validators whose account doesn't expose
+    /// its public key, like Ethereum accounts, register their key this way.
+    pub key: Address,
+    /// Number of rotations done so far by the validator.
+    pub nonce: u64,
+    /// Signature by the new key of [`key_rotation_payload`], proving the
+    /// validator holds it.
+    ///
+    /// [`key_rotation_payload`]: crate::key_rotation_payload
+    pub signature: Signature,
+}
+impl Cbor for RotateValidatorKeyParams {}
+
+/// Validator of the power table, with its stake scaled to a voting power.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct PowerTableEntry {
+    pub addr: Address,
+    pub net_addr: String,
+    pub power: u64,
+    /// Stake of the validator, including the part above the cap of its
+    /// voting power.
+    pub collateral: TokenAmount,
+}
+
+/// Active validators of the subnet in the format of the membership of
+/// Tendermint and Mir, sorted by decreasing power and then by address.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct PowerTable {
+    pub validators: Vec<PowerTableEntry>,
+    pub total_power: u64,
+    /// Checkpoint window the seed applies to.
+    pub window: ChainEpoch,
+    /// Seed for the leader rotation of the window, so that every node
+    /// elects the same leaders.
+    pub rotation_seed: Vec<u8>,
+}
+impl Cbor for PowerTable {}
+
+/// Height of the parent chain considered final by the validators.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct ParentFinality {
+    pub height: ChainEpoch,
+    /// Hash of the block of the parent at `height`.
+    pub block_hash: Vec<u8>,
+}
+impl Cbor for ParentFinality {}
+
 /// Consensus types supported by hierarchical consensus
 #[derive(PartialEq, Eq, Clone, Copy, Debug, Deserialize_repr, Serialize_repr)]
 #[repr(u64)]
@@ -40,6 +523,29 @@ pub enum ConsensusType {
     Dummy,
 }
 
+/// Policy applied to collateral that would push the stake
+/// of a validator above `max_validator_stake`.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default, Deserialize_repr, Serialize_repr)]
+#[repr(u64)]
+pub enum ExcessStakePolicy {
+    /// The join is rejected.
+    #[default]
+    Reject,
+    /// The excess is refunded to the validator in the same call.
+    Refund,
+}
+
+/// What to do with the join fees charged to new stakers.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default, Deserialize_repr, Serialize_repr)]
+#[repr(u64)]
+pub enum JoinFeePolicy {
+    /// Fees are added to the treasury.
+    #[default]
+    Treasury,
+    /// Fees are sent to the burnt funds actor.
+    Burn,
+}
+
 #[derive(PartialEq, Eq, Clone, Copy, Debug, Deserialize_repr, Serialize_repr)]
 #[repr(i32)]
 pub enum Status {
@@ -54,8 +560,12 @@ pub enum Status {
 pub struct ConstructParams {
     pub parent: SubnetID,
     pub name: String,
-    pub ipc_gateway_addr: u64,
+    /// Address of the IPC gateway, an ID (f0), actor (f2) or delegated
+    /// (f4) address resolved to its ID at construction.
+    pub ipc_gateway_addr: Address,
     pub consensus: ConsensusType,
+    /// Minimum stake of a validator to be in the validator set and the
+    /// power table. Zero uses the `MIN_COLLATERAL_AMOUNT` of the gateway.
     pub min_validator_stake: TokenAmount,
     pub min_validators: u64,
     pub finality_threshold: ChainEpoch,
@@ -64,15 +574,584 @@ pub struct ConstructParams {
     // on-the-fly, but it is accepted as a construct
     // param
     pub genesis: Vec<u8>,
+    /// Address entitled to perform administrative actions like issuing
+    /// invitations, setting metadata or pausing the actor, until it
+    /// renounces the ownership of the subnet.
+    pub owner: Option<Address>,
+    /// If set, new validators can only join the subnet
+    /// presenting an invitation issued by the owner.
+    pub invitation_only: bool,
+    /// ID address of the FRC-46 token the collateral of the subnet is
+    /// denominated in. Native FIL is used if not set.
+    pub collateral_token: Option<Address>,
+    /// Consecutive checkpoint windows a validator can miss before
+    /// being jailed. Zero disables jailing.
+    pub max_missed_windows: u64,
+    /// Chain ID of the subnet.
+    pub chain_id: u64,
+    /// Human-readable description of the subnet.
+    pub description: String,
+    /// Optional URL or ENS-style record with more info about the subnet.
+    pub metadata_url: Option<String>,
+    /// Maximum stake of a single validator. Zero means no cap.
+    pub max_validator_stake: TokenAmount,
+    /// What to do with collateral exceeding `max_validator_stake`.
+    pub excess_stake_policy: ExcessStakePolicy,
+    /// Epochs committed checkpoints can be challenged before they are
+    /// relayed to the gateway. Zero relays them right away.
+    pub dispute_period: ChainEpoch,
+    /// Minimum collateral of a single join, to prevent dust joins from
+    /// bloating the stake table.
+    pub min_join_amount: TokenAmount,
+    /// Actor types allowed to call the validator methods.
+    pub caller_policies: CallerPolicies,
+    /// Stake penalized for each signed checkpoint with a wrong source or
+    /// previous checkpoint, in basis points. Zero disables penalties.
+    pub checkpoint_penalty_rate: u64,
+    /// Maximum number of validators joining or leaving the validator set
+    /// in a checkpoint window, changes beyond it are queued to the next
+    /// windows. Zero means no limit.
+    pub churn_limit: u64,
+    /// Fee paid from the reward pool to whoever commits a checkpoint,
+    /// casting the final vote or submitting the aggregated signatures,
+    /// to compensate the extra gas of the commitment.
+    pub relayer_fee: TokenAmount,
+    /// Total collateral activating the subnet and registering it in the
+    /// gateway, at least its `MIN_COLLATERAL_AMOUNT`. Zero uses the
+    /// `MIN_COLLATERAL_AMOUNT`.
+    pub min_total_collateral: TokenAmount,
+    /// Maximum number of checkpoints a validator can submit while a
+    /// checkpoint window is open, votes and penalized submissions
+    /// included. Zero means no limit.
+    pub max_submissions_per_window: u64,
+    /// Maximum number of validators leaving the validator set in a
+    /// checkpoint window, further exits wait in the exit queue. Zero
+    /// means no limit.
+    pub max_exits_per_window: u64,
+    /// Non-refundable fee charged on joins of addresses without stake in
+    /// the subnet, on top of their collateral, to make sybil addresses
+    /// costly. Zero disables it. Not supported with a collateral token.
+    pub join_fee: TokenAmount,
+    /// What to do with the join fees.
+    pub join_fee_policy: JoinFeePolicy,
+    /// Addresses joining without the join fee.
+    pub join_fee_exempt: Vec<Address>,
+    /// Fraction of the stake withdrawn validators get back when leaving,
+    /// the rest is forfeited to the treasury.
+    pub leaving_coeff: StakeRatio,
+    /// Signature scheme validators sign checkpoints with.
+    pub sig_scheme: SigScheme,
+    /// Maximum share of the voting power of a single validator, in
+    /// percent, in the checkpoint quorum and the power table. Stake above
+    /// it still counts as collateral. `None` means no cap.
+    pub max_voting_power_pct: Option<u64>,
+    /// ID of the actor deploying the subnet actor through `Exec4` of the
+    /// init actor, the namespace of its delegated address. `None` when
+    /// deployed through `Exec`, without a delegated address.
+    pub delegated_namespace: Option<ActorID>,
+    /// Whether the subnet commits a genesis checkpoint anchoring its
+    /// genesis in the parent when it registers in the gateway, for the
+    /// chain of checkpoints to start at it.
+    pub anchor_genesis: bool,
 }
 impl Cbor for ConstructParams {}
 
+/// Economic parameters of a subnet, set at construction from the
+/// parameters of the same name and updated by the validators through
+/// `ProposeEconParams`.
+#[derive(Clone, Debug, Default, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct EconParams {
+    /// Minimum stake of a validator to be in the validator set.
+    pub min_validator_stake: TokenAmount,
+    /// Total stake activating the subnet.
+    pub min_total_collateral: TokenAmount,
+    /// Minimum collateral of a single join.
+    pub min_join_amount: TokenAmount,
+    /// Maximum stake of a single validator. Zero means no cap.
+    pub max_validator_stake: TokenAmount,
+    /// Fee charged on joins of addresses without stake.
+    pub join_fee: TokenAmount,
+    /// Fee paid to the relayer of each committed checkpoint.
+    pub relayer_fee: TokenAmount,
+    /// Stake penalized for each invalid checkpoint, in basis points.
+    pub checkpoint_penalty_rate: u64,
+    /// Fraction of the stake they withdraw validators keep when leaving
+    /// the subnet, the rest is forfeited to the treasury.
+    pub leaving_coeff: StakeRatio,
+}
+impl Cbor for EconParams {}
+
+/// Summary of the subnet returned by `GetSubnetSummary`, read from a single
+/// state so that its fields are consistent with each other.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct SubnetSummary {
+    pub status: Status,
+    pub consensus: ConsensusType,
+    pub total_stake: TokenAmount,
+    /// Number of members of the validator set.
+    pub validators: u64,
+    pub last_committed_epoch: ChainEpoch,
+    /// CID of the checkpoint committed at `last_committed_epoch`, if any.
+    pub last_committed_checkpoint: Option<Cid>,
+    /// End of the window of the next checkpoint expected.
+    pub next_checkpoint_epoch: ChainEpoch,
+    pub econ: EconParams,
+}
+impl Cbor for SubnetSummary {}
+
 #[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
 pub struct JoinParams {
-    pub validator_net_addr: String,
+    pub validator_net_addr: NetAddress,
+    /// Preimage of an invitation commitment. Only required
+    /// when joining an invitation-only subnet for the first time.
+    pub invitation: Option<Vec<u8>>,
+    /// Vests the collateral staked in the join, which can't be withdrawn
+    /// before it vests. The validator can't leave until all of it vests.
+    pub vesting: Option<VestingSchedule>,
+    /// Public key address of the worker key signing the checkpoints of the
+    /// validator, whose account submits them on its behalf. Required for
+    /// validators owned by a multisig, which can't sign. Only accepted
+    /// while the validator has no signing key, see `RotateValidatorKey`.
+    pub worker: Option<Address>,
 }
 impl Cbor for JoinParams {}
 
+/// Network address of a validator, a multiaddr in its text format, e.g.
+/// `/ip4/10.0.0.1/tcp/26656`.
+///
+/// It is encoded as a string, and is only checked when received by the
+/// actor, see [`NetAddress::validate`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct NetAddress(String);
+
+/// Reasons for a network address to be rejected.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum NetAddressError {
+    #[error("empty address")]
+    Empty,
+    #[error("address longer than {} bytes", MAX_NET_ADDR_LEN)]
+    TooLong,
+    #[error("address must start with '/'")]
+    MissingSlash,
+    #[error("unknown protocol: {0}")]
+    UnknownProtocol(String),
+    #[error("missing value for protocol {0}")]
+    MissingValue(String),
+    #[error("invalid value for protocol {0}: {1}")]
+    InvalidValue(String, String),
+}
+
+impl NetAddress {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Checks that the address is a non-empty multiaddr of known
+    /// protocols, of at most [`MAX_NET_ADDR_LEN`] bytes.
+    pub fn validate(&self) -> Result<(), NetAddressError> {
+        if self.0.is_empty() {
+            return Err(NetAddressError::Empty);
+        }
+        if self.0.len() > MAX_NET_ADDR_LEN {
+            return Err(NetAddressError::TooLong);
+        }
+        let mut parts = self
+            .0
+            .strip_prefix('/')
+            .ok_or(NetAddressError::MissingSlash)?
+            .split('/');
+        while let Some(protocol) = parts.next() {
+            let check: fn(&str) -> bool = match protocol {
+                "ip4" => |v| v.parse::<std::net::Ipv4Addr>().is_ok(),
+                "ip6" => |v| v.parse::<std::net::Ipv6Addr>().is_ok(),
+                "dns" | "dns4" | "dns6" | "dnsaddr" => |v| {
+                    v.bytes()
+                        .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'.')
+                },
+                "tcp" | "udp" => |v| v.parse::<u16>().is_ok(),
+                "p2p" | "ipfs" => |v| v.bytes().all(|b| b.is_ascii_alphanumeric()),
+                "quic" | "quic-v1" | "ws" | "wss" | "tls" | "noise" | "http" | "https"
+                | "webtransport" | "p2p-circuit" => continue,
+                _ => return Err(NetAddressError::UnknownProtocol(protocol.to_string())),
+            };
+            match parts.next() {
+                None | Some("") => return Err(NetAddressError::MissingValue(protocol.to_string())),
+                Some(v) if !check(v) => {
+                    return Err(NetAddressError::InvalidValue(
+                        protocol.to_string(),
+                        v.to_string(),
+                    ))
+                }
+                Some(_) => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for NetAddress {
+    type Err = NetAddressError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let addr = NetAddress(s.to_string());
+        addr.validate()?;
+        Ok(addr)
+    }
+}
+
+impl std::fmt::Display for NetAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Delegates the value of the message to a validator.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct DelegateParams {
+    pub validator: Address,
+}
+impl Cbor for DelegateParams {}
+
+/// Withdraws all the stake the caller delegated to a validator.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct UndelegateParams {
+    pub validator: Address,
+}
+impl Cbor for UndelegateParams {}
+
+/// Moves `amount` of the stake the caller delegated to `from` to `to`.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct RedelegateParams {
+    pub from: Address,
+    pub to: Address,
+    pub amount: TokenAmount,
+}
+impl Cbor for RedelegateParams {}
+
+/// Checks a join of `validator` staking `amount` of collateral.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct CanJoinParams {
+    pub validator: Address,
+    pub amount: TokenAmount,
+    /// Preimage of the invitation the validator would join with, see
+    /// `JoinParams`.
+    pub invitation: Option<Vec<u8>>,
+}
+impl Cbor for CanJoinParams {}
+
+/// Checks `validator` leaving the subnet, or only withdrawing `amount` of
+/// its stake with `WithdrawStake`.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct CanLeaveParams {
+    pub validator: Address,
+    pub amount: Option<TokenAmount>,
+}
+impl Cbor for CanLeaveParams {}
+
+/// Checks `validator` submitting the checkpoint of the window ending at
+/// `epoch`.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct CanSubmitCheckpointParams {
+    pub validator: Address,
+    pub epoch: ChainEpoch,
+}
+impl Cbor for CanSubmitCheckpointParams {}
+
+/// Outcome of the dry run of a method: the exit code and message it would
+/// fail with, `ExitCode::OK` and an empty message if it would pass.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct DryRunReturn {
+    pub exit_code: ExitCode,
+    pub reason: String,
+}
+impl Cbor for DryRunReturn {}
+
+impl DryRunReturn {
+    pub fn is_ok(&self) -> bool {
+        self.exit_code.is_success()
+    }
+}
+
+impl From<Result<(), ActorError>> for DryRunReturn {
+    fn from(res: Result<(), ActorError>) -> Self {
+        match res {
+            Ok(()) => Self {
+                exit_code: ExitCode::OK,
+                reason: String::new(),
+            },
+            Err(e) => Self {
+                exit_code: e.exit_code(),
+                reason: e.msg().to_string(),
+            },
+        }
+    }
+}
+
+/// Part of the stake withdrawn by a validator that stays in the subnet.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct WithdrawStakeParams {
+    pub amount: TokenAmount,
+}
+impl Cbor for WithdrawStakeParams {}
+
+/// Collateral of a validator released by the IPC gateway.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct ReleaseStakeForParams {
+    pub validator: Address,
+    pub amount: TokenAmount,
+}
+impl Cbor for ReleaseStakeForParams {}
+
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct TransferOwnershipParams {
+    pub new_owner: Address,
+    /// Number of ownership changes done so far, see `State::owner_nonce`.
+    pub nonce: u64,
+}
+impl Cbor for TransferOwnershipParams {}
+
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct Delegation {
+    pub delegator: Address,
+    pub amount: TokenAmount,
+}
+
+/// Stake put up by a funder for a staker, in the funding ledger of the
+/// staker.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct Funding {
+    pub funder: Address,
+    pub amount: TokenAmount,
+}
+
+/// Ledger of the stake delegated to a validator, which is part of the stake
+/// of the validator but can only be withdrawn by its delegators.
+#[derive(Clone, Debug, Default, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct Delegations {
+    /// Commission the validator keeps from the fees earned by the delegated
+    /// stake, in basis points.
+    pub commission_rate: u64,
+    pub total: TokenAmount,
+    pub delegators: Vec<Delegation>,
+}
+
+/// Increase of the commission rate of a validator, waiting for
+/// `COMMISSION_CHANGE_DELAY_WINDOWS` to apply.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct CommissionChange {
+    pub validator: Address,
+    pub rate: u64,
+    pub effective_at: ChainEpoch,
+}
+
+/// Schedule of the vesting of stake, in epochs since it was staked.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct VestingSchedule {
+    /// Epochs before any of the stake vests.
+    pub cliff: ChainEpoch,
+    /// The stake vests in steps of `period` epochs.
+    pub period: ChainEpoch,
+    /// Epochs until all of the stake is vested.
+    pub duration: ChainEpoch,
+}
+
+impl VestingSchedule {
+    pub fn is_valid(&self) -> bool {
+        self.period > 0
+            && self.period <= self.duration
+            && self.cliff >= 0
+            && self.cliff <= self.duration
+    }
+}
+
+/// Stake of a validator vesting with a schedule.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct Vesting {
+    pub amount: TokenAmount,
+    /// Epoch the stake was staked at.
+    pub start: ChainEpoch,
+    pub schedule: VestingSchedule,
+}
+
+impl Vesting {
+    /// Part of the vesting stake not vested yet at `epoch`.
+    pub fn locked(&self, epoch: ChainEpoch) -> TokenAmount {
+        let elapsed = epoch - self.start;
+        if elapsed < self.schedule.cliff {
+            return self.amount.clone();
+        }
+        if elapsed >= self.schedule.duration {
+            return TokenAmount::zero();
+        }
+        let vested_epochs = elapsed - elapsed % self.schedule.period;
+        let vested =
+            TokenAmount::from_atto(self.amount.atto() * vested_epochs / self.schedule.duration);
+        &self.amount - vested
+    }
+}
+
+/// Outcome of a join, returned so callers don't need an
+/// additional state read.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct JoinReturn {
+    pub status: Status,
+    pub total_stake: TokenAmount,
+    pub became_validator: bool,
+    /// Collateral above `max_validator_stake` refunded to the caller.
+    pub refunded: TokenAmount,
+    /// Ticket issued if the caller became a validator.
+    pub ticket: Option<MembershipTicket>,
+}
+impl Cbor for JoinReturn {}
+
+/// Genesis validator funded by the caller of `BootstrapValidators`.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct BootstrapValidator {
+    pub addr: Address,
+    pub net_addr: NetAddress,
+    pub stake: TokenAmount,
+}
+
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct BootstrapValidatorsParams {
+    pub validators: Vec<BootstrapValidator>,
+}
+impl Cbor for BootstrapValidatorsParams {}
+
+/// Requests a page of `limit` entries starting at `cursor`.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct ListParams {
+    pub cursor: u64,
+    pub limit: u64,
+}
+impl Cbor for ListParams {}
+
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct ListStakersReturn {
+    pub stakers: Vec<(Address, TokenAmount)>,
+    /// Cursor of the next page, `None` if this is the last one.
+    pub next_cursor: Option<u64>,
+    pub total: u64,
+}
+impl Cbor for ListStakersReturn {}
+
+/// Outcome of compacting a page of the stake table.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct CompactStakeReturn {
+    /// Entries without stake removed from the page.
+    pub removed: u64,
+    /// Cursor of the next page, `None` if this is the last one.
+    pub next_cursor: Option<u64>,
+}
+impl Cbor for CompactStakeReturn {}
+
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct ListCheckpointsReturn {
+    pub checkpoints: Vec<Checkpoint>,
+    /// Cursor of the next page, `None` if this is the last one.
+    pub next_cursor: Option<u64>,
+    pub total: u64,
+}
+impl Cbor for ListCheckpointsReturn {}
+
+/// Requests the checkpoints committed at epochs from `from` to `to`, both
+/// included.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct CheckpointRangeParams {
+    pub from: ChainEpoch,
+    pub to: ChainEpoch,
+}
+impl Cbor for CheckpointRangeParams {}
+
+/// Outcome of leaving the subnet.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct LeaveReturn {
+    pub refunded: TokenAmount,
+    pub remaining_stake: TokenAmount,
+    /// Stake refunded to each funder of the stake withdrawn, see
+    /// `State::funding`.
+    pub refunds: Vec<(Address, TokenAmount)>,
+}
+impl Cbor for LeaveReturn {}
+
+/// Single-use invitation issued by the owner of the subnet.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct Invitation {
+    pub redeemed_by: Option<Address>,
+}
+
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct IssueInvitationsParams {
+    /// Blake2b-256 hashes of the invitation secrets and their invitees,
+    /// see `invitation_commitment`.
+    pub commitments: Vec<Vec<u8>>,
+}
+impl Cbor for IssueInvitationsParams {}
+
+/// Registers the CID of the batch of bottom-up messages
+/// that will be included in the checkpoint of a window.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct RegisterCrossMsgParams {
+    pub epoch: ChainEpoch,
+    pub msgs_cid: Cid,
+}
+impl Cbor for RegisterCrossMsgParams {}
+
+/// Batch of bottom-up messages of the checkpoint of the window ending at
+/// `epoch`, as the DAG-CBOR encoding the checkpoint references by CID.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct PutCrossMsgBatchParams {
+    pub epoch: ChainEpoch,
+    pub batch: RawBytes,
+}
+impl Cbor for PutCrossMsgBatchParams {}
+
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct PutCrossMsgBatchReturn {
+    /// CID to reference the batch with in the `CrossMsgMeta` of the
+    /// checkpoint.
+    pub msgs_cid: Cid,
+    /// Size of the batch in bytes.
+    pub size: u64,
+}
+impl Cbor for PutCrossMsgBatchReturn {}
+
+/// Signature of a checkpoint by a validator.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct ValidatorSignature {
+    pub validator: Address,
+    pub signature: Signature,
+}
+
+/// Checkpoint with the signatures of validators collected off-chain,
+/// committed in a single message.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct AggregatedCheckpointParams {
+    pub submission: CheckpointSubmission,
+    pub signatures: Vec<ValidatorSignature>,
+}
+impl Cbor for AggregatedCheckpointParams {}
+
+/// Checkpoint signed by a majority of validators conflicting with a
+/// committed checkpoint still in its dispute period, in its checkpoint or
+/// in its state commitment.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct ChallengeCheckpointParams {
+    pub submission: CheckpointSubmission,
+    pub signatures: Vec<ValidatorSignature>,
+}
+impl Cbor for ChallengeCheckpointParams {}
+
+/// Evidence of the misbehavior of a validator in the consensus of the
+/// subnet, in the format of the consensus, see [`crate::evidence`].
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct SubmitEvidenceParams {
+    pub validator: Address,
+    pub evidence: RawBytes,
+}
+impl Cbor for SubmitEvidenceParams {}
+
 pub(crate) struct CrossActorPayload {
     pub to: Address,
     pub method: MethodNum,
@@ -0,0 +1,191 @@
+//! Validator set of the subnet.
+//!
+//! Validators are kept in a HAMT keyed by their address, so checking
+//! whether an address is a validator doesn't scan the whole set, along
+//! with an index of their addresses in the order of the set, to walk it
+//! when exporting weights and the power table.
+//!
+//! The set is ordered by decreasing stake and then by address, so that its
+//! order doesn't depend on the order validators joined in. The index caches
+//! the stake of each validator, so a change of stake only moves the entry
+//! of its validator, without loading the stake of the rest.
+use std::cmp::Ordering;
+
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::tuple::{Deserialize_tuple, Serialize_tuple};
+use fvm_ipld_hamt::BytesKey;
+use fvm_shared::address::Address;
+use fvm_shared::econ::TokenAmount;
+use primitives::{TCid, THamt};
+
+use crate::types::Validator;
+
+#[derive(Clone, Debug, Default, Serialize_tuple, Deserialize_tuple)]
+pub struct ValidatorSet {
+    validators: TCid<THamt<Address, Validator>>,
+    index: Vec<IndexEntry>,
+}
+
+/// Entry of the index of the validator set, with the stake of the
+/// validator the set is ordered by.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize_tuple, Deserialize_tuple)]
+pub struct IndexEntry {
+    pub addr: Address,
+    pub stake: TokenAmount,
+}
+
+impl IndexEntry {
+    /// Whether the entry goes before `other` in the set.
+    pub fn precedes(&self, other: &IndexEntry) -> bool {
+        match other.stake.cmp(&self.stake) {
+            Ordering::Equal => self.addr.to_bytes() < other.addr.to_bytes(),
+            ord => ord == Ordering::Less,
+        }
+    }
+}
+
+/// Validator set written by state versions before 34, indexing the
+/// addresses of the validators only.
+#[derive(Clone, Debug, Default, Serialize_tuple, Deserialize_tuple)]
+pub struct LegacyValidatorSet {
+    pub validators: TCid<THamt<Address, Validator>>,
+    pub index: Vec<Address>,
+}
+
+impl ValidatorSet {
+    pub fn new<BS: Blockstore>(store: &BS) -> anyhow::Result<Self> {
+        Ok(Self {
+            validators: TCid::new_hamt(store)?,
+            index: Vec::new(),
+        })
+    }
+
+    /// CID of the HAMT of validators.
+    pub fn cid(&self) -> Cid {
+        self.validators.cid()
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Addresses of the validators, in the order of the set.
+    pub fn addrs(&self) -> impl Iterator<Item = &Address> + '_ {
+        self.index.iter().map(|e| &e.addr)
+    }
+
+    /// Entries of the index, in the order of the set.
+    pub fn entries(&self) -> &[IndexEntry] {
+        &self.index
+    }
+
+    pub fn get<BS: Blockstore>(
+        &self,
+        store: &BS,
+        addr: &Address,
+    ) -> anyhow::Result<Option<Validator>> {
+        let hamt = self.validators.load(store)?;
+        let validator = hamt.get(&BytesKey::from(addr.to_bytes()))?;
+        Ok(validator.cloned())
+    }
+
+    pub fn contains<BS: Blockstore>(&self, store: &BS, addr: &Address) -> anyhow::Result<bool> {
+        Ok(self.get(store, addr)?.is_some())
+    }
+
+    /// Validators in the order of the set.
+    pub fn list<BS: Blockstore>(&self, store: &BS) -> anyhow::Result<Vec<Validator>> {
+        let hamt = self.validators.load(store)?;
+        let mut validators = Vec::with_capacity(self.index.len());
+        for entry in &self.index {
+            if let Some(v) = hamt.get(&BytesKey::from(entry.addr.to_bytes()))? {
+                validators.push(v.clone());
+            }
+        }
+        Ok(validators)
+    }
+
+    /// Adds a validator with `stake` at its position in the set, or updates
+    /// it if it is already in the set.
+    pub(crate) fn insert<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        validator: Validator,
+        stake: TokenAmount,
+    ) -> anyhow::Result<()> {
+        let addr = validator.addr;
+        let mut found = false;
+        self.validators.modify(store, |hamt| {
+            found = hamt
+                .set(BytesKey::from(addr.to_bytes()), validator)?
+                .is_some();
+            Ok(true)
+        })?;
+        if found {
+            self.update_stake(&addr, stake);
+        } else {
+            self.place(IndexEntry { addr, stake });
+        }
+        Ok(())
+    }
+
+    /// Removes a validator from the set, returning whether it was in it.
+    pub(crate) fn remove<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        addr: &Address,
+    ) -> anyhow::Result<bool> {
+        let mut found = false;
+        self.validators.modify(store, |hamt| {
+            found = hamt.delete(&BytesKey::from(addr.to_bytes()))?.is_some();
+            Ok(true)
+        })?;
+        if found {
+            self.index.retain(|e| e.addr != *addr);
+        }
+        Ok(found)
+    }
+
+    /// Moves `addr` to its position in the set for its new `stake`. Does
+    /// nothing if it isn't a validator.
+    pub(crate) fn update_stake(&mut self, addr: &Address, stake: TokenAmount) {
+        let pos = match self.index.iter().position(|e| e.addr == *addr) {
+            Some(pos) => pos,
+            None => return,
+        };
+        if self.index[pos].stake == stake {
+            return;
+        }
+        let mut entry = self.index.remove(pos);
+        entry.stake = stake;
+        self.place(entry);
+    }
+
+    /// Inserts `entry` in the index, after the entries preceding it.
+    fn place(&mut self, entry: IndexEntry) {
+        let pos = self.index.partition_point(|e| e.precedes(&entry));
+        self.index.insert(pos, entry);
+    }
+
+    /// Validator set with the validators of `legacy`, indexed with the
+    /// stake returned by `stake` for each of them.
+    pub(crate) fn from_legacy(
+        legacy: LegacyValidatorSet,
+        mut stake: impl FnMut(&Address) -> anyhow::Result<TokenAmount>,
+    ) -> anyhow::Result<Self> {
+        let mut set = Self {
+            validators: legacy.validators,
+            index: Vec::with_capacity(legacy.index.len()),
+        };
+        for addr in legacy.index {
+            let stake = stake(&addr)?;
+            set.place(IndexEntry { addr, stake });
+        }
+        Ok(set)
+    }
+}
@@ -0,0 +1,176 @@
+//! Stake-weighted voting on proposals identified by their CID.
+//!
+//! [`Voting`] keeps the votes cast for each proposal of a kind, and
+//! [`Tally`] weighs them with a [`WeightSnapshot`] of the validators to
+//! decide whether a proposal reached the quorum of a [`QuorumPolicy`].
+use std::fmt;
+use std::marker::PhantomData;
+
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_hamt::BytesKey;
+use fvm_shared::address::Address;
+use fvm_shared::econ::TokenAmount;
+use num_traits::Zero;
+use primitives::{TCid, THamt};
+use serde::{Deserialize, Serialize};
+
+use crate::quorum::{QuorumPolicy, StakeWeighted};
+use crate::types::{Votes, WeightSnapshot};
+
+/// Votes cast for proposals of type `T`, keyed by the CID of the proposal.
+///
+/// It is encoded as the CID of the HAMT of votes, so it can replace a
+/// `TCid<THamt<Cid, Votes>>` in the state without a migration.
+#[derive(Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Voting<T> {
+    votes: TCid<THamt<Cid, Votes>>,
+    #[serde(skip)]
+    proposal: PhantomData<T>,
+}
+
+impl<T> Voting<T> {
+    pub fn new<BS: Blockstore>(store: &BS) -> anyhow::Result<Self> {
+        Ok(Self {
+            votes: TCid::new_hamt(store)?,
+            proposal: PhantomData,
+        })
+    }
+
+    /// CID of the HAMT of votes.
+    pub fn cid(&self) -> Cid {
+        self.votes.cid()
+    }
+
+    pub fn get<BS: Blockstore>(&self, store: &BS, proposal: &Cid) -> anyhow::Result<Option<Votes>> {
+        let hamt = self.votes.load(store)?;
+        let votes = hamt.get(&BytesKey::from(proposal.to_bytes()))?;
+        Ok(votes.cloned())
+    }
+
+    pub fn set<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        proposal: &Cid,
+        votes: Votes,
+    ) -> anyhow::Result<()> {
+        self.votes.modify(store, |hamt| {
+            hamt.set(BytesKey::from(proposal.to_bytes()), votes)?;
+            Ok(true)
+        })?;
+        Ok(())
+    }
+
+    /// CIDs of the proposals with votes.
+    pub fn proposals<BS: Blockstore>(&self, store: &BS) -> anyhow::Result<Vec<Cid>> {
+        let mut proposals = Vec::new();
+        self.votes.load(store)?.for_each(|k, _| {
+            proposals.push(Cid::try_from(k.0.as_slice())?);
+            Ok(())
+        })?;
+        Ok(proposals)
+    }
+
+    /// Removes the votes of the given proposals.
+    pub fn remove<BS: Blockstore>(&mut self, store: &BS, proposals: &[Cid]) -> anyhow::Result<()> {
+        self.votes.modify(store, |hamt| {
+            for proposal in proposals {
+                hamt.delete(&BytesKey::from(proposal.to_bytes()))?;
+            }
+            Ok(true)
+        })?;
+        Ok(())
+    }
+
+    /// Weighs the votes cast for `proposal` by stake. See [`Tally::new`].
+    pub fn tally<BS: Blockstore>(
+        &self,
+        store: &BS,
+        proposal: &Cid,
+        snapshot: &WeightSnapshot,
+        excluded: impl FnMut(&Address) -> anyhow::Result<bool>,
+    ) -> anyhow::Result<Tally> {
+        let votes = self.get(store, proposal)?.unwrap_or(Votes {
+            validators: Vec::new(),
+        });
+        Tally::new(snapshot, &votes, excluded)
+    }
+}
+
+impl<T> Default for Voting<T> {
+    fn default() -> Self {
+        Self {
+            votes: TCid::default(),
+            proposal: PhantomData,
+        }
+    }
+}
+
+impl<T> Clone for Voting<T> {
+    fn clone(&self) -> Self {
+        Self {
+            votes: self.votes.clone(),
+            proposal: PhantomData,
+        }
+    }
+}
+
+impl<T> fmt::Debug for Voting<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Voting").field(&self.votes).finish()
+    }
+}
+
+/// Weight of the votes cast for a proposal.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Tally {
+    /// Weight of the validators that voted.
+    pub weight: TokenAmount,
+    /// Weight of the validators that can vote.
+    pub total: TokenAmount,
+}
+
+impl Tally {
+    /// Weighs `votes` by the stake of the validators. See
+    /// [`Tally::with_policy`].
+    pub fn new(
+        snapshot: &WeightSnapshot,
+        votes: &Votes,
+        excluded: impl FnMut(&Address) -> anyhow::Result<bool>,
+    ) -> anyhow::Result<Self> {
+        Self::with_policy(&StakeWeighted, snapshot, votes, excluded)
+    }
+
+    /// Weighs `votes` with the weights `policy` gives to the validators of
+    /// `snapshot`, leaving the validators for which `excluded` returns true
+    /// out of the total.
+    pub fn with_policy(
+        policy: &dyn QuorumPolicy,
+        snapshot: &WeightSnapshot,
+        votes: &Votes,
+        mut excluded: impl FnMut(&Address) -> anyhow::Result<bool>,
+    ) -> anyhow::Result<Self> {
+        let mut weight = TokenAmount::zero();
+        let mut total = policy.total_weight(snapshot);
+        for (addr, stake) in &snapshot.weights {
+            let w = policy.weight(stake);
+            if excluded(addr)? {
+                total -= w;
+            } else if votes.validators.contains(addr) {
+                weight += w;
+            }
+        }
+        Ok(Self { weight, total })
+    }
+
+    /// Whether the votes reach two thirds of the stake.
+    pub fn has_majority(&self) -> bool {
+        StakeWeighted.has_quorum(self)
+    }
+
+    /// Minimum stake that reaches two thirds of the stake.
+    pub fn threshold(&self) -> TokenAmount {
+        StakeWeighted.threshold(self)
+    }
+}
@@ -1,20 +1,64 @@
-#[cfg(test)]
+#[cfg(all(test, feature = "fil-actor"))]
 mod test {
     use cid::Cid;
-    use fil_actors_runtime::runtime::Runtime;
-    use fil_actors_runtime::test_utils::{expect_abort, ExpectedVerifySig, MockRuntime};
-    use fil_actors_runtime::{cbor, ActorError, INIT_ACTOR_ADDR};
+    use fil_actors_runtime::runtime::builtins::Type;
+    use fil_actors_runtime::runtime::{Primitives, Runtime};
+    use fil_actors_runtime::test_utils::{
+        expect_abort, ExpectedVerifySig, MockRuntime, ACCOUNT_ACTOR_CODE_ID, MINER_ACTOR_CODE_ID,
+        MULTISIG_ACTOR_CODE_ID,
+    };
+    use fil_actors_runtime::{cbor, ActorError, BURNT_FUNDS_ACTOR_ADDR, INIT_ACTOR_ADDR};
+    use fvm_ipld_blockstore::{Blockstore, MemoryBlockstore};
     use fvm_ipld_encoding::RawBytes;
-    use fvm_shared::address::Address;
+    use fvm_ipld_hamt::BytesKey;
+    use fvm_shared::address::{Address, Payload};
+    use fvm_shared::clock::ChainEpoch;
     use fvm_shared::crypto::signature::Signature;
     use fvm_shared::econ::TokenAmount;
     use fvm_shared::error::ExitCode;
-    use ipc_gateway::{Checkpoint, FundParams, SubnetID, MIN_COLLATERAL_AMOUNT};
+    use fvm_shared::METHOD_SEND;
+    use ipc_gateway::{
+        Checkpoint, CrossMsgMeta, FundParams, SubnetID, DEFAULT_CHECKPOINT_PERIOD,
+        MIN_COLLATERAL_AMOUNT,
+    };
+    use ipc_subnet_actor::caller_policy::{CallerPolicies, CallerPolicy};
+    use ipc_subnet_actor::checkpoint::CheckpointWindows;
+    use ipc_subnet_actor::compat::{self, GatewayMethod};
+    use ipc_subnet_actor::error::SubnetActorError;
+    use ipc_subnet_actor::eth;
+    use ipc_subnet_actor::evidence::{DoubleSignEvidence, SignedVote, Vote};
+    use ipc_subnet_actor::ext::frc46;
+    use ipc_subnet_actor::migrations::STATE_VERSION;
+    use ipc_subnet_actor::params::{ConstructParamsBuilder, ConstructParamsError};
+    use ipc_subnet_actor::sig::SigScheme;
+    use ipc_subnet_actor::stake_math::{self, StakeRatio};
+    use ipc_subnet_actor::state::{self, keys, StakeChange};
+    use ipc_subnet_actor::validator_set::{IndexEntry, LegacyValidatorSet, ValidatorSet};
+    use ipc_subnet_actor::voting::{Tally, Voting};
     use ipc_subnet_actor::{
-        ext, Actor, ConsensusType, ConstructParams, JoinParams, Method, State, Status,
+        ext, invitation_commitment, key_rotation_payload, Activation, ActivationInfo, Actor,
+        AggregatedCheckpointParams, BootstrapValidator, BootstrapValidatorsParams, CanJoinParams,
+        CanLeaveParams, CanSubmitCheckpointParams, ChallengeCheckpointParams, CheckPeriodChange,
+        CheckpointProof, CheckpointRangeParams, CheckpointSubmission, CheckpointTemplate,
+        CheckpointVotingStatus, CompactStakeReturn, ConsensusType, ConstructParams,
+        DelegateFailover, DelegateParams, DryRunReturn, EconParams, ExcessStakePolicy,
+        FinalityConfig, Funding, GatewayCall, IssueInvitationsParams, JoinFeePolicy, JoinParams,
+        JoinReturn, LeaveReturn, LegacyGatewayCall, ListCheckpointsReturn, ListParams,
+        ListStakersReturn, MembershipEventKind, MembershipHistoryReturn, MembershipTicket, Method,
+        NetAddress, NetAddressError, ParentFinality, PowerTable, PowerTableEntry,
+        PutCrossMsgBatchParams, PutCrossMsgBatchReturn, RedelegateParams, RegisterCrossMsgParams,
+        ReleaseStakeForParams, RotateValidatorKeyParams, SetMetadataParams, SpendParams,
+        SpendProposalInfo, State, StateCommitment, Stats, Status, SubmitEvidenceParams,
+        SubnetActor, SubnetSummary, TransferOwnershipParams, UndelegateParams, Validator,
+        ValidatorSetAt, ValidatorSignature, VestingSchedule, Votes, WeightSnapshot,
+        WithdrawStakeParams, COMMISSION_CHANGE_DELAY_WINDOWS, ERR_SUBNET_KILLED,
+        ERR_SUBNET_TERMINATING, EVIDENCE_MAX_AGE_WINDOWS, MAX_CHECKPOINT_SIZE,
+        MAX_CROSS_MSG_BATCHES_PER_VALIDATOR, MAX_CROSS_MSG_BATCH_SIZE, MAX_NET_ADDR_LEN,
+        MAX_PENDING_GATEWAY_CALLS, MAX_TOTAL_VOTING_POWER, PENALTY_RATE_DENOMINATOR,
+        REDELEGATION_COOLDOWN_WINDOWS,
     };
     use num_traits::Zero;
-    use primitives::TCid;
+    use primitives::{TCid, TLink};
     use std::str::FromStr;
 
     // just a test address
@@ -25,23 +69,52 @@ mod test {
         ConstructParams {
             parent: SubnetID::from_str("/root").unwrap(),
             name: NETWORK_NAME.to_string(),
-            ipc_gateway_addr: IPC_GATEWAY_ADDR,
+            ipc_gateway_addr: Address::new_id(IPC_GATEWAY_ADDR),
             consensus: ConsensusType::Dummy,
             min_validator_stake: Default::default(),
             min_validators: 0,
             finality_threshold: 0,
             check_period: 0,
             genesis: vec![],
+            owner: None,
+            invitation_only: false,
+            collateral_token: None,
+            max_missed_windows: 0,
+            chain_id: 0,
+            description: String::new(),
+            metadata_url: None,
+            max_validator_stake: TokenAmount::zero(),
+            excess_stake_policy: ExcessStakePolicy::Reject,
+            dispute_period: 0,
+            min_join_amount: TokenAmount::zero(),
+            caller_policies: CallerPolicies::default(),
+            checkpoint_penalty_rate: 0,
+            churn_limit: 0,
+            relayer_fee: TokenAmount::zero(),
+            min_total_collateral: TokenAmount::zero(),
+            max_submissions_per_window: 0,
+            max_exits_per_window: 0,
+            join_fee: TokenAmount::zero(),
+            join_fee_policy: JoinFeePolicy::Treasury,
+            join_fee_exempt: Vec::new(),
+            leaving_coeff: StakeRatio::default(),
+            sig_scheme: SigScheme::default(),
+            max_voting_power_pct: None,
+            delegated_namespace: None,
+            anchor_genesis: false,
         }
     }
 
     fn construct_runtime_with_receiver(receiver: Address) -> MockRuntime {
+        construct_runtime_with_params(receiver, std_construct_param())
+    }
+
+    fn construct_runtime_with_params(receiver: Address, params: ConstructParams) -> MockRuntime {
         let caller = *INIT_ACTOR_ADDR;
         let mut runtime = MockRuntime::new(receiver, caller);
 
-        let params = std_construct_param();
-
         runtime.expect_validate_caller_addr(vec![caller]);
+        expect_network_name(&mut runtime, IPC_GATEWAY_ADDR, &params.parent, ExitCode::OK);
 
         runtime
             .call::<Actor>(
@@ -53,6 +126,24 @@ mod test {
         runtime
     }
 
+    /// Expects the gateway of ID `gateway` to be asked for its network,
+    /// answering `network`.
+    fn expect_network_name(
+        runtime: &mut MockRuntime,
+        gateway: u64,
+        network: &SubnetID,
+        code: ExitCode,
+    ) {
+        runtime.expect_send(
+            Address::new_id(gateway),
+            ext::gateway::NETWORK_NAME_METHOD,
+            RawBytes::default(),
+            TokenAmount::zero(),
+            RawBytes::serialize(network).unwrap(),
+            code,
+        );
+    }
+
     fn construct_runtime() -> MockRuntime {
         let receiver = Address::new_id(1);
         construct_runtime_with_receiver(receiver)
@@ -67,7 +158,124 @@ mod test {
         assert_eq!(state.name, NETWORK_NAME);
         assert_eq!(state.ipc_gateway_addr, Address::new_id(IPC_GATEWAY_ADDR));
         assert_eq!(state.total_stake, TokenAmount::zero());
-        assert_eq!(state.validator_set.is_empty(), true);
+        assert_eq!(state.validators.is_empty(), true);
+    }
+
+    #[test]
+    fn test_gateway_compat() {
+        let root = SubnetID::from_str("/root").unwrap();
+        let actor = Address::new_id(9999);
+        assert_eq!(
+            compat::subnet_id(&root, actor).unwrap(),
+            SubnetID::new(&root, actor)
+        );
+        let value = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+        assert_eq!(
+            compat::fund_params(&value).unwrap(),
+            RawBytes::serialize(FundParams {
+                value: value.clone()
+            })
+            .unwrap()
+        );
+        assert_eq!(compat::convert::<_, TokenAmount>(&value).unwrap(), value);
+        assert_eq!(
+            GatewayMethod::ReleaseStake.method_num(),
+            ipc_gateway::Method::ReleaseStake as u64
+        );
+    }
+
+    #[test]
+    fn test_construct_params_builder() {
+        let builder = || {
+            ConstructParamsBuilder::new(
+                SubnetID::from_str("/root").unwrap(),
+                NETWORK_NAME,
+                Address::new_id(IPC_GATEWAY_ADDR),
+            )
+        };
+
+        // the defaults depend on the consensus
+        let params = builder().build().unwrap();
+        assert_eq!(params.consensus, ConsensusType::Mir);
+        assert_eq!(params.min_validators, 4);
+        assert_eq!(params.check_period, DEFAULT_CHECKPOINT_PERIOD);
+        let params = builder()
+            .with_consensus(ConsensusType::FilecoinEC)
+            .build()
+            .unwrap();
+        assert_eq!(params.min_validators, 1);
+        assert_eq!(params.finality_threshold, 900);
+
+        // the built parameters are accepted by the actor as they are
+        let stake = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT * 2);
+        let params = builder()
+            .with_min_validators(2)
+            .with_min_validator_stake(stake.clone())
+            .build()
+            .unwrap();
+        assert_eq!(params.min_total_collateral, stake);
+        let runtime = construct_runtime_with_params(Address::new_id(9999), params.clone());
+        let st: State = runtime.get_state();
+        assert_eq!(st.min_validators, 2);
+        assert_eq!(st.econ.min_validator_stake, stake);
+        assert_eq!(st.econ.min_total_collateral, stake);
+        assert_eq!(st.check_period, params.check_period);
+
+        // values the constructor would replace are rejected
+        assert_eq!(
+            builder()
+                .with_check_period(DEFAULT_CHECKPOINT_PERIOD - 1)
+                .build()
+                .err(),
+            Some(ConstructParamsError::CheckPeriodTooShort)
+        );
+        assert_eq!(
+            builder()
+                .with_params(|p| p.max_validator_stake = TokenAmount::from_atto(1))
+                .build()
+                .err(),
+            Some(ConstructParamsError::MaxValidatorStakeTooLow)
+        );
+        assert_eq!(
+            builder()
+                .with_params(|p| p.checkpoint_penalty_rate = PENALTY_RATE_DENOMINATOR + 1)
+                .build()
+                .err(),
+            Some(ConstructParamsError::PenaltyRateTooHigh)
+        );
+    }
+
+    #[test]
+    fn test_constructor_robust_gateway_addr() {
+        let construct = |runtime: &mut MockRuntime, gateway| {
+            let mut params = std_construct_param();
+            params.ipc_gateway_addr = gateway;
+            runtime.expect_validate_caller_addr(vec![*INIT_ACTOR_ADDR]);
+            runtime.call::<Actor>(
+                Method::Constructor as u64,
+                &cbor::serialize(&params, "test").unwrap(),
+            )
+        };
+
+        let robust = Address::new_actor(b"gateway");
+        let mut runtime = MockRuntime::new(Address::new_id(1), *INIT_ACTOR_ADDR);
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            construct(&mut runtime, robust),
+        );
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            construct(&mut runtime, Address::new_secp256k1(&[1; 65]).unwrap()),
+        );
+
+        // the robust address is kept along with the ID it resolves to
+        runtime.add_id_address(robust, Address::new_id(IPC_GATEWAY_ADDR));
+        let parent = std_construct_param().parent;
+        expect_network_name(&mut runtime, IPC_GATEWAY_ADDR, &parent, ExitCode::OK);
+        construct(&mut runtime, robust).unwrap();
+        let st: State = runtime.get_state();
+        assert_eq!(st.ipc_gateway_addr, Address::new_id(IPC_GATEWAY_ADDR));
+        assert_eq!(st.ipc_gateway_robust_addr, Some(robust));
     }
 
     #[test]
@@ -77,7 +285,10 @@ mod test {
 
         let validator = Address::new_id(100);
         let params = JoinParams {
-            validator_net_addr: validator.to_string(),
+            validator_net_addr: net_addr(&validator),
+            invitation: None,
+            vesting: None,
+            worker: None,
         };
 
         expect_abort(
@@ -89,6 +300,176 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_join_invalid_net_addr() {
+        for addr in [
+            "/ip4/10.0.0.1/tcp/26656",
+            "/dns4/validator.example.com/tcp/26656/p2p/12D3KooWD3eckifWpRn9wQpMG9R9hX3sD158z7EqHWmweQAJU5SA",
+            "/ip6/::1/udp/4001/quic-v1",
+        ] {
+            assert!(NetAddress::from_str(addr).is_ok(), "{}", addr);
+        }
+        let long = format!("/dns/{}/tcp/1", "a".repeat(MAX_NET_ADDR_LEN));
+        for (addr, err) in [
+            ("", NetAddressError::Empty),
+            (long.as_str(), NetAddressError::TooLong),
+            ("f0100", NetAddressError::MissingSlash),
+            ("/foo/1", NetAddressError::UnknownProtocol("foo".into())),
+            (
+                "/ip4/10.0.0.1/tcp",
+                NetAddressError::MissingValue("tcp".into()),
+            ),
+            (
+                "/ip4/10.0.0.256/tcp/1",
+                NetAddressError::InvalidValue("ip4".into(), "10.0.0.256".into()),
+            ),
+        ] {
+            assert_eq!(NetAddress::from_str(addr), Err(err), "{}", addr);
+        }
+
+        // malformed addresses sent to the actor are rejected
+        let mut runtime = construct_runtime();
+        let caller = Address::new_id(10);
+        let params = JoinParams {
+            validator_net_addr: RawBytes::serialize("/ip4/localhost")
+                .unwrap()
+                .deserialize()
+                .unwrap(),
+            invitation: None,
+            vesting: None,
+            worker: None,
+        };
+        runtime.set_value(TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT));
+        runtime.set_caller(Cid::default(), caller);
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            runtime.call::<Actor>(
+                Method::Join as u64,
+                &cbor::serialize(&params, "test").unwrap(),
+            ),
+        );
+        let st: State = runtime.get_state();
+        assert_eq!(st.total_stake, TokenAmount::zero());
+    }
+
+    #[test]
+    fn test_withdraw_stake() {
+        let mut runtime = construct_runtime();
+        let caller = Address::new_id(10);
+        let stake = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT * 2);
+        let params = JoinParams {
+            validator_net_addr: net_addr(&caller),
+            invitation: None,
+            vesting: None,
+            worker: None,
+        };
+        runtime.set_value(stake.clone());
+        runtime.set_caller(Cid::default(), caller);
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::Register as u64,
+            RawBytes::default(),
+            stake.clone(),
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        runtime
+            .call::<Actor>(
+                Method::Join as u64,
+                &cbor::serialize(&params, "test").unwrap(),
+            )
+            .unwrap();
+        runtime.verify();
+
+        let withdraw = |runtime: &mut MockRuntime, amount: u64| {
+            runtime.set_value(TokenAmount::zero());
+            runtime.expect_validate_caller_any();
+            runtime.call::<Actor>(
+                Method::WithdrawStake as u64,
+                &RawBytes::serialize(WithdrawStakeParams {
+                    amount: TokenAmount::from_atto(amount),
+                })
+                .unwrap(),
+            )
+        };
+
+        // validators need to keep the minimum stake, or leave
+        expect_abort(
+            SubnetActorError::WithdrawalBelowMinStake(caller).exit_code(),
+            withdraw(&mut runtime, MIN_COLLATERAL_AMOUNT + 1),
+        );
+
+        // the withdrawn stake is released in the gateway
+        let st: State = runtime.get_state();
+        let config_number = st.config_number;
+        let amount = MIN_COLLATERAL_AMOUNT / 2;
+        expect_release(&mut runtime, caller, &TokenAmount::from_atto(amount));
+        let ret: LeaveReturn = withdraw(&mut runtime, amount)
+            .unwrap()
+            .deserialize()
+            .unwrap();
+        runtime.verify();
+        let remaining = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT * 2 - amount);
+        assert_eq!(
+            ret,
+            LeaveReturn {
+                refunded: TokenAmount::from_atto(amount),
+                remaining_stake: remaining.clone(),
+                refunds: vec![(caller, TokenAmount::from_atto(amount))],
+            }
+        );
+
+        // the validator stays in the validator set with its reduced stake,
+        // under a new configuration
+        let st: State = runtime.get_state();
+        assert!(st.is_validator(runtime.store(), &caller).unwrap());
+        assert_eq!(st.total_stake, remaining);
+        assert_eq!(
+            st.get_stake(runtime.store(), &caller).unwrap(),
+            Some(remaining)
+        );
+        assert_eq!(st.config_number, config_number + 1);
+        assert!(st
+            .get_validator_set_at(runtime.store(), config_number + 1)
+            .unwrap()
+            .is_some());
+
+        // stakers withdrawing all of their stake leave no entry behind
+        let staker = Address::new_id(20);
+        let params = JoinParams {
+            validator_net_addr: net_addr(&staker),
+            invitation: None,
+            vesting: None,
+            worker: None,
+        };
+        runtime.set_value(TokenAmount::from_atto(amount));
+        runtime.set_caller(Cid::default(), staker);
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::AddStake as u64,
+            RawBytes::default(),
+            TokenAmount::from_atto(amount),
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        runtime
+            .call::<Actor>(
+                Method::Join as u64,
+                &cbor::serialize(&params, "test").unwrap(),
+            )
+            .unwrap();
+        expect_release(&mut runtime, staker, &TokenAmount::from_atto(amount));
+        withdraw(&mut runtime, amount).unwrap();
+        runtime.verify();
+        let st: State = runtime.get_state();
+        assert_eq!(st.get_stake(runtime.store(), &staker).unwrap(), None);
+        assert_eq!(st.stakers_count, 1);
+        assert_eq!(st.config_number, config_number + 1);
+    }
+
     #[test]
     fn test_join_works() {
         let mut runtime = construct_runtime();
@@ -97,7 +478,10 @@ mod test {
         let validator = Address::new_id(100);
         let start_token_value = 5_u64.pow(18);
         let params = JoinParams {
-            validator_net_addr: validator.to_string(),
+            validator_net_addr: net_addr(&validator),
+            invitation: None,
+            vesting: None,
+            worker: None,
         };
 
         // Part 1. join without enough to be activated
@@ -117,7 +501,7 @@ mod test {
         // verify state.
         // as the value is less than min collateral, state is initiated
         let st: State = runtime.get_state();
-        assert_eq!(st.validator_set.len(), 0);
+        assert_eq!(st.validators.len(), 0);
         assert_eq!(st.status, Status::Instantiated);
         assert_eq!(st.total_stake, value);
         let stake = st.get_stake(runtime.store(), &caller).unwrap();
@@ -137,17 +521,25 @@ mod test {
             RawBytes::default(),
             ExitCode::new(0),
         );
-        runtime
+        let ret: JoinReturn = runtime
             .call::<Actor>(
                 Method::Join as u64,
                 &cbor::serialize(&params, "test").unwrap(),
             )
+            .unwrap()
+            .deserialize()
             .unwrap();
+        assert_eq!(ret.status, Status::Active);
+        assert_eq!(
+            ret.total_stake,
+            TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT)
+        );
+        assert_eq!(ret.became_validator, true);
 
         // verify state.
         // as the value is less than min collateral, state is active
         let st: State = runtime.get_state();
-        assert_eq!(st.validator_set.len(), 1);
+        assert_eq!(st.validators.len(), 1);
         assert_eq!(st.status, Status::Active);
         assert_eq!(
             st.total_stake,
@@ -185,7 +577,7 @@ mod test {
         // verify state.
         // as the value is less than min collateral, state is active
         let st: State = runtime.get_state();
-        assert_eq!(st.validator_set.len(), 2);
+        assert_eq!(st.validators.len(), 2);
         assert_eq!(st.status, Status::Active);
         assert_eq!(
             st.total_stake,
@@ -199,6 +591,157 @@ mod test {
         runtime.verify();
     }
 
+    #[test]
+    fn test_join_with_invitation() {
+        let owner = Address::new_id(5);
+        let mut params = std_construct_param();
+        params.owner = Some(owner);
+        params.invitation_only = true;
+        let mut runtime = construct_runtime_with_params(Address::new_id(1), params);
+
+        let secret = b"invitation secret".to_vec();
+        let caller = Address::new_id(10);
+        let commitment = invitation_commitment(&runtime, &secret, &caller);
+
+        // only the owner can issue invitations
+        runtime.set_caller(Cid::default(), caller);
+        runtime.expect_validate_caller_addr(vec![owner]);
+        expect_abort(
+            ExitCode::USR_FORBIDDEN,
+            runtime.call::<Actor>(
+                Method::IssueInvitations as u64,
+                &cbor::serialize(
+                    &IssueInvitationsParams {
+                        commitments: vec![commitment.clone()],
+                    },
+                    "test",
+                )
+                .unwrap(),
+            ),
+        );
+
+        // commitments need to be hashes
+        runtime.set_caller(Cid::default(), owner);
+        runtime.expect_validate_caller_addr(vec![owner]);
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            runtime.call::<Actor>(
+                Method::IssueInvitations as u64,
+                &cbor::serialize(
+                    &IssueInvitationsParams {
+                        commitments: vec![secret.clone()],
+                    },
+                    "test",
+                )
+                .unwrap(),
+            ),
+        );
+
+        runtime.expect_validate_caller_addr(vec![owner]);
+        runtime
+            .call::<Actor>(
+                Method::IssueInvitations as u64,
+                &cbor::serialize(
+                    &IssueInvitationsParams {
+                        commitments: vec![commitment.clone()],
+                    },
+                    "test",
+                )
+                .unwrap(),
+            )
+            .unwrap();
+
+        // the dry run of the join checks the invitation
+        let value = TokenAmount::from_atto(5_u64.pow(18));
+        let mut can_join = |invitation: Option<Vec<u8>>| {
+            let params = CanJoinParams {
+                validator: caller,
+                amount: value.clone(),
+                invitation,
+            };
+            runtime.expect_validate_caller_any();
+            let ret: DryRunReturn = runtime
+                .call::<Actor>(
+                    Method::CanJoin as u64,
+                    &RawBytes::serialize(params).unwrap(),
+                )
+                .unwrap()
+                .deserialize()
+                .unwrap();
+            ret.exit_code
+        };
+        assert_eq!(can_join(None), ExitCode::USR_FORBIDDEN);
+        assert_eq!(
+            can_join(Some(b"wrong secret".to_vec())),
+            SubnetActorError::InvalidInvitation.exit_code()
+        );
+        assert_eq!(can_join(Some(secret.clone())), ExitCode::OK);
+
+        // joining without an invitation fails
+        runtime.set_value(value.clone());
+        runtime.set_caller(Cid::default(), caller);
+        runtime.expect_validate_caller_any();
+        let mut params = JoinParams {
+            validator_net_addr: net_addr(&caller),
+            invitation: None,
+            vesting: None,
+            worker: None,
+        };
+        expect_abort(
+            ExitCode::USR_FORBIDDEN,
+            runtime.call::<Actor>(
+                Method::Join as u64,
+                &cbor::serialize(&params, "test").unwrap(),
+            ),
+        );
+
+        // joining with the secret works
+        params.invitation = Some(secret.clone());
+        runtime.expect_validate_caller_any();
+        runtime
+            .call::<Actor>(
+                Method::Join as u64,
+                &cbor::serialize(&params, "test").unwrap(),
+            )
+            .unwrap();
+        let st: State = runtime.get_state();
+        assert_eq!(
+            st.get_stake(runtime.store(), &caller).unwrap().unwrap(),
+            value
+        );
+        let inv = st
+            .get_invitation(runtime.store(), &commitment)
+            .unwrap()
+            .unwrap();
+        assert_eq!(inv.redeemed_by, Some(caller));
+
+        // the secret is bound to the invitee, so others can't redeem it
+        let other = Address::new_id(11);
+        runtime.set_caller(Cid::default(), other);
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            SubnetActorError::InvalidInvitation.exit_code(),
+            runtime.call::<Actor>(
+                Method::Join as u64,
+                &cbor::serialize(&params, "test").unwrap(),
+            ),
+        );
+
+        // the invitation can't be redeemed twice
+        let mut st: State = runtime.get_state();
+        st.stake = TCid::new_hamt(runtime.store()).unwrap();
+        runtime.replace_state(&st);
+        runtime.set_caller(Cid::default(), caller);
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            SubnetActorError::InvitationAlreadyRedeemed.exit_code(),
+            runtime.call::<Actor>(
+                Method::Join as u64,
+                &cbor::serialize(&params, "test").unwrap(),
+            ),
+        );
+    }
+
     #[test]
     fn test_leave_and_kill() {
         let mut runtime = construct_runtime();
@@ -206,7 +749,10 @@ mod test {
         let caller = Address::new_id(10);
         let validator = Address::new_id(100);
         let params = JoinParams {
-            validator_net_addr: validator.to_string(),
+            validator_net_addr: net_addr(&validator),
+            invitation: None,
+            vesting: None,
+            worker: None,
         };
 
         // first miner joins the subnet
@@ -244,7 +790,10 @@ mod test {
         let caller = Address::new_id(20);
         let value = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
         let params = JoinParams {
-            validator_net_addr: caller.clone().to_string(),
+            validator_net_addr: net_addr(&caller),
+            invitation: None,
+            vesting: None,
+            worker: None,
         };
         total_stake = total_stake + &value;
         runtime.set_value(value.clone());
@@ -268,7 +817,7 @@ mod test {
 
         let st: State = runtime.get_state();
         assert_eq!(st.total_stake, total_stake);
-        assert_eq!(st.validator_set.len(), 2);
+        assert_eq!(st.validators.len(), 2);
         assert_eq!(
             st.get_stake(runtime.store(), &caller).unwrap().unwrap(),
             TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT)
@@ -277,7 +826,10 @@ mod test {
         // non-miner joins
         let caller = Address::new_id(30);
         let params = JoinParams {
-            validator_net_addr: caller.clone().to_string(),
+            validator_net_addr: net_addr(&caller),
+            invitation: None,
+            vesting: None,
+            worker: None,
         };
         let value = TokenAmount::from_atto(5u64.pow(18));
         total_stake = total_stake + &value;
@@ -302,7 +854,7 @@ mod test {
             .unwrap();
         let st: State = runtime.get_state();
         assert_eq!(st.total_stake, total_stake);
-        assert_eq!(st.validator_set.len(), 2);
+        assert_eq!(st.validators.len(), 2);
         assert_eq!(
             st.get_stake(runtime.store(), &caller).unwrap().unwrap(),
             value
@@ -315,23 +867,17 @@ mod test {
         runtime.set_value(value.clone());
         runtime.set_caller(Cid::default(), caller.clone());
         runtime.expect_validate_caller_any();
-        runtime.expect_send(
-            Address::new_id(IPC_GATEWAY_ADDR),
-            ipc_gateway::Method::ReleaseStake as u64,
-            RawBytes::serialize(FundParams {
-                value: value.clone(),
-            })
-            .unwrap(),
-            TokenAmount::zero(),
-            RawBytes::default(),
-            ExitCode::new(0),
-        );
-        runtime
+        expect_release(&mut runtime, caller, &value);
+        let ret: LeaveReturn = runtime
             .call::<Actor>(Method::Leave as u64, &RawBytes::default())
+            .unwrap()
+            .deserialize()
             .unwrap();
+        assert_eq!(ret.refunded, value);
+        assert_eq!(ret.remaining_stake, TokenAmount::zero());
 
         let st: State = runtime.get_state();
-        assert_eq!(st.validator_set.len(), 1);
+        assert_eq!(st.validators.len(), 1);
         assert_eq!(st.status, Status::Active);
         assert_eq!(st.total_stake, total_stake);
         assert_eq!(
@@ -353,23 +899,13 @@ mod test {
         runtime.set_value(value.clone());
         runtime.set_caller(Cid::default(), caller.clone());
         runtime.expect_validate_caller_any();
-        runtime.expect_send(
-            Address::new_id(IPC_GATEWAY_ADDR),
-            ipc_gateway::Method::ReleaseStake as u64,
-            RawBytes::serialize(FundParams {
-                value: value.clone(),
-            })
-            .unwrap(),
-            TokenAmount::zero(),
-            RawBytes::default(),
-            ExitCode::new(0),
-        );
+        expect_release(&mut runtime, caller, &value);
         runtime
             .call::<Actor>(Method::Leave as u64, &RawBytes::default())
             .unwrap();
 
         let st: State = runtime.get_state();
-        assert_eq!(st.validator_set.len(), 0);
+        assert_eq!(st.validators.len(), 0);
         assert_eq!(st.status, Status::Inactive);
         assert_eq!(st.total_stake, total_stake);
         assert_eq!(
@@ -384,22 +920,12 @@ mod test {
         runtime.set_value(value.clone());
         runtime.set_caller(Cid::default(), caller.clone());
         runtime.expect_validate_caller_any();
-        runtime.expect_send(
-            Address::new_id(IPC_GATEWAY_ADDR),
-            ipc_gateway::Method::ReleaseStake as u64,
-            RawBytes::serialize(FundParams {
-                value: value.clone(),
-            })
-            .unwrap(),
-            TokenAmount::zero(),
-            RawBytes::default(),
-            ExitCode::new(0),
-        );
+        expect_release(&mut runtime, caller, &value);
         runtime
             .call::<Actor>(Method::Leave as u64, &RawBytes::default())
             .unwrap();
         let st: State = runtime.get_state();
-        assert_eq!(st.validator_set.len(), 0);
+        assert_eq!(st.validators.len(), 0);
         assert_eq!(st.status, Status::Inactive);
         assert_eq!(st.total_stake, total_stake);
         assert_eq!(
@@ -427,6 +953,115 @@ mod test {
         assert_eq!(st.status, Status::Killed);
     }
 
+    #[test]
+    fn test_kill_with_reward_pool() {
+        let mut runtime = construct_runtime();
+        let validator = Address::new_id(10);
+        join_validators(&mut runtime, &[validator]);
+        let pool = TokenAmount::from_atto(7);
+
+        runtime.set_caller(Cid::default(), Address::new_id(50));
+        runtime.set_value(pool.clone());
+        runtime.set_balance(pool.clone());
+        runtime.expect_validate_caller_any();
+        runtime
+            .call::<Actor>(Method::FundRewardPool as u64, &RawBytes::default())
+            .unwrap();
+        runtime.set_value(TokenAmount::zero());
+
+        let stake = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+        runtime.set_caller(Cid::default(), validator);
+        runtime.expect_validate_caller_any();
+        expect_release(&mut runtime, validator, &stake);
+        runtime
+            .call::<Actor>(Method::Leave as u64, &RawBytes::default())
+            .unwrap();
+
+        // the pool doesn't keep the subnet alive, it is burnt on kill
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::Kill as u64,
+            RawBytes::default(),
+            TokenAmount::zero(),
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        runtime.expect_send(
+            *BURNT_FUNDS_ACTOR_ADDR,
+            METHOD_SEND,
+            RawBytes::default(),
+            pool.clone(),
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        runtime
+            .call::<Actor>(Method::Kill as u64, &RawBytes::default())
+            .unwrap();
+        runtime.verify();
+        let st: State = runtime.get_state();
+        assert_eq!(st.reward_pool, TokenAmount::zero());
+        assert_eq!(st.status, Status::Killed);
+
+        // and can't be funded anymore
+        runtime.set_value(pool.clone());
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            ERR_SUBNET_KILLED,
+            runtime.call::<Actor>(Method::FundRewardPool as u64, &RawBytes::default()),
+        );
+    }
+
+    #[test]
+    fn test_join_inactive_subnet() {
+        let mut runtime = construct_runtime();
+
+        let validator = Address::new_id(10);
+        join_validators(&mut runtime, &[validator]);
+        let stake = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+
+        let params = JoinParams {
+            validator_net_addr: "/ip4/127.0.0.1/tcp/26656".parse().unwrap(),
+            invitation: None,
+            vesting: None,
+            worker: None,
+        };
+        for (status, code) in [
+            (Status::Terminating, ERR_SUBNET_TERMINATING),
+            (Status::Killed, ERR_SUBNET_KILLED),
+        ] {
+            let mut st: State = runtime.get_state();
+            st.status = status;
+            runtime.replace_state(&st);
+
+            runtime.set_value(stake.clone());
+            runtime.set_caller(Cid::default(), Address::new_id(11));
+            runtime.expect_validate_caller_any();
+            expect_abort(
+                code,
+                runtime.call::<Actor>(
+                    Method::Join as u64,
+                    &cbor::serialize(&params, "test").unwrap(),
+                ),
+            );
+
+            runtime.set_caller(Cid::default(), validator);
+            runtime.expect_validate_caller_any();
+            expect_abort(
+                code,
+                runtime.call::<Actor>(Method::AddStake as u64, &RawBytes::default()),
+            );
+
+            // the funds are refused and no stake is recorded
+            let st: State = runtime.get_state();
+            assert_eq!(st.total_stake, stake);
+            assert_eq!(
+                st.get_stake(runtime.store(), &Address::new_id(11)).unwrap(),
+                None
+            );
+        }
+    }
+
     #[test]
     fn test_submit_checkpoint() {
         let test_actor_address = Address::new_id(9999);
@@ -439,7 +1074,10 @@ mod test {
         ];
         let validator = Address::new_id(100);
         let params = JoinParams {
-            validator_net_addr: validator.to_string(),
+            validator_net_addr: net_addr(&validator),
+            invitation: None,
+            vesting: None,
+            worker: None,
         };
 
         // first miner joins the subnet
@@ -483,7 +1121,7 @@ mod test {
 
         // verify that we have an active subnet with 3 validators.
         let st: State = runtime.get_state();
-        assert_eq!(st.validator_set.len(), 3);
+        assert_eq!(st.validators.len(), 3);
         assert_eq!(st.status, Status::Active);
 
         // Generate the check point
@@ -503,10 +1141,10 @@ mod test {
         runtime.set_caller(Cid::default(), non_miner.clone());
         runtime.expect_validate_caller_any();
         expect_abort(
-            ExitCode::USR_ILLEGAL_STATE,
+            SubnetActorError::NotValidator(non_miner).exit_code(),
             runtime.call::<Actor>(
                 Method::SubmitCheckpoint as u64,
-                &cbor::serialize(&checkpoint_0, "test").unwrap(),
+                &cbor::serialize(&submission(&runtime, &checkpoint_0), "test").unwrap(),
             ),
         );
 
@@ -516,12 +1154,15 @@ mod test {
 
         let st: State = runtime.get_state();
         let votes = st
-            .get_votes(runtime.store(), &checkpoint_0.cid())
+            .get_votes(
+                runtime.store(),
+                &submission(&runtime, &checkpoint_0).digest().unwrap(),
+            )
             .unwrap()
             .unwrap();
         assert_eq!(votes.validators, vec![sender.clone()]);
         expect_abort(
-            ExitCode::USR_ILLEGAL_STATE,
+            SubnetActorError::AlreadyVoted(sender).exit_code(),
             send_checkpoint(&mut runtime, sender.clone(), &checkpoint_0, false),
         );
 
@@ -530,7 +1171,12 @@ mod test {
         send_checkpoint(&mut runtime, sender2.clone(), &checkpoint_0, true).unwrap();
 
         let st: State = runtime.get_state();
-        let votes = st.get_votes(runtime.store(), &checkpoint_0.cid()).unwrap();
+        let votes = st
+            .get_votes(
+                runtime.store(),
+                &submission(&runtime, &checkpoint_0).digest().unwrap(),
+            )
+            .unwrap();
         assert_eq!(votes.is_none(), true);
 
         // Trying to submit an already committed checkpoint should fail
@@ -538,10 +1184,10 @@ mod test {
         runtime.set_caller(Cid::default(), sender2.clone());
         runtime.expect_validate_caller_any();
         expect_abort(
-            ExitCode::USR_ILLEGAL_STATE,
+            SubnetActorError::CheckpointAlreadyCommitted.exit_code(),
             runtime.call::<Actor>(
                 Method::SubmitCheckpoint as u64,
-                &cbor::serialize(&checkpoint_0, "test").unwrap(),
+                &cbor::serialize(&submission(&runtime, &checkpoint_0), "test").unwrap(),
             ),
         );
 
@@ -552,10 +1198,10 @@ mod test {
         runtime.set_caller(Cid::default(), sender.clone());
         runtime.expect_validate_caller_any();
         expect_abort(
-            ExitCode::USR_ILLEGAL_STATE,
+            SubnetActorError::NotSigningWindow.exit_code(),
             runtime.call::<Actor>(
                 Method::SubmitCheckpoint as u64,
-                &cbor::serialize(&checkpoint_1, "test").unwrap(),
+                &cbor::serialize(&submission(&runtime, &checkpoint_1), "test").unwrap(),
             ),
         );
 
@@ -566,10 +1212,10 @@ mod test {
         runtime.set_caller(Cid::default(), sender.clone());
         runtime.expect_validate_caller_any();
         expect_abort(
-            ExitCode::USR_ILLEGAL_STATE,
+            SubnetActorError::InconsistentPrevCheckpoint.exit_code(),
             runtime.call::<Actor>(
                 Method::SubmitCheckpoint as u64,
-                &cbor::serialize(&checkpoint_3, "test").unwrap(),
+                &cbor::serialize(&submission(&runtime, &checkpoint_3), "test").unwrap(),
             ),
         );
 
@@ -587,48 +1233,6390 @@ mod test {
         send_checkpoint(&mut runtime, sender.clone(), &checkpoint_4, false).unwrap();
         let st: State = runtime.get_state();
         let votes = st
-            .get_votes(runtime.store(), &checkpoint_4.cid())
+            .get_votes(
+                runtime.store(),
+                &submission(&runtime, &checkpoint_4).digest().unwrap(),
+            )
             .unwrap()
             .unwrap();
         assert_eq!(votes.validators, vec![sender.clone()]);
     }
 
-    fn send_checkpoint(
-        runtime: &mut MockRuntime,
-        sender: Address,
-        checkpoint: &Checkpoint,
-        is_commit: bool,
-    ) -> Result<RawBytes, ActorError> {
-        runtime.set_caller(Cid::default(), sender.clone());
+    #[test]
+    fn test_register_cross_msgs() {
+        let mut runtime = construct_runtime();
+
+        let caller = Address::new_id(10);
+        let params = JoinParams {
+            validator_net_addr: net_addr(&caller),
+            invitation: None,
+            vesting: None,
+            worker: None,
+        };
+        runtime.set_value(TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT));
+        runtime.set_caller(Cid::default(), caller.clone());
+        runtime.expect_validate_caller_any();
         runtime.expect_send(
-            sender.clone(),
-            ext::account::PUBKEY_ADDRESS_METHOD as u64,
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::Register as u64,
+            RawBytes::default(),
+            TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT),
             RawBytes::default(),
-            TokenAmount::zero(),
-            cbor::serialize(&sender.clone(), "test").unwrap(),
             ExitCode::new(0),
         );
+        runtime
+            .call::<Actor>(
+                Method::Join as u64,
+                &cbor::serialize(&params, "test").unwrap(),
+            )
+            .unwrap();
+
+        let msgs_cid = Cid::default();
+        let params = RegisterCrossMsgParams {
+            epoch: 10,
+            msgs_cid,
+        };
+
+        // only validators can register cross-msgs
+        runtime.set_caller(Cid::default(), Address::new_id(20));
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            SubnetActorError::NotValidator(Address::new_id(20)).exit_code(),
+            runtime.call::<Actor>(
+                Method::RegisterCrossMsg as u64,
+                &cbor::serialize(&params, "test").unwrap(),
+            ),
+        );
+
+        // the epoch needs to correspond to a window
+        runtime.set_caller(Cid::default(), caller.clone());
         runtime.expect_validate_caller_any();
-        runtime.expect_verify_signature(ExpectedVerifySig {
-            sig: Signature::new_secp256k1(vec![1, 2, 3, 4]),
-            signer: sender.clone(),
-            plaintext: checkpoint.cid().to_bytes(),
-            result: Ok(()),
-        });
+        expect_abort(
+            SubnetActorError::NotSigningWindow.exit_code(),
+            runtime.call::<Actor>(
+                Method::RegisterCrossMsg as u64,
+                &cbor::serialize(
+                    &RegisterCrossMsgParams {
+                        epoch: 11,
+                        msgs_cid,
+                    },
+                    "test",
+                )
+                .unwrap(),
+            ),
+        );
 
-        if is_commit {
-            runtime.expect_send(
-                Address::new_id(IPC_GATEWAY_ADDR),
-                ipc_gateway::Method::CommitChildCheckpoint as u64,
-                RawBytes::serialize(checkpoint)?,
-                TokenAmount::zero(),
-                cbor::serialize(&sender.clone(), "test").unwrap(),
-                ExitCode::new(0),
+        runtime.expect_validate_caller_any();
+        runtime
+            .call::<Actor>(
+                Method::RegisterCrossMsg as u64,
+                &cbor::serialize(&params, "test").unwrap(),
+            )
+            .unwrap();
+        let st: State = runtime.get_state();
+        assert!(st
+            .is_cross_msgs_registered(runtime.store(), &10, &msgs_cid)
+            .unwrap());
+        assert!(!st
+            .is_cross_msgs_registered(runtime.store(), &20, &msgs_cid)
+            .unwrap());
+
+        // later windows can't be registered before they close
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            SubnetActorError::WindowNotOpen(20).exit_code(),
+            runtime.call::<Actor>(
+                Method::RegisterCrossMsg as u64,
+                &cbor::serialize(
+                    &RegisterCrossMsgParams {
+                        epoch: 20,
+                        msgs_cid,
+                    },
+                    "test",
+                )
+                .unwrap(),
+            ),
+        );
+
+        // the batches a validator registers in a window are bounded
+        let register = |runtime: &mut MockRuntime, msgs_cid: Cid| {
+            runtime.expect_validate_caller_any();
+            runtime.call::<Actor>(
+                Method::RegisterCrossMsg as u64,
+                &cbor::serialize(
+                    &RegisterCrossMsgParams {
+                        epoch: 10,
+                        msgs_cid,
+                    },
+                    "test",
+                )
+                .unwrap(),
             )
+        };
+        for i in 1..MAX_CROSS_MSG_BATCHES_PER_VALIDATOR as u64 {
+            let cid = TCid::<TLink<u64>>::new_link(runtime.store(), &i)
+                .unwrap()
+                .cid();
+            register(&mut runtime, cid).unwrap();
         }
-        runtime.call::<Actor>(
-            Method::SubmitCheckpoint as u64,
-            &cbor::serialize(checkpoint, "test").unwrap(),
-        )
+        let cid = TCid::<TLink<u64>>::new_link(runtime.store(), &0)
+            .unwrap()
+            .cid();
+        expect_abort(
+            SubnetActorError::TooManyCrossMsgBatches(caller).exit_code(),
+            register(&mut runtime, cid),
+        );
+        // registering a batch again doesn't count
+        register(&mut runtime, msgs_cid).unwrap();
+    }
+
+    #[test]
+    fn test_put_cross_msg_batch() {
+        let test_actor_address = Address::new_id(9999);
+        let mut runtime = construct_runtime_with_receiver(test_actor_address.clone());
+        let miners = vec![Address::new_id(10), Address::new_id(20)];
+        join_validators(&mut runtime, &miners);
+
+        let batch = RawBytes::serialize(vec![1u64, 2, 3]).unwrap();
+        let put = |runtime: &mut MockRuntime, caller: Address, batch: RawBytes| {
+            runtime.set_caller(Cid::default(), caller);
+            runtime.expect_validate_caller_any();
+            runtime.call::<Actor>(
+                Method::PutCrossMsgBatch as u64,
+                &cbor::serialize(&PutCrossMsgBatchParams { epoch: 10, batch }, "test").unwrap(),
+            )
+        };
+
+        // only validators can put batches
+        expect_abort(
+            SubnetActorError::NotValidator(Address::new_id(30)).exit_code(),
+            put(&mut runtime, Address::new_id(30), batch.clone()),
+        );
+
+        // batches are bounded, and need to be valid CBOR
+        let too_large = RawBytes::new(vec![0; MAX_CROSS_MSG_BATCH_SIZE + 1]);
+        expect_abort(
+            SubnetActorError::CrossMsgBatchTooLarge(0).exit_code(),
+            put(&mut runtime, miners[0], too_large),
+        );
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            put(&mut runtime, miners[0], RawBytes::new(vec![0xff])),
+        );
+
+        // the batch is stored and registered for the window
+        let ret: PutCrossMsgBatchReturn = put(&mut runtime, miners[0], batch.clone())
+            .unwrap()
+            .deserialize()
+            .unwrap();
+        assert_eq!(ret.size, batch.bytes().len() as u64);
+        assert_eq!(
+            runtime.store().get(&ret.msgs_cid).unwrap(),
+            Some(batch.bytes().to_vec())
+        );
+        let st: State = runtime.get_state();
+        assert!(st
+            .is_cross_msgs_registered(runtime.store(), &10, &ret.msgs_cid)
+            .unwrap());
+
+        // checkpoints over the maximum size are rejected before voting
+        let root_subnet = SubnetID::from_str("/root").unwrap();
+        let subnet = SubnetID::new(&root_subnet, test_actor_address);
+        let mut checkpoint = Checkpoint::new(subnet, 10);
+        checkpoint.data.proof = vec![0; MAX_CHECKPOINT_SIZE];
+        runtime.set_caller(Cid::default(), miners[0]);
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            SubnetActorError::CheckpointTooLarge(0).exit_code(),
+            runtime.call::<Actor>(
+                Method::SubmitCheckpoint as u64,
+                &cbor::serialize(&submission(&runtime, &checkpoint), "test").unwrap(),
+            ),
+        );
+    }
+
+    #[test]
+    fn test_submit_aggregated_checkpoint() {
+        let test_actor_address = Address::new_id(9999);
+        let mut runtime = construct_runtime_with_receiver(test_actor_address.clone());
+
+        let miners = vec![
+            Address::new_id(10),
+            Address::new_id(20),
+            Address::new_id(30),
+        ];
+        join_validators(&mut runtime, &miners);
+
+        let root_subnet = SubnetID::from_str("/root").unwrap();
+        let subnet = SubnetID::new(&root_subnet, test_actor_address);
+        let checkpoint = submission(&runtime, &Checkpoint::new(subnet.clone(), 10));
+        let sig = Signature::new_secp256k1(vec![1, 2, 3, 4]);
+
+        // a single signature is not enough to commit the checkpoint
+        let params = AggregatedCheckpointParams {
+            submission: checkpoint.clone(),
+            signatures: vec![ValidatorSignature {
+                validator: miners[0],
+                signature: sig.clone(),
+            }],
+        };
+        runtime.set_caller(Cid::default(), Address::new_id(40));
+        runtime.expect_validate_caller_any();
+        expect_signature(&mut runtime, miners[0], &checkpoint);
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            runtime.call::<Actor>(
+                Method::SubmitAggregatedCheckpoint as u64,
+                &cbor::serialize(&params, "test").unwrap(),
+            ),
+        );
+
+        // signatures from non-validators are rejected
+        let params = AggregatedCheckpointParams {
+            submission: checkpoint.clone(),
+            signatures: vec![ValidatorSignature {
+                validator: Address::new_id(40),
+                signature: sig.clone(),
+            }],
+        };
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            SubnetActorError::NotValidator(Address::new_id(40)).exit_code(),
+            runtime.call::<Actor>(
+                Method::SubmitAggregatedCheckpoint as u64,
+                &cbor::serialize(&params, "test").unwrap(),
+            ),
+        );
+
+        // a relayer submits the signatures of the majority
+        let params = AggregatedCheckpointParams {
+            submission: checkpoint.clone(),
+            signatures: vec![
+                ValidatorSignature {
+                    validator: miners[0],
+                    signature: sig.clone(),
+                },
+                ValidatorSignature {
+                    validator: miners[1],
+                    signature: sig.clone(),
+                },
+            ],
+        };
+        runtime.expect_validate_caller_any();
+        expect_signature(&mut runtime, miners[0], &checkpoint);
+        expect_signature(&mut runtime, miners[1], &checkpoint);
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::CommitChildCheckpoint as u64,
+            RawBytes::serialize(&checkpoint.checkpoint).unwrap(),
+            TokenAmount::zero(),
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        runtime
+            .call::<Actor>(
+                Method::SubmitAggregatedCheckpoint as u64,
+                &cbor::serialize(&params, "test").unwrap(),
+            )
+            .unwrap();
+        runtime.verify();
+
+        // the checkpoint is committed
+        runtime.set_caller(Cid::default(), miners[2]);
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            SubnetActorError::CheckpointAlreadyCommitted.exit_code(),
+            runtime.call::<Actor>(
+                Method::SubmitCheckpoint as u64,
+                &cbor::serialize(&checkpoint, "test").unwrap(),
+            ),
+        );
+    }
+
+    #[test]
+    fn test_repeated_join_and_add_stake() {
+        let mut runtime = construct_runtime();
+
+        let caller = Address::new_id(10);
+        join_validators(&mut runtime, &[caller]);
+
+        // joining again doesn't duplicate the validator
+        let params = JoinParams {
+            validator_net_addr: net_addr(&caller),
+            invitation: None,
+            vesting: None,
+            worker: None,
+        };
+        runtime.set_value(TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT));
+        runtime.set_caller(Cid::default(), caller.clone());
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::AddStake as u64,
+            RawBytes::default(),
+            TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT),
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        let ret: JoinReturn = runtime
+            .call::<Actor>(
+                Method::Join as u64,
+                &cbor::serialize(&params, "test").unwrap(),
+            )
+            .unwrap()
+            .deserialize()
+            .unwrap();
+        assert_eq!(ret.became_validator, false);
+        let st: State = runtime.get_state();
+        assert_eq!(st.validators.len(), 1);
+
+        // stake can be topped-up without going through join
+        let value = TokenAmount::from_atto(5_u64.pow(18));
+        runtime.set_value(value.clone());
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::AddStake as u64,
+            RawBytes::default(),
+            value.clone(),
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        runtime
+            .call::<Actor>(Method::AddStake as u64, &RawBytes::default())
+            .unwrap();
+        let st: State = runtime.get_state();
+        assert_eq!(st.validators.len(), 1);
+        assert_eq!(
+            st.get_stake(runtime.store(), &caller).unwrap().unwrap(),
+            TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT * 2) + &value
+        );
+
+        // addresses without stake can't top-up
+        runtime.set_caller(Cid::default(), Address::new_id(20));
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            SubnetActorError::NotEnoughStake(Address::new_id(20)).exit_code(),
+            runtime.call::<Actor>(Method::AddStake as u64, &RawBytes::default()),
+        );
+    }
+
+    #[test]
+    fn test_token_collateral() {
+        let token = Address::new_id(200);
+        let owner = Address::new_id(5);
+        let mut params = std_construct_param();
+        params.collateral_token = Some(token);
+        assert_eq!(
+            params.validate(),
+            Err(ConstructParamsError::TokenCollateralWithoutOwner)
+        );
+        params.owner = Some(owner);
+        params.caller_policies.join = CallerPolicy::Accounts;
+
+        // the subnet is constructed with a native bond to register in the gateway
+        let bond = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+        let construct = |value: &TokenAmount| {
+            let mut runtime = MockRuntime::new(Address::new_id(1), *INIT_ACTOR_ADDR);
+            runtime.set_value(value.clone());
+            runtime.set_balance(value.clone());
+            runtime.expect_validate_caller_addr(vec![*INIT_ACTOR_ADDR]);
+            expect_network_name(&mut runtime, IPC_GATEWAY_ADDR, &params.parent, ExitCode::OK);
+            let ret = runtime.call::<Actor>(
+                Method::Constructor as u64,
+                &cbor::serialize(&params, "test").unwrap(),
+            );
+            (runtime, ret)
+        };
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            construct(&TokenAmount::zero()).1,
+        );
+        let (mut runtime, ret) = construct(&bond);
+        ret.unwrap();
+        let st: State = runtime.get_state();
+        assert_eq!(st.registration_bond, bond);
+
+        let caller = Address::new_id(10);
+        let join_params = JoinParams {
+            validator_net_addr: net_addr(&caller),
+            invitation: None,
+            vesting: None,
+            worker: None,
+        };
+        let value = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+        let contract = Address::new_id(11);
+        runtime
+            .actor_code_cids
+            .insert(caller, *ACCOUNT_ACTOR_CODE_ID);
+        runtime
+            .actor_code_cids
+            .insert(contract, *MINER_ACTOR_CODE_ID);
+
+        // native collateral is rejected
+        runtime.set_value(value.clone());
+        runtime.set_caller(*ACCOUNT_ACTOR_CODE_ID, caller);
+        runtime.expect_validate_caller_type(vec![Type::Account, Type::EthAccount]);
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            runtime.call::<Actor>(
+                Method::Join as u64,
+                &cbor::serialize(&join_params, "test").unwrap(),
+            ),
+        );
+
+        // tokens need to be received by the subnet
+        let receive_params = |from: Address, to: u64| frc46::UniversalReceiverParams {
+            type_: frc46::FRC46_TOKEN_TYPE,
+            payload: RawBytes::serialize(frc46::FRC46TokenReceived {
+                from: from.id().unwrap(),
+                to,
+                operator: from.id().unwrap(),
+                amount: value.clone(),
+                operator_data: RawBytes::serialize(&join_params).unwrap(),
+                token_data: RawBytes::default(),
+            })
+            .unwrap(),
+        };
+        runtime.set_value(TokenAmount::zero());
+        runtime.set_caller(Cid::default(), token);
+        runtime.expect_validate_caller_addr(vec![token]);
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            runtime.call::<Actor>(
+                Method::Receive as u64,
+                &cbor::serialize(&receive_params(caller, 2), "test").unwrap(),
+            ),
+        );
+
+        // and sent by callers allowed to join
+        runtime.expect_validate_caller_addr(vec![token]);
+        expect_abort(
+            ExitCode::USR_FORBIDDEN,
+            runtime.call::<Actor>(
+                Method::Receive as u64,
+                &cbor::serialize(&receive_params(contract, 1), "test").unwrap(),
+            ),
+        );
+
+        // the token transfer is staked on behalf of the sender, without
+        // locking the collateral in the gateway, which registers the subnet
+        // with its bond
+        runtime.expect_validate_caller_addr(vec![token]);
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::Register as u64,
+            RawBytes::default(),
+            bond.clone(),
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        let ret: JoinReturn = runtime
+            .call::<Actor>(
+                Method::Receive as u64,
+                &cbor::serialize(&receive_params(caller, 1), "test").unwrap(),
+            )
+            .unwrap()
+            .deserialize()
+            .unwrap();
+        assert_eq!(ret.became_validator, true);
+        assert_eq!(ret.status, Status::Active);
+        let st: State = runtime.get_state();
+        assert_eq!(
+            st.get_stake(runtime.store(), &caller).unwrap().unwrap(),
+            value
+        );
+
+        // leaving refunds the tokens
+        runtime.set_caller(Cid::default(), caller);
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            token,
+            frc46::TRANSFER_METHOD,
+            RawBytes::serialize(frc46::TransferParams {
+                to: caller,
+                amount: value.clone(),
+                operator_data: RawBytes::default(),
+            })
+            .unwrap(),
+            TokenAmount::zero(),
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        runtime
+            .call::<Actor>(Method::Leave as u64, &RawBytes::default())
+            .unwrap();
+        let st: State = runtime.get_state();
+        assert_eq!(st.total_stake, TokenAmount::zero());
+
+        // the bond of a subnet killed before registering is refunded to the owner
+        let (mut runtime, ret) = construct(&bond);
+        ret.unwrap();
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::Kill as u64,
+            RawBytes::default(),
+            TokenAmount::zero(),
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        runtime.expect_send(
+            owner,
+            METHOD_SEND,
+            RawBytes::default(),
+            bond,
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        runtime
+            .call::<Actor>(Method::Kill as u64, &RawBytes::default())
+            .unwrap();
+        let st: State = runtime.get_state();
+        assert_eq!(st.registration_bond, TokenAmount::zero());
+    }
+
+    #[test]
+    fn test_weight_snapshot() {
+        let test_actor_address = Address::new_id(9999);
+        let mut runtime = construct_runtime_with_receiver(test_actor_address.clone());
+
+        let validators = [Address::new_id(10), Address::new_id(11)];
+        join_validators(&mut runtime, &validators);
+
+        let subnet = SubnetID::new(&SubnetID::from_str("/root").unwrap(), test_actor_address);
+        let mut checkpoint = Checkpoint::new(subnet.clone(), 10);
+        checkpoint.set_signature(
+            RawBytes::serialize(Signature::new_secp256k1(vec![1, 2, 3, 4]))
+                .unwrap()
+                .bytes()
+                .to_vec(),
+        );
+        send_checkpoint(&mut runtime, validators[0], &checkpoint, false).unwrap();
+
+        // a validator joining mid-window doesn't change the quorum of the window
+        let late = Address::new_id(12);
+        let value = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT * 2);
+        runtime.set_value(value.clone());
+        runtime.set_caller(Cid::default(), late);
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::AddStake as u64,
+            RawBytes::default(),
+            value,
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        runtime
+            .call::<Actor>(
+                Method::Join as u64,
+                &cbor::serialize(
+                    &JoinParams {
+                        validator_net_addr: net_addr(&late),
+                        invitation: None,
+                        vesting: None,
+                        worker: None,
+                    },
+                    "test",
+                )
+                .unwrap(),
+            )
+            .unwrap();
+
+        send_checkpoint(&mut runtime, validators[1], &checkpoint, true).unwrap();
+        let st: State = runtime.get_state();
+        assert_eq!(st.last_committed_epoch, 10);
+        assert_eq!(st.get_weight_snapshot(runtime.store(), 10).unwrap(), None);
+
+        // the next window is weighted with the new validator
+        let snapshot = st
+            .get_weight_snapshot(runtime.store(), 20)
+            .unwrap()
+            .unwrap();
+        assert_eq!(snapshot.weights.len(), 3);
+        assert_eq!(
+            snapshot.total,
+            TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT * 4)
+        );
+    }
+
+    #[test]
+    fn test_migrate_state() {
+        let mut runtime = construct_runtime();
+
+        // simulate a state deployed before versioning was introduced
+        let mut st: State = runtime.get_state();
+        st.version = 0;
+        st.invitations = TCid::default();
+        runtime.replace_state(&st);
+
+        runtime.expect_validate_caller_any();
+        runtime
+            .call::<Actor>(Method::MigrateState as u64, &RawBytes::default())
+            .unwrap();
+
+        let st: State = runtime.get_state();
+        assert_eq!(st.version, STATE_VERSION);
+        assert_eq!(st.get_invitation(runtime.store(), &[1]).unwrap(), None);
+    }
+
+    #[test]
+    fn test_upgrade() {
+        let owner = Address::new_id(5);
+        let actor = Address::new_id(1);
+        let mut params = std_construct_param();
+        params.owner = Some(owner);
+        let mut runtime = construct_runtime_with_params(actor, params);
+        let code = *MINER_ACTOR_CODE_ID;
+        let upgrade = |runtime: &mut MockRuntime| {
+            runtime.set_caller(Cid::default(), owner);
+            runtime.expect_validate_caller_addr(vec![owner]);
+            runtime.call::<Actor>(Method::Upgrade as u64, &RawBytes::serialize(code).unwrap())
+        };
+
+        // only codes committed by the gateway are allowed
+        expect_abort(
+            SubnetActorError::UpgradeCodeNotAllowed(code).exit_code(),
+            upgrade(&mut runtime),
+        );
+        let gateway = Address::new_id(IPC_GATEWAY_ADDR);
+        runtime.set_caller(Cid::default(), owner);
+        runtime.expect_validate_caller_addr(vec![gateway]);
+        expect_abort(
+            ExitCode::USR_FORBIDDEN,
+            runtime.call::<Actor>(
+                Method::SetUpgradeCodes as u64,
+                &RawBytes::serialize(vec![code]).unwrap(),
+            ),
+        );
+        runtime.set_caller(Cid::default(), gateway);
+        runtime.expect_validate_caller_addr(vec![gateway]);
+        runtime
+            .call::<Actor>(
+                Method::SetUpgradeCodes as u64,
+                &RawBytes::serialize(vec![code]).unwrap(),
+            )
+            .unwrap();
+
+        // the network needs to have swapped the code of the actor
+        expect_abort(ExitCode::USR_ILLEGAL_STATE, upgrade(&mut runtime));
+
+        // simulate a state of a previous version, migrated by the upgrade
+        let mut st: State = runtime.get_state();
+        st.version = 0;
+        st.invitations = TCid::default();
+        runtime.replace_state(&st);
+        runtime.actor_code_cids.insert(actor, code);
+        upgrade(&mut runtime).unwrap();
+        runtime.verify();
+        let st: State = runtime.get_state();
+        assert_eq!(st.version, STATE_VERSION);
+        assert_eq!(st.get_invitation(runtime.store(), &[1]).unwrap(), None);
+
+        // only the owner can upgrade the actor
+        runtime.set_caller(Cid::default(), Address::new_id(6));
+        runtime.expect_validate_caller_addr(vec![owner]);
+        expect_abort(
+            ExitCode::USR_FORBIDDEN,
+            runtime.call::<Actor>(Method::Upgrade as u64, &RawBytes::serialize(code).unwrap()),
+        );
+    }
+
+    #[test]
+    fn test_migrate_epoch_keys() {
+        let mut runtime = construct_runtime();
+
+        // simulate checkpoints and batches keyed by the legacy epoch encoding
+        let ch = Checkpoint::new(SubnetID::from_str("/root/f01").unwrap(), 10);
+        let msgs_cid = Cid::default();
+        let legacy_key = BytesKey::from(10_i64.to_le_bytes().to_vec());
+        let mut st: State = runtime.get_state();
+        st.version = 3;
+        let mut legacy_checkpoints = TCid::new_hamt(runtime.store()).unwrap();
+        legacy_checkpoints
+            .modify(runtime.store(), |hamt| {
+                hamt.set(legacy_key.clone(), ch.clone())?;
+                Ok(true)
+            })
+            .unwrap();
+        st.legacy_checkpoints = Some(legacy_checkpoints);
+        st.cross_msgs
+            .modify(runtime.store(), |hamt| {
+                hamt.set(legacy_key.clone(), vec![msgs_cid])?;
+                Ok(true)
+            })
+            .unwrap();
+        runtime.replace_state(&st);
+
+        runtime.expect_validate_caller_any();
+        runtime
+            .call::<Actor>(Method::MigrateState as u64, &RawBytes::default())
+            .unwrap();
+
+        let st: State = runtime.get_state();
+        assert_eq!(st.version, STATE_VERSION);
+        // the checkpoints keyed by epoch end up logged by window number
+        assert!(st.legacy_checkpoints.is_none());
+        assert_eq!(st.checkpoints_count, 1);
+        assert_eq!(
+            st.get_checkpoint_by_window(runtime.store(), 0).unwrap(),
+            Some(ch)
+        );
+        assert!(st
+            .is_cross_msgs_registered(runtime.store(), &10, &msgs_cid)
+            .unwrap());
+        assert_eq!(keys::parse_epoch_key(&keys::epoch_key(10)).unwrap(), 10);
+    }
+
+    #[test]
+    fn test_migrate_validator_set() {
+        let mut runtime = construct_runtime();
+        let validators = [Address::new_id(10), Address::new_id(20)];
+        join_validators(&mut runtime, &validators);
+
+        // simulate a validator set kept in the legacy list
+        let mut st: State = runtime.get_state();
+        let legacy = st.validators.list(runtime.store()).unwrap();
+        st.version = 13;
+        st.legacy_validator_set = legacy.clone();
+        st.validators = ValidatorSet::default();
+        runtime.replace_state(&st);
+
+        runtime.expect_validate_caller_any();
+        runtime
+            .call::<Actor>(Method::MigrateState as u64, &RawBytes::default())
+            .unwrap();
+
+        let st: State = runtime.get_state();
+        assert_eq!(st.version, STATE_VERSION);
+        assert!(st.legacy_validator_set.is_empty());
+        assert_eq!(st.validators.list(runtime.store()).unwrap(), legacy);
+        assert!(st.is_validator(runtime.store(), &validators[1]).unwrap());
+    }
+
+    #[test]
+    fn test_migrate_validator_index() {
+        let mut runtime = construct_runtime();
+        let validators = [
+            Address::new_id(10),
+            Address::new_id(20),
+            Address::new_id(30),
+        ];
+        join_validators(&mut runtime, &validators);
+
+        // simulate a validator set indexed without stakes, in join order
+        let mut st: State = runtime.get_state();
+        st.version = 33;
+        st.legacy_validators = Some(LegacyValidatorSet {
+            validators: TCid::from(st.validators.cid()),
+            index: vec![validators[2], validators[0], validators[1]],
+        });
+        st.validators = ValidatorSet::default();
+        runtime.replace_state(&st);
+
+        runtime.expect_validate_caller_any();
+        runtime
+            .call::<Actor>(Method::MigrateState as u64, &RawBytes::default())
+            .unwrap();
+
+        // validators with the same stake are ordered by address
+        let st: State = runtime.get_state();
+        assert_eq!(st.version, STATE_VERSION);
+        assert!(st.legacy_validators.is_none());
+        let stake = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+        assert_eq!(
+            st.validators.entries().to_vec(),
+            validators
+                .iter()
+                .map(|addr| IndexEntry {
+                    addr: *addr,
+                    stake: stake.clone(),
+                })
+                .collect::<Vec<_>>()
+        );
+        assert!(st.is_validator(runtime.store(), &validators[2]).unwrap());
+    }
+
+    #[test]
+    fn test_migrate_fee_dust() {
+        let mut runtime = construct_runtime();
+
+        // simulate fees kept aside as dust
+        let mut st: State = runtime.get_state();
+        st.version = 29;
+        st.legacy_fee_dust = Some(TokenAmount::from_atto(7));
+        st.reward_pool = TokenAmount::from_atto(3);
+        runtime.replace_state(&st);
+
+        runtime.expect_validate_caller_any();
+        runtime
+            .call::<Actor>(Method::MigrateState as u64, &RawBytes::default())
+            .unwrap();
+
+        let st: State = runtime.get_state();
+        assert_eq!(st.version, STATE_VERSION);
+        assert_eq!(st.legacy_fee_dust, None);
+        assert_eq!(st.reward_pool, TokenAmount::from_atto(10));
+    }
+
+    #[test]
+    fn test_migrate_finality_votes() {
+        let mut runtime = construct_runtime();
+
+        // simulate votes keyed by the CBOR encoding of the finality
+        let finality = ParentFinality {
+            height: 10,
+            block_hash: vec![1, 2, 3],
+        };
+        let votes = Votes {
+            validators: vec![Address::new_id(10)],
+        };
+        let mut st: State = runtime.get_state();
+        st.version = 30;
+        let mut legacy_votes = TCid::new_hamt(runtime.store()).unwrap();
+        legacy_votes
+            .modify(runtime.store(), |hamt| {
+                let key = RawBytes::serialize(&finality)?.bytes().to_vec();
+                hamt.set(BytesKey::from(key), votes.clone())?;
+                Ok(true)
+            })
+            .unwrap();
+        st.legacy_finality_votes = Some(legacy_votes);
+        st.finality_votes = Voting::default();
+        runtime.replace_state(&st);
+
+        runtime.expect_validate_caller_any();
+        runtime
+            .call::<Actor>(Method::MigrateState as u64, &RawBytes::default())
+            .unwrap();
+
+        let st: State = runtime.get_state();
+        assert_eq!(st.version, STATE_VERSION);
+        assert!(st.legacy_finality_votes.is_none());
+        let cid = TCid::new_link(runtime.store(), &finality).unwrap().cid();
+        assert_eq!(
+            st.finality_votes.get(runtime.store(), &cid).unwrap(),
+            Some(votes)
+        );
+    }
+
+    #[test]
+    fn test_migrate_econ_params() {
+        let mut runtime = construct_runtime();
+
+        // simulate economic parameters kept in the top-level fields
+        let mut st: State = runtime.get_state();
+        st.version = 24;
+        st.legacy_min_validator_stake = Some(st.econ.min_validator_stake.clone());
+        st.legacy_min_total_collateral = Some(st.econ.min_total_collateral.clone());
+        st.legacy_relayer_fee = Some(TokenAmount::from_atto(5));
+        st.econ = EconParams::default();
+        runtime.replace_state(&st);
+
+        runtime.expect_validate_caller_any();
+        runtime
+            .call::<Actor>(Method::MigrateState as u64, &RawBytes::default())
+            .unwrap();
+
+        let st: State = runtime.get_state();
+        assert_eq!(st.version, STATE_VERSION);
+        assert_eq!(
+            st.econ.min_validator_stake,
+            TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT)
+        );
+        assert_eq!(st.econ.relayer_fee, TokenAmount::from_atto(5));
+        assert_eq!(st.econ.join_fee, TokenAmount::zero());
+        assert_eq!(st.legacy_min_validator_stake, None);
+        assert_eq!(st.legacy_relayer_fee, None);
+    }
+
+    #[test]
+    fn test_catch_up_checkpoint() {
+        let test_actor_address = Address::new_id(9999);
+        let mut runtime = construct_runtime_with_receiver(test_actor_address.clone());
+
+        let validator = Address::new_id(10);
+        join_validators(&mut runtime, &[validator]);
+
+        let subnet = SubnetID::new(&SubnetID::from_str("/root").unwrap(), test_actor_address);
+        let sig = RawBytes::serialize(Signature::new_secp256k1(vec![1, 2, 3, 4]))
+            .unwrap()
+            .bytes()
+            .to_vec();
+
+        let mut checkpoint_0 = Checkpoint::new(subnet.clone(), 10);
+        checkpoint_0.set_signature(sig.clone());
+        send_checkpoint(&mut runtime, validator, &checkpoint_0, true).unwrap();
+        assert_eq!(next_checkpoint_epoch(&mut runtime), 20);
+
+        // regular submissions for a later window are rejected
+        let mut checkpoint_1 = Checkpoint::new(subnet.clone(), 30);
+        checkpoint_1.data.prev_check = TCid::from(checkpoint_0.cid());
+        checkpoint_1.set_signature(sig.clone());
+        runtime.set_caller(Cid::default(), validator);
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            SubnetActorError::NotNextWindow(20).exit_code(),
+            runtime.call::<Actor>(
+                Method::SubmitCheckpoint as u64,
+                &cbor::serialize(&submission(&runtime, &checkpoint_1), "test").unwrap(),
+            ),
+        );
+
+        // catching up is only possible once the windows have passed
+        runtime.set_epoch(25);
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            SubnetActorError::NoSkippedWindows.exit_code(),
+            runtime.call::<Actor>(
+                Method::SubmitCatchUpCheckpoint as u64,
+                &cbor::serialize(&submission(&runtime, &checkpoint_1), "test").unwrap(),
+            ),
+        );
+
+        runtime.set_epoch(35);
+        let catch_up = submission(&runtime, &checkpoint_1);
+        runtime.expect_validate_caller_any();
+        expect_signature(&mut runtime, validator, &catch_up);
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::CommitChildCheckpoint as u64,
+            RawBytes::serialize(&checkpoint_1).unwrap(),
+            TokenAmount::zero(),
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        runtime
+            .call::<Actor>(
+                Method::SubmitCatchUpCheckpoint as u64,
+                &cbor::serialize(&catch_up, "test").unwrap(),
+            )
+            .unwrap();
+        runtime.verify();
+
+        let st: State = runtime.get_state();
+        assert_eq!(st.last_committed_epoch, 30);
+        assert_eq!(next_checkpoint_epoch(&mut runtime), 40);
+    }
+
+    #[test]
+    fn test_jail_inactive_validators() {
+        let test_actor_address = Address::new_id(9999);
+        let mut params = std_construct_param();
+        params.max_missed_windows = 1;
+        let mut runtime = construct_runtime_with_params(test_actor_address.clone(), params);
+
+        let validators = [
+            Address::new_id(10),
+            Address::new_id(11),
+            Address::new_id(12),
+        ];
+        join_validators(&mut runtime, &validators);
+
+        let subnet = SubnetID::new(&SubnetID::from_str("/root").unwrap(), test_actor_address);
+        let sig = RawBytes::serialize(Signature::new_secp256k1(vec![1, 2, 3, 4]))
+            .unwrap()
+            .bytes()
+            .to_vec();
+
+        // the third validator misses the window
+        let mut checkpoint_0 = Checkpoint::new(subnet.clone(), 10);
+        checkpoint_0.set_signature(sig.clone());
+        send_checkpoint(&mut runtime, validators[0], &checkpoint_0, false).unwrap();
+        send_checkpoint(&mut runtime, validators[1], &checkpoint_0, true).unwrap();
+
+        let st: State = runtime.get_state();
+        assert!(st.is_jailed(runtime.store(), &validators[2]).unwrap());
+        assert!(!st.is_jailed(runtime.store(), &validators[0]).unwrap());
+
+        // jailed validators can't vote
+        let mut checkpoint_1 = Checkpoint::new(subnet.clone(), 20);
+        checkpoint_1.data.prev_check = TCid::from(checkpoint_0.cid());
+        checkpoint_1.set_signature(sig.clone());
+        runtime.set_caller(Cid::default(), validators[2]);
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            SubnetActorError::NotValidator(validators[2]).exit_code(),
+            runtime.call::<Actor>(
+                Method::SubmitCheckpoint as u64,
+                &cbor::serialize(&submission(&runtime, &checkpoint_1), "test").unwrap(),
+            ),
+        );
+
+        // only jailed validators can be released
+        runtime.set_caller(Cid::default(), validators[0]);
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            SubnetActorError::NotJailed(validators[0]).exit_code(),
+            runtime.call::<Actor>(Method::Unjail as u64, &RawBytes::default()),
+        );
+
+        // the penalty would leave the validator below the minimum stake
+        runtime.set_caller(Cid::default(), validators[2]);
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            SubnetActorError::UnjailBelowMinStake(validators[2]).exit_code(),
+            runtime.call::<Actor>(Method::Unjail as u64, &RawBytes::default()),
+        );
+
+        // so it tops up its stake first
+        let top_up = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT / 50);
+        runtime.set_value(top_up.clone());
+        runtime.set_balance(top_up.clone());
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::AddStake as u64,
+            RawBytes::default(),
+            top_up.clone(),
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        runtime
+            .call::<Actor>(Method::AddStake as u64, &RawBytes::default())
+            .unwrap();
+        runtime.set_value(TokenAmount::zero());
+        runtime.expect_validate_caller_any();
+        runtime
+            .call::<Actor>(Method::Unjail as u64, &RawBytes::default())
+            .unwrap();
+
+        let stake = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT) + &top_up;
+        let penalty = stake.div_floor(100);
+        let st: State = runtime.get_state();
+        assert!(!st.is_jailed(runtime.store(), &validators[2]).unwrap());
+        assert_eq!(
+            st.get_stake(runtime.store(), &validators[2]).unwrap(),
+            Some(&stake - &penalty)
+        );
+        assert_eq!(st.penalized_stake, penalty);
+        assert_eq!(
+            st.total_stake,
+            TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT * 2) + &stake - &penalty
+        );
+        // the validator keeps its place in the validator set
+        assert!(st.is_validator(runtime.store(), &validators[2]).unwrap());
+    }
+
+    #[test]
+    fn test_bootstrap_validators() {
+        let owner = Address::new_id(5);
+        let mut params = std_construct_param();
+        params.owner = Some(owner);
+        let mut runtime = construct_runtime_with_params(Address::new_id(1), params);
+
+        let stake = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+        let params = BootstrapValidatorsParams {
+            validators: (10..13)
+                .map(|id| BootstrapValidator {
+                    addr: Address::new_id(id),
+                    net_addr: format!("/ip4/127.0.0.1/tcp/{}", id).parse().unwrap(),
+                    stake: stake.clone(),
+                })
+                .collect(),
+        };
+        let total = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT * 3);
+
+        // the funds need to match the stake of the validators
+        runtime.set_caller(Cid::default(), owner);
+        runtime.set_value(stake.clone());
+        runtime.expect_validate_caller_addr(vec![owner]);
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            runtime.call::<Actor>(
+                Method::BootstrapValidators as u64,
+                &cbor::serialize(&params, "test").unwrap(),
+            ),
+        );
+
+        runtime.set_value(total.clone());
+        runtime.expect_validate_caller_addr(vec![owner]);
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::Register as u64,
+            RawBytes::default(),
+            total.clone(),
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        runtime
+            .call::<Actor>(
+                Method::BootstrapValidators as u64,
+                &cbor::serialize(&params, "test").unwrap(),
+            )
+            .unwrap();
+        runtime.verify();
+
+        let st: State = runtime.get_state();
+        assert_eq!(st.status, Status::Active);
+        assert_eq!(st.total_stake, total);
+        assert_eq!(st.validators.len(), 3);
+        let validators = st.validators.list(runtime.store()).unwrap();
+        assert_eq!(validators[1].net_addr, "/ip4/127.0.0.1/tcp/11");
+
+        // it can only be called once
+        runtime.expect_validate_caller_addr(vec![owner]);
+        expect_abort(
+            SubnetActorError::AlreadyBootstrapped.exit_code(),
+            runtime.call::<Actor>(
+                Method::BootstrapValidators as u64,
+                &cbor::serialize(&params, "test").unwrap(),
+            ),
+        );
+    }
+
+    #[test]
+    fn test_funding_ledger() {
+        let owner = Address::new_id(5);
+        let validator = Address::new_id(10);
+        let delegator = Address::new_id(20);
+        let mut params = std_construct_param();
+        params.owner = Some(owner);
+        let mut runtime = construct_runtime_with_params(Address::new_id(1), params);
+        let stake = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+
+        // the owner funds the genesis stake of the validator
+        runtime.set_caller(Cid::default(), owner);
+        runtime.set_value(stake.clone());
+        runtime.expect_validate_caller_addr(vec![owner]);
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::Register as u64,
+            RawBytes::default(),
+            stake.clone(),
+            RawBytes::default(),
+            ExitCode::OK,
+        );
+        runtime
+            .call::<Actor>(
+                Method::BootstrapValidators as u64,
+                &cbor::serialize(
+                    &BootstrapValidatorsParams {
+                        validators: vec![BootstrapValidator {
+                            addr: validator,
+                            net_addr: net_addr(&validator),
+                            stake: stake.clone(),
+                        }],
+                    },
+                    "test",
+                )
+                .unwrap(),
+            )
+            .unwrap();
+
+        // the validator tops its stake up, and a delegator delegates to it
+        let add = |runtime: &mut MockRuntime, caller: Address, method: Method, params: RawBytes| {
+            runtime.set_caller(Cid::default(), caller);
+            runtime.set_value(stake.clone());
+            runtime.expect_validate_caller_any();
+            runtime.expect_send(
+                Address::new_id(IPC_GATEWAY_ADDR),
+                ipc_gateway::Method::AddStake as u64,
+                RawBytes::default(),
+                stake.clone(),
+                RawBytes::default(),
+                ExitCode::OK,
+            );
+            runtime.call::<Actor>(method as u64, &params).unwrap();
+        };
+        add(
+            &mut runtime,
+            validator,
+            Method::AddStake,
+            RawBytes::default(),
+        );
+        add(
+            &mut runtime,
+            delegator,
+            Method::Delegate,
+            RawBytes::serialize(DelegateParams { validator }).unwrap(),
+        );
+
+        let st: State = runtime.get_state();
+        let funding = |addr: Address| Funding {
+            funder: addr,
+            amount: stake.clone(),
+        };
+        assert_eq!(
+            st.get_funding(runtime.store(), &validator).unwrap(),
+            vec![funding(owner), funding(validator), funding(delegator)]
+        );
+        assert!(st.check_invariants(runtime.store()).unwrap().is_ok());
+
+        // leaving refunds the own stake to its funders pro-rata, and the
+        // delegated stake stays with its delegator
+        runtime.set_caller(Cid::default(), validator);
+        runtime.set_value(TokenAmount::zero());
+        runtime.expect_validate_caller_any();
+        for funder in [owner, validator] {
+            expect_release(&mut runtime, funder, &stake);
+        }
+        let ret: LeaveReturn = runtime
+            .call::<Actor>(Method::Leave as u64, &RawBytes::default())
+            .unwrap()
+            .deserialize()
+            .unwrap();
+        runtime.verify();
+        assert_eq!(ret.refunded, &stake * 2);
+        assert_eq!(
+            ret.refunds,
+            vec![(owner, stake.clone()), (validator, stake.clone())]
+        );
+        let st: State = runtime.get_state();
+        assert_eq!(
+            st.get_funding(runtime.store(), &validator).unwrap(),
+            vec![funding(delegator)]
+        );
+        assert!(st.check_invariants(runtime.store()).unwrap().is_ok());
+
+        // ledgers of states predating them are rebuilt from the stake
+        let mut st: State = runtime.get_state();
+        st.version = 26;
+        st.funding = TCid::default();
+        runtime.replace_state(&st);
+        runtime.expect_validate_caller_any();
+        runtime
+            .call::<Actor>(Method::MigrateState as u64, &RawBytes::default())
+            .unwrap();
+        let st: State = runtime.get_state();
+        assert_eq!(
+            st.get_funding(runtime.store(), &validator).unwrap(),
+            vec![funding(delegator)]
+        );
+        assert!(st.check_invariants(runtime.store()).unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_list_stakers_and_checkpoints() {
+        let test_actor_address = Address::new_id(9999);
+        let mut runtime = construct_runtime_with_receiver(test_actor_address.clone());
+
+        let validators = [
+            Address::new_id(10),
+            Address::new_id(11),
+            Address::new_id(12),
+        ];
+        join_validators(&mut runtime, &validators);
+
+        let list_stakers = |runtime: &mut MockRuntime, cursor, limit| -> ListStakersReturn {
+            runtime.expect_validate_caller_any();
+            runtime
+                .call::<Actor>(
+                    Method::ListStakers as u64,
+                    &cbor::serialize(&ListParams { cursor, limit }, "test").unwrap(),
+                )
+                .unwrap()
+                .deserialize()
+                .unwrap()
+        };
+        let page = list_stakers(&mut runtime, 0, 2);
+        assert_eq!(page.stakers.len(), 2);
+        assert_eq!(page.next_cursor, Some(2));
+        assert_eq!(page.total, 3);
+        let last = list_stakers(&mut runtime, 2, 2);
+        assert_eq!(last.stakers.len(), 1);
+        assert_eq!(last.next_cursor, None);
+        // stakers are listed in order of their first stake
+        let listed: Vec<Address> = page
+            .stakers
+            .iter()
+            .chain(last.stakers.iter())
+            .map(|(addr, _)| *addr)
+            .collect();
+        assert_eq!(listed, validators);
+
+        let subnet = SubnetID::new(&SubnetID::from_str("/root").unwrap(), test_actor_address);
+        let mut checkpoint = Checkpoint::new(subnet, 10);
+        checkpoint.set_signature(
+            RawBytes::serialize(Signature::new_secp256k1(vec![1, 2, 3, 4]))
+                .unwrap()
+                .bytes()
+                .to_vec(),
+        );
+        send_checkpoint(&mut runtime, validators[0], &checkpoint, false).unwrap();
+        send_checkpoint(&mut runtime, validators[1], &checkpoint, true).unwrap();
+
+        runtime.expect_validate_caller_any();
+        let ret: ListCheckpointsReturn = runtime
+            .call::<Actor>(
+                Method::ListCheckpoints as u64,
+                &cbor::serialize(
+                    &ListParams {
+                        cursor: 0,
+                        limit: 10,
+                    },
+                    "test",
+                )
+                .unwrap(),
+            )
+            .unwrap()
+            .deserialize()
+            .unwrap();
+        assert_eq!(ret.checkpoints, vec![checkpoint.clone()]);
+        assert_eq!(ret.next_cursor, None);
+        assert_eq!(ret.total, 1);
+
+        // checkpoints are found by the range of epochs they were committed at
+        let mut list_range = |from, to| -> ListCheckpointsReturn {
+            runtime.expect_validate_caller_any();
+            runtime
+                .call::<Actor>(
+                    Method::ListCheckpointsInRange as u64,
+                    &cbor::serialize(&CheckpointRangeParams { from, to }, "test").unwrap(),
+                )
+                .unwrap()
+                .deserialize()
+                .unwrap()
+        };
+        assert_eq!(list_range(5, 10).checkpoints, vec![checkpoint.clone()]);
+        assert_eq!(list_range(10, 10).checkpoints, vec![checkpoint]);
+        assert_eq!(list_range(0, 9).total, 0);
+        assert_eq!(list_range(11, 20).total, 0);
+        assert_eq!(list_range(10, 5).total, 0);
+    }
+
+    #[test]
+    fn test_state_commitment() {
+        let test_actor_address = Address::new_id(9999);
+        let mut runtime = construct_runtime_with_receiver(test_actor_address.clone());
+        let validators = [
+            Address::new_id(10),
+            Address::new_id(11),
+            Address::new_id(12),
+        ];
+        join_validators(&mut runtime, &validators);
+
+        let get_commitment = |runtime: &mut MockRuntime| -> Option<StateCommitment> {
+            runtime.expect_validate_caller_any();
+            runtime
+                .call::<Actor>(Method::GetLastStateCommitment as u64, &RawBytes::default())
+                .unwrap()
+                .deserialize()
+                .unwrap()
+        };
+        assert_eq!(get_commitment(&mut runtime), None);
+
+        let subnet = SubnetID::new(&SubnetID::from_str("/root").unwrap(), test_actor_address);
+        let signature = RawBytes::serialize(Signature::new_secp256k1(vec![1, 2, 3, 4]))
+            .unwrap()
+            .bytes()
+            .to_vec();
+
+        // the claimed state can't be ahead of the window
+        let mut checkpoint = Checkpoint::new(subnet.clone(), 10);
+        checkpoint.set_signature(signature.clone());
+        let mut vote = submission(&runtime, &checkpoint);
+        vote.state_commitment.height = 11;
+        runtime.set_caller(Cid::default(), validators[0]);
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            SubnetActorError::InvalidStateCommitment.exit_code(),
+            runtime.call::<Actor>(
+                Method::SubmitCheckpoint as u64,
+                &cbor::serialize(&vote, "test").unwrap(),
+            ),
+        );
+
+        // the state claimed by a committed checkpoint is exposed, and the
+        // checkpoint relayed to the gateway is left as it was submitted
+        vote.state_commitment.height = 8;
+        send_submission(&mut runtime, validators[0], &vote, false).unwrap();
+        send_submission(&mut runtime, validators[1], &vote, true).unwrap();
+        let commitment = get_commitment(&mut runtime).unwrap();
+        assert_eq!(commitment.state_root, Cid::default());
+        assert_eq!(commitment.height, 8);
+        let st: State = runtime.get_state();
+        assert_eq!(
+            st.get_checkpoint_by_window(runtime.store(), 0).unwrap(),
+            Some(checkpoint.clone())
+        );
+
+        // the claimed state can't go back in the history of the subnet
+        let mut next = Checkpoint::new(subnet, 20);
+        next.data.prev_check = TCid::from(checkpoint.cid());
+        next.set_signature(signature);
+        let mut vote = submission(&runtime, &next);
+        vote.state_commitment.height = 5;
+        runtime.set_caller(Cid::default(), validators[0]);
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            SubnetActorError::InvalidStateCommitment.exit_code(),
+            runtime.call::<Actor>(
+                Method::SubmitCheckpoint as u64,
+                &cbor::serialize(&vote, "test").unwrap(),
+            ),
+        );
+    }
+
+    #[test]
+    fn test_challenge_checkpoint() {
+        let test_actor_address = Address::new_id(9999);
+        let mut params = std_construct_param();
+        params.dispute_period = 5;
+        let mut runtime = construct_runtime_with_params(test_actor_address, params);
+        let miners = vec![
+            Address::new_id(10),
+            Address::new_id(20),
+            Address::new_id(30),
+        ];
+        join_validators(&mut runtime, &miners);
+
+        let subnet = SubnetID::new(&SubnetID::from_str("/root").unwrap(), test_actor_address);
+        let sig = Signature::new_secp256k1(vec![1, 2, 3, 4]);
+        let mut checkpoint = Checkpoint::new(subnet.clone(), 10);
+        checkpoint.set_signature(RawBytes::serialize(sig.clone()).unwrap().bytes().to_vec());
+
+        // committed checkpoints are not relayed during the dispute period
+        send_checkpoint(&mut runtime, miners[0], &checkpoint, false).unwrap();
+        send_checkpoint(&mut runtime, miners[1], &checkpoint, false).unwrap();
+        let st: State = runtime.get_state();
+        assert_eq!(st.last_committed_epoch, 10);
+        assert_eq!(st.pending_checkpoints.len(), 1);
+        runtime.expect_validate_caller_any();
+        runtime
+            .call::<Actor>(Method::RelayCheckpoints as u64, &RawBytes::default())
+            .unwrap();
+        runtime.verify();
+
+        // a majority signing the same checkpoint with a conflicting state
+        // commitment reverts the commit
+        let mut conflicting = submission(&runtime, &Checkpoint::new(subnet.clone(), 10));
+        conflicting.state_commitment.height = 9;
+        let params = ChallengeCheckpointParams {
+            submission: conflicting.clone(),
+            signatures: vec![
+                ValidatorSignature {
+                    validator: miners[0],
+                    signature: sig.clone(),
+                },
+                ValidatorSignature {
+                    validator: miners[2],
+                    signature: sig.clone(),
+                },
+            ],
+        };
+        runtime.expect_validate_caller_any();
+        expect_signature(&mut runtime, miners[0], &conflicting);
+        expect_signature(&mut runtime, miners[2], &conflicting);
+        runtime
+            .call::<Actor>(
+                Method::ChallengeCheckpoint as u64,
+                &cbor::serialize(&params, "test").unwrap(),
+            )
+            .unwrap();
+        runtime.verify();
+
+        // the validator that signed both checkpoints is slashed
+        let st: State = runtime.get_state();
+        assert_eq!(st.last_committed_epoch, 0);
+        assert!(st.pending_checkpoints.is_empty());
+        assert_eq!(st.checkpoints_count, 0);
+        assert!(!st.is_validator(runtime.store(), &miners[0]).unwrap());
+        assert_eq!(
+            st.get_stake(runtime.store(), &miners[0]).unwrap(),
+            Some(TokenAmount::zero())
+        );
+        assert_eq!(
+            st.penalized_stake,
+            TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT)
+        );
+
+        // the window is committed again and relayed once the period ends
+        send_checkpoint(&mut runtime, miners[1], &checkpoint, false).unwrap();
+        send_checkpoint(&mut runtime, miners[2], &checkpoint, false).unwrap();
+        runtime.set_epoch(5);
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            SubnetActorError::NotDisputable(10).exit_code(),
+            runtime.call::<Actor>(
+                Method::ChallengeCheckpoint as u64,
+                &cbor::serialize(&params, "test").unwrap(),
+            ),
+        );
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::CommitChildCheckpoint as u64,
+            RawBytes::serialize(&checkpoint).unwrap(),
+            TokenAmount::zero(),
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        runtime
+            .call::<Actor>(Method::RelayCheckpoints as u64, &RawBytes::default())
+            .unwrap();
+        runtime.verify();
+        let st: State = runtime.get_state();
+        assert!(st.pending_checkpoints.is_empty());
+    }
+
+    #[test]
+    fn test_submit_evidence() {
+        let test_actor_address = Address::new_id(9999);
+        let mut params = std_construct_param();
+        params.consensus = ConsensusType::Mir;
+        let mut runtime = construct_runtime_with_params(test_actor_address, params);
+        let gateway = Address::new_id(IPC_GATEWAY_ADDR);
+        let miners = vec![
+            Address::new_id(10),
+            Address::new_id(20),
+            Address::new_id(30),
+        ];
+        join_validators(&mut runtime, &miners);
+        let stake = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+
+        // the stake delegated to the validator is slashed along with its own
+        let delegator = Address::new_id(50);
+        runtime.set_caller(Cid::default(), delegator);
+        runtime.set_value(stake.clone());
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            gateway,
+            ipc_gateway::Method::AddStake as u64,
+            RawBytes::default(),
+            stake.clone(),
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        runtime
+            .call::<Actor>(
+                Method::Delegate as u64,
+                &RawBytes::serialize(DelegateParams {
+                    validator: miners[0],
+                })
+                .unwrap(),
+            )
+            .unwrap();
+        runtime.set_value(TokenAmount::zero());
+
+        let subnet = SubnetID::new(&SubnetID::from_str("/root").unwrap(), test_actor_address);
+        let sig = Signature::new_secp256k1(vec![1, 2, 3, 4]);
+        let vote = |block_hash: Vec<u8>| SignedVote {
+            vote: Vote {
+                subnet: subnet.clone(),
+                chain_id: 0,
+                height: 7,
+                round: 0,
+                block_hash,
+            },
+            signature: sig.clone(),
+        };
+        let submit = |runtime: &mut MockRuntime, evidence: &DoubleSignEvidence| {
+            let params = SubmitEvidenceParams {
+                validator: miners[0],
+                evidence: RawBytes::serialize(evidence).unwrap(),
+            };
+            runtime.call::<Actor>(
+                Method::SubmitEvidence as u64,
+                &cbor::serialize(&params, "test").unwrap(),
+            )
+        };
+        let expect_signatures = |runtime: &mut MockRuntime, evidence: &DoubleSignEvidence| {
+            for v in [&evidence.first, &evidence.second] {
+                runtime.expect_send(
+                    miners[0],
+                    ext::account::PUBKEY_ADDRESS_METHOD as u64,
+                    RawBytes::default(),
+                    TokenAmount::zero(),
+                    cbor::serialize(&miners[0], "test").unwrap(),
+                    ExitCode::new(0),
+                );
+                runtime.expect_verify_signature(ExpectedVerifySig {
+                    sig: sig.clone(),
+                    signer: miners[0],
+                    plaintext: RawBytes::serialize(&v.vote).unwrap().bytes().to_vec(),
+                    result: Ok(()),
+                });
+            }
+        };
+
+        // votes for the same block are not a double sign
+        let evidence = DoubleSignEvidence {
+            first: vote(vec![1]),
+            second: vote(vec![1]),
+        };
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            submit(&mut runtime, &evidence),
+        );
+
+        // votes in another subnet, or another chain, are not evidence here
+        let mut evidence = DoubleSignEvidence {
+            first: vote(vec![1]),
+            second: vote(vec![2]),
+        };
+        evidence.first.vote.subnet =
+            SubnetID::new(&SubnetID::from_str("/root").unwrap(), Address::new_id(1));
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            submit(&mut runtime, &evidence),
+        );
+        evidence.first = vote(vec![1]);
+        evidence.second.vote.chain_id = 1;
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            submit(&mut runtime, &evidence),
+        );
+
+        // evidence expires past the unbonding period
+        let evidence = DoubleSignEvidence {
+            first: vote(vec![1]),
+            second: vote(vec![2]),
+        };
+        let mut st: State = runtime.get_state();
+        st.last_committed_epoch = 8 + EVIDENCE_MAX_AGE_WINDOWS * st.check_period;
+        runtime.replace_state(&st);
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            SubnetActorError::EvidenceTooOld(7).exit_code(),
+            submit(&mut runtime, &evidence),
+        );
+        st.last_committed_epoch = 0;
+        runtime.replace_state(&st);
+
+        // both votes need to be signed by the validator
+        runtime.expect_validate_caller_any();
+        expect_signatures(&mut runtime, &evidence);
+        submit(&mut runtime, &evidence).unwrap();
+        runtime.verify();
+
+        let st: State = runtime.get_state();
+        assert!(!st.is_validator(runtime.store(), &miners[0]).unwrap());
+        assert_eq!(
+            st.get_stake(runtime.store(), &miners[0]).unwrap(),
+            Some(TokenAmount::zero())
+        );
+        assert_eq!(st.penalized_stake, &stake * 2);
+        assert_eq!(
+            st.get_delegations(runtime.store(), &miners[0]).unwrap(),
+            None
+        );
+        assert!(st
+            .get_funding(runtime.store(), &miners[0])
+            .unwrap()
+            .is_empty());
+
+        // the evidence can't be used again, not even once the validator
+        // joins back
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            SubnetActorError::NotValidator(miners[0]).exit_code(),
+            submit(&mut runtime, &evidence),
+        );
+        runtime.set_caller(Cid::default(), miners[0]);
+        runtime.set_value(stake.clone());
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            gateway,
+            ipc_gateway::Method::AddStake as u64,
+            RawBytes::default(),
+            stake.clone(),
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        runtime
+            .call::<Actor>(
+                Method::Join as u64,
+                &cbor::serialize(
+                    &JoinParams {
+                        validator_net_addr: net_addr(&miners[0]),
+                        invitation: None,
+                        vesting: None,
+                        worker: None,
+                    },
+                    "test",
+                )
+                .unwrap(),
+            )
+            .unwrap();
+        runtime.set_value(TokenAmount::zero());
+        runtime.expect_validate_caller_any();
+        expect_signatures(&mut runtime, &evidence);
+        expect_abort(
+            SubnetActorError::EvidenceAlreadyUsed.exit_code(),
+            submit(&mut runtime, &evidence),
+        );
+        runtime.verify();
+
+        // consensuses without a verifier don't accept evidence
+        let mut runtime = construct_runtime();
+        join_validators(&mut runtime, &miners);
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            SubnetActorError::UnsupportedEvidence(ConsensusType::Dummy).exit_code(),
+            submit(&mut runtime, &evidence),
+        );
+    }
+
+    #[test]
+    fn test_finality_threshold() {
+        let test_actor_address = Address::new_id(9999);
+        let mut params = std_construct_param();
+        params.finality_threshold = 20;
+        let mut runtime = construct_runtime_with_params(test_actor_address, params);
+        let validator = Address::new_id(10);
+        join_validators(&mut runtime, &[validator]);
+
+        runtime.expect_validate_caller_any();
+        let config: FinalityConfig = runtime
+            .call::<Actor>(Method::GetFinalityConfig as u64, &RawBytes::default())
+            .unwrap()
+            .deserialize()
+            .unwrap();
+        assert_eq!(
+            config,
+            FinalityConfig {
+                finality_threshold: 20,
+                dispute_period: 0,
+            }
+        );
+
+        // the checkpoint is committed but not relayed before its epoch is final
+        let subnet = SubnetID::new(&SubnetID::from_str("/root").unwrap(), test_actor_address);
+        let checkpoint = Checkpoint::new(subnet, DEFAULT_CHECKPOINT_PERIOD);
+        runtime.set_epoch(DEFAULT_CHECKPOINT_PERIOD + 1);
+        send_checkpoint(&mut runtime, validator, &checkpoint, false).unwrap();
+        let st: State = runtime.get_state();
+        assert_eq!(st.last_committed_epoch, DEFAULT_CHECKPOINT_PERIOD);
+        assert_eq!(
+            st.pending_checkpoints[0].relay_at,
+            DEFAULT_CHECKPOINT_PERIOD + 20
+        );
+
+        runtime.set_epoch(DEFAULT_CHECKPOINT_PERIOD + 19);
+        runtime.expect_validate_caller_any();
+        runtime
+            .call::<Actor>(Method::RelayCheckpoints as u64, &RawBytes::default())
+            .unwrap();
+        runtime.verify();
+
+        runtime.set_epoch(DEFAULT_CHECKPOINT_PERIOD + 20);
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::CommitChildCheckpoint as u64,
+            RawBytes::serialize(&checkpoint).unwrap(),
+            TokenAmount::zero(),
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        runtime
+            .call::<Actor>(Method::RelayCheckpoints as u64, &RawBytes::default())
+            .unwrap();
+        runtime.verify();
+        let st: State = runtime.get_state();
+        assert!(st.pending_checkpoints.is_empty());
+    }
+
+    #[test]
+    fn test_rotate_validator_key() {
+        let test_actor_address = Address::new_id(9999);
+        let mut runtime = construct_runtime_with_receiver(test_actor_address);
+        let miners = vec![
+            Address::new_id(10),
+            Address::new_id(20),
+            Address::new_id(30),
+        ];
+        join_validators(&mut runtime, &miners);
+
+        let key = Address::new_secp256k1(&[1; 65]).unwrap();
+        let key_sig = Signature::new_secp256k1(vec![5, 6, 7, 8]);
+        let rotate = |runtime: &mut MockRuntime, caller, key, nonce, valid: bool| {
+            runtime.set_caller(Cid::default(), caller);
+            runtime.expect_validate_caller_any();
+            let st: State = runtime.get_state();
+            runtime.expect_verify_signature(ExpectedVerifySig {
+                sig: key_sig.clone(),
+                signer: key,
+                plaintext: key_rotation_payload(&caller, st.config_number).unwrap(),
+                result: if valid {
+                    Ok(())
+                } else {
+                    Err(anyhow::anyhow!("invalid signature"))
+                },
+            });
+            let params = RotateValidatorKeyParams {
+                key,
+                nonce,
+                signature: key_sig.clone(),
+            };
+            runtime.call::<Actor>(
+                Method::RotateValidatorKey as u64,
+                &cbor::serialize(&params, "test").unwrap(),
+            )
+        };
+        runtime.set_caller(Cid::default(), miners[0]);
+        runtime.expect_validate_caller_any();
+        let params = RotateValidatorKeyParams {
+            key: Address::new_id(40),
+            nonce: 0,
+            signature: key_sig.clone(),
+        };
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            runtime.call::<Actor>(
+                Method::RotateValidatorKey as u64,
+                &cbor::serialize(&params, "test").unwrap(),
+            ),
+        );
+        // the new key needs to sign the rotation
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            rotate(&mut runtime, miners[0], key, 0, false),
+        );
+        expect_abort(
+            SubnetActorError::NotValidator(Address::new_id(40)).exit_code(),
+            rotate(&mut runtime, Address::new_id(40), key, 0, true),
+        );
+        expect_abort(
+            SubnetActorError::InvalidKeyNonce(0).exit_code(),
+            rotate(&mut runtime, miners[0], key, 1, true),
+        );
+        rotate(&mut runtime, miners[0], key, 0, true).unwrap();
+
+        let st: State = runtime.get_state();
+        let rotated = st
+            .get_validator_key(runtime.store(), &miners[0])
+            .unwrap()
+            .unwrap();
+        assert_eq!(rotated.effective_at, 10);
+        assert_eq!(rotated.nonce, 1);
+
+        // checkpoints from the next window are verified with the new key
+        let subnet = SubnetID::new(&SubnetID::from_str("/root").unwrap(), test_actor_address);
+        let sig = Signature::new_secp256k1(vec![1, 2, 3, 4]);
+        let mut checkpoint = Checkpoint::new(subnet, 10);
+        checkpoint.set_signature(RawBytes::serialize(sig.clone()).unwrap().bytes().to_vec());
+        let vote = submission(&runtime, &checkpoint);
+        runtime.set_caller(Cid::default(), miners[0]);
+        runtime.expect_validate_caller_any();
+        runtime.expect_verify_signature(ExpectedVerifySig {
+            sig,
+            signer: key,
+            plaintext: vote.digest().unwrap().to_bytes(),
+            result: Ok(()),
+        });
+        runtime
+            .call::<Actor>(
+                Method::SubmitCheckpoint as u64,
+                &cbor::serialize(&vote, "test").unwrap(),
+            )
+            .unwrap();
+        runtime.verify();
+    }
+
+    #[test]
+    fn test_eth_wallet_signature() {
+        // 0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266, the first account of
+        // the Hardhat test mnemonic
+        let eth_addr = [
+            0xf3, 0x9f, 0xd6, 0xe5, 0x1a, 0xad, 0x88, 0xf6, 0xf4, 0xce, 0x6a, 0xb8, 0x82, 0x72,
+            0x79, 0xcf, 0xff, 0xb9, 0x22, 0x66,
+        ];
+        let signer = Address::new_delegated(eth::EAM_ACTOR_ID, &eth_addr).unwrap();
+        // personal_sign of "hello" by the account, with a recovery ID of 28
+        let sig = Signature::new_secp256k1(vec![
+            0xf1, 0x6e, 0xa9, 0xa3, 0x47, 0x86, 0x98, 0xf6, 0x95, 0xfd, 0x14, 0x01, 0xbf, 0xe2,
+            0x7e, 0x9e, 0x4a, 0x7e, 0x8e, 0x3d, 0xa9, 0x4a, 0xa7, 0x2b, 0x02, 0x11, 0x25, 0xe3,
+            0x1f, 0xa8, 0x99, 0xcc, 0x57, 0x3c, 0x48, 0xea, 0x3f, 0xe1, 0xd4, 0xab, 0x61, 0xa9,
+            0xdb, 0x10, 0xc1, 0x90, 0x32, 0x02, 0x6e, 0x3e, 0xd2, 0xdb, 0xcc, 0xba, 0x5a, 0x17,
+            0x82, 0x35, 0xac, 0x27, 0xf9, 0x45, 0x04, 0x31, 0x1c,
+        ]);
+
+        let runtime = construct_runtime();
+        eth::verify_signature(&runtime, &sig, &signer, b"hello").unwrap();
+        assert!(eth::verify_signature(&runtime, &sig, &signer, b"hello!").is_err());
+        let other = Address::new_delegated(eth::EAM_ACTOR_ID, &[0; 20]).unwrap();
+        assert!(eth::verify_signature(&runtime, &sig, &other, b"hello").is_err());
+    }
+
+    #[test]
+    fn test_eth_validator_key() {
+        // public key of the secp256k1 private key 1
+        let pubkey: [u8; 65] = [
+            0x04, 0x79, 0xbe, 0x66, 0x7e, 0xf9, 0xdc, 0xbb, 0xac, 0x55, 0xa0, 0x62, 0x95, 0xce,
+            0x87, 0x0b, 0x07, 0x02, 0x9b, 0xfc, 0xdb, 0x2d, 0xce, 0x28, 0xd9, 0x59, 0xf2, 0x81,
+            0x5b, 0x16, 0xf8, 0x17, 0x98, 0x48, 0x3a, 0xda, 0x77, 0x26, 0xa3, 0xc4, 0x65, 0x5d,
+            0xa4, 0xfb, 0xfc, 0x0e, 0x11, 0x08, 0xa8, 0xfd, 0x17, 0xb4, 0x48, 0xa6, 0x85, 0x54,
+            0x19, 0x9c, 0x47, 0xd0, 0x8f, 0xfb, 0x10, 0xd4, 0xb8,
+        ];
+        let eth_addr = eth::eth_address_from_pubkey(&pubkey);
+        // 0x7E5F4552091A69125d5DfCb7b8C2659029395Bdf
+        assert_eq!(
+            eth_addr,
+            [
+                0x7e, 0x5f, 0x45, 0x52, 0x09, 0x1a, 0x69, 0x12, 0x5d, 0x5d, 0xfc, 0xb7, 0xb8, 0xc2,
+                0x65, 0x90, 0x29, 0x39, 0x5b, 0xdf
+            ]
+        );
+
+        let key = Address::new_delegated(eth::EAM_ACTOR_ID, &eth_addr).unwrap();
+        assert_eq!(eth::eth_address(&key), Some(eth_addr));
+        assert!(!eth::is_eth_address(
+            &Address::new_delegated(32, &eth_addr).unwrap()
+        ));
+
+        let mut runtime = construct_runtime();
+        let miner = Address::new_id(10);
+        join_validators(&mut runtime, &[miner]);
+
+        // the runtime doesn't verify signatures by f410 addresses, the key is
+        // recovered from a signature by the private key 1
+        let mut secret = [0; 32];
+        secret[31] = 1;
+        let st: State = runtime.get_state();
+        let payload = key_rotation_payload(&miner, st.config_number).unwrap();
+        let (sig, recovery_id) = libsecp256k1::sign(
+            &libsecp256k1::Message::parse(&eth::eip191_hash(&payload)),
+            &libsecp256k1::SecretKey::parse(&secret).unwrap(),
+        );
+        let mut bytes = sig.serialize().to_vec();
+        bytes.push(recovery_id.serialize());
+        let signature = Signature::new_secp256k1(bytes);
+
+        runtime.set_caller(Cid::default(), miner);
+        runtime.expect_validate_caller_any();
+        let params = RotateValidatorKeyParams {
+            key,
+            nonce: 0,
+            signature,
+        };
+        runtime
+            .call::<Actor>(
+                Method::RotateValidatorKey as u64,
+                &cbor::serialize(&params, "test").unwrap(),
+            )
+            .unwrap();
+        let st: State = runtime.get_state();
+        let rotated = st
+            .get_validator_key(runtime.store(), &miner)
+            .unwrap()
+            .unwrap();
+        assert_eq!(rotated.key, key);
+    }
+
+    #[test]
+    #[cfg(feature = "testing")]
+    fn test_state_builder() {
+        use ipc_subnet_actor::testing::StateBuilder;
+
+        let validators = [Address::new_id(10), Address::new_id(20)];
+        let (store, st) = StateBuilder::new()
+            .with_validators(&validators)
+            .with_stake(Address::new_id(30), TokenAmount::from_atto(1))
+            .build();
+        assert_eq!(st.status, Status::Active);
+        assert_eq!(st.stakers_count, 3);
+        assert!(st.is_validator(&store, &validators[0]).unwrap());
+        assert!(!st.is_validator(&store, &Address::new_id(30)).unwrap());
+
+        let (shares, dust) = st.fee_shares(&store, &TokenAmount::from_atto(5)).unwrap();
+        assert_eq!(
+            shares,
+            vec![
+                (validators[0], TokenAmount::from_atto(2)),
+                (validators[1], TokenAmount::from_atto(2)),
+            ]
+        );
+        assert_eq!(dust, TokenAmount::from_atto(1));
+    }
+
+    #[test]
+    #[cfg(feature = "dump")]
+    fn test_state_dump() {
+        use ipc_subnet_actor::state;
+
+        let mut runtime = construct_runtime();
+        let validators = [Address::new_id(10), Address::new_id(20)];
+        join_validators(&mut runtime, &validators);
+
+        let st: State = runtime.get_state();
+        let dump = state::dump(&st, runtime.store()).unwrap();
+        let stake = MIN_COLLATERAL_AMOUNT.to_string();
+        assert_eq!(dump["status"], "Active");
+        assert_eq!(dump["total_stake"], (MIN_COLLATERAL_AMOUNT * 2).to_string());
+        assert_eq!(dump["stake"][validators[0].to_string()], stake);
+        assert_eq!(dump["stake"][validators[1].to_string()], stake);
+        assert_eq!(dump["validators"][1]["addr"], validators[1].to_string());
+        assert_eq!(dump["checkpoints"].as_array().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_vesting() {
+        let mut runtime = construct_runtime();
+        let caller = Address::new_id(10);
+        let amount = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT * 2);
+        let params = JoinParams {
+            validator_net_addr: net_addr(&caller),
+            invitation: None,
+            vesting: Some(VestingSchedule {
+                cliff: 10,
+                period: 10,
+                duration: 40,
+            }),
+            worker: None,
+        };
+        runtime.set_value(amount.clone());
+        runtime.set_caller(Cid::default(), caller);
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::Register as u64,
+            RawBytes::default(),
+            amount.clone(),
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        runtime
+            .call::<Actor>(
+                Method::Join as u64,
+                &cbor::serialize(&params, "test").unwrap(),
+            )
+            .unwrap();
+        runtime.verify();
+
+        // validators can't leave while their stake vests
+        runtime.set_epoch(5);
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            SubnetActorError::StakeStillVesting(caller).exit_code(),
+            runtime.call::<Actor>(Method::Leave as u64, &RawBytes::default()),
+        );
+        runtime.set_epoch(25);
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            SubnetActorError::StakeStillVesting(caller).exit_code(),
+            runtime.call::<Actor>(Method::Leave as u64, &RawBytes::default()),
+        );
+
+        // only the vested stake is released
+        let release = |runtime: &mut MockRuntime, method: Method, params: RawBytes, value| {
+            runtime.expect_validate_caller_any();
+            expect_release(runtime, caller, &value);
+            let ret: LeaveReturn = runtime
+                .call::<Actor>(method as u64, &params)
+                .unwrap()
+                .deserialize()
+                .unwrap();
+            runtime.verify();
+            ret
+        };
+        let half = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+        let withdraw = RawBytes::serialize(WithdrawStakeParams {
+            amount: half.clone(),
+        })
+        .unwrap();
+        expect_abort(SubnetActorError::NotEnoughStake(caller).exit_code(), {
+            runtime.expect_validate_caller_any();
+            runtime.call::<Actor>(
+                Method::WithdrawStake as u64,
+                &RawBytes::serialize(WithdrawStakeParams {
+                    amount: &half + TokenAmount::from_atto(1),
+                })
+                .unwrap(),
+            )
+        });
+        let ret = release(&mut runtime, Method::WithdrawStake, withdraw, half.clone());
+        assert_eq!(ret.refunded, half);
+        assert_eq!(ret.remaining_stake, half);
+        let st: State = runtime.get_state();
+        assert!(st.is_validator(runtime.store(), &caller).unwrap());
+
+        // and the validator leaves once it is fully vested
+        runtime.set_epoch(40);
+        let ret = release(&mut runtime, Method::Leave, RawBytes::default(), half);
+        assert_eq!(ret.remaining_stake, TokenAmount::zero());
+        let st: State = runtime.get_state();
+        assert!(!st.is_validator(runtime.store(), &caller).unwrap());
+        assert_eq!(st.get_vesting(runtime.store(), &caller).unwrap(), None);
+    }
+
+    #[test]
+    fn test_delegated_address() {
+        let get_delegated_address = |runtime: &mut MockRuntime| -> Option<Address> {
+            runtime.expect_validate_caller_any();
+            runtime
+                .call::<Actor>(Method::GetDelegatedAddress as u64, &RawBytes::default())
+                .unwrap()
+                .deserialize()
+                .unwrap()
+        };
+        let mut runtime = construct_runtime();
+        assert_eq!(get_delegated_address(&mut runtime), None);
+
+        // the address deploying through Exec4 is known beforehand
+        let mut params = std_construct_param();
+        params.delegated_namespace = Some(1000);
+        let expected = params.delegated_address().unwrap().unwrap();
+        match expected.payload() {
+            Payload::Delegated(d) => {
+                assert_eq!(d.namespace(), 1000);
+                assert_eq!(d.subaddress(), params.subaddress().unwrap());
+            }
+            _ => panic!("not a delegated address: {}", expected),
+        }
+        let exec4 = params.exec4_params(Cid::default()).unwrap();
+        assert_eq!(exec4.subaddress.bytes(), params.subaddress().unwrap());
+        assert_eq!(
+            exec4
+                .constructor_params
+                .deserialize::<ConstructParams>()
+                .unwrap(),
+            params
+        );
+
+        // and unique to the subnet
+        let mut other = params.clone();
+        other.name = "other".to_string();
+        assert_ne!(other.delegated_address().unwrap(), Some(expected));
+
+        let mut runtime = construct_runtime_with_params(Address::new_id(1), params);
+        assert_eq!(get_delegated_address(&mut runtime), Some(expected));
+    }
+
+    #[test]
+    fn test_get_power_table() {
+        let mut runtime = construct_runtime();
+        let validators = vec![Address::new_id(10), Address::new_id(20)];
+        join_validators(&mut runtime, &validators);
+
+        // the second validator doubles its stake
+        let value = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+        runtime.set_value(value.clone());
+        runtime.set_caller(Cid::default(), validators[1]);
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::AddStake as u64,
+            RawBytes::default(),
+            value,
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        runtime
+            .call::<Actor>(Method::AddStake as u64, &RawBytes::default())
+            .unwrap();
+
+        runtime.expect_validate_caller_any();
+        let table: PowerTable = runtime
+            .call::<Actor>(Method::GetPowerTable as u64, &RawBytes::default())
+            .unwrap()
+            .deserialize()
+            .unwrap();
+        let third = (MAX_TOTAL_VOTING_POWER - 2) / 3;
+        assert_eq!(
+            table.validators,
+            vec![
+                PowerTableEntry {
+                    addr: validators[1],
+                    net_addr: net_addr(&validators[1]).to_string(),
+                    power: third * 2 + ((MAX_TOTAL_VOTING_POWER - 2) % 3 * 2) / 3,
+                    collateral: TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT) * 2,
+                },
+                PowerTableEntry {
+                    addr: validators[0],
+                    net_addr: net_addr(&validators[0]).to_string(),
+                    power: third,
+                    collateral: TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT),
+                },
+            ]
+        );
+        assert_eq!(
+            table.total_power,
+            table.validators.iter().map(|v| v.power).sum::<u64>()
+        );
+        assert!(table.total_power <= MAX_TOTAL_VOTING_POWER);
+
+        // the validator set is ordered by stake rather than by join order
+        let st: State = runtime.get_state();
+        let order = st.validators.addrs().copied().collect::<Vec<_>>();
+        assert_eq!(order, vec![validators[1], validators[0]]);
+
+        // the rotation seed changes with every committed checkpoint
+        assert_eq!(table.window, st.next_checkpoint_epoch());
+        assert!(table.rotation_seed.is_empty());
+        let subnet = SubnetID::new(&SubnetID::from_str("/root").unwrap(), Address::new_id(1));
+        let mut checkpoint = Checkpoint::new(subnet, table.window);
+        let sig = Signature::new_secp256k1(vec![1, 2, 3, 4]);
+        checkpoint.set_signature(RawBytes::serialize(sig).unwrap().bytes().to_vec());
+        send_checkpoint(&mut runtime, validators[1], &checkpoint, true).unwrap();
+
+        runtime.expect_validate_caller_any();
+        let table: PowerTable = runtime
+            .call::<Actor>(Method::GetPowerTable as u64, &RawBytes::default())
+            .unwrap()
+            .deserialize()
+            .unwrap();
+        assert_eq!(table.window, checkpoint.epoch() + st.check_period);
+        assert_eq!(table.rotation_seed, checkpoint.cid().hash().digest());
+    }
+
+    #[test]
+    fn test_min_validator_stake_and_total_collateral() {
+        let construct = |min_validator_stake: TokenAmount, min_total_collateral: TokenAmount| {
+            let mut params = std_construct_param();
+            params.min_validator_stake = min_validator_stake;
+            params.min_total_collateral = min_total_collateral;
+            let mut runtime = MockRuntime::new(Address::new_id(1), *INIT_ACTOR_ADDR);
+            runtime.expect_validate_caller_addr(vec![*INIT_ACTOR_ADDR]);
+            if params.validate().is_ok() {
+                expect_network_name(&mut runtime, IPC_GATEWAY_ADDR, &params.parent, ExitCode::OK);
+            }
+            let ret = runtime.call::<Actor>(
+                Method::Constructor as u64,
+                &cbor::serialize(&params, "test").unwrap(),
+            );
+            ret.map(|_| runtime)
+        };
+        let join = |runtime: &mut MockRuntime, caller: Address, amount: TokenAmount| {
+            let params = JoinParams {
+                validator_net_addr: net_addr(&caller),
+                invitation: None,
+                vesting: None,
+                worker: None,
+            };
+            runtime.set_value(amount);
+            runtime.set_caller(Cid::default(), caller);
+            runtime.expect_validate_caller_any();
+            runtime
+                .call::<Actor>(
+                    Method::Join as u64,
+                    &cbor::serialize(&params, "test").unwrap(),
+                )
+                .unwrap();
+        };
+        let min = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+
+        assert_eq!(
+            construct(TokenAmount::zero(), &min - TokenAmount::from_atto(1))
+                .err()
+                .map(|e| e.exit_code()),
+            Some(ExitCode::USR_ILLEGAL_ARGUMENT)
+        );
+
+        // validators can stake less than the collateral activating the
+        // subnet, which is only reached by their total stake
+        let mut runtime = construct(min.div_floor(2), min.clone()).unwrap();
+        join(&mut runtime, Address::new_id(10), min.div_floor(2));
+        let st: State = runtime.get_state();
+        assert_eq!(
+            st.validators.addrs().copied().collect::<Vec<_>>(),
+            vec![Address::new_id(10)]
+        );
+        assert_eq!(st.status, Status::Instantiated);
+
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::Register as u64,
+            RawBytes::default(),
+            min.clone(),
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        join(&mut runtime, Address::new_id(11), min.div_floor(2));
+        let st: State = runtime.get_state();
+        assert_eq!(st.validators.len(), 2);
+        assert_eq!(st.status, Status::Active);
+
+        // stake below the validator minimum doesn't make a validator
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::AddStake as u64,
+            RawBytes::default(),
+            min.div_floor(4),
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        join(&mut runtime, Address::new_id(12), min.div_floor(4));
+        let st: State = runtime.get_state();
+        assert_eq!(st.validators.len(), 2);
+        let table = st.power_table(runtime.store()).unwrap();
+        assert_eq!(table.validators.len(), 2);
+
+        // the validator minimum defaults to the minimum of the gateway
+        let runtime = construct(TokenAmount::zero(), TokenAmount::zero()).unwrap();
+        let st: State = runtime.get_state();
+        assert_eq!(st.econ.min_validator_stake, min);
+        assert_eq!(st.econ.min_total_collateral, min);
+    }
+
+    #[test]
+    fn test_min_join_amount_and_compact_stake() {
+        let mut params = std_construct_param();
+        params.min_join_amount = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+        let mut runtime = construct_runtime_with_params(Address::new_id(1), params);
+
+        let caller = Address::new_id(10);
+        let join_params = JoinParams {
+            validator_net_addr: net_addr(&caller),
+            invitation: None,
+            vesting: None,
+            worker: None,
+        };
+        let dust = TokenAmount::from_atto(1);
+        runtime.set_value(dust.clone());
+        runtime.set_caller(Cid::default(), caller);
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            SubnetActorError::JoinAmountTooLow.exit_code(),
+            runtime.call::<Actor>(
+                Method::Join as u64,
+                &cbor::serialize(&join_params, "test").unwrap(),
+            ),
+        );
+
+        // the validators lower the minimum
+        let validator = Address::new_id(20);
+        join_validators(&mut runtime, &[validator]);
+        let st: State = runtime.get_state();
+        runtime.set_caller(Cid::default(), validator);
+        runtime.expect_validate_caller_any();
+        runtime
+            .call::<Actor>(
+                Method::ProposeEconParams as u64,
+                &RawBytes::serialize(EconParams {
+                    min_join_amount: dust.clone(),
+                    ..st.econ
+                })
+                .unwrap(),
+            )
+            .unwrap();
+
+        runtime.set_value(dust.clone());
+        runtime.set_caller(Cid::default(), caller);
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::AddStake as u64,
+            RawBytes::default(),
+            dust.clone(),
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        runtime
+            .call::<Actor>(
+                Method::Join as u64,
+                &cbor::serialize(&join_params, "test").unwrap(),
+            )
+            .unwrap();
+        runtime.expect_validate_caller_any();
+        expect_release(&mut runtime, caller, &dust);
+        runtime
+            .call::<Actor>(Method::Leave as u64, &RawBytes::default())
+            .unwrap();
+        runtime.verify();
+
+        // the entry left without stake is compacted
+        let st: State = runtime.get_state();
+        assert_eq!(
+            st.get_stake(runtime.store(), &caller).unwrap(),
+            Some(TokenAmount::zero())
+        );
+        let compact = |runtime: &mut MockRuntime, cursor, limit| -> CompactStakeReturn {
+            runtime.expect_validate_caller_any();
+            runtime
+                .call::<Actor>(
+                    Method::CompactStake as u64,
+                    &RawBytes::serialize(ListParams { cursor, limit }).unwrap(),
+                )
+                .unwrap()
+                .deserialize()
+                .unwrap()
+        };
+        // empty pages leave the table untouched
+        assert_eq!(
+            compact(&mut runtime, 0, 0),
+            CompactStakeReturn {
+                removed: 0,
+                next_cursor: Some(0),
+            }
+        );
+        assert_eq!(
+            compact(&mut runtime, 0, 10),
+            CompactStakeReturn {
+                removed: 1,
+                next_cursor: None,
+            }
+        );
+        let st: State = runtime.get_state();
+        assert_eq!(st.get_stake(runtime.store(), &caller).unwrap(), None);
+    }
+
+    #[test]
+    fn test_submit_parent_finality() {
+        let mut params = std_construct_param();
+        params.finality_threshold = 10;
+        let mut runtime = construct_runtime_with_params(Address::new_id(1), params);
+        let validators = vec![
+            Address::new_id(10),
+            Address::new_id(20),
+            Address::new_id(30),
+        ];
+        join_validators(&mut runtime, &validators);
+        runtime.set_epoch(100);
+
+        let submit = |runtime: &mut MockRuntime, caller, height| {
+            let finality = ParentFinality {
+                height,
+                block_hash: vec![1, 2, 3],
+            };
+            runtime.set_caller(Cid::default(), caller);
+            runtime.expect_validate_caller_any();
+            runtime.call::<Actor>(
+                Method::SubmitParentFinality as u64,
+                &cbor::serialize(&finality, "test").unwrap(),
+            )
+        };
+        let get = |runtime: &mut MockRuntime| -> Option<ParentFinality> {
+            runtime.expect_validate_caller_any();
+            runtime
+                .call::<Actor>(Method::GetParentFinality as u64, &RawBytes::default())
+                .unwrap()
+                .deserialize()
+                .unwrap()
+        };
+
+        expect_abort(
+            SubnetActorError::NotValidator(Address::new_id(40)).exit_code(),
+            submit(&mut runtime, Address::new_id(40), 80),
+        );
+        // heights within the finality threshold are not final yet
+        expect_abort(
+            SubnetActorError::InvalidParentFinality(95).exit_code(),
+            submit(&mut runtime, validators[0], 95),
+        );
+
+        submit(&mut runtime, validators[0], 80).unwrap();
+        assert_eq!(get(&mut runtime), None);
+        expect_abort(
+            SubnetActorError::AlreadyVoted(validators[0]).exit_code(),
+            submit(&mut runtime, validators[0], 80),
+        );
+        submit(&mut runtime, validators[1], 80).unwrap();
+        assert_eq!(
+            get(&mut runtime),
+            Some(ParentFinality {
+                height: 80,
+                block_hash: vec![1, 2, 3],
+            })
+        );
+
+        // the committed finality can't go back
+        expect_abort(
+            SubnetActorError::InvalidParentFinality(80).exit_code(),
+            submit(&mut runtime, validators[2], 80),
+        );
+    }
+
+    #[test]
+    fn test_set_metadata() {
+        let owner = Address::new_id(5);
+        let mut params = std_construct_param();
+        params.owner = Some(owner);
+        params.chain_id = 1;
+        params.description = "test subnet".to_string();
+        let mut runtime = construct_runtime_with_params(Address::new_id(1), params);
+
+        let st: State = runtime.get_state();
+        assert_eq!(st.chain_id, 1);
+        assert_eq!(st.description, "test subnet");
+        assert_eq!(st.metadata_url, None);
+
+        let metadata = SetMetadataParams {
+            chain_id: 2,
+            description: "updated subnet".to_string(),
+            metadata_url: Some("subnet.eth".to_string()),
+        };
+
+        // only the owner can update the metadata
+        runtime.set_caller(Cid::default(), Address::new_id(10));
+        runtime.expect_validate_caller_addr(vec![owner]);
+        expect_abort(
+            ExitCode::USR_FORBIDDEN,
+            runtime.call::<Actor>(
+                Method::SetMetadata as u64,
+                &cbor::serialize(&metadata, "test").unwrap(),
+            ),
+        );
+
+        runtime.set_caller(Cid::default(), owner);
+        runtime.expect_validate_caller_addr(vec![owner]);
+        runtime
+            .call::<Actor>(
+                Method::SetMetadata as u64,
+                &cbor::serialize(&metadata, "test").unwrap(),
+            )
+            .unwrap();
+
+        let st: State = runtime.get_state();
+        assert_eq!(st.chain_id, 2);
+        assert_eq!(st.description, "updated subnet");
+        assert_eq!(st.metadata_url, Some("subnet.eth".to_string()));
+    }
+
+    #[test]
+    fn test_max_validator_stake() {
+        let cap = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT * 2);
+        let mut params = std_construct_param();
+        params.max_validator_stake = cap.clone();
+        params.excess_stake_policy = ExcessStakePolicy::Refund;
+        let mut runtime = construct_runtime_with_params(Address::new_id(1), params);
+
+        // the collateral above the cap is refunded
+        let caller = Address::new_id(10);
+        let join_params = JoinParams {
+            validator_net_addr: net_addr(&caller),
+            invitation: None,
+            vesting: None,
+            worker: None,
+        };
+        let excess = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+        runtime.set_value(&cap + &excess);
+        runtime.set_caller(Cid::default(), caller);
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::Register as u64,
+            RawBytes::default(),
+            cap.clone(),
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        runtime.expect_send(
+            caller,
+            METHOD_SEND,
+            RawBytes::default(),
+            excess.clone(),
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        let ret: JoinReturn = runtime
+            .call::<Actor>(
+                Method::Join as u64,
+                &cbor::serialize(&join_params, "test").unwrap(),
+            )
+            .unwrap()
+            .deserialize()
+            .unwrap();
+        runtime.verify();
+        assert_eq!(ret.refunded, excess);
+        assert_eq!(ret.total_stake, cap);
+
+        // nothing can be added once at the cap
+        runtime.set_value(excess.clone());
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            SubnetActorError::StakeAtCap(caller).exit_code(),
+            runtime.call::<Actor>(Method::AddStake as u64, &RawBytes::default()),
+        );
+
+        // joins above the cap are rejected with the reject policy
+        let mut params = std_construct_param();
+        params.max_validator_stake = cap.clone();
+        let mut runtime = construct_runtime_with_params(Address::new_id(1), params);
+        runtime.set_value(&cap + &excess);
+        runtime.set_caller(Cid::default(), caller);
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            SubnetActorError::StakeCapExceeded(caller).exit_code(),
+            runtime.call::<Actor>(
+                Method::Join as u64,
+                &cbor::serialize(&join_params, "test").unwrap(),
+            ),
+        );
+    }
+
+    #[test]
+    fn test_delegate_failover() {
+        let mut params = std_construct_param();
+        params.consensus = ConsensusType::Delegated;
+        let mut runtime = construct_runtime_with_params(Address::new_id(1), params);
+
+        let validators = vec![
+            Address::new_id(10),
+            Address::new_id(20),
+            Address::new_id(30),
+        ];
+        join_validators(&mut runtime, &validators);
+        let st: State = runtime.get_state();
+        assert_eq!(
+            st.validators.addrs().copied().collect::<Vec<_>>(),
+            vec![validators[0]]
+        );
+        assert_eq!(
+            st.standby.iter().map(|v| v.addr).collect::<Vec<_>>(),
+            validators[1..].to_vec()
+        );
+        assert_eq!(st.last_failover, None);
+
+        // every time the delegate leaves the next staker on standby is promoted
+        let value = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+        for (i, caller) in validators.iter().enumerate() {
+            runtime.set_caller(Cid::default(), *caller);
+            runtime.expect_validate_caller_any();
+            expect_release(&mut runtime, *caller, &value);
+            runtime
+                .call::<Actor>(Method::Leave as u64, &RawBytes::default())
+                .unwrap();
+            runtime.verify();
+
+            let st: State = runtime.get_state();
+            let delegate = validators.get(i + 1).cloned();
+            assert_eq!(
+                st.validators.addrs().copied().collect::<Vec<_>>(),
+                delegate.into_iter().collect::<Vec<_>>()
+            );
+            assert_eq!(
+                st.last_failover,
+                Some(DelegateFailover {
+                    nonce: i as u64 + 1,
+                    prev: *caller,
+                    delegate,
+                })
+            );
+        }
+        let st: State = runtime.get_state();
+        assert!(st.standby.is_empty());
+    }
+
+    #[test]
+    fn test_transfer_ownership() {
+        let owner = Address::new_id(5);
+        let new_owner = Address::new_id(6);
+        let mut params = std_construct_param();
+        params.owner = Some(owner);
+        let mut runtime = construct_runtime_with_params(Address::new_id(1), params);
+
+        let transfer = |runtime: &mut MockRuntime, caller, nonce| {
+            runtime.set_caller(Cid::default(), caller);
+            runtime.expect_validate_caller_addr(vec![owner]);
+            runtime.call::<Actor>(
+                Method::TransferOwnership as u64,
+                &cbor::serialize(&TransferOwnershipParams { new_owner, nonce }, "test").unwrap(),
+            )
+        };
+        expect_abort(
+            ExitCode::USR_FORBIDDEN,
+            transfer(&mut runtime, new_owner, 0),
+        );
+        expect_abort(
+            SubnetActorError::InvalidOwnerNonce(0).exit_code(),
+            transfer(&mut runtime, owner, 1),
+        );
+        transfer(&mut runtime, owner, 0).unwrap();
+        runtime.verify();
+        let st: State = runtime.get_state();
+        assert_eq!(st.owner, Some(new_owner));
+        assert_eq!(st.owner_nonce, 1);
+
+        // the ownership can only be renounced once the subnet is bootstrapped
+        let renounce = |runtime: &mut MockRuntime, nonce: u64| {
+            runtime.set_caller(Cid::default(), new_owner);
+            runtime.expect_validate_caller_addr(vec![new_owner]);
+            runtime.call::<Actor>(
+                Method::RenounceOwnership as u64,
+                &cbor::serialize(&nonce, "test").unwrap(),
+            )
+        };
+        expect_abort(ExitCode::USR_ILLEGAL_STATE, renounce(&mut runtime, 1));
+        join_validators(&mut runtime, &[Address::new_id(10)]);
+        expect_abort(
+            SubnetActorError::InvalidOwnerNonce(1).exit_code(),
+            renounce(&mut runtime, 0),
+        );
+        renounce(&mut runtime, 1).unwrap();
+        let st: State = runtime.get_state();
+        assert_eq!(st.owner, None);
+
+        // without owner, administrative methods are forbidden
+        runtime.set_caller(Cid::default(), new_owner);
+        expect_abort(
+            ExitCode::USR_FORBIDDEN,
+            runtime.call::<Actor>(Method::Pause as u64, &RawBytes::default()),
+        );
+    }
+
+    #[test]
+    fn test_churn_limit() {
+        let test_actor_address = Address::new_id(9999);
+        let mut params = std_construct_param();
+        params.churn_limit = 1;
+        let mut runtime = construct_runtime_with_params(test_actor_address, params);
+
+        // the first validator bootstraps the subnet, and only one more
+        // validator can join in the window
+        let validators = vec![
+            Address::new_id(10),
+            Address::new_id(20),
+            Address::new_id(30),
+        ];
+        join_validators(&mut runtime, &validators);
+        let st: State = runtime.get_state();
+        assert!(st.is_validator(runtime.store(), &validators[1]).unwrap());
+        assert!(!st.is_validator(runtime.store(), &validators[2]).unwrap());
+
+        // leaving is queued as well
+        runtime.set_caller(Cid::default(), validators[0]);
+        runtime.expect_validate_caller_any();
+        let ret: LeaveReturn = runtime
+            .call::<Actor>(Method::Leave as u64, &RawBytes::default())
+            .unwrap()
+            .deserialize()
+            .unwrap();
+        assert_eq!(ret.refunded, TokenAmount::zero());
+        let st: State = runtime.get_state();
+        assert!(st.is_validator(runtime.store(), &validators[0]).unwrap());
+        assert_eq!(st.pending_changes.len(), 2);
+
+        // queued changes are applied in order as windows are committed
+        let subnet = SubnetID::new(&SubnetID::from_str("/root").unwrap(), test_actor_address);
+        let checkpoint = Checkpoint::new(subnet, DEFAULT_CHECKPOINT_PERIOD);
+        send_checkpoint(&mut runtime, validators[0], &checkpoint, false).unwrap();
+        send_checkpoint(&mut runtime, validators[1], &checkpoint, true).unwrap();
+        let st: State = runtime.get_state();
+        assert!(st.is_validator(runtime.store(), &validators[2]).unwrap());
+        assert!(st.is_validator(runtime.store(), &validators[0]).unwrap());
+        assert_eq!(st.pending_changes.len(), 1);
+        assert!(!st.pending_changes[0].join);
+    }
+
+    #[test]
+    fn test_exit_queue() {
+        let test_actor_address = Address::new_id(9999);
+        let mut params = std_construct_param();
+        params.max_exits_per_window = 1;
+        let mut runtime = construct_runtime_with_params(test_actor_address, params);
+        let validators = vec![
+            Address::new_id(10),
+            Address::new_id(20),
+            Address::new_id(30),
+            Address::new_id(40),
+        ];
+        join_validators(&mut runtime, &validators);
+
+        let stake = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+        let leave = |runtime: &mut MockRuntime, caller: Address, refunded: bool| {
+            runtime.set_caller(Cid::default(), caller);
+            runtime.expect_validate_caller_any();
+            if refunded {
+                expect_release(runtime, caller, &stake);
+            }
+            runtime.call::<Actor>(Method::Leave as u64, &RawBytes::default())
+        };
+        let position = |runtime: &mut MockRuntime, validator: Address| -> Option<u64> {
+            runtime.expect_validate_caller_any();
+            runtime
+                .call::<Actor>(
+                    Method::GetExitQueuePosition as u64,
+                    &RawBytes::serialize(validator).unwrap(),
+                )
+                .unwrap()
+                .deserialize()
+                .unwrap()
+        };
+
+        // the first exit of the window is processed right away
+        leave(&mut runtime, validators[0], true).unwrap();
+        runtime.verify();
+
+        // the next ones wait in the queue, still validating with their stake
+        let ret: LeaveReturn = leave(&mut runtime, validators[1], false)
+            .unwrap()
+            .deserialize()
+            .unwrap();
+        assert_eq!(ret.refunded, TokenAmount::zero());
+        assert_eq!(ret.remaining_stake, stake);
+        let st: State = runtime.get_state();
+        assert!(st.is_validator(runtime.store(), &validators[1]).unwrap());
+        assert_eq!(position(&mut runtime, validators[1]), Some(0));
+        assert_eq!(position(&mut runtime, validators[2]), None);
+        expect_abort(
+            SubnetActorError::ExitPending(validators[1]).exit_code(),
+            leave(&mut runtime, validators[1], false),
+        );
+
+        // the queue is drained as windows are committed
+        let subnet = SubnetID::new(&SubnetID::from_str("/root").unwrap(), test_actor_address);
+        let checkpoint = Checkpoint::new(subnet, DEFAULT_CHECKPOINT_PERIOD);
+        send_checkpoint(&mut runtime, validators[1], &checkpoint, false).unwrap();
+        send_checkpoint(&mut runtime, validators[2], &checkpoint, true).unwrap();
+        let st: State = runtime.get_state();
+        assert!(!st.is_validator(runtime.store(), &validators[1]).unwrap());
+        assert_eq!(position(&mut runtime, validators[1]), None);
+
+        // and the stake is withdrawn once out of the validator set
+        leave(&mut runtime, validators[1], true).unwrap();
+        runtime.verify();
+    }
+
+    #[test]
+    fn test_relayer_fee() {
+        let test_actor_address = Address::new_id(9999);
+        let mut params = std_construct_param();
+        params.relayer_fee = TokenAmount::from_atto(5);
+        let mut runtime = construct_runtime_with_params(test_actor_address, params);
+        let validators = vec![Address::new_id(10), Address::new_id(20)];
+        join_validators(&mut runtime, &validators);
+
+        runtime.set_caller(Cid::default(), Address::new_id(50));
+        runtime.set_value(TokenAmount::from_atto(7));
+        runtime.expect_validate_caller_any();
+        runtime
+            .call::<Actor>(Method::FundRewardPool as u64, &RawBytes::default())
+            .unwrap();
+        runtime.set_value(TokenAmount::zero());
+
+        // the validator casting the final vote is paid the fee
+        let subnet = SubnetID::new(&SubnetID::from_str("/root").unwrap(), test_actor_address);
+        let checkpoint = Checkpoint::new(subnet, DEFAULT_CHECKPOINT_PERIOD);
+        send_checkpoint(&mut runtime, validators[0], &checkpoint, false).unwrap();
+
+        let vote = submission(&runtime, &checkpoint);
+        runtime.set_caller(Cid::default(), validators[1]);
+        expect_signature(&mut runtime, validators[1], &vote);
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::CommitChildCheckpoint as u64,
+            RawBytes::serialize(&checkpoint).unwrap(),
+            TokenAmount::zero(),
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        runtime.expect_send(
+            validators[1],
+            METHOD_SEND,
+            RawBytes::default(),
+            TokenAmount::from_atto(5),
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        runtime
+            .call::<Actor>(
+                Method::SubmitCheckpoint as u64,
+                &cbor::serialize(&vote, "test").unwrap(),
+            )
+            .unwrap();
+        runtime.verify();
+
+        // the fee is taken from the pool
+        let st: State = runtime.get_state();
+        assert_eq!(st.reward_pool, TokenAmount::from_atto(2));
+    }
+
+    #[test]
+    fn test_genesis_epoch() {
+        let mut runtime = construct_runtime();
+        let get = |runtime: &mut MockRuntime| -> ActivationInfo {
+            runtime.expect_validate_caller_any();
+            runtime
+                .call::<Actor>(Method::GetGenesisEpoch as u64, &RawBytes::default())
+                .unwrap()
+                .deserialize()
+                .unwrap()
+        };
+        let info = get(&mut runtime);
+        assert_eq!(info.activation, None);
+        assert_eq!(info.status, Status::Instantiated);
+
+        runtime.set_epoch(42);
+        let validator = Address::new_id(10);
+        join_validators(&mut runtime, &[validator]);
+        runtime.set_epoch(50);
+
+        let info = get(&mut runtime);
+        assert_eq!(
+            info.activation,
+            Some(Activation {
+                epoch: 42,
+                sender: validator
+            })
+        );
+        assert_eq!(info.status, Status::Active);
+        assert_eq!(info.status_changed_at, 42);
+    }
+
+    #[test]
+    fn test_state_diff() {
+        let mut runtime = construct_runtime();
+        let before = runtime.state.unwrap();
+        let validator = Address::new_id(10);
+        join_validators(&mut runtime, &[validator]);
+        let after = runtime.state.unwrap();
+
+        let stake = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+        let diff = state::diff(runtime.store(), &before, &after).unwrap();
+        assert_eq!(diff.status, Some((Status::Instantiated, Status::Active)));
+        assert_eq!(diff.total_stake, Some((TokenAmount::zero(), stake.clone())));
+        assert_eq!(
+            diff.stake,
+            vec![StakeChange {
+                addr: validator,
+                before: TokenAmount::zero(),
+                after: stake,
+            }]
+        );
+        assert_eq!(
+            diff.validators_added,
+            vec![Validator {
+                addr: validator,
+                net_addr: net_addr(&validator).to_string(),
+            }]
+        );
+        assert!(diff.validators_removed.is_empty());
+        assert!(diff.new_checkpoints.is_empty());
+
+        assert!(state::diff(runtime.store(), &after, &after)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_stake_math() {
+        let (a, b) = (TokenAmount::from_atto(5), TokenAmount::from_atto(7));
+        assert_eq!(
+            stake_math::checked_add(&a, &b),
+            Ok(TokenAmount::from_atto(12))
+        );
+        assert_eq!(
+            stake_math::checked_sub(&b, &a),
+            Ok(TokenAmount::from_atto(2))
+        );
+        assert_eq!(
+            stake_math::checked_sub(&a, &b),
+            Err(SubnetActorError::StakeUnderflow)
+        );
+        assert_eq!(
+            stake_math::checked_add(&a, &TokenAmount::from_atto(-1)),
+            Err(SubnetActorError::NegativeStakeAmount)
+        );
+        assert!(StakeRatio::new(3, 2).is_err());
+        assert!(StakeRatio::new(1, 0).is_err());
+
+        // leaving validators keep the leaving coefficient of their stake
+        let mut runtime = construct_runtime();
+        let validator = Address::new_id(10);
+        join_validators(&mut runtime, &[validator]);
+        let mut st: State = runtime.get_state();
+        st.econ.leaving_coeff = StakeRatio::new(3, 4).unwrap();
+        runtime.replace_state(&st);
+
+        let stake = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+        let kept = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT * 3 / 4);
+        runtime.set_caller(Cid::default(), validator);
+        runtime.expect_validate_caller_any();
+        expect_release(&mut runtime, validator, &kept);
+        let ret: LeaveReturn = runtime
+            .call::<Actor>(Method::Leave as u64, &RawBytes::default())
+            .unwrap()
+            .deserialize()
+            .unwrap();
+        assert_eq!(ret.refunded, kept);
+        let st: State = runtime.get_state();
+        assert_eq!(st.total_stake, TokenAmount::zero());
+        assert_eq!(st.penalized_stake, stake - kept);
+    }
+
+    #[test]
+    fn test_update_gateway_addr() {
+        let owner = Address::new_id(5);
+        let gateway = Address::new_id(200);
+        let setup = || {
+            let mut params = std_construct_param();
+            params.owner = Some(owner);
+            let mut runtime = construct_runtime_with_params(Address::new_id(1), params);
+            runtime
+                .actor_code_cids
+                .insert(gateway, *MINER_ACTOR_CODE_ID);
+            runtime
+        };
+        let set_codes = |runtime: &mut MockRuntime| {
+            runtime.set_caller(Cid::default(), owner);
+            runtime.expect_validate_caller_addr(vec![owner]);
+            runtime
+                .call::<Actor>(
+                    Method::SetGatewayCodes as u64,
+                    &RawBytes::serialize(vec![*MINER_ACTOR_CODE_ID]).unwrap(),
+                )
+                .unwrap();
+        };
+        let update = |runtime: &mut MockRuntime, gateway: Address, value: TokenAmount| {
+            runtime.set_value(value);
+            runtime.set_caller(Cid::default(), owner);
+            runtime.expect_validate_caller_addr(vec![owner]);
+            runtime.call::<Actor>(
+                Method::UpdateGatewayAddr as u64,
+                &RawBytes::serialize(gateway).unwrap(),
+            )
+        };
+        let mut runtime = setup();
+
+        // the gateway can only be moved to an allowed code
+        expect_abort(
+            SubnetActorError::GatewayCodeNotAllowed(gateway).exit_code(),
+            update(&mut runtime, gateway, TokenAmount::zero()),
+        );
+        set_codes(&mut runtime);
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            update(
+                &mut runtime,
+                Address::new_id(IPC_GATEWAY_ADDR),
+                TokenAmount::zero(),
+            ),
+        );
+
+        // the new gateway needs to be deployed on the parent
+        let parent = std_construct_param().parent;
+        let other = SubnetID::from_str("/root/f0100").unwrap();
+        expect_network_name(&mut runtime, 200, &other, ExitCode::OK);
+        expect_abort(
+            SubnetActorError::ParentMismatch(other).exit_code(),
+            update(&mut runtime, gateway, TokenAmount::zero()),
+        );
+
+        expect_network_name(&mut runtime, 200, &parent, ExitCode::OK);
+        update(&mut runtime, gateway, TokenAmount::zero()).unwrap();
+        let st: State = runtime.get_state();
+        assert_eq!(st.ipc_gateway_addr, gateway);
+        assert_eq!(st.ipc_gateway_robust_addr, None);
+        assert_eq!(st.parent_checked_gateway, Some(gateway));
+        assert!(!st.registered_with_gateway);
+
+        // a registered subnet registers again in the new gateway, with the
+        // collateral locked in the old one sent by the owner, less the stake
+        // of the calls queued for the old one, held by the actor
+        let mut runtime = setup();
+        set_codes(&mut runtime);
+        join_validators(&mut runtime, &[Address::new_id(10), Address::new_id(20)]);
+        let stake = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+        let old = Address::new_id(IPC_GATEWAY_ADDR);
+        let checkpoint_call = GatewayCall {
+            to: old,
+            method: ipc_gateway::Method::CommitChildCheckpoint as u64,
+            params: RawBytes::default(),
+            value: TokenAmount::zero(),
+        };
+        let mut st: State = runtime.get_state();
+        assert!(st.registered_with_gateway);
+        st.pending_gateway_calls = vec![
+            GatewayCall {
+                to: old,
+                method: ipc_gateway::Method::AddStake as u64,
+                params: RawBytes::default(),
+                value: stake.clone(),
+            },
+            checkpoint_call.clone(),
+        ];
+        runtime.replace_state(&st);
+
+        expect_network_name(&mut runtime, 200, &parent, ExitCode::OK);
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            update(&mut runtime, gateway, &stake * 2),
+        );
+
+        runtime.set_balance(&stake * 2);
+        expect_network_name(&mut runtime, 200, &parent, ExitCode::OK);
+        runtime.expect_send(
+            gateway,
+            ipc_gateway::Method::Register as u64,
+            RawBytes::default(),
+            &stake * 2,
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        update(&mut runtime, gateway, stake.clone()).unwrap();
+        runtime.verify();
+
+        // the queued stake is part of the registration, the other calls are
+        // retried with the new gateway
+        let st: State = runtime.get_state();
+        assert!(st.registered_with_gateway);
+        assert_eq!(st.ipc_gateway_addr, gateway);
+        assert!(st.notified_power_table.is_none());
+        assert_eq!(
+            st.pending_gateway_calls,
+            vec![GatewayCall {
+                to: gateway,
+                ..checkpoint_call
+            }]
+        );
+    }
+
+    #[test]
+    fn test_genesis_checkpoint() {
+        let receiver = Address::new_id(1);
+        let mut params = std_construct_param();
+        params.genesis = vec![1, 2, 3];
+        params.anchor_genesis = true;
+        let mut runtime = construct_runtime_with_params(receiver, params);
+
+        // a rejected registration queues the genesis checkpoint after it
+        let validator = Address::new_id(10);
+        let stake = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+        runtime.set_value(stake.clone());
+        runtime.set_caller(Cid::default(), validator);
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::Register as u64,
+            RawBytes::default(),
+            stake.clone(),
+            RawBytes::default(),
+            ExitCode::USR_ILLEGAL_STATE,
+        );
+        runtime
+            .call::<Actor>(
+                Method::Join as u64,
+                &cbor::serialize(
+                    &JoinParams {
+                        validator_net_addr: net_addr(&validator),
+                        invitation: None,
+                        vesting: None,
+                        worker: None,
+                    },
+                    "test",
+                )
+                .unwrap(),
+            )
+            .unwrap();
+        runtime.verify();
+
+        let st: State = runtime.get_state();
+        let genesis = st.genesis_checkpoint.clone().unwrap();
+        assert_eq!(genesis.epoch(), 0);
+        assert_eq!(genesis.prev_check().cid(), Cid::default());
+        assert!(genesis.data.proof.is_empty());
+        let commitment = st.last_state_commitment.clone().unwrap();
+        let link: TCid<TLink<RawBytes>> =
+            TCid::new_link(runtime.store(), &RawBytes::new(vec![1, 2, 3])).unwrap();
+        assert_eq!(commitment.state_root, link.cid());
+        assert_eq!(commitment.config_number, st.config_number);
+        assert_eq!(
+            st.pending_gateway_calls
+                .iter()
+                .map(|c| c.method)
+                .collect::<Vec<_>>(),
+            vec![
+                ipc_gateway::Method::Register as u64,
+                ipc_gateway::Method::CommitChildCheckpoint as u64
+            ]
+        );
+
+        // both reach the gateway in order
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::Register as u64,
+            RawBytes::default(),
+            stake.clone(),
+            RawBytes::default(),
+            ExitCode::OK,
+        );
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::CommitChildCheckpoint as u64,
+            RawBytes::serialize(&genesis).unwrap(),
+            TokenAmount::zero(),
+            RawBytes::default(),
+            ExitCode::OK,
+        );
+        runtime
+            .call::<Actor>(Method::RetryGatewayCalls as u64, &RawBytes::default())
+            .unwrap();
+        runtime.verify();
+
+        // the first checkpoint of the subnet links to the genesis checkpoint
+        let st: State = runtime.get_state();
+        let template = st.checkpoint_template(runtime.store(), receiver).unwrap();
+        assert_eq!(template.prev_check, genesis.cid());
+    }
+
+    #[test]
+    fn test_parent_guard() {
+        let parent = std_construct_param().parent;
+        let other = SubnetID::from_str("/root/f0100").unwrap();
+        let construct = |network: &SubnetID, code: ExitCode| {
+            let mut runtime = MockRuntime::new(Address::new_id(1), *INIT_ACTOR_ADDR);
+            runtime.expect_validate_caller_addr(vec![*INIT_ACTOR_ADDR]);
+            expect_network_name(&mut runtime, IPC_GATEWAY_ADDR, network, code);
+            runtime
+                .call::<Actor>(
+                    Method::Constructor as u64,
+                    &cbor::serialize(&std_construct_param(), "test").unwrap(),
+                )
+                .map(|_| runtime)
+        };
+
+        // subnets can't be deployed for a parent other than the network of
+        // the gateway
+        expect_abort(
+            SubnetActorError::ParentMismatch(other.clone()).exit_code(),
+            construct(&other, ExitCode::OK),
+        );
+        let runtime = construct(&parent, ExitCode::OK).unwrap();
+        let st: State = runtime.get_state();
+        assert_eq!(
+            st.parent_checked_gateway,
+            Some(Address::new_id(IPC_GATEWAY_ADDR))
+        );
+
+        // gateways not reporting their network are asked again on Register
+        let mut runtime = construct(&parent, ExitCode::USR_UNHANDLED_MESSAGE).unwrap();
+        let st: State = runtime.get_state();
+        assert_eq!(st.parent_checked_gateway, None);
+        let validator = Address::new_id(10);
+        let join = |runtime: &mut MockRuntime| {
+            runtime.set_value(TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT));
+            runtime.set_caller(Cid::default(), validator);
+            runtime.expect_validate_caller_any();
+            runtime.call::<Actor>(
+                Method::Join as u64,
+                &cbor::serialize(
+                    &JoinParams {
+                        validator_net_addr: net_addr(&validator),
+                        invitation: None,
+                        vesting: None,
+                        worker: None,
+                    },
+                    "test",
+                )
+                .unwrap(),
+            )
+        };
+        expect_network_name(&mut runtime, IPC_GATEWAY_ADDR, &other, ExitCode::OK);
+        expect_abort(
+            SubnetActorError::ParentMismatch(other).exit_code(),
+            join(&mut runtime),
+        );
+        let st: State = runtime.get_state();
+        assert!(!st.registered_with_gateway);
+
+        expect_network_name(&mut runtime, IPC_GATEWAY_ADDR, &parent, ExitCode::OK);
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::Register as u64,
+            RawBytes::default(),
+            TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT),
+            RawBytes::default(),
+            ExitCode::OK,
+        );
+        join(&mut runtime).unwrap();
+        let st: State = runtime.get_state();
+        assert!(st.registered_with_gateway);
+        assert_eq!(
+            st.parent_checked_gateway,
+            Some(Address::new_id(IPC_GATEWAY_ADDR))
+        );
+    }
+
+    #[test]
+    fn test_set_leaving_coeff() {
+        let owner = Address::new_id(5);
+        let mut params = std_construct_param();
+        params.owner = Some(owner);
+        params.leaving_coeff = StakeRatio { numer: 2, denom: 1 };
+        assert_eq!(
+            params.validate(),
+            Err(ConstructParamsError::InvalidLeavingCoeff)
+        );
+        params.leaving_coeff = StakeRatio::new(3, 4).unwrap();
+        let mut runtime = construct_runtime_with_params(Address::new_id(1), params);
+        let st: State = runtime.get_state();
+        assert_eq!(st.econ.leaving_coeff, StakeRatio::new(3, 4).unwrap());
+
+        // the coefficient is voted by the validators
+        let validator = Address::new_id(10);
+        join_validators(&mut runtime, &[validator]);
+        let set = |runtime: &mut MockRuntime, caller: Address, coeff: StakeRatio| {
+            let st: State = runtime.get_state();
+            runtime.set_caller(Cid::default(), caller);
+            runtime.expect_validate_caller_any();
+            runtime.call::<Actor>(
+                Method::ProposeEconParams as u64,
+                &RawBytes::serialize(EconParams {
+                    leaving_coeff: coeff,
+                    ..st.econ
+                })
+                .unwrap(),
+            )
+        };
+        expect_abort(
+            SubnetActorError::NotValidator(owner).exit_code(),
+            set(&mut runtime, owner, StakeRatio::default()),
+        );
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            set(&mut runtime, validator, StakeRatio { numer: 1, denom: 0 }),
+        );
+        set(&mut runtime, validator, StakeRatio::new(1, 3).unwrap()).unwrap();
+
+        // the penalty is rounded down, in favor of the leaving validator
+        let stake = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+        let penalty = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT * 2 / 3);
+        let released = &stake - &penalty;
+        runtime.set_caller(Cid::default(), validator);
+        runtime.expect_validate_caller_any();
+        expect_release(&mut runtime, validator, &released);
+        let ret: LeaveReturn = runtime
+            .call::<Actor>(Method::Leave as u64, &RawBytes::default())
+            .unwrap()
+            .deserialize()
+            .unwrap();
+        assert_eq!(ret.refunded, released);
+        let st: State = runtime.get_state();
+        assert_eq!(st.penalized_stake, penalty);
+        assert_eq!(st.treasury, penalty);
+    }
+
+    #[test]
+    fn test_set_check_period() {
+        let owner = Address::new_id(5);
+        let test_actor_address = Address::new_id(9999);
+        let mut params = std_construct_param();
+        params.owner = Some(owner);
+        let mut runtime = construct_runtime_with_params(test_actor_address, params);
+        let validator = Address::new_id(10);
+        join_validators(&mut runtime, &[validator]);
+
+        let set_period = |runtime: &mut MockRuntime, period: ChainEpoch| {
+            runtime.set_caller(Cid::default(), owner);
+            runtime.expect_validate_caller_addr(vec![owner]);
+            runtime.call::<Actor>(
+                Method::SetCheckPeriod as u64,
+                &RawBytes::serialize(period).unwrap(),
+            )
+        };
+        expect_abort(
+            SubnetActorError::InvalidCheckPeriod(5).exit_code(),
+            set_period(&mut runtime, 5),
+        );
+        set_period(&mut runtime, 25).unwrap();
+
+        // the current window is not affected
+        assert_eq!(next_checkpoint_epoch(&mut runtime), 10);
+
+        // windows of both periods are valid until the change takes effect
+        runtime.set_epoch(35);
+        let register = |runtime: &mut MockRuntime, epoch: ChainEpoch| {
+            runtime.set_caller(Cid::default(), validator);
+            runtime.expect_validate_caller_any();
+            runtime.call::<Actor>(
+                Method::RegisterCrossMsg as u64,
+                &cbor::serialize(
+                    &RegisterCrossMsgParams {
+                        epoch,
+                        msgs_cid: Cid::default(),
+                    },
+                    "test",
+                )
+                .unwrap(),
+            )
+        };
+        register(&mut runtime, 20).unwrap();
+        register(&mut runtime, 35).unwrap();
+        expect_abort(
+            SubnetActorError::NotSigningWindow.exit_code(),
+            register(&mut runtime, 15),
+        );
+
+        let subnet = SubnetID::new(&SubnetID::from_str("/root").unwrap(), test_actor_address);
+        let checkpoint = Checkpoint::new(subnet, 10);
+        send_checkpoint(&mut runtime, validator, &checkpoint, true).unwrap();
+
+        // windows are counted from the checkpoint with the new period
+        assert_eq!(next_checkpoint_epoch(&mut runtime), 35);
+        expect_abort(
+            SubnetActorError::NotSigningWindow.exit_code(),
+            register(&mut runtime, 20),
+        );
+        let st: State = runtime.get_state();
+        assert_eq!(st.pending_check_period, None);
+        assert_eq!(
+            st.check_period_changes,
+            vec![CheckPeriodChange {
+                effective_epoch: 10,
+                old_period: 10,
+                new_period: 25,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_checkpoint_windows() {
+        let owner = Address::new_id(5);
+        let mut params = std_construct_param();
+        params.owner = Some(owner);
+        let mut runtime = construct_runtime_with_params(Address::new_id(1), params);
+        join_validators(&mut runtime, &[Address::new_id(10)]);
+
+        let get_windows = |runtime: &mut MockRuntime| -> CheckpointWindows {
+            runtime.expect_validate_caller_any();
+            runtime
+                .call::<Actor>(Method::GetCheckpointWindows as u64, &RawBytes::default())
+                .unwrap()
+                .deserialize()
+                .unwrap()
+        };
+        let windows = get_windows(&mut runtime);
+        assert_eq!(
+            windows,
+            CheckpointWindows {
+                anchor: 0,
+                period: 10,
+                pending: None,
+                next_epoch: 10,
+            }
+        );
+
+        // windows end at the epochs their checkpoints are signed at
+        assert_eq!(windows.window_for_epoch(1), (0, 10));
+        assert_eq!(windows.window_for_epoch(10), (0, 10));
+        assert_eq!(windows.window_for_epoch(11), (10, 20));
+        assert_eq!(windows.window_for_epoch(-5), (-10, 0));
+
+        // the next window is open, and later ones once they are closed
+        assert!(windows.is_submission_open(10, 0));
+        assert!(!windows.is_submission_open(15, 20));
+        assert!(!windows.is_submission_open(20, 19));
+        assert!(windows.is_submission_open(20, 20));
+        assert!(!windows.is_submission_open(0, 20));
+
+        // windows after a pending change use the new period
+        runtime.set_caller(Cid::default(), owner);
+        runtime.expect_validate_caller_addr(vec![owner]);
+        runtime
+            .call::<Actor>(
+                Method::SetCheckPeriod as u64,
+                &RawBytes::serialize(25 as ChainEpoch).unwrap(),
+            )
+            .unwrap();
+        let windows = get_windows(&mut runtime);
+        assert_eq!(windows.window_for_epoch(10), (0, 10));
+        assert_eq!(windows.window_for_epoch(11), (10, 35));
+        assert!(windows.is_window_end(20));
+        assert!(windows.is_window_end(35));
+    }
+
+    #[test]
+    fn test_membership_ticket() {
+        let mut runtime = construct_runtime();
+        let validators = [Address::new_id(10), Address::new_id(20)];
+        join_validators(&mut runtime, &validators);
+
+        let get_ticket = |runtime: &mut MockRuntime, addr: Address| {
+            runtime.expect_validate_caller_any();
+            runtime
+                .call::<Actor>(
+                    Method::GetMembershipTicket as u64,
+                    &RawBytes::serialize(addr).unwrap(),
+                )
+                .unwrap()
+                .deserialize::<Option<MembershipTicket>>()
+                .unwrap()
+        };
+        assert_eq!(
+            get_ticket(&mut runtime, validators[1]),
+            Some(MembershipTicket {
+                validator: validators[1],
+                config_number: 2,
+                effective_epoch: DEFAULT_CHECKPOINT_PERIOD,
+            })
+        );
+
+        // joins that don't grant validator status get no ticket
+        let caller = Address::new_id(30);
+        runtime.set_value(TokenAmount::from_atto(1));
+        runtime.set_caller(Cid::default(), caller);
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::AddStake as u64,
+            RawBytes::default(),
+            TokenAmount::from_atto(1),
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        let params = JoinParams {
+            validator_net_addr: net_addr(&caller),
+            invitation: None,
+            vesting: None,
+            worker: None,
+        };
+        let ret: JoinReturn = runtime
+            .call::<Actor>(
+                Method::Join as u64,
+                &cbor::serialize(&params, "test").unwrap(),
+            )
+            .unwrap()
+            .deserialize()
+            .unwrap();
+        assert_eq!(ret.ticket, None);
+        assert_eq!(get_ticket(&mut runtime, caller), None);
+
+        // leaving validators lose their ticket in a new configuration
+        runtime.set_caller(Cid::default(), validators[0]);
+        runtime.expect_validate_caller_any();
+        expect_release(
+            &mut runtime,
+            validators[0],
+            &TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT),
+        );
+        runtime
+            .call::<Actor>(Method::Leave as u64, &RawBytes::default())
+            .unwrap();
+        assert_eq!(get_ticket(&mut runtime, validators[0]), None);
+        let st: State = runtime.get_state();
+        assert_eq!(st.config_number, 3);
+    }
+
+    #[test]
+    fn test_checkpoint_proof() {
+        let test_actor_address = Address::new_id(9999);
+        let mut runtime = construct_runtime_with_receiver(test_actor_address);
+        let validator = Address::new_id(10);
+        join_validators(&mut runtime, &[validator]);
+
+        let subnet = SubnetID::new(&SubnetID::from_str("/root").unwrap(), test_actor_address);
+        let checkpoint = Checkpoint::new(subnet, DEFAULT_CHECKPOINT_PERIOD);
+        send_checkpoint(&mut runtime, validator, &checkpoint, true).unwrap();
+
+        let get_proof = |runtime: &mut MockRuntime, window: u64| {
+            runtime.expect_validate_caller_any();
+            runtime
+                .call::<Actor>(
+                    Method::GetCheckpointProof as u64,
+                    &RawBytes::serialize(window).unwrap(),
+                )
+                .unwrap()
+                .deserialize::<Option<CheckpointProof>>()
+                .unwrap()
+        };
+        assert_eq!(get_proof(&mut runtime, 1), None);
+
+        let proof = get_proof(&mut runtime, 0).unwrap();
+        assert_eq!(proof.checkpoint, checkpoint);
+        assert_eq!(proof.config_number, Some(1));
+
+        // the proof links the state root to the log of checkpoints
+        let st: State = runtime.get_state();
+        assert_eq!(Some(proof.blocks[0].cid), runtime.state);
+        let proved: State = proof.blocks[0].data.deserialize().unwrap();
+        assert_eq!(proved.checkpoints.cid(), proof.blocks[1].cid);
+        assert_eq!(proof.blocks[1].cid, st.checkpoints.cid());
+        for block in &proof.blocks {
+            assert_eq!(
+                runtime.store().get(&block.cid).unwrap(),
+                Some(block.data.to_vec())
+            );
+        }
+    }
+
+    #[test]
+    fn test_membership_history() {
+        let mut runtime = construct_runtime();
+        let miners = vec![
+            Address::new_id(10),
+            Address::new_id(20),
+            Address::new_id(30),
+        ];
+        join_validators(&mut runtime, &miners);
+
+        runtime.set_caller(Cid::default(), miners[1]);
+        runtime.expect_validate_caller_any();
+        expect_release(
+            &mut runtime,
+            miners[1],
+            &TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT),
+        );
+        runtime
+            .call::<Actor>(Method::Leave as u64, &RawBytes::default())
+            .unwrap();
+
+        let history = |runtime: &mut MockRuntime, cursor, limit| -> MembershipHistoryReturn {
+            runtime.expect_validate_caller_any();
+            runtime
+                .call::<Actor>(
+                    Method::GetMembershipHistory as u64,
+                    &RawBytes::serialize(ListParams { cursor, limit }).unwrap(),
+                )
+                .unwrap()
+                .deserialize()
+                .unwrap()
+        };
+        let page = history(&mut runtime, 0, 2);
+        assert_eq!(page.total, 4);
+        assert_eq!(page.next_cursor, Some(2));
+        assert_eq!(
+            page.events
+                .iter()
+                .map(|e| (e.kind, e.validator, e.config_number))
+                .collect::<Vec<_>>(),
+            vec![
+                (MembershipEventKind::Join, miners[0], 1),
+                (MembershipEventKind::Join, miners[1], 2),
+            ]
+        );
+        let page = history(&mut runtime, 2, 2);
+        assert_eq!(page.next_cursor, None);
+        assert_eq!(page.events[1].kind, MembershipEventKind::Leave);
+        assert_eq!(page.events[1].validator, miners[1]);
+        assert_eq!(page.events[1].config_number, 4);
+        assert_eq!(page.events[1].epoch, DEFAULT_CHECKPOINT_PERIOD);
+    }
+
+    #[test]
+    fn test_validator_set_at() {
+        let mut runtime = construct_runtime();
+        let validators = [Address::new_id(20), Address::new_id(10)];
+        join_validators(&mut runtime, &validators);
+
+        let get_set = |runtime: &mut MockRuntime, config_number: u64| {
+            runtime.expect_validate_caller_any();
+            runtime
+                .call::<Actor>(
+                    Method::GetValidatorSetAt as u64,
+                    &RawBytes::serialize(config_number).unwrap(),
+                )
+                .unwrap()
+                .deserialize::<Option<ValidatorSetAt>>()
+                .unwrap()
+        };
+        let addrs =
+            |set: &ValidatorSetAt| set.validators.iter().map(|v| v.addr).collect::<Vec<_>>();
+
+        assert_eq!(
+            addrs(&get_set(&mut runtime, 0).unwrap()),
+            Vec::<Address>::new()
+        );
+        assert_eq!(
+            addrs(&get_set(&mut runtime, 1).unwrap()),
+            vec![validators[0]]
+        );
+        // validators are sorted by address
+        let set = get_set(&mut runtime, 2).unwrap();
+        assert_eq!(addrs(&set), vec![validators[1], validators[0]]);
+        assert_eq!(get_set(&mut runtime, 3), None);
+
+        // configurations are kept once the set changes
+        runtime.set_caller(Cid::default(), validators[0]);
+        runtime.expect_validate_caller_any();
+        expect_release(
+            &mut runtime,
+            validators[0],
+            &TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT),
+        );
+        runtime
+            .call::<Actor>(Method::Leave as u64, &RawBytes::default())
+            .unwrap();
+        assert_eq!(get_set(&mut runtime, 2), Some(set));
+        assert_eq!(
+            addrs(&get_set(&mut runtime, 3).unwrap()),
+            vec![validators[1]]
+        );
+    }
+
+    #[test]
+    fn test_max_voting_power() {
+        let amounts = |atto: &[u64]| -> Vec<TokenAmount> {
+            atto.iter().map(|a| TokenAmount::from_atto(*a)).collect()
+        };
+        let zero = TokenAmount::zero();
+        assert_eq!(
+            stake_math::cap_voting_power(&amounts(&[50, 10, 10]), &zero, 40),
+            amounts(&[13, 10, 10])
+        );
+        assert_eq!(
+            stake_math::cap_voting_power(&amounts(&[50, 10, 10]), &zero, 100),
+            amounts(&[50, 10, 10])
+        );
+        // too few validators to stay under the cap get the same power
+        assert_eq!(
+            stake_math::cap_voting_power(&amounts(&[50, 10]), &zero, 40),
+            amounts(&[10, 10])
+        );
+
+        let test_actor_address = Address::new_id(9999);
+        let mut params = std_construct_param();
+        params.max_voting_power_pct = Some(40);
+        let mut runtime = construct_runtime_with_params(test_actor_address, params);
+        let validators = [
+            Address::new_id(10),
+            Address::new_id(20),
+            Address::new_id(30),
+        ];
+        join_validators(&mut runtime, &validators);
+
+        // the first validator holds most of the stake
+        let value = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT) * 4;
+        runtime.set_value(value.clone());
+        runtime.set_caller(Cid::default(), validators[0]);
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::AddStake as u64,
+            RawBytes::default(),
+            value,
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        runtime
+            .call::<Actor>(Method::AddStake as u64, &RawBytes::default())
+            .unwrap();
+
+        // its stake above the cap counts as collateral, not as power
+        runtime.expect_validate_caller_any();
+        let table: PowerTable = runtime
+            .call::<Actor>(Method::GetPowerTable as u64, &RawBytes::default())
+            .unwrap()
+            .deserialize()
+            .unwrap();
+        let whale = &table.validators[0];
+        assert_eq!(whale.addr, validators[0]);
+        assert_eq!(
+            whale.collateral,
+            TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT) * 5
+        );
+        assert!(whale.power * 5 <= MAX_TOTAL_VOTING_POWER * 2);
+        assert!(whale.power * 5 >= (MAX_TOTAL_VOTING_POWER - 10) * 2);
+
+        // nor does it let it commit checkpoints alone
+        let subnet = SubnetID::new(&SubnetID::from_str("/root").unwrap(), test_actor_address);
+        let mut checkpoint = Checkpoint::new(subnet, 10);
+        checkpoint.set_signature(
+            RawBytes::serialize(Signature::new_secp256k1(vec![1, 2, 3, 4]))
+                .unwrap()
+                .bytes()
+                .to_vec(),
+        );
+        send_checkpoint(&mut runtime, validators[0], &checkpoint, false).unwrap();
+        send_checkpoint(&mut runtime, validators[1], &checkpoint, true).unwrap();
+
+        // the cap needs to be a share of the voting power
+        let mut params = std_construct_param();
+        params.max_voting_power_pct = Some(0);
+        assert_eq!(
+            params.validate(),
+            Err(ConstructParamsError::InvalidMaxVotingPower)
+        );
+    }
+
+    #[test]
+    fn test_validator_set_notifications() {
+        let owner = Address::new_id(5);
+        let mut params = std_construct_param();
+        params.owner = Some(owner);
+        let mut runtime = construct_runtime_with_params(Address::new_id(1), params);
+        let validators = [Address::new_id(10), Address::new_id(11)];
+
+        runtime.set_caller(Cid::default(), owner);
+        runtime.expect_validate_caller_addr(vec![owner]);
+        runtime
+            .call::<Actor>(
+                Method::SetValidatorSetNotifications as u64,
+                &RawBytes::serialize(true).unwrap(),
+            )
+            .unwrap();
+
+        let entry = |addr: &Address, power: u64| PowerTableEntry {
+            addr: *addr,
+            net_addr: net_addr(addr).to_string(),
+            power,
+            collateral: TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT),
+        };
+        let join = |runtime: &mut MockRuntime, i: usize, table: Vec<PowerTableEntry>, code| {
+            let method = if i == 0 {
+                ipc_gateway::Method::Register
+            } else {
+                ipc_gateway::Method::AddStake
+            };
+            runtime.set_caller(Cid::default(), validators[i]);
+            runtime.set_value(TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT));
+            runtime.expect_validate_caller_any();
+            runtime.expect_send(
+                Address::new_id(IPC_GATEWAY_ADDR),
+                method as u64,
+                RawBytes::default(),
+                TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT),
+                RawBytes::default(),
+                ExitCode::new(0),
+            );
+            let power_table = TCid::new_link(runtime.store(), &table).unwrap().cid();
+            runtime.expect_send(
+                Address::new_id(IPC_GATEWAY_ADDR),
+                ext::gateway::VALIDATOR_SET_UPDATED_METHOD,
+                RawBytes::serialize(ext::gateway::ValidatorSetUpdatedParams {
+                    config_number: i as u64 + 1,
+                    power_table,
+                })
+                .unwrap(),
+                TokenAmount::zero(),
+                RawBytes::default(),
+                code,
+            );
+            let params = JoinParams {
+                validator_net_addr: net_addr(&validators[i]),
+                invitation: None,
+                vesting: None,
+                worker: None,
+            };
+            runtime
+                .call::<Actor>(
+                    Method::Join as u64,
+                    &cbor::serialize(&params, "test").unwrap(),
+                )
+                .unwrap();
+            power_table
+        };
+
+        let first = join(
+            &mut runtime,
+            0,
+            vec![entry(&validators[0], MAX_TOTAL_VOTING_POWER - 1)],
+            ExitCode::OK,
+        );
+        let st: State = runtime.get_state();
+        assert_eq!(st.notified_power_table.map(|l| l.cid()), Some(first));
+
+        // a failed notification doesn't revert the join, and is retried
+        // with the next change
+        let half = (MAX_TOTAL_VOTING_POWER - 2) / 2;
+        join(
+            &mut runtime,
+            1,
+            vec![entry(&validators[0], half), entry(&validators[1], half)],
+            ExitCode::USR_UNHANDLED_MESSAGE,
+        );
+        let st: State = runtime.get_state();
+        assert_eq!(st.validators.len(), 2);
+        assert_eq!(st.notified_power_table.map(|l| l.cid()), Some(first));
+    }
+
+    #[test]
+    fn test_checkpoint_template() {
+        let test_actor_address = Address::new_id(9999);
+        let mut runtime = construct_runtime_with_receiver(test_actor_address);
+        let validator = Address::new_id(10);
+        join_validators(&mut runtime, &[validator]);
+
+        let get_template = |runtime: &mut MockRuntime| {
+            runtime.expect_validate_caller_any();
+            runtime
+                .call::<Actor>(Method::GetCheckpointTemplate as u64, &RawBytes::default())
+                .unwrap()
+                .deserialize::<CheckpointTemplate>()
+                .unwrap()
+        };
+        let subnet = SubnetID::new(&SubnetID::from_str("/root").unwrap(), test_actor_address);
+        let st: State = runtime.get_state();
+        let (validator_set, _) = st
+            .get_validator_set_at(runtime.store(), 1)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            get_template(&mut runtime),
+            CheckpointTemplate {
+                epoch: DEFAULT_CHECKPOINT_PERIOD,
+                source: subnet.clone(),
+                prev_check: Cid::default(),
+                config_number: 1,
+                validator_set,
+            }
+        );
+
+        let checkpoint = Checkpoint::new(subnet.clone(), DEFAULT_CHECKPOINT_PERIOD);
+        send_checkpoint(&mut runtime, validator, &checkpoint, true).unwrap();
+
+        // a checkpoint built from the template is accepted
+        let template = get_template(&mut runtime);
+        assert_eq!(template.epoch, DEFAULT_CHECKPOINT_PERIOD * 2);
+        assert_eq!(template.prev_check, checkpoint.cid());
+        let mut next = Checkpoint::new(template.source, template.epoch);
+        next.data.prev_check = TCid::from(template.prev_check);
+        send_checkpoint(&mut runtime, validator, &next, true).unwrap();
+        assert_eq!(get_template(&mut runtime).prev_check, next.cid());
+    }
+
+    #[test]
+    fn test_validator_set_commitment() {
+        let test_actor_address = Address::new_id(9999);
+        let mut runtime = construct_runtime_with_receiver(test_actor_address);
+        let validators = vec![Address::new_id(10), Address::new_id(20)];
+        join_validators(&mut runtime, &validators[..1]);
+
+        // the validator set changes after the checkpoint was produced
+        let subnet = SubnetID::new(&SubnetID::from_str("/root").unwrap(), test_actor_address);
+        let mut checkpoint = Checkpoint::new(subnet, DEFAULT_CHECKPOINT_PERIOD);
+        checkpoint.set_signature(
+            RawBytes::serialize(Signature::new_secp256k1(vec![1, 2, 3, 4]))
+                .unwrap()
+                .bytes()
+                .to_vec(),
+        );
+        let stale = submission(&runtime, &checkpoint);
+        join_validators(&mut runtime, &validators[1..]);
+        let st: State = runtime.get_state();
+        assert_eq!(st.config_number, 2);
+
+        runtime.set_caller(Cid::default(), validators[0]);
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            SubnetActorError::ValidatorSetMismatch(2).exit_code(),
+            runtime.call::<Actor>(
+                Method::SubmitCheckpoint as u64,
+                &cbor::serialize(&stale, "test").unwrap(),
+            ),
+        );
+        runtime.verify();
+
+        // it is accepted once produced under the current one
+        assert_eq!(
+            submission(&runtime, &checkpoint)
+                .state_commitment
+                .config_number,
+            2
+        );
+        send_checkpoint(&mut runtime, validators[0], &checkpoint, false).unwrap();
+        send_checkpoint(&mut runtime, validators[1], &checkpoint, true).unwrap();
+    }
+
+    #[test]
+    fn test_gateway_failures() {
+        let test_actor_address = Address::new_id(9999);
+        let mut runtime = construct_runtime_with_receiver(test_actor_address);
+
+        // failures of calls the message can't go without abort it
+        runtime.set_caller(Cid::default(), Address::new_id(10));
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::Kill as u64,
+            RawBytes::default(),
+            TokenAmount::zero(),
+            RawBytes::default(),
+            ExitCode::USR_FORBIDDEN,
+        );
+        expect_abort(
+            SubnetActorError::GatewayCallFailed(
+                ipc_gateway::Method::Kill as u64,
+                ExitCode::USR_FORBIDDEN,
+            )
+            .exit_code(),
+            runtime.call::<Actor>(Method::Kill as u64, &RawBytes::default()),
+        );
+        runtime.verify();
+        let st: State = runtime.get_state();
+        assert_eq!(st.status, Status::Instantiated);
+
+        // a checkpoint rejected by the gateway isn't committed
+        let validator = Address::new_id(10);
+        join_validators(&mut runtime, &[validator]);
+        let subnet = SubnetID::new(&SubnetID::from_str("/root").unwrap(), test_actor_address);
+        let checkpoint = Checkpoint::new(subnet, DEFAULT_CHECKPOINT_PERIOD);
+        let vote = submission(&runtime, &checkpoint);
+        runtime.set_caller(Cid::default(), validator);
+        expect_signature(&mut runtime, validator, &vote);
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::CommitChildCheckpoint as u64,
+            RawBytes::serialize(&checkpoint).unwrap(),
+            TokenAmount::zero(),
+            RawBytes::default(),
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+        );
+        expect_abort(
+            SubnetActorError::GatewayRejectedCheckpoint(ExitCode::USR_ILLEGAL_ARGUMENT).exit_code(),
+            runtime.call::<Actor>(
+                Method::SubmitCheckpoint as u64,
+                &cbor::serialize(&vote, "test").unwrap(),
+            ),
+        );
+        runtime.verify();
+        let st: State = runtime.get_state();
+        assert_eq!(st.last_committed_epoch, 0);
+
+        // releases the gateway refuses stand for missing collateral
+        assert_eq!(
+            SubnetActorError::from_gateway(
+                ipc_gateway::Method::ReleaseStake as u64,
+                ExitCode::USR_ILLEGAL_STATE
+            ),
+            SubnetActorError::InsufficientGatewayCollateral
+        );
+    }
+
+    #[test]
+    fn test_retry_gateway_calls() {
+        let mut runtime = construct_runtime();
+        let validators = [Address::new_id(10), Address::new_id(11)];
+        let collateral = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+
+        let join = |runtime: &mut MockRuntime, caller: Address| {
+            let params = JoinParams {
+                validator_net_addr: net_addr(&caller),
+                invitation: None,
+                vesting: None,
+                worker: None,
+            };
+            runtime.set_value(TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT));
+            runtime.set_caller(Cid::default(), caller);
+            runtime.expect_validate_caller_any();
+            runtime
+                .call::<Actor>(
+                    Method::Join as u64,
+                    &cbor::serialize(&params, "test").unwrap(),
+                )
+                .unwrap();
+        };
+        let expect_gateway_call = |runtime: &mut MockRuntime, method: ipc_gateway::Method, code| {
+            runtime.expect_send(
+                Address::new_id(IPC_GATEWAY_ADDR),
+                method as u64,
+                RawBytes::default(),
+                TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT),
+                RawBytes::default(),
+                code,
+            );
+        };
+        let retry = |runtime: &mut MockRuntime| -> u64 {
+            runtime.expect_validate_caller_any();
+            runtime
+                .call::<Actor>(Method::RetryGatewayCalls as u64, &RawBytes::default())
+                .unwrap()
+                .deserialize()
+                .unwrap()
+        };
+
+        // the join goes through when the gateway rejects the registration
+        expect_gateway_call(
+            &mut runtime,
+            ipc_gateway::Method::Register,
+            ExitCode::USR_ILLEGAL_STATE,
+        );
+        join(&mut runtime, validators[0]);
+        let st: State = runtime.get_state();
+        assert_eq!(st.total_stake, collateral);
+        assert_eq!(st.pending_gateway_calls.len(), 1);
+
+        // later calls wait for the queued ones
+        join(&mut runtime, validators[1]);
+        let st: State = runtime.get_state();
+        assert_eq!(
+            st.pending_gateway_calls,
+            vec![
+                GatewayCall {
+                    to: Address::new_id(IPC_GATEWAY_ADDR),
+                    method: ipc_gateway::Method::Register as u64,
+                    params: RawBytes::default(),
+                    value: collateral.clone(),
+                },
+                GatewayCall {
+                    to: Address::new_id(IPC_GATEWAY_ADDR),
+                    method: ipc_gateway::Method::AddStake as u64,
+                    params: RawBytes::default(),
+                    value: collateral.clone(),
+                },
+            ]
+        );
+
+        // retries stop at the first call rejected again
+        expect_gateway_call(&mut runtime, ipc_gateway::Method::Register, ExitCode::OK);
+        expect_gateway_call(
+            &mut runtime,
+            ipc_gateway::Method::AddStake,
+            ExitCode::USR_ILLEGAL_STATE,
+        );
+        assert_eq!(retry(&mut runtime), 1);
+        expect_gateway_call(&mut runtime, ipc_gateway::Method::AddStake, ExitCode::OK);
+        assert_eq!(retry(&mut runtime), 1);
+        let st: State = runtime.get_state();
+        assert!(st.pending_gateway_calls.is_empty());
+        assert_eq!(retry(&mut runtime), 0);
+
+        // calls fail once the queue is full
+        let mut st: State = runtime.get_state();
+        let call = GatewayCall {
+            to: Address::new_id(IPC_GATEWAY_ADDR),
+            method: ipc_gateway::Method::AddStake as u64,
+            params: RawBytes::default(),
+            value: collateral.clone(),
+        };
+        st.pending_gateway_calls = vec![call; MAX_PENDING_GATEWAY_CALLS];
+        runtime.replace_state(&st);
+        let caller = Address::new_id(12);
+        let params = JoinParams {
+            validator_net_addr: net_addr(&caller),
+            invitation: None,
+            vesting: None,
+            worker: None,
+        };
+        runtime.set_value(collateral.clone());
+        runtime.set_caller(Cid::default(), caller);
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            SubnetActorError::GatewayQueueFull(ipc_gateway::Method::AddStake as u64).exit_code(),
+            runtime.call::<Actor>(
+                Method::Join as u64,
+                &cbor::serialize(&params, "test").unwrap(),
+            ),
+        );
+        runtime.verify();
+    }
+
+    #[test]
+    fn test_migrate_gateway_calls() {
+        let mut runtime = construct_runtime();
+
+        // simulate calls queued without the gateway they were sent to
+        let legacy = LegacyGatewayCall {
+            method: ipc_gateway::Method::AddStake as u64,
+            params: RawBytes::default(),
+            value: TokenAmount::from_atto(5),
+        };
+        let mut st: State = runtime.get_state();
+        st.version = 31;
+        st.legacy_pending_gateway_calls = Some(vec![legacy.clone()]);
+        runtime.replace_state(&st);
+
+        runtime.expect_validate_caller_any();
+        runtime
+            .call::<Actor>(Method::MigrateState as u64, &RawBytes::default())
+            .unwrap();
+
+        let st: State = runtime.get_state();
+        assert_eq!(st.version, STATE_VERSION);
+        assert!(st.legacy_pending_gateway_calls.is_none());
+        assert_eq!(
+            st.pending_gateway_calls,
+            vec![GatewayCall {
+                to: st.ipc_gateway_addr,
+                method: legacy.method,
+                params: legacy.params,
+                value: legacy.value,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_heartbeat() {
+        let mut runtime = construct_runtime();
+        let validators = [Address::new_id(10), Address::new_id(11)];
+        join_validators(&mut runtime, &validators);
+
+        let heartbeat = |runtime: &mut MockRuntime, caller: Address| {
+            runtime.set_caller(Cid::default(), caller);
+            runtime.expect_validate_caller_any();
+            runtime.call::<Actor>(Method::Heartbeat as u64, &RawBytes::default())
+        };
+        let live = |runtime: &mut MockRuntime, window: ChainEpoch| {
+            runtime.expect_validate_caller_any();
+            runtime
+                .call::<Actor>(
+                    Method::GetLiveValidators as u64,
+                    &RawBytes::serialize(window).unwrap(),
+                )
+                .unwrap()
+                .deserialize::<Vec<Address>>()
+                .unwrap()
+        };
+
+        expect_abort(
+            SubnetActorError::NotValidator(Address::new_id(12)).exit_code(),
+            heartbeat(&mut runtime, Address::new_id(12)),
+        );
+
+        runtime.set_epoch(10);
+        heartbeat(&mut runtime, validators[1]).unwrap();
+        runtime.set_epoch(12);
+        heartbeat(&mut runtime, validators[0]).unwrap();
+
+        runtime.set_epoch(15);
+        assert_eq!(live(&mut runtime, 5), validators.to_vec());
+        assert_eq!(live(&mut runtime, 3), vec![validators[0]]);
+        assert_eq!(live(&mut runtime, 2), Vec::<Address>::new());
+
+        let st: State = runtime.get_state();
+        assert_eq!(
+            st.get_last_seen(runtime.store(), &validators[1]).unwrap(),
+            Some(10)
+        );
+
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            runtime.call::<Actor>(
+                Method::GetLiveValidators as u64,
+                &RawBytes::serialize(-1 as ChainEpoch).unwrap(),
+            ),
+        );
+    }
+
+    #[test]
+    fn test_stats() {
+        let test_actor_address = Address::new_id(9999);
+        let mut runtime = construct_runtime_with_receiver(test_actor_address);
+        let validators = vec![Address::new_id(10), Address::new_id(20)];
+        join_validators(&mut runtime, &validators);
+
+        let subnet = SubnetID::new(&SubnetID::from_str("/root").unwrap(), test_actor_address);
+        let checkpoint = Checkpoint::new(subnet, DEFAULT_CHECKPOINT_PERIOD);
+        send_checkpoint(&mut runtime, validators[0], &checkpoint, false).unwrap();
+        send_checkpoint(&mut runtime, validators[1], &checkpoint, true).unwrap();
+
+        runtime.expect_validate_caller_any();
+        let stats: Stats = runtime
+            .call::<Actor>(Method::GetStats as u64, &RawBytes::default())
+            .unwrap()
+            .deserialize()
+            .unwrap();
+        assert_eq!(
+            stats,
+            Stats {
+                joins: 2,
+                committed_checkpoints: 1,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_check_invariants() {
+        let mut runtime = construct_runtime();
+        let validators = vec![Address::new_id(10), Address::new_id(20)];
+        join_validators(&mut runtime, &validators);
+
+        let st: State = runtime.get_state();
+        assert!(st.check_invariants(runtime.store()).unwrap().is_ok());
+
+        let mut st: State = runtime.get_state();
+        st.total_stake = TokenAmount::zero();
+        let report = st.check_invariants(runtime.store()).unwrap();
+        assert_eq!(report.violations.len(), 2);
+
+        // debug builds check the invariants after every message
+        if cfg!(debug_assertions) {
+            runtime.replace_state(&st);
+            runtime.expect_validate_caller_any();
+            expect_abort(
+                ExitCode::USR_ILLEGAL_STATE,
+                runtime.call::<Actor>(Method::GetPowerTable as u64, &RawBytes::default()),
+            );
+        }
+    }
+
+    #[test]
+    fn test_release_stake_for() {
+        let mut runtime = construct_runtime();
+        let gateway = Address::new_id(IPC_GATEWAY_ADDR);
+        let validators = vec![Address::new_id(10), Address::new_id(20)];
+        join_validators(&mut runtime, &validators);
+
+        let release = |runtime: &mut MockRuntime, caller, amount: TokenAmount| {
+            runtime.set_value(amount.clone());
+            runtime.set_caller(Cid::default(), caller);
+            runtime.expect_validate_caller_addr(vec![gateway]);
+            runtime.call::<Actor>(
+                Method::ReleaseStakeFor as u64,
+                &cbor::serialize(
+                    &ReleaseStakeForParams {
+                        validator: validators[0],
+                        amount,
+                    },
+                    "test",
+                )
+                .unwrap(),
+            )
+        };
+
+        // only the gateway can release stake, and not more than staked
+        expect_abort(
+            ExitCode::USR_FORBIDDEN,
+            release(&mut runtime, validators[0], TokenAmount::from_atto(1)),
+        );
+        expect_abort(
+            SubnetActorError::NotEnoughStake(validators[0]).exit_code(),
+            release(
+                &mut runtime,
+                gateway,
+                TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT + 1),
+            ),
+        );
+
+        // the released collateral is refunded to the validator, which is
+        // left without enough stake to validate
+        let amount = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT / 2);
+        runtime.expect_send(
+            validators[0],
+            METHOD_SEND,
+            RawBytes::default(),
+            amount.clone(),
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        release(&mut runtime, gateway, amount.clone()).unwrap();
+        runtime.verify();
+
+        let st: State = runtime.get_state();
+        assert_eq!(
+            st.get_stake(runtime.store(), &validators[0]).unwrap(),
+            Some(TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT) - &amount)
+        );
+        assert_eq!(
+            st.total_stake,
+            TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT * 2) - &amount
+        );
+        assert!(!st.is_validator(runtime.store(), &validators[0]).unwrap());
+        assert!(st.is_validator(runtime.store(), &validators[1]).unwrap());
+    }
+
+    #[test]
+    fn test_distribute_fees() {
+        let mut runtime = construct_runtime();
+        let gateway = Address::new_id(IPC_GATEWAY_ADDR);
+
+        // without validators all the fees go to the reward pool
+        runtime.set_value(TokenAmount::from_atto(10));
+        runtime.set_caller(Cid::default(), gateway);
+        runtime.expect_validate_caller_addr(vec![gateway]);
+        runtime
+            .call::<Actor>(Method::DistributeFees as u64, &RawBytes::default())
+            .unwrap();
+        runtime.verify();
+        let st: State = runtime.get_state();
+        assert_eq!(st.reward_pool, TokenAmount::from_atto(10));
+
+        let validators = vec![
+            Address::new_id(10),
+            Address::new_id(20),
+            Address::new_id(30),
+        ];
+        join_validators(&mut runtime, &validators);
+
+        // only the gateway can distribute fees
+        runtime.set_value(TokenAmount::from_atto(10));
+        runtime.set_caller(Cid::default(), validators[0]);
+        runtime.expect_validate_caller_addr(vec![gateway]);
+        expect_abort(
+            ExitCode::USR_FORBIDDEN,
+            runtime.call::<Actor>(Method::DistributeFees as u64, &RawBytes::default()),
+        );
+
+        // fees are split by stake and the remainder goes to the reward pool
+        runtime.set_caller(Cid::default(), gateway);
+        runtime.expect_validate_caller_addr(vec![gateway]);
+        for v in &validators {
+            runtime.expect_send(
+                *v,
+                METHOD_SEND,
+                RawBytes::default(),
+                TokenAmount::from_atto(3),
+                RawBytes::default(),
+                ExitCode::new(0),
+            );
+        }
+        runtime
+            .call::<Actor>(Method::DistributeFees as u64, &RawBytes::default())
+            .unwrap();
+        runtime.verify();
+        let st: State = runtime.get_state();
+        assert_eq!(st.reward_pool, TokenAmount::from_atto(11));
+    }
+
+    #[test]
+    fn test_get_stake_by_robust_address() {
+        let mut runtime = construct_runtime();
+
+        let validator = Address::new_id(10);
+        join_validators(&mut runtime, &[validator]);
+
+        let robust = Address::new_actor(b"validator");
+        runtime.add_id_address(robust, validator);
+
+        let get_stake = |runtime: &mut MockRuntime, addr: &Address| -> TokenAmount {
+            runtime.expect_validate_caller_any();
+            runtime
+                .call::<Actor>(
+                    Method::GetStakeByRobustAddress as u64,
+                    &cbor::serialize(addr, "test").unwrap(),
+                )
+                .unwrap()
+                .deserialize()
+                .unwrap()
+        };
+        assert_eq!(
+            get_stake(&mut runtime, &robust),
+            TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT)
+        );
+        assert_eq!(
+            get_stake(&mut runtime, &validator),
+            TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT)
+        );
+        // addresses without an actor yet have no stake
+        assert_eq!(
+            get_stake(&mut runtime, &Address::new_actor(b"unknown")),
+            TokenAmount::zero()
+        );
+    }
+
+    /// Subnet actor only accepting validators with an even actor ID, and
+    /// bottom-up messages that don't carry any value.
+    struct EvenActor;
+
+    impl SubnetActor for EvenActor {
+        fn constructor<BS, RT>(rt: &mut RT, params: ConstructParams) -> Result<(), ActorError>
+        where
+            BS: Blockstore,
+            RT: Runtime<BS>,
+        {
+            Actor::constructor(rt, params)
+        }
+
+        fn join<BS, RT>(rt: &mut RT, params: JoinParams) -> Result<JoinReturn, ActorError>
+        where
+            BS: Blockstore,
+            RT: Runtime<BS>,
+        {
+            if rt.message().caller().id().unwrap() % 2 != 0 {
+                return Err(ActorError::forbidden("odd validator".to_string()));
+            }
+            Actor::join(rt, params)
+        }
+
+        fn leave<BS, RT>(rt: &mut RT) -> Result<LeaveReturn, ActorError>
+        where
+            BS: Blockstore,
+            RT: Runtime<BS>,
+        {
+            Actor::leave(rt)
+        }
+
+        fn kill<BS, RT>(rt: &mut RT) -> Result<Option<RawBytes>, ActorError>
+        where
+            BS: Blockstore,
+            RT: Runtime<BS>,
+        {
+            Actor::kill(rt)
+        }
+
+        fn submit_checkpoint<BS, RT>(
+            rt: &mut RT,
+            submission: CheckpointSubmission,
+        ) -> Result<Option<RawBytes>, ActorError>
+        where
+            BS: Blockstore,
+            RT: Runtime<BS>,
+        {
+            Actor::vote_checkpoint::<Self, BS, RT>(rt, submission, false)
+        }
+
+        fn validate_cross_msg<BS, RT>(
+            _rt: &mut RT,
+            _ch: &Checkpoint,
+            meta: &CrossMsgMeta,
+        ) -> Result<(), ActorError>
+        where
+            BS: Blockstore,
+            RT: Runtime<BS>,
+        {
+            if meta.value.is_positive() {
+                return Err(ActorError::forbidden(
+                    "value leaving the subnet".to_string(),
+                ));
+            }
+            Ok(())
+        }
+    }
+
+    ipc_subnet_actor::impl_subnet_actor_code!(EvenActor);
+
+    #[test]
+    fn test_custom_subnet_actor() {
+        let caller = *INIT_ACTOR_ADDR;
+        let mut runtime = MockRuntime::new(Address::new_id(1), caller);
+        runtime.expect_validate_caller_addr(vec![caller]);
+        let parent = std_construct_param().parent;
+        expect_network_name(&mut runtime, IPC_GATEWAY_ADDR, &parent, ExitCode::OK);
+        runtime
+            .call::<EvenActor>(
+                Method::Constructor as u64,
+                &cbor::serialize(&std_construct_param(), "test").unwrap(),
+            )
+            .unwrap();
+
+        let params = JoinParams {
+            validator_net_addr: "/ip4/127.0.0.1/tcp/26656".parse().unwrap(),
+            invitation: None,
+            vesting: None,
+            worker: None,
+        };
+        runtime.set_value(TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT));
+        runtime.set_caller(Cid::default(), Address::new_id(11));
+        expect_abort(
+            ExitCode::USR_FORBIDDEN,
+            runtime.call::<EvenActor>(
+                Method::Join as u64,
+                &cbor::serialize(&params, "test").unwrap(),
+            ),
+        );
+
+        runtime.set_caller(Cid::default(), Address::new_id(10));
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::Register as u64,
+            RawBytes::default(),
+            TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT),
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        runtime
+            .call::<EvenActor>(
+                Method::Join as u64,
+                &cbor::serialize(&params, "test").unwrap(),
+            )
+            .unwrap();
+        let st: State = runtime.get_state();
+        assert!(st
+            .is_validator(runtime.store(), &Address::new_id(10))
+            .unwrap());
+
+        // methods outside of the trait are not routed
+        expect_abort(
+            ExitCode::USR_UNHANDLED_MESSAGE,
+            runtime.call::<EvenActor>(Method::AddStake as u64, &RawBytes::default()),
+        );
+
+        // checkpoints go through the policy of the actor on bottom-up messages
+        let validator = Address::new_id(10);
+        let epoch = next_checkpoint_epoch(&mut runtime);
+        let msgs_cid = TCid::new_amt(runtime.store()).unwrap();
+        runtime.set_caller(Cid::default(), validator);
+        runtime.set_value(TokenAmount::zero());
+        runtime.expect_validate_caller_any();
+        runtime
+            .call::<Actor>(
+                Method::RegisterCrossMsg as u64,
+                &cbor::serialize(
+                    &RegisterCrossMsgParams {
+                        epoch,
+                        msgs_cid: msgs_cid.cid(),
+                    },
+                    "test",
+                )
+                .unwrap(),
+            )
+            .unwrap();
+
+        let subnet = SubnetID::new(&parent, Address::new_id(1));
+        let mut checkpoint = Checkpoint::new(subnet, epoch);
+        checkpoint.data.cross_msgs = Some(CrossMsgMeta {
+            msgs_cid,
+            nonce: 0,
+            value: TokenAmount::from_atto(1),
+            fee: TokenAmount::zero(),
+        });
+        let sig = Signature::new_secp256k1(vec![1, 2, 3, 4]);
+        checkpoint.set_signature(RawBytes::serialize(&sig).unwrap().bytes().to_vec());
+        let submission = submission(&runtime, &checkpoint);
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            validator,
+            ext::account::PUBKEY_ADDRESS_METHOD as u64,
+            RawBytes::default(),
+            TokenAmount::zero(),
+            cbor::serialize(&validator, "test").unwrap(),
+            ExitCode::new(0),
+        );
+        runtime.expect_verify_signature(ExpectedVerifySig {
+            sig,
+            signer: validator,
+            plaintext: submission.digest().unwrap().to_bytes(),
+            result: Ok(()),
+        });
+        expect_abort(
+            ExitCode::USR_FORBIDDEN,
+            runtime.call::<EvenActor>(
+                Method::SubmitCheckpoint as u64,
+                &cbor::serialize(&submission, "test").unwrap(),
+            ),
+        );
+    }
+
+    fn net_addr(addr: &Address) -> NetAddress {
+        format!("/dns/{}.test/tcp/26656", addr).parse().unwrap()
+    }
+
+    /// State commitment of a checkpoint of the window at `epoch`, produced
+    /// under the validator set the window is voted with.
+    fn state_commitment(runtime: &MockRuntime, epoch: ChainEpoch) -> StateCommitment {
+        let st: State = runtime.get_state();
+        let config_number = st.window_config_number(runtime.store(), epoch).unwrap();
+        let (validator_set, _) = st
+            .get_validator_set_at(runtime.store(), config_number)
+            .unwrap()
+            .unwrap_or_default();
+        StateCommitment {
+            state_root: Cid::default(),
+            height: epoch,
+            config_number,
+            validator_set,
+        }
+    }
+
+    /// Submission of `checkpoint` with the state commitment of its window.
+    fn submission(runtime: &MockRuntime, checkpoint: &Checkpoint) -> CheckpointSubmission {
+        CheckpointSubmission {
+            checkpoint: checkpoint.clone(),
+            state_commitment: state_commitment(runtime, checkpoint.epoch()),
+        }
+    }
+
+    fn next_checkpoint_epoch(runtime: &mut MockRuntime) -> ChainEpoch {
+        runtime.expect_validate_caller_any();
+        runtime
+            .call::<Actor>(Method::NextCheckpointEpoch as u64, &RawBytes::default())
+            .unwrap()
+            .deserialize()
+            .unwrap()
+    }
+
+    /// Expects `amount` of native collateral released by the gateway, which
+    /// sends it back to the subnet actor, and refunded to `funder`.
+    fn expect_release(runtime: &mut MockRuntime, funder: Address, amount: &TokenAmount) {
+        runtime.add_balance(amount.clone());
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::ReleaseStake as u64,
+            RawBytes::serialize(FundParams {
+                value: amount.clone(),
+            })
+            .unwrap(),
+            TokenAmount::zero(),
+            RawBytes::default(),
+            ExitCode::OK,
+        );
+        runtime.expect_send(
+            funder,
+            METHOD_SEND,
+            RawBytes::default(),
+            amount.clone(),
+            RawBytes::default(),
+            ExitCode::OK,
+        );
+    }
+
+    fn join_validators(runtime: &mut MockRuntime, validators: &[Address]) {
+        for (i, caller) in validators.iter().enumerate() {
+            let params = JoinParams {
+                validator_net_addr: net_addr(caller),
+                invitation: None,
+                vesting: None,
+                worker: None,
+            };
+            runtime.set_value(TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT));
+            runtime.set_caller(Cid::default(), caller.clone());
+            runtime.expect_validate_caller_any();
+            let method = if i == 0 {
+                ipc_gateway::Method::Register
+            } else {
+                ipc_gateway::Method::AddStake
+            };
+            runtime.expect_send(
+                Address::new_id(IPC_GATEWAY_ADDR),
+                method as u64,
+                RawBytes::default(),
+                TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT),
+                RawBytes::default(),
+                ExitCode::new(0),
+            );
+            runtime
+                .call::<Actor>(
+                    Method::Join as u64,
+                    &cbor::serialize(&params, "test").unwrap(),
+                )
+                .unwrap();
+        }
+    }
+
+    fn expect_signature(
+        runtime: &mut MockRuntime,
+        signer: Address,
+        submission: &CheckpointSubmission,
+    ) {
+        runtime.expect_send(
+            signer.clone(),
+            ext::account::PUBKEY_ADDRESS_METHOD as u64,
+            RawBytes::default(),
+            TokenAmount::zero(),
+            cbor::serialize(&signer.clone(), "test").unwrap(),
+            ExitCode::new(0),
+        );
+        runtime.expect_verify_signature(ExpectedVerifySig {
+            sig: Signature::new_secp256k1(vec![1, 2, 3, 4]),
+            signer: signer.clone(),
+            plaintext: submission.digest().unwrap().to_bytes(),
+            result: Ok(()),
+        });
+    }
+
+    /// Submits `checkpoint` with the state commitment of its window.
+    fn send_checkpoint(
+        runtime: &mut MockRuntime,
+        sender: Address,
+        checkpoint: &Checkpoint,
+        is_commit: bool,
+    ) -> Result<RawBytes, ActorError> {
+        let submission = submission(runtime, checkpoint);
+        send_submission(runtime, sender, &submission, is_commit)
+    }
+
+    fn send_submission(
+        runtime: &mut MockRuntime,
+        sender: Address,
+        submission: &CheckpointSubmission,
+        is_commit: bool,
+    ) -> Result<RawBytes, ActorError> {
+        runtime.set_caller(Cid::default(), sender.clone());
+        runtime.expect_send(
+            sender.clone(),
+            ext::account::PUBKEY_ADDRESS_METHOD as u64,
+            RawBytes::default(),
+            TokenAmount::zero(),
+            cbor::serialize(&sender.clone(), "test").unwrap(),
+            ExitCode::new(0),
+        );
+        runtime.expect_validate_caller_any();
+        runtime.expect_verify_signature(ExpectedVerifySig {
+            sig: Signature::new_secp256k1(vec![1, 2, 3, 4]),
+            signer: sender.clone(),
+            plaintext: submission.digest().unwrap().to_bytes(),
+            result: Ok(()),
+        });
+
+        if is_commit {
+            runtime.expect_send(
+                Address::new_id(IPC_GATEWAY_ADDR),
+                ipc_gateway::Method::CommitChildCheckpoint as u64,
+                RawBytes::serialize(&submission.checkpoint)?,
+                TokenAmount::zero(),
+                cbor::serialize(&sender.clone(), "test").unwrap(),
+                ExitCode::new(0),
+            )
+        }
+        runtime.call::<Actor>(
+            Method::SubmitCheckpoint as u64,
+            &cbor::serialize(submission, "test").unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_subnet_summary() {
+        let test_actor_address = Address::new_id(9999);
+        let mut runtime = construct_runtime_with_receiver(test_actor_address);
+        let get = |runtime: &mut MockRuntime| -> SubnetSummary {
+            runtime.expect_validate_caller_any();
+            runtime
+                .call::<Actor>(Method::GetSubnetSummary as u64, &RawBytes::default())
+                .unwrap()
+                .deserialize()
+                .unwrap()
+        };
+
+        let summary = get(&mut runtime);
+        assert_eq!(summary.status, Status::Instantiated);
+        assert_eq!(summary.validators, 0);
+        assert_eq!(summary.last_committed_checkpoint, None);
+
+        let miners = vec![
+            Address::new_id(10),
+            Address::new_id(20),
+            Address::new_id(30),
+        ];
+        join_validators(&mut runtime, &miners);
+        let subnet = SubnetID::new(&SubnetID::from_str("/root").unwrap(), test_actor_address);
+        let sig = Signature::new_secp256k1(vec![1, 2, 3, 4]);
+        let mut checkpoint = Checkpoint::new(subnet, 10);
+        checkpoint.set_signature(RawBytes::serialize(sig).unwrap().bytes().to_vec());
+        send_checkpoint(&mut runtime, miners[0], &checkpoint, false).unwrap();
+        send_checkpoint(&mut runtime, miners[1], &checkpoint, true).unwrap();
+
+        // the summary matches the state it was read from
+        let st: State = runtime.get_state();
+        assert_eq!(
+            get(&mut runtime),
+            SubnetSummary {
+                status: st.status,
+                consensus: ConsensusType::Dummy,
+                total_stake: st.total_stake.clone(),
+                validators: 3,
+                last_committed_epoch: 10,
+                last_committed_checkpoint: Some(checkpoint.cid()),
+                next_checkpoint_epoch: st.next_checkpoint_epoch(),
+                econ: st.econ.clone(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_caller_policy() {
+        let mut params = std_construct_param();
+        params.caller_policies.join = CallerPolicy::AccountsAndMultisigs;
+        let mut runtime = construct_runtime_with_params(Address::new_id(1), params);
+
+        let join_params = JoinParams {
+            validator_net_addr: "/ip4/127.0.0.1/tcp/26656".parse().unwrap(),
+            invitation: None,
+            vesting: None,
+            worker: None,
+        };
+        let value = TokenAmount::from_atto(1);
+        let types = vec![Type::Account, Type::EthAccount, Type::Multisig];
+
+        // contracts can't join
+        runtime.set_value(value.clone());
+        runtime.set_caller(*MINER_ACTOR_CODE_ID, Address::new_id(10));
+        runtime.expect_validate_caller_type(types.clone());
+        expect_abort(
+            ExitCode::USR_FORBIDDEN,
+            runtime.call::<Actor>(
+                Method::Join as u64,
+                &cbor::serialize(&join_params, "test").unwrap(),
+            ),
+        );
+        runtime.verify();
+
+        // multisig validators are allowed, signing with a worker key
+        let msig = Address::new_id(11);
+        let worker = Address::new_secp256k1(&[2; 65]).unwrap();
+        runtime.add_id_address(worker, Address::new_id(12));
+        let join_params = JoinParams {
+            worker: Some(worker),
+            ..join_params
+        };
+        runtime.set_value(value.clone());
+        runtime.set_caller(*MULTISIG_ACTOR_CODE_ID, msig);
+        runtime.expect_validate_caller_type(types);
+        runtime
+            .call::<Actor>(
+                Method::Join as u64,
+                &cbor::serialize(&join_params, "test").unwrap(),
+            )
+            .unwrap();
+        runtime.verify();
+
+        let st: State = runtime.get_state();
+        let stake = st.get_stake(runtime.store(), &msig).unwrap();
+        assert_eq!(stake.unwrap(), value);
+    }
+
+    #[test]
+    fn test_multisig_validator() {
+        let test_actor_address = Address::new_id(9999);
+        let mut runtime = construct_runtime_with_receiver(test_actor_address);
+        let msig = Address::new_id(11);
+        let worker_key = Address::new_secp256k1(&[2; 65]).unwrap();
+        let worker = Address::new_id(12);
+        runtime.add_id_address(worker_key, worker);
+
+        let join = |runtime: &mut MockRuntime, worker: Option<Address>| {
+            runtime.set_value(TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT));
+            runtime.set_caller(*MULTISIG_ACTOR_CODE_ID, msig);
+            runtime.expect_validate_caller_any();
+            runtime.call::<Actor>(
+                Method::Join as u64,
+                &cbor::serialize(
+                    &JoinParams {
+                        validator_net_addr: net_addr(&msig),
+                        invitation: None,
+                        vesting: None,
+                        worker,
+                    },
+                    "test",
+                )
+                .unwrap(),
+            )
+        };
+
+        // multisigs can't sign checkpoints themselves
+        expect_abort(
+            SubnetActorError::WorkerKeyRequired(msig).exit_code(),
+            join(&mut runtime, None),
+        );
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            join(&mut runtime, Some(Address::new_id(12))),
+        );
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::Register as u64,
+            RawBytes::default(),
+            TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT),
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        join(&mut runtime, Some(worker_key)).unwrap();
+
+        let st: State = runtime.get_state();
+        assert_eq!(
+            st.signing_key(runtime.store(), &msig, 10).unwrap(),
+            Some(worker_key)
+        );
+        assert_eq!(
+            st.validator_for_caller(runtime.store(), &worker).unwrap(),
+            msig
+        );
+
+        // the worker submits checkpoints signed with its key for the multisig
+        let subnet = SubnetID::new(&SubnetID::from_str("/root").unwrap(), test_actor_address);
+        let sig = Signature::new_secp256k1(vec![1, 2, 3, 4]);
+        let mut checkpoint = Checkpoint::new(subnet, 10);
+        checkpoint.set_signature(RawBytes::serialize(sig.clone()).unwrap().bytes().to_vec());
+        let vote = submission(&runtime, &checkpoint);
+        runtime.set_caller(*ACCOUNT_ACTOR_CODE_ID, worker);
+        runtime.expect_validate_caller_any();
+        runtime.expect_verify_signature(ExpectedVerifySig {
+            sig,
+            signer: worker_key,
+            plaintext: vote.digest().unwrap().to_bytes(),
+            result: Ok(()),
+        });
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::CommitChildCheckpoint as u64,
+            RawBytes::serialize(&checkpoint).unwrap(),
+            TokenAmount::zero(),
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        runtime
+            .call::<Actor>(
+                Method::SubmitCheckpoint as u64,
+                &cbor::serialize(&vote, "test").unwrap(),
+            )
+            .unwrap();
+        runtime.verify();
+
+        // only the multisig can withdraw its stake
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            ExitCode::USR_ILLEGAL_STATE,
+            runtime.call::<Actor>(Method::Leave as u64, &RawBytes::default()),
+        );
+        runtime.set_caller(*MULTISIG_ACTOR_CODE_ID, msig);
+        runtime.expect_validate_caller_any();
+        expect_release(
+            &mut runtime,
+            msig,
+            &TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT),
+        );
+        runtime
+            .call::<Actor>(Method::Leave as u64, &RawBytes::default())
+            .unwrap();
+        runtime.verify();
+    }
+
+    #[test]
+    fn test_penalize_bad_checkpoint() {
+        let test_actor_address = Address::new_id(9999);
+        let mut params = std_construct_param();
+        params.checkpoint_penalty_rate = 100;
+        let mut runtime = construct_runtime_with_params(test_actor_address, params);
+        let miners = vec![Address::new_id(10), Address::new_id(20)];
+        join_validators(&mut runtime, &miners);
+
+        // a checkpoint signed for another subnet is recorded and penalized
+        let epoch = next_checkpoint_epoch(&mut runtime);
+        let wrong_source = SubnetID::new(&SubnetID::from_str("/root").unwrap(), Address::new_id(1));
+        let mut checkpoint = Checkpoint::new(wrong_source, epoch);
+        let sig = Signature::new_secp256k1(vec![1, 2, 3, 4]);
+        checkpoint.set_signature(RawBytes::serialize(sig).unwrap().bytes().to_vec());
+        send_checkpoint(&mut runtime, miners[0], &checkpoint, false).unwrap();
+        runtime.verify();
+
+        let penalty = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT / 100);
+        let st: State = runtime.get_state();
+        assert_eq!(
+            st.get_stake(runtime.store(), &miners[0]).unwrap().unwrap(),
+            TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT) - &penalty
+        );
+        assert_eq!(st.penalized_stake, penalty);
+        let record = st
+            .get_penalties(runtime.store(), &miners[0])
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.bad_submissions, 1);
+        assert_eq!(record.penalized, penalty);
+        assert!(st
+            .get_votes(
+                runtime.store(),
+                &submission(&runtime, &checkpoint).digest().unwrap()
+            )
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_penalize_whole_stake() {
+        let test_actor_address = Address::new_id(9999);
+        let mut params = std_construct_param();
+        params.checkpoint_penalty_rate = PENALTY_RATE_DENOMINATOR;
+        let mut runtime = construct_runtime_with_params(test_actor_address, params);
+        let caller = Address::new_id(10);
+        let amount = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+        let params = JoinParams {
+            validator_net_addr: net_addr(&caller),
+            invitation: None,
+            vesting: Some(VestingSchedule {
+                cliff: 10,
+                period: 10,
+                duration: 40,
+            }),
+            worker: None,
+        };
+        runtime.set_value(amount.clone());
+        runtime.set_caller(Cid::default(), caller);
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::Register as u64,
+            RawBytes::default(),
+            amount.clone(),
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        runtime
+            .call::<Actor>(
+                Method::Join as u64,
+                &cbor::serialize(&params, "test").unwrap(),
+            )
+            .unwrap();
+        runtime.set_value(TokenAmount::zero());
+        runtime.verify();
+
+        // a penalty taking the whole stake drops the staker, as leaving does
+        let epoch = next_checkpoint_epoch(&mut runtime);
+        let wrong_source = SubnetID::new(&SubnetID::from_str("/root").unwrap(), Address::new_id(1));
+        let mut checkpoint = Checkpoint::new(wrong_source, epoch);
+        let sig = Signature::new_secp256k1(vec![1, 2, 3, 4]);
+        checkpoint.set_signature(RawBytes::serialize(sig).unwrap().bytes().to_vec());
+        send_checkpoint(&mut runtime, caller, &checkpoint, false).unwrap();
+        runtime.verify();
+
+        let st: State = runtime.get_state();
+        assert_eq!(st.penalized_stake, amount);
+        assert_eq!(st.stakers_count, 0);
+        assert!(st.get_vesting(runtime.store(), &caller).unwrap().is_none());
+        assert!(!st.is_validator(runtime.store(), &caller).unwrap());
+        assert!(st.check_invariants(runtime.store()).unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_penalize_delegations() {
+        let test_actor_address = Address::new_id(9999);
+        let mut params = std_construct_param();
+        params.checkpoint_penalty_rate = 100;
+        let mut runtime = construct_runtime_with_params(test_actor_address, params);
+        let gateway = Address::new_id(IPC_GATEWAY_ADDR);
+        let miners = vec![Address::new_id(10), Address::new_id(20)];
+        let delegator = Address::new_id(50);
+        join_validators(&mut runtime, &miners);
+        let stake = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+
+        runtime.set_caller(Cid::default(), delegator);
+        runtime.set_value(stake.clone());
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            gateway,
+            ipc_gateway::Method::AddStake as u64,
+            RawBytes::default(),
+            stake.clone(),
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        runtime
+            .call::<Actor>(
+                Method::Delegate as u64,
+                &RawBytes::serialize(DelegateParams {
+                    validator: miners[0],
+                })
+                .unwrap(),
+            )
+            .unwrap();
+        runtime.set_value(TokenAmount::zero());
+
+        let epoch = next_checkpoint_epoch(&mut runtime);
+        let wrong_source = SubnetID::new(&SubnetID::from_str("/root").unwrap(), Address::new_id(1));
+        let mut checkpoint = Checkpoint::new(wrong_source, epoch);
+        let sig = Signature::new_secp256k1(vec![1, 2, 3, 4]);
+        checkpoint.set_signature(RawBytes::serialize(sig).unwrap().bytes().to_vec());
+        send_checkpoint(&mut runtime, miners[0], &checkpoint, false).unwrap();
+        runtime.verify();
+
+        // half of the penalty falls on the delegated stake
+        let penalty = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT / 100);
+        let st: State = runtime.get_state();
+        assert_eq!(
+            st.get_stake(runtime.store(), &miners[0]).unwrap().unwrap(),
+            &stake * 2 - &penalty * 2
+        );
+        let delegations = st
+            .get_delegations(runtime.store(), &miners[0])
+            .unwrap()
+            .unwrap();
+        assert_eq!(delegations.total, &stake - &penalty);
+        assert_eq!(delegations.delegators[0].amount, &stake - &penalty);
+        let funding = st.get_funding(runtime.store(), &miners[0]).unwrap();
+        assert!(funding.contains(&Funding {
+            funder: delegator,
+            amount: &stake - &penalty,
+        }));
+        assert!(st.check_invariants(runtime.store()).unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_max_submissions_per_window() {
+        let test_actor_address = Address::new_id(9999);
+        let subnet = SubnetID::new(&SubnetID::from_str("/root").unwrap(), test_actor_address);
+        let miners = vec![
+            Address::new_id(10),
+            Address::new_id(20),
+            Address::new_id(30),
+        ];
+        let sig = Signature::new_secp256k1(vec![1, 2, 3, 4]);
+        let signed = |runtime: &MockRuntime, epoch: ChainEpoch, height: ChainEpoch| {
+            let mut ch = Checkpoint::new(subnet.clone(), epoch);
+            ch.set_signature(RawBytes::serialize(sig.clone()).unwrap().bytes().to_vec());
+            let mut vote = submission(runtime, &ch);
+            vote.state_commitment.height = height;
+            vote
+        };
+
+        // submissions beyond the cap are penalized when penalties are enabled
+        let mut params = std_construct_param();
+        params.max_submissions_per_window = 1;
+        params.checkpoint_penalty_rate = 100;
+        let mut runtime = construct_runtime_with_params(test_actor_address, params);
+        join_validators(&mut runtime, &miners);
+        let epoch = next_checkpoint_epoch(&mut runtime);
+        let checkpoint = signed(&runtime, epoch, epoch);
+        send_submission(&mut runtime, miners[0], &checkpoint, false).unwrap();
+        let checkpoint = signed(&runtime, epoch, epoch - 1);
+        send_submission(&mut runtime, miners[0], &checkpoint, false).unwrap();
+        runtime.verify();
+
+        let st: State = runtime.get_state();
+        assert_eq!(
+            st.submissions_in_window(runtime.store(), &miners[0], runtime.epoch)
+                .unwrap(),
+            2
+        );
+        let record = st
+            .get_penalties(runtime.store(), &miners[0])
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.bad_submissions, 1);
+        assert_eq!(
+            record.penalized,
+            TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT / 100)
+        );
+
+        // and rejected otherwise
+        let mut params = std_construct_param();
+        params.max_submissions_per_window = 1;
+        let mut runtime = construct_runtime_with_params(test_actor_address, params);
+        join_validators(&mut runtime, &miners);
+        let epoch = next_checkpoint_epoch(&mut runtime);
+        let checkpoint = signed(&runtime, epoch, epoch);
+        send_submission(&mut runtime, miners[0], &checkpoint, false).unwrap();
+        send_submission(&mut runtime, miners[1], &checkpoint, true).unwrap();
+
+        // the count is reset when the window is committed
+        let epoch = next_checkpoint_epoch(&mut runtime);
+        let st: State = runtime.get_state();
+        assert_eq!(
+            st.submissions_in_window(runtime.store(), &miners[0], runtime.epoch)
+                .unwrap(),
+            0
+        );
+        let checkpoint = signed(&runtime, epoch, epoch);
+        send_submission(&mut runtime, miners[0], &checkpoint, false).unwrap();
+        runtime.verify();
+
+        let checkpoint = signed(&runtime, epoch, epoch - 1);
+        runtime.set_caller(Cid::default(), miners[0]);
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            SubnetActorError::TooManySubmissions(miners[0]).exit_code(),
+            runtime.call::<Actor>(
+                Method::SubmitCheckpoint as u64,
+                &cbor::serialize(&checkpoint, "test").unwrap(),
+            ),
+        );
+
+        // and when the votes of the window expire, so it can be voted again
+        runtime.set_epoch(epoch + DEFAULT_CHECKPOINT_PERIOD);
+        let st: State = runtime.get_state();
+        assert_eq!(
+            st.submissions_in_window(runtime.store(), &miners[0], runtime.epoch)
+                .unwrap(),
+            0
+        );
+        send_submission(&mut runtime, miners[0], &checkpoint, false).unwrap();
+        runtime.verify();
+        let st: State = runtime.get_state();
+        assert_eq!(st.stats.expired_windows, 1);
+        assert_eq!(
+            st.get_candidates(runtime.store(), epoch).unwrap(),
+            vec![checkpoint.digest().unwrap()]
+        );
+        assert_eq!(
+            st.submissions_in_window(runtime.store(), &miners[0], runtime.epoch)
+                .unwrap(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_econ_params() {
+        let mut runtime = construct_runtime();
+        let miners = vec![
+            Address::new_id(10),
+            Address::new_id(20),
+            Address::new_id(30),
+        ];
+        join_validators(&mut runtime, &miners);
+
+        let get = |runtime: &mut MockRuntime| -> EconParams {
+            runtime.expect_validate_caller_any();
+            runtime
+                .call::<Actor>(Method::GetEconParams as u64, &RawBytes::default())
+                .unwrap()
+                .deserialize()
+                .unwrap()
+        };
+        let propose = |runtime: &mut MockRuntime, caller: Address, params: &EconParams| {
+            runtime.set_caller(Cid::default(), caller);
+            runtime.expect_validate_caller_any();
+            runtime.call::<Actor>(
+                Method::ProposeEconParams as u64,
+                &RawBytes::serialize(params).unwrap(),
+            )
+        };
+        let current = get(&mut runtime);
+        assert_eq!(current, std_construct_param().econ_params());
+
+        // proposals are checked as construction parameters
+        let mut params = current.clone();
+        params.checkpoint_penalty_rate = PENALTY_RATE_DENOMINATOR + 1;
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            propose(&mut runtime, miners[0], &params),
+        );
+
+        // only validators vote, once
+        params.checkpoint_penalty_rate = 100;
+        params.relayer_fee = TokenAmount::from_atto(5);
+        expect_abort(
+            SubnetActorError::NotValidator(Address::new_id(50)).exit_code(),
+            propose(&mut runtime, Address::new_id(50), &params),
+        );
+        let applied: bool = propose(&mut runtime, miners[0], &params)
+            .unwrap()
+            .deserialize()
+            .unwrap();
+        assert!(!applied);
+        expect_abort(
+            SubnetActorError::AlreadyVoted(miners[0]).exit_code(),
+            propose(&mut runtime, miners[0], &params),
+        );
+        assert_eq!(get(&mut runtime), current);
+
+        // the parameters are applied once voted by a majority of the stake
+        let applied: bool = propose(&mut runtime, miners[1], &params)
+            .unwrap()
+            .deserialize()
+            .unwrap();
+        assert!(applied);
+        assert_eq!(get(&mut runtime), params);
+        let st: State = runtime.get_state();
+        assert!(st.econ_votes.proposals(runtime.store()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_treasury_spend() {
+        let test_actor_address = Address::new_id(9999);
+        let mut params = std_construct_param();
+        params.checkpoint_penalty_rate = 100;
+        let mut runtime = construct_runtime_with_params(test_actor_address, params);
+        let miners = vec![
+            Address::new_id(10),
+            Address::new_id(20),
+            Address::new_id(30),
+        ];
+        join_validators(&mut runtime, &miners);
+
+        // penalties fund the treasury
+        let epoch = next_checkpoint_epoch(&mut runtime);
+        let wrong_source = SubnetID::new(&SubnetID::from_str("/root").unwrap(), Address::new_id(1));
+        let mut checkpoint = Checkpoint::new(wrong_source, epoch);
+        let sig = Signature::new_secp256k1(vec![1, 2, 3, 4]);
+        checkpoint.set_signature(RawBytes::serialize(sig).unwrap().bytes().to_vec());
+        send_checkpoint(&mut runtime, miners[0], &checkpoint, false).unwrap();
+        let penalty = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT / 100);
+        let st: State = runtime.get_state();
+        assert_eq!(st.treasury, penalty);
+
+        let to = Address::new_id(500);
+        let propose = |runtime: &mut MockRuntime, amount: TokenAmount| {
+            runtime.set_caller(Cid::default(), miners[1]);
+            runtime.expect_validate_caller_any();
+            runtime.call::<Actor>(
+                Method::ProposeSpend as u64,
+                &RawBytes::serialize(SpendParams { to, amount }).unwrap(),
+            )
+        };
+        let approve = |runtime: &mut MockRuntime, caller: Address, cid: Cid| {
+            runtime.set_caller(Cid::default(), caller);
+            runtime.expect_validate_caller_any();
+            runtime.call::<Actor>(
+                Method::ApproveSpend as u64,
+                &RawBytes::serialize(cid).unwrap(),
+            )
+        };
+        let list = |runtime: &mut MockRuntime| -> Vec<SpendProposalInfo> {
+            runtime.expect_validate_caller_any();
+            runtime
+                .call::<Actor>(Method::ListSpendProposals as u64, &RawBytes::default())
+                .unwrap()
+                .deserialize()
+                .unwrap()
+        };
+
+        // proposals can't exceed the treasury
+        expect_abort(
+            SubnetActorError::InsufficientTreasury(&penalty + TokenAmount::from_atto(1))
+                .exit_code(),
+            propose(&mut runtime, &penalty + TokenAmount::from_atto(1)),
+        );
+
+        // the proposer approves its proposal
+        let cid: Cid = propose(&mut runtime, penalty.clone())
+            .unwrap()
+            .deserialize()
+            .unwrap();
+        let proposals = list(&mut runtime);
+        assert_eq!(proposals.len(), 1);
+        assert_eq!(proposals[0].cid, cid);
+        assert_eq!(proposals[0].proposal.amount, penalty);
+        assert_eq!(proposals[0].approvals, vec![miners[1]]);
+        expect_abort(
+            SubnetActorError::AlreadyVoted(miners[1]).exit_code(),
+            approve(&mut runtime, miners[1], cid),
+        );
+
+        // and it is paid once approved by a majority of the stake
+        runtime.set_balance(penalty.clone());
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::ReleaseStake as u64,
+            RawBytes::serialize(FundParams {
+                value: penalty.clone(),
+            })
+            .unwrap(),
+            TokenAmount::zero(),
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        runtime.expect_send(
+            to,
+            METHOD_SEND,
+            RawBytes::default(),
+            penalty.clone(),
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        let paid: bool = approve(&mut runtime, miners[2], cid)
+            .unwrap()
+            .deserialize()
+            .unwrap();
+        assert!(paid);
+        runtime.verify();
+
+        let st: State = runtime.get_state();
+        assert!(st.treasury.is_zero());
+        assert_eq!(st.penalized_stake, penalty);
+        assert!(list(&mut runtime).is_empty());
+    }
+
+    #[test]
+    fn test_join_fee() {
+        let fee = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT / 10);
+        let stake = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+        let (a, b, c) = (
+            Address::new_id(10),
+            Address::new_id(20),
+            Address::new_id(30),
+        );
+        let mut params = std_construct_param();
+        params.join_fee = fee.clone();
+        params.join_fee_exempt = vec![b];
+        let mut runtime = construct_runtime_with_params(Address::new_id(1), params.clone());
+
+        let join = |runtime: &mut MockRuntime, caller: Address, value: TokenAmount| {
+            let join_params = JoinParams {
+                validator_net_addr: net_addr(&caller),
+                invitation: None,
+                vesting: None,
+                worker: None,
+            };
+            runtime.set_value(value);
+            runtime.set_caller(Cid::default(), caller);
+            runtime.expect_validate_caller_any();
+            runtime.call::<Actor>(
+                Method::Join as u64,
+                &cbor::serialize(&join_params, "test").unwrap(),
+            )
+        };
+
+        // the collateral needs to cover the fee
+        expect_abort(
+            SubnetActorError::JoinFeeNotCovered(fee.clone()).exit_code(),
+            join(&mut runtime, a, fee.clone()),
+        );
+
+        // new stakers pay the fee on top of their collateral
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::Register as u64,
+            RawBytes::default(),
+            stake.clone(),
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        let ret: JoinReturn = join(&mut runtime, a, &stake + &fee)
+            .unwrap()
+            .deserialize()
+            .unwrap();
+        runtime.verify();
+        assert_eq!(ret.total_stake, stake);
+        let st: State = runtime.get_state();
+        assert_eq!(
+            st.get_stake(runtime.store(), &a).unwrap(),
+            Some(stake.clone())
+        );
+        assert_eq!(st.treasury, fee);
+        assert_eq!(st.treasury_held, fee);
+
+        // but not when adding to their stake, nor when exempted
+        for caller in [a, b] {
+            runtime.expect_send(
+                Address::new_id(IPC_GATEWAY_ADDR),
+                ipc_gateway::Method::AddStake as u64,
+                RawBytes::default(),
+                stake.clone(),
+                RawBytes::default(),
+                ExitCode::new(0),
+            );
+            join(&mut runtime, caller, stake.clone()).unwrap();
+            runtime.verify();
+        }
+        let st: State = runtime.get_state();
+        assert_eq!(st.treasury, fee);
+
+        // fees held by the actor are spent without releasing stake
+        let to = Address::new_id(500);
+        runtime.set_caller(Cid::default(), a);
+        runtime.expect_validate_caller_any();
+        let cid: Cid = runtime
+            .call::<Actor>(
+                Method::ProposeSpend as u64,
+                &RawBytes::serialize(SpendParams {
+                    to,
+                    amount: fee.clone(),
+                })
+                .unwrap(),
+            )
+            .unwrap()
+            .deserialize()
+            .unwrap();
+        runtime.set_balance(fee.clone());
+        runtime.set_caller(Cid::default(), b);
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            to,
+            METHOD_SEND,
+            RawBytes::default(),
+            fee.clone(),
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        runtime
+            .call::<Actor>(
+                Method::ApproveSpend as u64,
+                &RawBytes::serialize(cid).unwrap(),
+            )
+            .unwrap();
+        runtime.verify();
+        let st: State = runtime.get_state();
+        assert!(st.treasury.is_zero());
+        assert!(st.treasury_held.is_zero());
+
+        // leaving only returns the stake
+        runtime.set_caller(Cid::default(), a);
+        runtime.expect_validate_caller_any();
+        expect_release(&mut runtime, a, &(&stake * 2));
+        let ret: LeaveReturn = runtime
+            .call::<Actor>(Method::Leave as u64, &RawBytes::default())
+            .unwrap()
+            .deserialize()
+            .unwrap();
+        runtime.verify();
+        assert_eq!(ret.refunded, &stake * 2);
+
+        // fees can be burnt instead
+        params.join_fee_policy = JoinFeePolicy::Burn;
+        let mut runtime = construct_runtime_with_params(Address::new_id(1), params);
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::Register as u64,
+            RawBytes::default(),
+            stake.clone(),
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        runtime.expect_send(
+            *BURNT_FUNDS_ACTOR_ADDR,
+            METHOD_SEND,
+            RawBytes::default(),
+            fee.clone(),
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        join(&mut runtime, c, &stake + &fee).unwrap();
+        runtime.verify();
+        let st: State = runtime.get_state();
+        assert!(st.treasury.is_zero());
+    }
+
+    #[test]
+    fn test_kill_with_join_fees() {
+        let fee = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT / 10);
+        let stake = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+        let validator = Address::new_id(10);
+        let mut params = std_construct_param();
+        params.join_fee = fee.clone();
+        let mut runtime = construct_runtime_with_params(Address::new_id(1), params);
+
+        runtime.set_value(&stake + &fee);
+        runtime.set_balance(&stake + &fee);
+        runtime.set_caller(Cid::default(), validator);
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::Register as u64,
+            RawBytes::default(),
+            stake.clone(),
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        runtime
+            .call::<Actor>(
+                Method::Join as u64,
+                &cbor::serialize(
+                    &JoinParams {
+                        validator_net_addr: net_addr(&validator),
+                        invitation: None,
+                        vesting: None,
+                        worker: None,
+                    },
+                    "test",
+                )
+                .unwrap(),
+            )
+            .unwrap();
+        runtime.set_value(TokenAmount::zero());
+        runtime.expect_validate_caller_any();
+        expect_release(&mut runtime, validator, &stake);
+        runtime
+            .call::<Actor>(Method::Leave as u64, &RawBytes::default())
+            .unwrap();
+
+        // the fees held in the treasury can't be spent once the validators
+        // left, and are burnt on kill
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::Kill as u64,
+            RawBytes::default(),
+            TokenAmount::zero(),
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        runtime.expect_send(
+            *BURNT_FUNDS_ACTOR_ADDR,
+            METHOD_SEND,
+            RawBytes::default(),
+            fee.clone(),
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        runtime
+            .call::<Actor>(Method::Kill as u64, &RawBytes::default())
+            .unwrap();
+        runtime.verify();
+        let st: State = runtime.get_state();
+        assert!(st.treasury.is_zero());
+        assert!(st.treasury_held.is_zero());
+        assert_eq!(st.status, Status::Killed);
+    }
+
+    #[test]
+    fn test_checkpoint_voting_status() {
+        let test_actor_address = Address::new_id(9999);
+        let mut runtime = construct_runtime_with_receiver(test_actor_address);
+        let miners = vec![
+            Address::new_id(10),
+            Address::new_id(20),
+            Address::new_id(30),
+        ];
+        join_validators(&mut runtime, &miners);
+
+        let subnet = SubnetID::new(&SubnetID::from_str("/root").unwrap(), test_actor_address);
+        let epoch = next_checkpoint_epoch(&mut runtime);
+        let sig = Signature::new_secp256k1(vec![1, 2, 3, 4]);
+        let mut checkpoint = Checkpoint::new(subnet, epoch);
+        checkpoint.set_signature(RawBytes::serialize(sig).unwrap().bytes().to_vec());
+        let mut other = submission(&runtime, &checkpoint);
+        other.state_commitment.height = epoch - 1;
+
+        send_checkpoint(&mut runtime, miners[0], &checkpoint, false).unwrap();
+        send_submission(&mut runtime, miners[1], &other, false).unwrap();
+
+        let voting_status = |runtime: &mut MockRuntime| -> CheckpointVotingStatus {
+            runtime.expect_validate_caller_any();
+            runtime
+                .call::<Actor>(
+                    Method::GetCheckpointVotingStatus as u64,
+                    &RawBytes::serialize(epoch).unwrap(),
+                )
+                .unwrap()
+                .deserialize()
+                .unwrap()
+        };
+        let status = voting_status(&mut runtime);
+        let stake = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+        assert!(!status.committed);
+        assert_eq!(
+            status.total_weight,
+            TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT * 3)
+        );
+        assert_eq!(
+            status.threshold,
+            TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT * 2)
+        );
+        assert_eq!(status.voted, vec![miners[0], miners[1]]);
+        assert_eq!(status.candidates.len(), 2);
+        assert_eq!(
+            status.candidates[0].cid,
+            submission(&runtime, &checkpoint).digest().unwrap()
+        );
+        assert_eq!(status.candidates[0].validators, vec![miners[0]]);
+        assert_eq!(status.candidates[0].weight, stake);
+
+        // the votes of every candidate are removed once the window closes
+        send_checkpoint(&mut runtime, miners[2], &checkpoint, true).unwrap();
+        let status = voting_status(&mut runtime);
+        assert!(status.committed);
+        assert!(status.candidates.is_empty());
+        let st: State = runtime.get_state();
+        assert!(st
+            .get_votes(runtime.store(), &other.digest().unwrap())
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_competing_checkpoints() {
+        let test_actor_address = Address::new_id(9999);
+        let mut runtime = construct_runtime_with_receiver(test_actor_address);
+        let miners = vec![
+            Address::new_id(10),
+            Address::new_id(20),
+            Address::new_id(30),
+            Address::new_id(40),
+        ];
+        join_validators(&mut runtime, &miners);
+
+        // validators with forked views submit competing checkpoints
+        let subnet = SubnetID::new(&SubnetID::from_str("/root").unwrap(), test_actor_address);
+        let epoch = next_checkpoint_epoch(&mut runtime);
+        let checkpoint = Checkpoint::new(subnet, epoch);
+        let mut fork = submission(&runtime, &checkpoint);
+        fork.state_commitment.height = epoch - 1;
+        send_checkpoint(&mut runtime, miners[0], &checkpoint, false).unwrap();
+        send_submission(&mut runtime, miners[1], &fork, false).unwrap();
+        send_submission(&mut runtime, miners[2], &fork, false).unwrap();
+
+        // validators can't vote both sides of the fork
+        expect_abort(
+            SubnetActorError::ConflictingVote(miners[0]).exit_code(),
+            send_submission(&mut runtime, miners[0], &fork, false),
+        );
+
+        // the first candidate reaching the threshold is committed, and the
+        // rest are discarded
+        send_submission(&mut runtime, miners[3], &fork, true).unwrap();
+        let st: State = runtime.get_state();
+        assert_eq!(
+            st.last_state_commitment.as_ref(),
+            Some(&fork.state_commitment)
+        );
+        assert!(st
+            .get_votes(
+                runtime.store(),
+                &submission(&runtime, &checkpoint).digest().unwrap()
+            )
+            .unwrap()
+            .is_none());
+        assert!(st
+            .get_candidates(runtime.store(), epoch)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_sig_scheme() {
+        let mut params = std_construct_param();
+        params.sig_scheme = SigScheme::AcceptAll;
+        assert_eq!(params.validate().is_ok(), cfg!(feature = "testing"));
+
+        // BLS subnets reject checkpoints signed with secp256k1
+        params.sig_scheme = SigScheme::Bls;
+        let test_actor_address = Address::new_id(9999);
+        let mut runtime = construct_runtime_with_params(test_actor_address, params);
+        let miners = vec![Address::new_id(10), Address::new_id(20)];
+        join_validators(&mut runtime, &miners);
+
+        let subnet = SubnetID::new(&SubnetID::from_str("/root").unwrap(), test_actor_address);
+        let epoch = next_checkpoint_epoch(&mut runtime);
+        let checkpoint = Checkpoint::new(subnet, epoch);
+        expect_abort(
+            ExitCode::USR_ILLEGAL_STATE,
+            send_checkpoint(&mut runtime, miners[0], &checkpoint, false),
+        );
+    }
+
+    #[test]
+    fn test_vote_expiry() {
+        let test_actor_address = Address::new_id(9999);
+        let mut runtime = construct_runtime_with_receiver(test_actor_address);
+        let miners = vec![
+            Address::new_id(10),
+            Address::new_id(20),
+            Address::new_id(30),
+        ];
+        join_validators(&mut runtime, &miners);
+
+        let subnet = SubnetID::new(&SubnetID::from_str("/root").unwrap(), test_actor_address);
+        let epoch = next_checkpoint_epoch(&mut runtime);
+        let checkpoint = Checkpoint::new(subnet, epoch);
+        let mut other = submission(&runtime, &checkpoint);
+        other.state_commitment.height = epoch - 1;
+        send_checkpoint(&mut runtime, miners[0], &checkpoint, false).unwrap();
+
+        // votes hold while the window after the one they were cast in is open
+        runtime.set_epoch(epoch + DEFAULT_CHECKPOINT_PERIOD / 2);
+        expect_abort(
+            SubnetActorError::ConflictingVote(miners[0]).exit_code(),
+            send_submission(&mut runtime, miners[0], &other, false),
+        );
+
+        // and expire once it closes without quorum
+        runtime.set_epoch(epoch + DEFAULT_CHECKPOINT_PERIOD);
+        send_submission(&mut runtime, miners[0], &other, false).unwrap();
+        let st: State = runtime.get_state();
+        assert_eq!(st.stats.expired_windows, 1);
+        assert!(st
+            .get_votes(
+                runtime.store(),
+                &submission(&runtime, &checkpoint).digest().unwrap()
+            )
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            st.get_candidates(runtime.store(), epoch).unwrap(),
+            vec![other.digest().unwrap()]
+        );
+        assert!(!st.votes_expired(epoch, epoch + DEFAULT_CHECKPOINT_PERIOD));
+    }
+
+    #[test]
+    fn test_voting() {
+        let store = MemoryBlockstore::new();
+        let mut voting: Voting<Checkpoint> = Voting::new(&store).unwrap();
+        let proposal = Cid::default();
+        let (a, b, c) = (
+            Address::new_id(10),
+            Address::new_id(20),
+            Address::new_id(30),
+        );
+        let snapshot = WeightSnapshot {
+            weights: vec![
+                (a, TokenAmount::from_atto(1)),
+                (b, TokenAmount::from_atto(1)),
+                (c, TokenAmount::from_atto(1)),
+            ],
+            total: TokenAmount::from_atto(3),
+            config_number: 0,
+            collateral: Vec::new(),
+        };
+
+        voting
+            .set(
+                &store,
+                &proposal,
+                Votes {
+                    validators: vec![a],
+                },
+            )
+            .unwrap();
+        let tally = voting
+            .tally(&store, &proposal, &snapshot, |_| Ok(false))
+            .unwrap();
+        assert_eq!(tally.weight, TokenAmount::from_atto(1));
+        assert_eq!(tally.threshold(), TokenAmount::from_atto(2));
+        assert!(!tally.has_majority());
+
+        // excluded validators are left out of the quorum
+        let tally = voting
+            .tally(&store, &proposal, &snapshot, |addr| Ok(*addr == c))
+            .unwrap();
+        assert_eq!(
+            tally,
+            Tally {
+                weight: TokenAmount::from_atto(1),
+                total: TokenAmount::from_atto(2),
+            }
+        );
+        assert!(!tally.has_majority());
+
+        voting
+            .set(
+                &store,
+                &proposal,
+                Votes {
+                    validators: vec![a, b],
+                },
+            )
+            .unwrap();
+        let tally = voting
+            .tally(&store, &proposal, &snapshot, |_| Ok(false))
+            .unwrap();
+        assert!(tally.has_majority());
+
+        voting.remove(&store, &[proposal]).unwrap();
+        assert_eq!(voting.get(&store, &proposal).unwrap(), None);
+    }
+
+    #[test]
+    fn test_quorum_policy() {
+        let (a, b, c) = (
+            Address::new_id(10),
+            Address::new_id(20),
+            Address::new_id(30),
+        );
+        let snapshot = WeightSnapshot {
+            weights: vec![
+                (a, TokenAmount::from_atto(8)),
+                (b, TokenAmount::from_atto(1)),
+                (c, TokenAmount::from_atto(1)),
+            ],
+            total: TokenAmount::from_atto(10),
+            config_number: 0,
+            collateral: Vec::new(),
+        };
+        let votes = |validators: Vec<Address>| Votes { validators };
+
+        // BFT consensuses weigh votes by stake
+        let policy = ConsensusType::Mir.quorum_policy();
+        let tally = Tally::with_policy(policy, &snapshot, &votes(vec![a]), |_| Ok(false)).unwrap();
+        assert_eq!(tally.weight, TokenAmount::from_atto(8));
+        assert_eq!(policy.threshold(&tally), TokenAmount::from_atto(7));
+        assert!(policy.has_quorum(&tally));
+        let tally =
+            Tally::with_policy(policy, &snapshot, &votes(vec![b, c]), |_| Ok(false)).unwrap();
+        assert!(!policy.has_quorum(&tally));
+
+        // PoW counts validators regardless of their stake
+        let policy = ConsensusType::PoW.quorum_policy();
+        let tally = Tally::with_policy(policy, &snapshot, &votes(vec![a]), |_| Ok(false)).unwrap();
+        assert_eq!(
+            tally,
+            Tally {
+                weight: TokenAmount::from_atto(1),
+                total: TokenAmount::from_atto(3),
+            }
+        );
+        assert_eq!(policy.threshold(&tally), TokenAmount::from_atto(2));
+        assert!(!policy.has_quorum(&tally));
+        let tally =
+            Tally::with_policy(policy, &snapshot, &votes(vec![b, c]), |_| Ok(false)).unwrap();
+        assert!(policy.has_quorum(&tally));
+
+        // excluded validators are left out of the count
+        let tally =
+            Tally::with_policy(policy, &snapshot, &votes(vec![b]), |addr| Ok(*addr == a)).unwrap();
+        assert_eq!(tally.total, TokenAmount::from_atto(2));
+        assert!(!policy.has_quorum(&tally));
+        let tally =
+            Tally::with_policy(policy, &snapshot, &votes(vec![b, c]), |addr| Ok(*addr == a))
+                .unwrap();
+        assert!(policy.has_quorum(&tally));
+    }
+
+    #[test]
+    fn test_pause() {
+        let owner = Address::new_id(5);
+        let mut params = std_construct_param();
+        params.owner = Some(owner);
+        let mut runtime = construct_runtime_with_params(Address::new_id(1), params);
+
+        // only the owner can pause the actor
+        let caller = Address::new_id(10);
+        runtime.set_caller(Cid::default(), caller);
+        runtime.expect_validate_caller_addr(vec![owner]);
+        expect_abort(
+            ExitCode::USR_FORBIDDEN,
+            runtime.call::<Actor>(Method::Pause as u64, &RawBytes::default()),
+        );
+
+        runtime.set_caller(Cid::default(), owner);
+        runtime.expect_validate_caller_addr(vec![owner]);
+        runtime
+            .call::<Actor>(Method::Pause as u64, &RawBytes::default())
+            .unwrap();
+        runtime.verify();
+
+        // joining is frozen, reads are not
+        let join_params = JoinParams {
+            validator_net_addr: net_addr(&caller),
+            invitation: None,
+            vesting: None,
+            worker: None,
+        };
+        runtime.set_caller(Cid::default(), caller);
+        runtime.set_value(TokenAmount::from_atto(1));
+        expect_abort(
+            SubnetActorError::Paused.exit_code(),
+            runtime.call::<Actor>(
+                Method::Join as u64,
+                &cbor::serialize(&join_params, "test").unwrap(),
+            ),
+        );
+        expect_abort(
+            SubnetActorError::Paused.exit_code(),
+            runtime.call::<Actor>(Method::MigrateState as u64, &RawBytes::default()),
+        );
+        assert_eq!(
+            next_checkpoint_epoch(&mut runtime),
+            DEFAULT_CHECKPOINT_PERIOD
+        );
+        runtime.verify();
+
+        // the gateway can still release stake, which it sends funds along
+        assert!(!Method::ReleaseStakeFor.is_pausable());
+
+        runtime.set_caller(Cid::default(), owner);
+        runtime.expect_validate_caller_addr(vec![owner]);
+        runtime
+            .call::<Actor>(Method::Unpause as u64, &RawBytes::default())
+            .unwrap();
+
+        runtime.set_caller(Cid::default(), caller);
+        runtime.expect_validate_caller_any();
+        runtime
+            .call::<Actor>(
+                Method::Join as u64,
+                &cbor::serialize(&join_params, "test").unwrap(),
+            )
+            .unwrap();
+        runtime.verify();
+    }
+
+    #[test]
+    fn test_register_with_gateway_once() {
+        let mut runtime = construct_runtime();
+        let caller = Address::new_id(10);
+        let value = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+        let join_params = JoinParams {
+            validator_net_addr: net_addr(&caller),
+            invitation: None,
+            vesting: None,
+            worker: None,
+        };
+
+        for method in [
+            ipc_gateway::Method::Register,
+            ipc_gateway::Method::AddStake,
+            ipc_gateway::Method::AddStake,
+        ] {
+            runtime.set_caller(Cid::default(), caller);
+            runtime.set_value(value.clone());
+            runtime.expect_validate_caller_any();
+            runtime.expect_send(
+                Address::new_id(IPC_GATEWAY_ADDR),
+                method as u64,
+                RawBytes::default(),
+                value.clone(),
+                RawBytes::default(),
+                ExitCode::new(0),
+            );
+            runtime
+                .call::<Actor>(
+                    Method::Join as u64,
+                    &cbor::serialize(&join_params, "test").unwrap(),
+                )
+                .unwrap();
+            runtime.verify();
+            let st: State = runtime.get_state();
+            assert_eq!(st.status, Status::Active);
+            assert!(st.registered_with_gateway);
+
+            runtime.set_value(TokenAmount::zero());
+            runtime.expect_validate_caller_any();
+            expect_release(&mut runtime, caller, &value);
+            runtime
+                .call::<Actor>(Method::Leave as u64, &RawBytes::default())
+                .unwrap();
+            runtime.verify();
+            let st: State = runtime.get_state();
+            assert_eq!(st.status, Status::Inactive);
+        }
+
+        // subnets active before the flag was introduced are registered
+        let mut st: State = runtime.get_state();
+        st.version = 11;
+        st.registered_with_gateway = false;
+        runtime.replace_state(&st);
+        runtime.expect_validate_caller_any();
+        runtime
+            .call::<Actor>(Method::MigrateState as u64, &RawBytes::default())
+            .unwrap();
+        let st: State = runtime.get_state();
+        assert!(st.registered_with_gateway);
+    }
+
+    #[test]
+    fn test_delegation_commission() {
+        let mut runtime = construct_runtime();
+        let gateway = Address::new_id(IPC_GATEWAY_ADDR);
+        let validator = Address::new_id(10);
+        let delegator = Address::new_id(50);
+        join_validators(&mut runtime, &[validator]);
+        let stake = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+
+        runtime.set_caller(Cid::default(), delegator);
+        runtime.set_value(stake.clone());
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            gateway,
+            ipc_gateway::Method::AddStake as u64,
+            RawBytes::default(),
+            stake.clone(),
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        runtime
+            .call::<Actor>(
+                Method::Delegate as u64,
+                &RawBytes::serialize(DelegateParams { validator }).unwrap(),
+            )
+            .unwrap();
+
+        // the validator keeps 10% of the fees earned by the delegated stake
+        runtime.set_caller(Cid::default(), validator);
+        runtime.set_value(TokenAmount::zero());
+        runtime.expect_validate_caller_any();
+        runtime
+            .call::<Actor>(
+                Method::SetCommissionRate as u64,
+                &RawBytes::serialize(1000u64).unwrap(),
+            )
+            .unwrap();
+        runtime.verify();
+
+        let distribute = |runtime: &mut MockRuntime, shares: [(Address, i64); 2]| {
+            runtime.set_caller(Cid::default(), gateway);
+            runtime.set_value(TokenAmount::from_atto(100));
+            runtime.expect_validate_caller_addr(vec![gateway]);
+            for (to, amount) in shares {
+                runtime.expect_send(
+                    to,
+                    METHOD_SEND,
+                    RawBytes::default(),
+                    TokenAmount::from_atto(amount),
+                    RawBytes::default(),
+                    ExitCode::new(0),
+                );
+            }
+            runtime
+                .call::<Actor>(Method::DistributeFees as u64, &RawBytes::default())
+                .unwrap();
+            runtime.verify();
+        };
+
+        // once the delay of the increase is over
+        distribute(&mut runtime, [(validator, 50), (delegator, 50)]);
+        let st: State = runtime.get_state();
+        assert_eq!(st.commission_changes.len(), 1);
+        runtime.set_epoch(COMMISSION_CHANGE_DELAY_WINDOWS * DEFAULT_CHECKPOINT_PERIOD);
+        distribute(&mut runtime, [(validator, 55), (delegator, 45)]);
+        let st: State = runtime.get_state();
+        assert!(st.commission_changes.is_empty());
+
+        // the validator can only withdraw its own stake
+        let release = |runtime: &mut MockRuntime, caller: Address| {
+            runtime.set_caller(Cid::default(), caller);
+            runtime.set_value(TokenAmount::zero());
+            runtime.expect_validate_caller_any();
+            expect_release(runtime, caller, &stake);
+        };
+        release(&mut runtime, validator);
+        let ret: LeaveReturn = runtime
+            .call::<Actor>(Method::Leave as u64, &RawBytes::default())
+            .unwrap()
+            .deserialize()
+            .unwrap();
+        assert_eq!(ret.remaining_stake, stake);
+        runtime.verify();
+
+        // and the delegator gets its delegated stake back
+        release(&mut runtime, delegator);
+        let amount: TokenAmount = runtime
+            .call::<Actor>(
+                Method::Undelegate as u64,
+                &RawBytes::serialize(UndelegateParams { validator }).unwrap(),
+            )
+            .unwrap()
+            .deserialize()
+            .unwrap();
+        assert_eq!(amount, stake);
+        runtime.verify();
+
+        let st: State = runtime.get_state();
+        assert_eq!(st.total_stake, TokenAmount::zero());
+        assert_eq!(
+            st.get_delegations(runtime.store(), &validator)
+                .unwrap()
+                .unwrap()
+                .total,
+            TokenAmount::zero()
+        );
+    }
+
+    #[test]
+    fn test_redelegate() {
+        let mut runtime = construct_runtime();
+        let (a, b) = (Address::new_id(10), Address::new_id(20));
+        let delegator = Address::new_id(50);
+        join_validators(&mut runtime, &[a, b]);
+        let stake = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+
+        runtime.set_caller(Cid::default(), delegator);
+        runtime.set_value(&stake * 2);
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::AddStake as u64,
+            RawBytes::default(),
+            &stake * 2,
+            RawBytes::default(),
+            ExitCode::new(0),
+        );
+        runtime
+            .call::<Actor>(
+                Method::Delegate as u64,
+                &RawBytes::serialize(DelegateParams { validator: a }).unwrap(),
+            )
+            .unwrap();
+        runtime.verify();
+        runtime.set_value(TokenAmount::zero());
+
+        let redelegate = |runtime: &mut MockRuntime, to: Address, amount: TokenAmount| {
+            runtime.expect_validate_caller_any();
+            runtime.call::<Actor>(
+                Method::Redelegate as u64,
+                &RawBytes::serialize(RedelegateParams {
+                    from: a,
+                    to,
+                    amount,
+                })
+                .unwrap(),
+            )
+        };
+        expect_abort(
+            SubnetActorError::NotValidator(Address::new_id(30)).exit_code(),
+            redelegate(&mut runtime, Address::new_id(30), stake.clone()),
+        );
+        expect_abort(
+            SubnetActorError::NotEnoughStake(delegator).exit_code(),
+            redelegate(&mut runtime, b, &stake * 3),
+        );
+
+        // the stake moves without going through the gateway
+        redelegate(&mut runtime, b, stake.clone()).unwrap();
+        runtime.verify();
+        let st: State = runtime.get_state();
+        assert_eq!(st.total_stake, &stake * 4);
+        for v in [a, b] {
+            assert_eq!(st.get_stake(runtime.store(), &v).unwrap(), Some(&stake * 2));
+            assert_eq!(st.delegated_stake(runtime.store(), &v).unwrap(), stake);
+        }
+
+        // and can't move again until the cooldown is over
+        let until = REDELEGATION_COOLDOWN_WINDOWS * DEFAULT_CHECKPOINT_PERIOD;
+        assert_eq!(
+            st.redelegation_cooldown_end(runtime.store(), &delegator)
+                .unwrap(),
+            Some(until)
+        );
+        expect_abort(
+            SubnetActorError::RedelegationCooldown(until).exit_code(),
+            redelegate(&mut runtime, b, stake.clone()),
+        );
+        runtime.set_epoch(until);
+        redelegate(&mut runtime, b, stake.clone()).unwrap();
+        let st: State = runtime.get_state();
+        assert_eq!(
+            st.get_stake(runtime.store(), &a).unwrap(),
+            Some(stake.clone())
+        );
+        assert_eq!(st.get_delegations(runtime.store(), &a).unwrap(), None);
+        assert_eq!(st.delegated_stake(runtime.store(), &b).unwrap(), &stake * 2);
+    }
+
+    #[test]
+    fn test_dry_run() {
+        let mut runtime = construct_runtime();
+        let (a, b) = (Address::new_id(10), Address::new_id(20));
+        let stake = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+
+        let dry_run = |runtime: &mut MockRuntime, method: Method, params: RawBytes| {
+            runtime.expect_validate_caller_any();
+            let ret: DryRunReturn = runtime
+                .call::<Actor>(method as u64, &params)
+                .unwrap()
+                .deserialize()
+                .unwrap();
+            ret.exit_code
+        };
+        let can_join = |runtime: &mut MockRuntime, validator: Address, amount: TokenAmount| {
+            let params = CanJoinParams {
+                validator,
+                amount,
+                invitation: None,
+            };
+            dry_run(
+                runtime,
+                Method::CanJoin,
+                RawBytes::serialize(params).unwrap(),
+            )
+        };
+        let can_leave =
+            |runtime: &mut MockRuntime, validator: Address, amount: Option<TokenAmount>| {
+                let params = CanLeaveParams { validator, amount };
+                dry_run(
+                    runtime,
+                    Method::CanLeave,
+                    RawBytes::serialize(params).unwrap(),
+                )
+            };
+        let can_submit = |runtime: &mut MockRuntime, validator: Address, epoch: ChainEpoch| {
+            let params = CanSubmitCheckpointParams { validator, epoch };
+            dry_run(
+                runtime,
+                Method::CanSubmitCheckpoint,
+                RawBytes::serialize(params).unwrap(),
+            )
+        };
+
+        assert_eq!(
+            can_join(&mut runtime, a, TokenAmount::zero()),
+            ExitCode::USR_ILLEGAL_ARGUMENT
+        );
+        assert_eq!(can_join(&mut runtime, a, stake.clone()), ExitCode::OK);
+        assert_eq!(
+            can_leave(&mut runtime, a, None),
+            ExitCode::USR_ILLEGAL_STATE
+        );
+        assert_eq!(
+            can_submit(&mut runtime, a, DEFAULT_CHECKPOINT_PERIOD),
+            SubnetActorError::NotValidator(a).exit_code()
+        );
+
+        // the dry runs don't change the state
+        let st: State = runtime.get_state();
+        assert_eq!(st.total_stake, TokenAmount::zero());
+
+        join_validators(&mut runtime, &[a]);
+        assert_eq!(can_leave(&mut runtime, a, None), ExitCode::OK);
+        // validators can only withdraw part of their stake while they keep
+        // the minimum stake
+        assert_eq!(
+            can_leave(&mut runtime, a, Some(stake.clone())),
+            SubnetActorError::WithdrawalBelowMinStake(a).exit_code()
+        );
+        assert_eq!(
+            can_leave(&mut runtime, a, Some(&stake * 2)),
+            SubnetActorError::NotEnoughStake(a).exit_code()
+        );
+        assert_eq!(
+            can_leave(&mut runtime, b, None),
+            ExitCode::USR_ILLEGAL_STATE
+        );
+        let st: State = runtime.get_state();
+        assert_eq!(st.total_stake, stake);
+
+        let epoch = DEFAULT_CHECKPOINT_PERIOD;
+        assert_eq!(can_submit(&mut runtime, a, epoch), ExitCode::OK);
+        assert_eq!(
+            can_submit(&mut runtime, a, epoch + 1),
+            SubnetActorError::NotSigningWindow.exit_code()
+        );
+        // later windows can only be caught up with once closed
+        assert_eq!(
+            can_submit(&mut runtime, a, epoch * 2),
+            SubnetActorError::NoSkippedWindows.exit_code()
+        );
+        runtime.set_epoch(epoch * 2);
+        assert_eq!(can_submit(&mut runtime, a, epoch * 2), ExitCode::OK);
+    }
+
+    #[test]
+    fn test_no_panic_on_extreme_inputs() {
+        let mut runtime = construct_runtime();
+
+        // leaving without a stake used to unwrap a missing stake
+        runtime.set_caller(Cid::default(), Address::new_id(10));
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            ExitCode::USR_ILLEGAL_STATE,
+            runtime.call::<Actor>(Method::Leave as u64, &RawBytes::default()),
+        );
+
+        // epochs are given by callers and can be anywhere in the range
+        let st: State = runtime.get_state();
+        assert!(!st.is_window_epoch(ChainEpoch::MIN));
+        assert!(st.is_window_epoch(0));
+        assert_eq!(
+            st.relay_epoch(ChainEpoch::MAX, ChainEpoch::MAX),
+            ChainEpoch::MAX
+        );
     }
 }
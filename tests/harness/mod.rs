@@ -0,0 +1,329 @@
+//! Harness deploying the subnet actor next to a real IPC gateway actor
+//! on the FVM, through `fvm_integration_tests::Tester`.
+//!
+//! The compiled gateway WASM and a builtin-actors bundle are read from the
+//! paths in the `IPC_GATEWAY_WASM` and `BUILTIN_ACTORS_BUNDLE` environment
+//! variables.
+#![allow(dead_code)]
+
+use cid::Cid;
+use fil_actors_runtime::INIT_ACTOR_ADDR;
+use fvm::executor::{ApplyKind, ApplyRet, Executor};
+use fvm::state_tree::ActorState;
+use fvm_integration_tests::bundle;
+use fvm_integration_tests::dummy::DummyExterns;
+use fvm_integration_tests::tester::{Account, Tester};
+use fvm_ipld_blockstore::MemoryBlockstore;
+use fvm_ipld_encoding::{CborStore, RawBytes};
+use fvm_shared::address::Address;
+use fvm_shared::clock::ChainEpoch;
+use fvm_shared::crypto::signature::Signature;
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::message::Message;
+use fvm_shared::state::StateTreeVersion;
+use fvm_shared::version::NetworkVersion;
+use fvm_shared::ActorID;
+use ipc_gateway::{Checkpoint, SubnetID, DEFAULT_CHECKPOINT_PERIOD, MIN_COLLATERAL_AMOUNT};
+use ipc_subnet_actor::caller_policy::CallerPolicies;
+use ipc_subnet_actor::sig::SigScheme;
+use ipc_subnet_actor::stake_math::StakeRatio;
+use ipc_subnet_actor::{
+    CheckpointSubmission, ConsensusType, ConstructParams, ExcessStakePolicy, JoinFeePolicy,
+    JoinParams, Method, State, StateCommitment,
+};
+use libsecp256k1::{Message as SecpMessage, SecretKey};
+use num_traits::Zero;
+use std::str::FromStr;
+
+pub const GATEWAY_ID: ActorID = 64;
+pub const SUBNET_ACTOR_ID: ActorID = 1000;
+const GAS_LIMIT: i64 = 1_000_000_000;
+
+/// Parameters of a subnet with dummy consensus, registered in the gateway
+/// by the collateral of a single validator.
+pub fn construct_params() -> ConstructParams {
+    ConstructParams {
+        parent: SubnetID::from_str("/root").unwrap(),
+        name: "test".to_string(),
+        ipc_gateway_addr: Address::new_id(GATEWAY_ID),
+        consensus: ConsensusType::Dummy,
+        min_validator_stake: TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT),
+        min_validators: 0,
+        finality_threshold: 0,
+        check_period: DEFAULT_CHECKPOINT_PERIOD,
+        genesis: vec![],
+        owner: None,
+        invitation_only: false,
+        collateral_token: None,
+        max_missed_windows: 0,
+        chain_id: 0,
+        description: String::new(),
+        metadata_url: None,
+        max_validator_stake: TokenAmount::zero(),
+        excess_stake_policy: ExcessStakePolicy::Reject,
+        dispute_period: 0,
+        min_join_amount: TokenAmount::zero(),
+        caller_policies: CallerPolicies::default(),
+        checkpoint_penalty_rate: 0,
+        churn_limit: 0,
+        relayer_fee: TokenAmount::zero(),
+        min_total_collateral: TokenAmount::zero(),
+        max_submissions_per_window: 0,
+        max_exits_per_window: 0,
+        join_fee: TokenAmount::zero(),
+        join_fee_policy: JoinFeePolicy::Treasury,
+        join_fee_exempt: Vec::new(),
+        leaving_coeff: StakeRatio::default(),
+        sig_scheme: SigScheme::default(),
+        max_voting_power_pct: None,
+        delegated_namespace: None,
+        anchor_genesis: false,
+    }
+}
+
+/// Account of a validator along with its secp256k1 key.
+pub struct Validator {
+    pub account: Account,
+    pub key: SecretKey,
+    sequence: u64,
+}
+
+impl Validator {
+    pub fn addr(&self) -> Address {
+        self.account.1
+    }
+}
+
+pub struct Harness {
+    pub tester: Tester<MemoryBlockstore, DummyExterns>,
+    pub validators: Vec<Validator>,
+    /// Whether the invariants of the subnet state are checked after each
+    /// successful message. Benchmarks disable it to only time the message.
+    pub check_invariants: bool,
+}
+
+impl Harness {
+    /// Deploys the gateway and an unconstructed subnet actor, and creates
+    /// `validators` funded accounts.
+    pub fn new(validators: usize) -> Self {
+        let gateway_wasm =
+            std::fs::read(std::env::var("IPC_GATEWAY_WASM").expect("IPC_GATEWAY_WASM not set"))
+                .unwrap();
+        let bundle_car = std::fs::read(
+            std::env::var("BUILTIN_ACTORS_BUNDLE").expect("BUILTIN_ACTORS_BUNDLE not set"),
+        )
+        .unwrap();
+        let subnet_wasm = ipc_subnet_actor::wasm::WASM_BINARY.unwrap();
+
+        let store = MemoryBlockstore::default();
+        let root = bundle::import_bundle(&store, &bundle_car).unwrap();
+        let mut tester =
+            Tester::new(NetworkVersion::V18, StateTreeVersion::V5, root, store).unwrap();
+
+        let validators = (0..validators)
+            .map(|i| {
+                let key = SecretKey::parse(&[i as u8 + 1; 32]).unwrap();
+                let account = tester
+                    .make_secp256k1_account(
+                        key.clone(),
+                        TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT * 10),
+                    )
+                    .unwrap();
+                Validator {
+                    account,
+                    key,
+                    sequence: 0,
+                }
+            })
+            .collect();
+
+        // deploy the gateway
+        let gateway_st = ipc_gateway::State::new(
+            tester.state_tree.as_ref().unwrap().store(),
+            ipc_gateway::ConstructorParams {
+                network_name: "/root".to_string(),
+                checkpoint_period: DEFAULT_CHECKPOINT_PERIOD,
+            },
+        )
+        .unwrap();
+        let gateway_st = tester.set_state(&gateway_st).unwrap();
+        tester
+            .set_actor_from_bin(
+                &gateway_wasm,
+                gateway_st,
+                Address::new_id(GATEWAY_ID),
+                TokenAmount::zero(),
+            )
+            .unwrap();
+
+        // deploy the subnet actor with an empty state, to be
+        // populated by its constructor
+        let empty_st = tester.set_state(&Vec::<u64>::new()).unwrap();
+        tester
+            .set_actor_from_bin(
+                subnet_wasm,
+                empty_st,
+                Address::new_id(SUBNET_ACTOR_ID),
+                TokenAmount::zero(),
+            )
+            .unwrap();
+
+        tester.instantiate_machine(DummyExterns).unwrap();
+
+        Harness {
+            tester,
+            validators,
+            check_invariants: true,
+        }
+    }
+
+    pub fn subnet_id() -> SubnetID {
+        SubnetID::new(
+            &SubnetID::from_str("/root").unwrap(),
+            Address::new_id(SUBNET_ACTOR_ID),
+        )
+    }
+
+    /// Parameters of the join of the validator at index `validator`.
+    pub fn join_params(&self, validator: usize) -> RawBytes {
+        RawBytes::serialize(JoinParams {
+            validator_net_addr: format!(
+                "/dns/{}.test/tcp/26656",
+                self.validators[validator].addr()
+            )
+            .parse()
+            .unwrap(),
+            invitation: None,
+            vesting: None,
+            worker: None,
+        })
+        .unwrap()
+    }
+
+    /// Calls the constructor of the subnet actor as the init actor would.
+    pub fn construct(&mut self, params: ConstructParams) -> ApplyRet {
+        let message = Message {
+            from: *INIT_ACTOR_ADDR,
+            to: Address::new_id(SUBNET_ACTOR_ID),
+            gas_limit: GAS_LIMIT,
+            method_num: Method::Constructor as u64,
+            params: RawBytes::serialize(params).unwrap(),
+            ..Message::default()
+        };
+
+        let executor = self.tester.executor.as_mut().unwrap();
+        let ret = executor
+            .execute_message(message, ApplyKind::Implicit, 100)
+            .unwrap();
+        self.assert_invariants(&ret);
+        ret
+    }
+
+    /// Sends a message from the validator at index `from` to the subnet actor.
+    pub fn apply(
+        &mut self,
+        from: usize,
+        method: Method,
+        params: RawBytes,
+        value: TokenAmount,
+    ) -> ApplyRet {
+        let validator = &mut self.validators[from];
+        let message = Message {
+            from: validator.addr(),
+            to: Address::new_id(SUBNET_ACTOR_ID),
+            gas_limit: GAS_LIMIT,
+            method_num: method as u64,
+            params,
+            value,
+            sequence: validator.sequence,
+            ..Message::default()
+        };
+        validator.sequence += 1;
+
+        let executor = self.tester.executor.as_mut().unwrap();
+        let ret = executor
+            .execute_message(message, ApplyKind::Explicit, 100)
+            .unwrap();
+        self.assert_invariants(&ret);
+        ret
+    }
+
+    /// Checks the invariants of the subnet state after a successful message.
+    fn assert_invariants(&self, ret: &ApplyRet) {
+        if !self.check_invariants || !ret.msg_receipt.exit_code.is_success() {
+            return;
+        }
+        let report = self.subnet_state().check_invariants(self.store()).unwrap();
+        assert!(report.is_ok(), "{}", report);
+    }
+
+    pub fn actor_state(&self, id: ActorID) -> ActorState {
+        self.tester
+            .executor
+            .as_ref()
+            .unwrap()
+            .state_tree()
+            .get_actor_id(id)
+            .unwrap()
+            .unwrap()
+    }
+
+    pub fn subnet_state(&self) -> State {
+        let head = self.actor_state(SUBNET_ACTOR_ID).state;
+        self.store().get_cbor(&head).unwrap().unwrap()
+    }
+
+    /// Checkpoint of the window at `epoch`, without signature, submitted
+    /// with the state produced under the validator set the window is voted
+    /// with.
+    pub fn submission(&self, epoch: ChainEpoch) -> CheckpointSubmission {
+        let st = self.subnet_state();
+        let config_number = st.window_config_number(self.store(), epoch).unwrap();
+        let (validator_set, _) = st
+            .get_validator_set_at(self.store(), config_number)
+            .unwrap()
+            .unwrap_or_default();
+        CheckpointSubmission {
+            checkpoint: Checkpoint::new(Self::subnet_id(), epoch),
+            state_commitment: StateCommitment {
+                state_root: Cid::default(),
+                height: epoch,
+                config_number,
+                validator_set,
+            },
+        }
+    }
+
+    pub fn gateway_state(&self) -> ipc_gateway::State {
+        let head = self.actor_state(GATEWAY_ID).state;
+        self.store().get_cbor(&head).unwrap().unwrap()
+    }
+
+    pub fn store(&self) -> &MemoryBlockstore {
+        self.tester.executor.as_ref().unwrap().blockstore()
+    }
+
+    /// Signs the submission with the key of the validator at index
+    /// `signer`, encoded as the signature carried by the checkpoint.
+    pub fn sign(&self, signer: usize, submission: &CheckpointSubmission) -> Vec<u8> {
+        RawBytes::serialize(self.signature(signer, submission))
+            .unwrap()
+            .bytes()
+            .to_vec()
+    }
+
+    /// Signature of the submission by the key of the validator at index
+    /// `signer`.
+    pub fn signature(&self, signer: usize, submission: &CheckpointSubmission) -> Signature {
+        let digest = blake2b_simd::Params::new()
+            .hash_length(32)
+            .hash(&submission.digest().unwrap().to_bytes());
+        let (sig, recovery_id) = libsecp256k1::sign(
+            &SecpMessage::parse_slice(digest.as_bytes()).unwrap(),
+            &self.validators[signer].key,
+        );
+        let mut bytes = sig.serialize().to_vec();
+        bytes.push(recovery_id.serialize());
+        Signature::new_secp256k1(bytes)
+    }
+}
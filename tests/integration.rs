@@ -0,0 +1,139 @@
+//! End-to-end tests running the subnet actor WASM against a real IPC
+//! gateway actor on the FVM.
+//!
+//! `MockRuntime` tests only check that the subnet actor sends the expected
+//! messages, they can't catch encoding mismatches with the parameters the
+//! gateway actually accepts. These tests are slow and need the compiled
+//! gateway WASM and a builtin-actors bundle, so they are gated behind the
+//! `integration` feature:
+//!
+//! ```text
+//! IPC_GATEWAY_WASM=<path> BUILTIN_ACTORS_BUNDLE=<path> \
+//!     cargo test --features integration --test integration
+//! ```
+#![cfg(feature = "integration")]
+
+mod harness;
+
+use fvm_ipld_encoding::RawBytes;
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::error::ExitCode;
+use harness::{construct_params, Harness};
+use ipc_gateway::{DEFAULT_CHECKPOINT_PERIOD, MIN_COLLATERAL_AMOUNT};
+use ipc_subnet_actor::{JoinParams, Method, Status};
+use num_traits::Zero;
+
+#[test]
+fn test_lifecycle_against_gateway() {
+    let mut h = Harness::new(1);
+    let subnet_id = Harness::subnet_id();
+    let collateral = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+
+    // construct
+    let ret = h.construct(construct_params());
+    assert_eq!(ret.msg_receipt.exit_code, ExitCode::OK);
+    let st = h.subnet_state();
+    assert_eq!(st.status, Status::Instantiated);
+
+    // join and register the subnet in the gateway
+    let params = RawBytes::serialize(JoinParams {
+        validator_net_addr: format!("/dns/{}.test/tcp/26656", h.validators[0].addr())
+            .parse()
+            .unwrap(),
+        invitation: None,
+        vesting: None,
+        worker: None,
+    })
+    .unwrap();
+    let ret = h.apply(0, Method::Join, params, collateral.clone());
+    assert_eq!(ret.msg_receipt.exit_code, ExitCode::OK);
+
+    let st = h.subnet_state();
+    assert_eq!(st.status, Status::Active);
+    assert_eq!(st.total_stake, collateral);
+    let subnet = h
+        .gateway_state()
+        .get_subnet(h.store(), &subnet_id)
+        .unwrap()
+        .unwrap();
+    assert_eq!(subnet.stake, st.total_stake);
+
+    // commit a checkpoint and propagate it to the gateway
+    let mut submission = h.submission(DEFAULT_CHECKPOINT_PERIOD);
+    let signature = h.sign(0, &submission);
+    submission.checkpoint.set_signature(signature);
+    let ret = h.apply(
+        0,
+        Method::SubmitCheckpoint,
+        RawBytes::serialize(&submission).unwrap(),
+        TokenAmount::zero(),
+    );
+    assert_eq!(ret.msg_receipt.exit_code, ExitCode::OK);
+
+    let subnet = h
+        .gateway_state()
+        .get_subnet(h.store(), &subnet_id)
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        subnet.prev_checkpoint.map(|c| c.cid()),
+        Some(submission.checkpoint.cid())
+    );
+    let st = h.subnet_state();
+    assert_eq!(
+        st.get_votes(h.store(), &submission.digest().unwrap())
+            .unwrap(),
+        None
+    );
+
+    // leave and release the stake from the gateway
+    let ret = h.apply(0, Method::Leave, RawBytes::default(), TokenAmount::zero());
+    assert_eq!(ret.msg_receipt.exit_code, ExitCode::OK);
+
+    let st = h.subnet_state();
+    assert_eq!(st.total_stake, TokenAmount::zero());
+    assert!(st.validators.is_empty());
+    let subnet = h
+        .gateway_state()
+        .get_subnet(h.store(), &subnet_id)
+        .unwrap()
+        .unwrap();
+    assert_eq!(subnet.stake, st.total_stake);
+
+    // kill the subnet once everyone has left
+    let ret = h.apply(0, Method::Kill, RawBytes::default(), TokenAmount::zero());
+    assert_eq!(ret.msg_receipt.exit_code, ExitCode::OK);
+    assert_eq!(h.subnet_state().status, Status::Terminating);
+}
+
+/// Fills the validator set and empties it again. The gas used by these
+/// changes as the set grows is measured in `benches/gas.rs`.
+#[test]
+fn test_large_validator_set() {
+    const VALIDATORS: usize = 32;
+    let mut h = Harness::new(VALIDATORS);
+    let collateral = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+    let ret = h.construct(construct_params());
+    assert_eq!(ret.msg_receipt.exit_code, ExitCode::OK);
+
+    for i in 0..VALIDATORS {
+        let params = RawBytes::serialize(JoinParams {
+            validator_net_addr: format!("/dns/{}.test/tcp/26656", h.validators[i].addr())
+                .parse()
+                .unwrap(),
+            invitation: None,
+            vesting: None,
+            worker: None,
+        })
+        .unwrap();
+        let ret = h.apply(i, Method::Join, params, collateral.clone());
+        assert_eq!(ret.msg_receipt.exit_code, ExitCode::OK);
+    }
+    assert_eq!(h.subnet_state().validators.len(), VALIDATORS);
+
+    for i in (0..VALIDATORS).rev() {
+        let ret = h.apply(i, Method::Leave, RawBytes::default(), TokenAmount::zero());
+        assert_eq!(ret.msg_receipt.exit_code, ExitCode::OK);
+    }
+    assert!(h.subnet_state().validators.is_empty());
+}
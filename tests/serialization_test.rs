@@ -0,0 +1,320 @@
+//! Golden CBOR encodings of the parameters, return values and state of the
+//! subnet actor.
+//!
+//! Clients in other languages, like the Go gateway and the agents, encode
+//! these structures by hand. A reordered or inserted field silently
+//! changes the tuple encoding, so any change to these vectors is a breaking
+//! change of the interface of the actor and needs to be coordinated with
+//! them. Types owned by `ipc_gateway` are spliced in with their own
+//! encoding, their stability is the concern of that crate.
+use std::fmt::Debug;
+use std::str::FromStr;
+
+use cid::Cid;
+use fvm_ipld_encoding::{from_slice, to_vec};
+use fvm_shared::address::Address;
+use fvm_shared::crypto::signature::Signature;
+use fvm_shared::econ::TokenAmount;
+use ipc_gateway::{Checkpoint, SubnetID};
+use ipc_subnet_actor::caller_policy::{CallerPolicies, CallerPolicy};
+use ipc_subnet_actor::sig::SigScheme;
+use ipc_subnet_actor::stake_math::StakeRatio;
+use ipc_subnet_actor::{
+    ChallengeCheckpointParams, CheckpointSubmission, ConsensusType, ConstructParams,
+    ExcessStakePolicy, JoinFeePolicy, JoinParams, JoinReturn, MembershipTicket, State,
+    StateCommitment, Status, Validator, ValidatorSignature, VestingSchedule,
+};
+use serde::de::{DeserializeOwned, IgnoredAny, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+
+const NET_ADDR: &str = "/ip4/127.0.0.1/tcp/26656";
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(s: &str) -> Vec<u8> {
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+/// Asserts that `value` encodes to `golden`, and that `golden` decodes back
+/// to `value`.
+fn assert_golden<T>(value: &T, golden: &str)
+where
+    T: Serialize + DeserializeOwned + PartialEq + Debug,
+{
+    assert_eq!(to_hex(&to_vec(value).unwrap()), golden);
+    assert_eq!(&from_slice::<T>(&from_hex(golden)).unwrap(), value);
+}
+
+fn subnet() -> SubnetID {
+    SubnetID::new(&SubnetID::from_str("/root").unwrap(), Address::new_id(100))
+}
+
+#[test]
+fn test_validator_encoding() {
+    let validator = Validator {
+        addr: Address::new_id(100),
+        net_addr: NET_ADDR.to_string(),
+    };
+    assert_golden(
+        &validator,
+        "8242006478182f6970342f3132372e302e302e312f7463702f3236363536",
+    );
+}
+
+#[test]
+fn test_join_params_encoding() {
+    let params = JoinParams {
+        validator_net_addr: NET_ADDR.parse().unwrap(),
+        invitation: Some(vec![1, 2, 3]),
+        vesting: Some(VestingSchedule {
+            cliff: 10,
+            period: 10,
+            duration: 40,
+        }),
+        worker: Some(Address::new_id(100)),
+    };
+    assert_golden(
+        &params,
+        "8478182f6970342f3132372e302e302e312f7463702f323636353683010203830a0a1828420064",
+    );
+}
+
+#[test]
+fn test_join_return_encoding() {
+    let ret = JoinReturn {
+        status: Status::Active,
+        total_stake: TokenAmount::from_atto(1000),
+        became_validator: true,
+        refunded: TokenAmount::from_atto(0),
+        ticket: Some(MembershipTicket {
+            validator: Address::new_id(100),
+            config_number: 1,
+            effective_epoch: 10,
+        }),
+    };
+    assert_golden(&ret, "8501430003e8f54083420064010a");
+}
+
+#[test]
+fn test_construct_params_encoding() {
+    let params = ConstructParams {
+        parent: SubnetID::from_str("/root").unwrap(),
+        name: "test".to_string(),
+        ipc_gateway_addr: Address::new_id(64),
+        consensus: ConsensusType::Mir,
+        min_validator_stake: TokenAmount::from_atto(1000),
+        min_validators: 1,
+        finality_threshold: 5,
+        check_period: 10,
+        genesis: Vec::new(),
+        owner: Some(Address::new_id(101)),
+        invitation_only: false,
+        collateral_token: None,
+        max_missed_windows: 3,
+        chain_id: 42,
+        description: String::new(),
+        metadata_url: None,
+        max_validator_stake: TokenAmount::from_atto(0),
+        excess_stake_policy: ExcessStakePolicy::Refund,
+        dispute_period: 0,
+        min_join_amount: TokenAmount::from_atto(0),
+        caller_policies: CallerPolicies {
+            join: CallerPolicy::Accounts,
+            leave: CallerPolicy::Any,
+            submit_checkpoint: CallerPolicy::AccountsAndMultisigs,
+        },
+        checkpoint_penalty_rate: 100,
+        churn_limit: 0,
+        relayer_fee: TokenAmount::from_atto(5),
+        min_total_collateral: TokenAmount::from_atto(2000),
+        max_submissions_per_window: 2,
+        max_exits_per_window: 1,
+        join_fee: TokenAmount::from_atto(0),
+        join_fee_policy: JoinFeePolicy::Burn,
+        join_fee_exempt: vec![Address::new_id(102)],
+        leaving_coeff: StakeRatio::new(3, 4).unwrap(),
+        sig_scheme: SigScheme::Bls,
+        max_voting_power_pct: None,
+        delegated_namespace: None,
+        anchor_genesis: false,
+    };
+    let golden = format!(
+        "9823{}{}",
+        to_hex(&to_vec(&params.parent).unwrap()),
+        "647465737442004003430003e801050a80420065f4f603182a60f64001004083010002186400420005430007d0020140018142006682030402f6f6f4",
+    );
+    assert_golden(&params, &golden);
+}
+
+#[test]
+fn test_challenge_checkpoint_params_encoding() {
+    let params = ChallengeCheckpointParams {
+        submission: CheckpointSubmission {
+            checkpoint: Checkpoint::new(subnet(), 10),
+            state_commitment: StateCommitment {
+                state_root: Cid::default(),
+                height: 10,
+                config_number: 1,
+                validator_set: Cid::default(),
+            },
+        },
+        signatures: vec![ValidatorSignature {
+            validator: Address::new_id(100),
+            signature: Signature::new_secp256k1(vec![1, 2, 3, 4]),
+        }],
+    };
+    let golden = format!(
+        "82{}{}",
+        to_hex(&to_vec(&params.submission).unwrap()),
+        "8182420064450101020304",
+    );
+    assert_golden(&params, &golden);
+}
+
+/// Keys of a CBOR map, in the order they are encoded.
+struct MapKeys(Vec<String>);
+
+impl<'de> Deserialize<'de> for MapKeys {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct KeysVisitor;
+
+        impl<'de> Visitor<'de> for KeysVisitor {
+            type Value = MapKeys;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a map")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<MapKeys, A::Error> {
+                let mut keys = Vec::new();
+                while let Some((key, _)) = map.next_entry::<String, IgnoredAny>()? {
+                    keys.push(key);
+                }
+                Ok(MapKeys(keys))
+            }
+        }
+
+        deserializer.deserialize_map(KeysVisitor)
+    }
+}
+
+/// The state is encoded as a map, so decoding only depends on the names of
+/// its fields, which can't be renamed without a migration.
+#[test]
+fn test_state_keys() {
+    let bytes = to_vec(&State::default()).unwrap();
+    let mut keys = from_slice::<MapKeys>(&bytes).unwrap().0;
+    keys.sort();
+
+    let mut expected = vec![
+        "name",
+        "parent_id",
+        "ipc_gateway_addr",
+        "consensus",
+        "econ",
+        "total_stake",
+        "stake",
+        "status",
+        "status_changed_at",
+        "activation",
+        "genesis",
+        "finality_threshold",
+        "check_period",
+        "pending_check_period",
+        "check_period_changes",
+        "checkpoint_log",
+        "checkpoint_configs",
+        "window_checks",
+        "ordered_validators",
+        "config_number",
+        "validator_sets",
+        "membership_log",
+        "membership_events",
+        "membership_tickets",
+        "min_validators",
+        "owner",
+        "invitation_only",
+        "invitations",
+        "cross_msgs",
+        "cross_msg_registrants",
+        "collateral_token",
+        "last_committed_epoch",
+        "max_missed_windows",
+        "participation",
+        "last_seen",
+        "penalized_stake",
+        "treasury",
+        "treasury_held",
+        "join_fee_policy",
+        "join_fee_exempt",
+        "spend_proposals",
+        "spend_votes",
+        "econ_votes",
+        "stats",
+        "reward_pool",
+        "stakers_count",
+        "staker_index",
+        "staker_index_len",
+        "staker_positions",
+        "checkpoints_count",
+        "bootstrapped",
+        "weight_snapshots",
+        "standby",
+        "last_failover",
+        "last_state_commitment",
+        "dispute_period",
+        "pending_checkpoints",
+        "ipc_gateway_robust_addr",
+        "sig_scheme",
+        "gateway_codes",
+        "upgrade_codes",
+        "validator_keys",
+        "workers",
+        "vesting",
+        "queued_gateway_calls",
+        "notify_validator_set",
+        "notified_power_table",
+        "parent_finality",
+        "caller_policies",
+        "paused",
+        "owner_nonce",
+        "delegations",
+        "redelegations",
+        "commission_changes",
+        "funding",
+        "churn_limit",
+        "churn_window",
+        "churn",
+        "pending_changes",
+        "max_exits_per_window",
+        "exit_window",
+        "exits",
+        "exit_queue",
+        "registered_with_gateway",
+        "registration_bond",
+        "parent_finality_votes",
+        "candidates",
+        "vote_expiry",
+        "penalties",
+        "max_submissions_per_window",
+        "submissions",
+        "chain_id",
+        "description",
+        "metadata_url",
+        "excess_stake_policy",
+        "max_voting_power_pct",
+        "delegated_addr",
+        "parent_checked_gateway",
+        "anchor_genesis",
+        "genesis_checkpoint",
+        "slashed_offences",
+        "version",
+    ];
+    expected.sort();
+    assert_eq!(keys, expected);
+}